@@ -0,0 +1,84 @@
+use crate::types::*;
+use crate::hdrimage::*;
+
+/// Buffer of weighted linear RGB sums for merging many samples into one image,
+/// such as when progressively merging exposure brackets or stitching panoramas.
+///
+/// Samples are accumulated at full precision and only resolved (divided by their total weight)
+/// and packed at the end.
+#[derive(Clone, Debug)]
+pub struct AccumImage {
+    pub extent: Extent2D,
+    /// Sum of weighted colours for each texel.
+    pub color: Box<[[f32;3]]>,
+    /// Sum of weights for each texel.
+    pub weight: Box<[f32]>,
+}
+
+impl AccumImage {
+    /// Create an empty buffer with zero weight everywhere.
+    pub fn new(extent: Extent2D) -> Self {
+        let size = extent.texel_count();
+        AccumImage {
+            extent,
+            color: vec![[0.0; 3]; size].into_boxed_slice(),
+            weight: vec![0.0; size].into_boxed_slice(),
+        }
+    }
+
+    /// Add a weighted sample to texel `(x, y)`.
+    pub fn add_sample(&mut self, x: u32, y: u32, rgb: [f32;3], weight: f32) {
+        assert!(x < self.extent.width() && y < self.extent.height(), "sample is outside the image");
+        let i = y as usize * self.extent.width() as usize + x as usize;
+        let acc = &mut self.color[i];
+        for c in 0..3 {
+            acc[c] += rgb[c] * weight;
+        }
+        self.weight[i] += weight;
+    }
+
+    /// Add a whole image (in any [RgbTexel] format) with a weight for each texel.
+    pub fn add_image<T: RgbTexel>(&mut self, data: &[T], weights: &[f32]) {
+        assert_eq!(data.len(), self.color.len(), "data does not match image dimensions");
+        assert_eq!(weights.len(), self.color.len(), "weights do not match image dimensions");
+        for ((acc, acc_weight), (texel, &weight)) in self.color.iter_mut().zip(self.weight.iter_mut()).zip(data.iter().zip(weights)) {
+            let rgb = texel.to_rgb();
+            for c in 0..3 {
+                acc[c] += rgb[c] * weight;
+            }
+            *acc_weight += weight;
+        }
+    }
+
+    /// Divide each texel by its total weight. Texels with no weight are black.
+    pub fn resolve(&self) -> Box<[[f32;3]]> {
+        self.color.iter().zip(self.weight.iter()).map(|(acc, &weight)| {
+            if weight > 0.0 {
+                [acc[0] / weight, acc[1] / weight, acc[2] / weight]
+            } else {
+                [0.0; 3]
+            }
+        }).collect()
+    }
+
+    /// Resolve the buffer and pack it as [RGBE8] texels, ready to be saved as a PNG.
+    pub fn resolve_to_rgbe8(&self) -> HdrImage<RGBE8> {
+        HdrImage::new(self.extent, self.resolve().iter().copied().map(RGBE8::pack).collect())
+    }
+
+    /// Resolve the buffer and pack it as [RGBE8] texels with [triangular_dither],
+    /// which avoids banding in smooth gradients such as baked skies.
+    pub fn resolve_to_rgbe8_dithered(&self) -> HdrImage<RGBE8> {
+        self.resolve_to_rgbe8_dithered_with_seed(0)
+    }
+
+    /// Resolve the buffer and pack it as [RGBE8] texels with the dither pattern of [triangular_dither_seeded] for `seed`.
+    pub fn resolve_to_rgbe8_dithered_with_seed(&self, seed: u32) -> HdrImage<RGBE8> {
+        pack_rgbe8_dithered(self.extent, self.resolve().iter().copied(), seed)
+    }
+
+    /// Resolve the buffer and pack it as [RGB9E5] texels.
+    pub fn resolve_to_rgb9e5(&self) -> HdrImage<RGB9E5> {
+        HdrImage::new(self.extent, self.resolve().iter().copied().map(RGB9E5::pack).collect())
+    }
+}
@@ -0,0 +1,276 @@
+use crate::types::*;
+use crate::hdrimage::*;
+
+use bytemuck::Pod;
+
+/// A borrowed 2D image of [RGB9E5] texels, such as one mip level of a probe.
+#[derive(Clone, Copy, Debug)]
+pub struct AtlasImage<'a> {
+    pub width: u32,
+    pub height: u32,
+    pub data: &'a [RGB9E5],
+}
+
+/// Location of one mip level of one probe within an atlas.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct AtlasRect {
+    pub probe: u32,
+    pub mip: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Many probes packed into a single [RGB9E5] texture, with the location of each mip level.
+#[derive(Clone, Debug)]
+pub struct ProbeAtlas {
+    pub width: u32,
+    pub height: u32,
+    pub data: Box<[RGB9E5]>,
+    /// Rects in the order the probes and mips were given.
+    pub rects: Vec<AtlasRect>,
+}
+
+/// Magic number at the start of the binary index.
+const INDEX_MAGIC: &[u8; 4] = b"PRBA";
+
+impl ProbeAtlas {
+    /// Serialize the atlas layout as JSON, in the form
+    /// `{"width": W, "height": H, "rects": [{"probe": 0, "mip": 0, "x": 0, "y": 0, "width": 32, "height": 32}, ...]}`.
+    pub fn index_json(&self) -> String {
+        let rects: Vec<String> = self.rects.iter().map(|r| format!(
+            "{{\"probe\": {}, \"mip\": {}, \"x\": {}, \"y\": {}, \"width\": {}, \"height\": {}}}",
+            r.probe, r.mip, r.x, r.y, r.width, r.height
+        )).collect();
+        format!("{{\"width\": {}, \"height\": {}, \"rects\": [\n  {}\n]}}\n", self.width, self.height, rects.join(",\n  "))
+    }
+
+    /// Serialize the atlas layout in a compact binary form for streaming.
+    ///
+    /// The index consists of the magic bytes `PRBA`, then the atlas width, height, and number of rects,
+    /// followed by the probe, mip, x, y, width, and height of each rect, all as little-endian `u32`s.
+    pub fn index_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(16 + 24 * self.rects.len());
+        out.extend_from_slice(INDEX_MAGIC);
+        for field in [self.width, self.height, self.rects.len() as u32] {
+            out.extend_from_slice(&field.to_le_bytes());
+        }
+        for r in &self.rects {
+            for field in [r.probe, r.mip, r.x, r.y, r.width, r.height] {
+                out.extend_from_slice(&field.to_le_bytes());
+            }
+        }
+        out
+    }
+}
+
+/// Pack every mip level of many probes into a single atlas texture `atlas_width` texels wide.
+///
+/// Each probe is given as a list of mip levels. Levels are placed using shelf packing (tallest first)
+/// and surrounded by `padding` texels copied from their edges so that bilinear filtering does not bleed between probes.
+/// The atlas is as tall as necessary, and unused space is black.
+///
+/// Panics if any padded level is wider than the atlas, or if the atlas has too many texels to fit in memory.
+pub fn pack_probe_atlas(probes: &[&[AtlasImage]], atlas_width: u32, padding: u32) -> ProbeAtlas {
+    let mut rects = Vec::new();
+    let mut images = Vec::new();
+    for (p, mips) in probes.iter().enumerate() {
+        for (m, img) in mips.iter().enumerate() {
+            assert_eq!(Some(img.data.len()), (img.width as usize).checked_mul(img.height as usize), "data does not match image dimensions");
+            assert!(img.width + 2 * padding <= atlas_width, "probe {p} mip {m} does not fit in the atlas");
+            rects.push(AtlasRect{probe: p as u32, mip: m as u32, x: 0, y: 0, width: img.width, height: img.height});
+            images.push(*img);
+        }
+    }
+
+    // shelf packing in order of decreasing height
+    let mut order: Vec<usize> = (0..rects.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(rects[i].height));
+    let (mut x, mut shelf_y, mut shelf_height) = (0, 0, 0);
+    for &i in &order {
+        let r = &mut rects[i];
+        let (w, h) = (r.width + 2 * padding, r.height + 2 * padding);
+        if x + w > atlas_width {
+            shelf_y += shelf_height;
+            x = 0;
+            shelf_height = 0;
+        }
+        r.x = x + padding;
+        r.y = shelf_y + padding;
+        x += w;
+        shelf_height = shelf_height.max(h);
+    }
+    let atlas_height = shelf_y + shelf_height;
+
+    let len = (atlas_width as usize).checked_mul(atlas_height as usize).expect("atlas is too large");
+    let mut data = vec![RGB9E5(0); len].into_boxed_slice();
+    for (r, img) in rects.iter().zip(images.iter()) {
+        if img.width == 0 || img.height == 0 {
+            continue;
+        }
+        copy_padded(&mut data, atlas_width, (r.x, r.y), img.data, (img.width, img.height), padding);
+    }
+
+    ProbeAtlas{width: atlas_width, height: atlas_height, data, rects}
+}
+
+/// Copies a `width` by `height` image into an atlas `atlas_width` texels wide with its top left corner at `(x, y)`,
+/// surrounded by `padding` texels copied from its edges.
+fn copy_padded<T: Copy>(atlas: &mut [T], atlas_width: u32, (x, y): (u32, u32), src: &[T], (width, height): (u32, u32), padding: u32) {
+    for py in 0..height + 2 * padding {
+        // clamp to the source edges to fill the padding
+        let src_y = py.saturating_sub(padding).min(height - 1);
+        let dst_y = y + py - padding;
+        for px in 0..width + 2 * padding {
+            let src_x = px.saturating_sub(padding).min(width - 1);
+            let dst_x = x + px - padding;
+            atlas[dst_y as usize * atlas_width as usize + dst_x as usize] = src[src_y as usize * width as usize + src_x as usize];
+        }
+    }
+}
+
+/// Layout settings for [pack_texture_atlas].
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct AtlasOptions {
+    /// Largest allowed width and height of the atlas.
+    pub max_size: u32,
+    /// Texels copied from the edges of each image around it, so that bilinear filtering and mipmapping do not bleed between images.
+    pub padding: u32,
+    /// Round the width and height of the atlas up to powers of two.
+    pub power_of_two: bool,
+}
+
+impl Default for AtlasOptions {
+    fn default() -> Self {
+        AtlasOptions{max_size: 4096, padding: 1, power_of_two: false}
+    }
+}
+
+/// Location of one image within a [TextureAtlas], excluding its padding.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct AtlasPlacement {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl AtlasPlacement {
+    /// Texture coordinates of the image in an atlas of the given extent, as `[u_min, v_min, u_max, v_max]`.
+    pub fn uv_rect(&self, atlas: Extent2D) -> [f32;4] {
+        let (w, h) = (atlas.width() as f32, atlas.height() as f32);
+        [self.x as f32 / w, self.y as f32 / h, (self.x + self.width) as f32 / w, (self.y + self.height) as f32 / h]
+    }
+}
+
+/// Many images packed into a single texture by [pack_texture_atlas].
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct TextureAtlas<T: Pod> {
+    pub image: HdrImage<T>,
+    /// Placements in the order the images were given.
+    pub placements: Vec<AtlasPlacement>,
+}
+
+/// Places rectangles with the skyline bottom-left heuristic in an area `width` texels wide and at most `max_height` tall,
+/// in the given order. Returns the position of each rectangle and the height used, or `None` if they do not fit.
+fn skyline_pack(sizes: &[(u32, u32)], order: &[usize], width: u32, max_height: u32) -> Option<(Vec<(u32, u32)>, u32)> {
+    // segments of the top edge of the placed rectangles, as (x, y, width), covering the whole width from left to right
+    let mut skyline = vec![(0u32, 0u32, width)];
+    let mut positions = vec![(0, 0); sizes.len()];
+    let mut used_height = 0;
+    for &i in order {
+        let (w, h) = sizes[i];
+        // lowest position where the rectangle rests on the skyline, preferring the leftmost
+        let mut best: Option<(u32, usize)> = None;
+        for start in 0..skyline.len() {
+            let x = skyline[start].0;
+            if x + w > width {
+                break;
+            }
+            let mut y = 0;
+            let mut end = start;
+            while end < skyline.len() && skyline[end].0 < x + w {
+                y = y.max(skyline[end].1);
+                end += 1;
+            }
+            if y + h <= max_height && best.is_none_or(|(best_y, _)| y < best_y) {
+                best = Some((y, start));
+            }
+        }
+        let (y, start) = best?;
+        let x = skyline[start].0;
+        positions[i] = (x, y);
+        used_height = used_height.max(y + h);
+
+        // replace the segments under the rectangle, keeping any part of the last one which sticks out to the right
+        let mut end = start;
+        while end < skyline.len() && skyline[end].0 < x + w {
+            end += 1;
+        }
+        let (last_x, last_y, last_width) = skyline[end - 1];
+        let mut replacement = vec![(x, y + h, w)];
+        if last_x + last_width > x + w {
+            replacement.push((x + w, last_y, last_x + last_width - (x + w)));
+        }
+        skyline.splice(start..end, replacement);
+        // merge neighbouring segments of the same height
+        skyline.dedup_by(|next, prev| {
+            let merge = next.1 == prev.1;
+            if merge {
+                prev.2 += next.2;
+            }
+            merge
+        });
+    }
+    Some((positions, used_height))
+}
+
+/// Packs many images (such as small emissive textures) into a single atlas texture with a rectangle packer,
+/// returning the atlas and the placement of each image.
+///
+/// Texels are copied without conversion, so [RGBE8] and [RGB9E5] images keep their full HDR range.
+/// The atlas is the narrowest (power-of-two, if requested) width that fits all of the images within `options.max_size`,
+/// and unused space is zero (black in the RGBE formats). Returns `None` if the images do not fit.
+///
+/// Panics if `images` is empty.
+pub fn pack_texture_atlas<T: Pod>(images: &[&HdrImage<T>], options: &AtlasOptions) -> Option<TextureAtlas<T>> {
+    assert!(!images.is_empty(), "atlas needs at least one image");
+    let padding = options.padding;
+    let sizes: Vec<(u32, u32)> = images.iter()
+        .map(|image| Some((image.width().checked_add(padding.checked_mul(2)?)?, image.height().checked_add(2 * padding)?)))
+        .collect::<Option<_>>()?;
+    // placing tall images first leaves a flatter skyline for the rest
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse((sizes[i].1, sizes[i].0)));
+
+    let round = |size: u32| if options.power_of_two { size.checked_next_power_of_two() } else { Some(size) };
+    // the largest height which stays within the maximum size after rounding
+    let max_height = match options.max_size {
+        0 => return None,
+        max if options.power_of_two => 1 << max.ilog2(),
+        max => max,
+    };
+    let area: u64 = sizes.iter().map(|&(w, h)| w as u64 * h as u64).sum();
+    let widest = sizes.iter().map(|&(w, _)| w).max().unwrap();
+    let mut width = round(widest.max((area as f64).sqrt().ceil() as u32))?;
+    let (positions, height) = loop {
+        if width > options.max_size {
+            return None;
+        }
+        if let Some(packed) = skyline_pack(&sizes, &order, width, max_height) {
+            break packed;
+        }
+        // grow by a quarter at a time, so that atlases are not much wider than needed
+        width = round(width.checked_add(width.div_ceil(4))?)?;
+    };
+    let extent = Extent2D::new(width, round(height)?)?;
+
+    let mut data = vec![T::zeroed(); extent.texel_count()].into_boxed_slice();
+    let placements = images.iter().zip(&positions).map(|(image, &(x, y))| {
+        let (x, y) = (x + padding, y + padding);
+        copy_padded(&mut data, width, (x, y), image.pixels(), (image.width(), image.height()), padding);
+        AtlasPlacement{x, y, width: image.width(), height: image.height()}
+    }).collect();
+    Some(TextureAtlas{image: HdrImage::new(extent, data), placements})
+}
@@ -0,0 +1,454 @@
+use crate::types::*;
+
+use bytemuck::{Pod, Zeroable};
+use half::f16;
+
+/// A 128-bit BC6H block encoding a 4×4 tile of half-float RGB texels.
+///
+/// Blocks can be in either the unsigned (`BC6H_UF16`) or signed (`BC6H_SF16`) variant of the format,
+/// which is not recorded in the block itself. The encoder in this crate produces unsigned blocks.
+#[repr(transparent)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Pod, Zeroable)]
+pub struct BC6HBlock(pub [u8; 16]);
+
+/// Interpolation weights for 3-bit indices (two-region modes).
+const WEIGHTS_3: [i32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+/// Interpolation weights for 4-bit indices (one-region modes).
+const WEIGHTS_4: [i32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+/// Number of blocks needed to cover an image, rounding partial blocks up.
+pub fn bc6h_block_count(extent: Extent2D) -> usize {
+    extent.width().div_ceil(4) as usize * extent.height().div_ceil(4) as usize
+}
+
+/// Convert a half float to the 16-bit interpolation domain used by BC6H_UF16.
+/// Negative and NaN values become zero and infinities are clamped to the largest finite half.
+fn to_unquantized(h: f16) -> f32 {
+    let bits = if h.is_nan() || h.is_sign_negative() {0} else {h.to_bits().min(0x7BFF)};
+    // inverse of the decoder's final `(x * 31) >> 6` scaling.
+    bits as f32 * 64.0 / 31.0
+}
+
+/// Unquantize a 10-bit endpoint as the decoder does.
+fn unquantize_10(q: i32) -> i32 {
+    match q {
+        0 => 0,
+        0x3FF => 0xFFFF,
+        _ => ((q << 16) + 0x8000) >> 10,
+    }
+}
+
+fn quantize_10(u: f32) -> i32 {
+    let q = ((u - 32.0) / 64.0).round().clamp(0.0, 1023.0) as i32;
+    // the ends of the range are special-cased by the decoder, so check the neighbours
+    [q - 1, q, q + 1].into_iter().filter(|q| (0..=1023).contains(q))
+        .min_by(|&a, &b| (unquantize_10(a) as f32 - u).abs().total_cmp(&(unquantize_10(b) as f32 - u).abs()))
+        .unwrap_or(q)
+}
+
+/// Choose the best index for each texel given quantized endpoints, returning the indices and the squared error.
+fn fit_indices(texels: &[[f32;3]; 16], q0: [i32;3], q1: [i32;3]) -> ([u8; 16], f32) {
+    let e0 = q0.map(unquantize_10);
+    let e1 = q1.map(unquantize_10);
+    let palette: [[f32;3]; 16] = std::array::from_fn(|i| {
+        let w = WEIGHTS_4[i];
+        std::array::from_fn(|c| (((64 - w) * e0[c] + w * e1[c] + 32) >> 6) as f32)
+    });
+
+    let mut indices = [0u8; 16];
+    let mut total = 0.0;
+    for (px, index) in texels.iter().zip(indices.iter_mut()) {
+        let (best, err) = palette.iter().enumerate().map(|(i, p)| {
+            let d = [p[0] - px[0], p[1] - px[1], p[2] - px[2]];
+            (i, d[0] * d[0] + d[1] * d[1] + d[2] * d[2])
+        }).min_by(|a, b| a.1.total_cmp(&b.1)).unwrap();
+        *index = best as u8;
+        total += err;
+    }
+    (indices, total)
+}
+
+/// Least-squares fit of endpoints to the texels for the given indices.
+fn refit_endpoints(texels: &[[f32;3]; 16], indices: &[u8; 16]) -> Option<([f32;3], [f32;3])> {
+    let (mut aa, mut ab, mut bb) = (0.0f32, 0.0f32, 0.0f32);
+    let mut ax = [0.0f32; 3];
+    let mut bx = [0.0f32; 3];
+    for (px, &i) in texels.iter().zip(indices.iter()) {
+        let b = WEIGHTS_4[i as usize] as f32 / 64.0;
+        let a = 1.0 - b;
+        aa += a * a;
+        ab += a * b;
+        bb += b * b;
+        for c in 0..3 {
+            ax[c] += a * px[c];
+            bx[c] += b * px[c];
+        }
+    }
+    let det = aa * bb - ab * ab;
+    if det.abs() < 1e-6 {
+        return None;
+    }
+    let e0 = std::array::from_fn(|c| (bb * ax[c] - ab * bx[c]) / det);
+    let e1 = std::array::from_fn(|c| (aa * bx[c] - ab * ax[c]) / det);
+    Some((e0, e1))
+}
+
+/// Encode a single block using mode 11 (one region, 10-bit endpoints).
+fn encode_block(texels: &[[f32;3]; 16]) -> BC6HBlock {
+    // Start with the bounding box, choosing the diagonal that best matches the covariance
+    // of each channel with the channel of largest range.
+    let mut lo = [f32::INFINITY; 3];
+    let mut hi = [f32::NEG_INFINITY; 3];
+    let mut mean = [0.0f32; 3];
+    for px in texels {
+        for c in 0..3 {
+            lo[c] = lo[c].min(px[c]);
+            hi[c] = hi[c].max(px[c]);
+            mean[c] += px[c] / 16.0;
+        }
+    }
+    let main = (0..3).max_by(|&a, &b| (hi[a] - lo[a]).total_cmp(&(hi[b] - lo[b]))).unwrap();
+    let mut e0 = lo;
+    let mut e1 = hi;
+    for c in 0..3 {
+        let cov: f32 = texels.iter().map(|px| (px[c] - mean[c]) * (px[main] - mean[main])).sum();
+        if cov < 0.0 {
+            std::mem::swap(&mut e0[c], &mut e1[c]);
+        }
+    }
+
+    let mut q0 = e0.map(quantize_10);
+    let mut q1 = e1.map(quantize_10);
+    let (mut indices, err) = fit_indices(texels, q0, q1);
+    if let Some((r0, r1)) = refit_endpoints(texels, &indices) {
+        let rq0 = r0.map(quantize_10);
+        let rq1 = r1.map(quantize_10);
+        let (r_indices, r_err) = fit_indices(texels, rq0, rq1);
+        if r_err < err {
+            (q0, q1, indices) = (rq0, rq1, r_indices);
+        }
+    }
+
+    // The anchor index (texel 0) is stored without its high bit, so it must be less than 8.
+    if indices[0] >= 8 {
+        std::mem::swap(&mut q0, &mut q1);
+        indices.iter_mut().for_each(|i| *i = 15 - *i);
+    }
+
+    let mut bits = 0u128;
+    let mut pos = 0;
+    let mut write = |value: u32, len: u32| {
+        bits |= ((value & ((1 << len) - 1)) as u128) << pos;
+        pos += len;
+    };
+    write(0x03, 5);
+    for c in q0.into_iter().chain(q1) {
+        write(c as u32, 10);
+    }
+    write(indices[0] as u32, 3);
+    for &i in &indices[1..] {
+        write(i as u32, 4);
+    }
+    BC6HBlock(bits.to_le_bytes())
+}
+
+/// Compress half-float RGB data (in row-major order) into BC6H blocks, using a fetch function to read texels.
+fn encode_bc6h_with<F: Fn(usize) -> [f16;3]>(extent: Extent2D, fetch: F) -> Box<[BC6HBlock]> {
+    let (width, height) = (extent.width(), extent.height());
+    let blocks_x = width.div_ceil(4);
+    let blocks_y = height.div_ceil(4);
+    let mut out = Vec::with_capacity(bc6h_block_count(extent));
+    for by in 0..blocks_y {
+        for bx in 0..blocks_x {
+            let texels: [[f32;3]; 16] = std::array::from_fn(|i| {
+                // partial blocks at the edges repeat the last row or column.
+                let x = (bx * 4 + (i as u32 % 4)).min(width - 1);
+                let y = (by * 4 + (i as u32 / 4)).min(height - 1);
+                fetch(y as usize * width as usize + x as usize).map(to_unquantized)
+            });
+            out.push(encode_block(&texels));
+        }
+    }
+    out.into_boxed_slice()
+}
+
+/// Compress [RGBA16F] texel data into BC6H (`BC6H_UF16`) blocks in row-major block order, ignoring alpha.
+///
+/// Negative values are clamped to zero. Images whose dimensions are not multiples of 4 are padded by repeating edge texels.
+/// This uses a simple single-region encoder which is fast but does not reach the quality of dedicated BC6H compressors.
+pub fn encode_bc6h(extent: Extent2D, data: &[RGBA16F]) -> Box<[BC6HBlock]> {
+    assert_eq!(data.len(), extent.texel_count(), "data does not match image dimensions");
+    encode_bc6h_with(extent, |i| [data[i].r, data[i].g, data[i].b])
+}
+
+/// Compress [RGBE8] texel data into BC6H (`BC6H_UF16`) blocks in row-major block order.
+///
+/// Values beyond the range of f16 are clamped. See [encode_bc6h] for details.
+pub fn encode_rgbe8_bc6h(extent: Extent2D, data: &[RGBE8]) -> Box<[BC6HBlock]> {
+    assert_eq!(data.len(), extent.texel_count(), "data does not match image dimensions");
+    encode_bc6h_with(extent, |i| data[i].unpack().map(f16::from_f32))
+}
+
+// Endpoint fields, in the order channel * 4 + endpoint.
+// W and X are the endpoints of the first region, and Y and Z those of the second.
+const RW: u8 = 0; const RX: u8 = 1; const RY: u8 = 2; const RZ: u8 = 3;
+const GW: u8 = 4; const GX: u8 = 5; const GY: u8 = 6; const GZ: u8 = 7;
+const BW: u8 = 8; const BX: u8 = 9; const BY: u8 = 10; const BZ: u8 = 11;
+
+/// Parameters of a BC6H mode.
+struct ModeInfo {
+    /// Whether endpoints other than W are stored as deltas from W.
+    transformed: bool,
+    two_regions: bool,
+    endpoint_bits: u32,
+    delta_bits: [u32; 3],
+    /// Where endpoint bits are stored after the mode bits, as (field, shift, length) runs.
+    layout: &'static [(u8, u8, u8)],
+}
+
+const fn mode(transformed: bool, two_regions: bool, endpoint_bits: u32, delta_bits: [u32; 3], layout: &'static [(u8, u8, u8)]) -> Option<ModeInfo> {
+    Some(ModeInfo{transformed, two_regions, endpoint_bits, delta_bits, layout})
+}
+
+/// Modes indexed by their 2-bit or 5-bit mode number, as listed in the Direct3D BC6H format documentation.
+static MODES: [Option<ModeInfo>; 32] = {
+    let mut modes = [const {None}; 32];
+    modes[0x00] = mode(true, true, 10, [5, 5, 5], &[(GY, 4, 1), (BY, 4, 1), (BZ, 4, 1), (RW, 0, 10), (GW, 0, 10), (BW, 0, 10), (RX, 0, 5), (GZ, 4, 1), (GY, 0, 4), (GX, 0, 5), (BZ, 0, 1), (GZ, 0, 4), (BX, 0, 5), (BZ, 1, 1), (BY, 0, 4), (RY, 0, 5), (BZ, 2, 1), (RZ, 0, 5), (BZ, 3, 1)]);
+    modes[0x01] = mode(true, true, 7, [6, 6, 6], &[(GY, 5, 1), (GZ, 4, 2), (RW, 0, 7), (BZ, 0, 2), (BY, 4, 1), (GW, 0, 7), (BY, 5, 1), (BZ, 2, 1), (GY, 4, 1), (BW, 0, 7), (BZ, 3, 1), (BZ, 5, 1), (BZ, 4, 1), (RX, 0, 6), (GY, 0, 4), (GX, 0, 6), (GZ, 0, 4), (BX, 0, 6), (BY, 0, 4), (RY, 0, 6), (RZ, 0, 6)]);
+    modes[0x02] = mode(true, true, 11, [5, 4, 4], &[(RW, 0, 10), (GW, 0, 10), (BW, 0, 10), (RX, 0, 5), (RW, 10, 1), (GY, 0, 4), (GX, 0, 4), (GW, 10, 1), (BZ, 0, 1), (GZ, 0, 4), (BX, 0, 4), (BW, 10, 1), (BZ, 1, 1), (BY, 0, 4), (RY, 0, 5), (BZ, 2, 1), (RZ, 0, 5), (BZ, 3, 1)]);
+    modes[0x06] = mode(true, true, 11, [4, 5, 4], &[(RW, 0, 10), (GW, 0, 10), (BW, 0, 10), (RX, 0, 4), (RW, 10, 1), (GZ, 4, 1), (GY, 0, 4), (GX, 0, 5), (GW, 10, 1), (GZ, 0, 4), (BX, 0, 4), (BW, 10, 1), (BZ, 1, 1), (BY, 0, 4), (RY, 0, 4), (BZ, 0, 1), (BZ, 2, 1), (RZ, 0, 4), (GY, 4, 1), (BZ, 3, 1)]);
+    modes[0x0A] = mode(true, true, 11, [4, 4, 5], &[(RW, 0, 10), (GW, 0, 10), (BW, 0, 10), (RX, 0, 4), (RW, 10, 1), (BY, 4, 1), (GY, 0, 4), (GX, 0, 4), (GW, 10, 1), (BZ, 0, 1), (GZ, 0, 4), (BX, 0, 5), (BW, 10, 1), (BY, 0, 4), (RY, 0, 4), (BZ, 1, 2), (RZ, 0, 4), (BZ, 4, 1), (BZ, 3, 1)]);
+    modes[0x0E] = mode(true, true, 9, [5, 5, 5], &[(RW, 0, 9), (BY, 4, 1), (GW, 0, 9), (GY, 4, 1), (BW, 0, 9), (BZ, 4, 1), (RX, 0, 5), (GZ, 4, 1), (GY, 0, 4), (GX, 0, 5), (BZ, 0, 1), (GZ, 0, 4), (BX, 0, 5), (BZ, 1, 1), (BY, 0, 4), (RY, 0, 5), (BZ, 2, 1), (RZ, 0, 5), (BZ, 3, 1)]);
+    modes[0x12] = mode(true, true, 8, [6, 5, 5], &[(RW, 0, 8), (GZ, 4, 1), (BY, 4, 1), (GW, 0, 8), (BZ, 2, 1), (GY, 4, 1), (BW, 0, 8), (BZ, 3, 2), (RX, 0, 6), (GY, 0, 4), (GX, 0, 5), (BZ, 0, 1), (GZ, 0, 4), (BX, 0, 5), (BZ, 1, 1), (BY, 0, 4), (RY, 0, 6), (RZ, 0, 6)]);
+    modes[0x16] = mode(true, true, 8, [5, 6, 5], &[(RW, 0, 8), (BZ, 0, 1), (BY, 4, 1), (GW, 0, 8), (GY, 5, 1), (GY, 4, 1), (BW, 0, 8), (GZ, 5, 1), (BZ, 4, 1), (RX, 0, 5), (GZ, 4, 1), (GY, 0, 4), (GX, 0, 6), (GZ, 0, 4), (BX, 0, 5), (BZ, 1, 1), (BY, 0, 4), (RY, 0, 5), (BZ, 2, 1), (RZ, 0, 5), (BZ, 3, 1)]);
+    modes[0x1A] = mode(true, true, 8, [5, 5, 6], &[(RW, 0, 8), (BZ, 1, 1), (BY, 4, 1), (GW, 0, 8), (BY, 5, 1), (GY, 4, 1), (BW, 0, 8), (BZ, 5, 1), (BZ, 4, 1), (RX, 0, 5), (GZ, 4, 1), (GY, 0, 4), (GX, 0, 5), (BZ, 0, 1), (GZ, 0, 4), (BX, 0, 6), (BY, 0, 4), (RY, 0, 5), (BZ, 2, 1), (RZ, 0, 5), (BZ, 3, 1)]);
+    modes[0x1E] = mode(false, true, 6, [6, 6, 6], &[(RW, 0, 6), (GZ, 4, 1), (BZ, 0, 2), (BY, 4, 1), (GW, 0, 6), (GY, 5, 1), (BY, 5, 1), (BZ, 2, 1), (GY, 4, 1), (BW, 0, 6), (GZ, 5, 1), (BZ, 3, 1), (BZ, 5, 1), (BZ, 4, 1), (RX, 0, 6), (GY, 0, 4), (GX, 0, 6), (GZ, 0, 4), (BX, 0, 6), (BY, 0, 4), (RY, 0, 6), (RZ, 0, 6)]);
+    modes[0x03] = mode(false, false, 10, [10, 10, 10], &[(RW, 0, 10), (GW, 0, 10), (BW, 0, 10), (RX, 0, 10), (GX, 0, 10), (BX, 0, 10)]);
+    modes[0x07] = mode(true, false, 11, [9, 9, 9], &[(RW, 0, 10), (GW, 0, 10), (BW, 0, 10), (RX, 0, 9), (RW, 10, 1), (GX, 0, 9), (GW, 10, 1), (BX, 0, 9), (BW, 10, 1)]);
+    // the high bits of W are stored in reverse order in the last two modes.
+    modes[0x0B] = mode(true, false, 12, [8, 8, 8], &[(RW, 0, 10), (GW, 0, 10), (BW, 0, 10), (RX, 0, 8), (RW, 11, 1), (RW, 10, 1), (GX, 0, 8), (GW, 11, 1), (GW, 10, 1), (BX, 0, 8), (BW, 11, 1), (BW, 10, 1)]);
+    modes[0x0F] = mode(true, false, 16, [4, 4, 4], &[(RW, 0, 10), (GW, 0, 10), (BW, 0, 10), (RX, 0, 4), (RW, 15, 1), (RW, 14, 1), (RW, 13, 1), (RW, 12, 1), (RW, 11, 1), (RW, 10, 1), (GX, 0, 4), (GW, 15, 1), (GW, 14, 1), (GW, 13, 1), (GW, 12, 1), (GW, 11, 1), (GW, 10, 1), (BX, 0, 4), (BW, 15, 1), (BW, 14, 1), (BW, 13, 1), (BW, 12, 1), (BW, 11, 1), (BW, 10, 1)]);
+    modes
+};
+
+/// Region masks for the 32 two-region partitions (bit i is set if texel i is in the second region).
+static PARTITIONS: [u16; 32] = [
+    0xCCCC, 0x8888, 0xEEEE, 0xECC8, 0xC880, 0xFEEC, 0xFEC8, 0xEC80, 0xC800, 0xFFEC, 0xFE80, 0xE800, 0xFFE8, 0xFF00, 0xFFF0, 0xF000,
+    0xF710, 0x008E, 0x7100, 0x08CE, 0x008C, 0x7310, 0x3100, 0x8CCE, 0x088C, 0x3110, 0x6666, 0x366C, 0x17E8, 0x0FF0, 0x718E, 0x399C,
+];
+
+/// Anchor texel of the second region for each partition (the first region is always anchored at texel 0).
+static ANCHORS: [usize; 32] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,
+    15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2, 8, 8, 2, 2,
+];
+
+/// Reads a little-endian bit stream from a block.
+struct BitReader {
+    bits: u128,
+}
+
+impl BitReader {
+    fn read(&mut self, len: u32) -> i32 {
+        let value = (self.bits & ((1 << len) - 1)) as i32;
+        self.bits >>= len;
+        value
+    }
+}
+
+fn sign_extend(value: i32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    (value << shift) >> shift
+}
+
+fn unquantize(comp: i32, bits: u32, signed: bool) -> i32 {
+    if signed {
+        if bits >= 16 {
+            return comp;
+        }
+        let unq = match comp.abs() {
+            0 => 0,
+            c if c >= (1 << (bits - 1)) - 1 => 0x7FFF,
+            c => ((c << 15) + 0x4000) >> (bits - 1),
+        };
+        if comp < 0 {-unq} else {unq}
+    } else {
+        if bits >= 15 {
+            return comp;
+        }
+        match comp {
+            0 => 0,
+            c if c == (1 << bits) - 1 => 0xFFFF,
+            c => ((c << 16) + 0x8000) >> bits,
+        }
+    }
+}
+
+/// Scale an interpolated value to the bits of a half float.
+fn finish_unquantize(comp: i32, signed: bool) -> u16 {
+    if signed {
+        if comp < 0 {
+            0x8000 | (((-comp) * 31) >> 5) as u16
+        } else {
+            ((comp * 31) >> 5) as u16
+        }
+    } else {
+        ((comp * 31) >> 6) as u16
+    }
+}
+
+/// Decode a single block into 16 texels of half-float bits in row-major order.
+fn decode_block(block: &BC6HBlock, signed: bool) -> [[u16; 3]; 16] {
+    let mut reader = BitReader{bits: u128::from_le_bytes(block.0)};
+    let mut mode_bits = reader.read(2);
+    if mode_bits & 2 != 0 {
+        mode_bits |= reader.read(3) << 2;
+    }
+    let Some(mode) = &MODES[mode_bits as usize] else {
+        // reserved modes decode as black.
+        return [[0; 3]; 16];
+    };
+
+    let mut e = [0i32; 12];
+    for &(field, shift, len) in mode.layout {
+        e[field as usize] |= reader.read(len as u32) << shift;
+    }
+    let partition = if mode.two_regions {reader.read(5) as usize} else {0};
+
+    let num_endpoints = if mode.two_regions {4} else {2};
+    for c in 0..3 {
+        let ep = &mut e[c * 4..c * 4 + 4];
+        if signed {
+            ep[0] = sign_extend(ep[0], mode.endpoint_bits);
+        }
+        for i in 1..num_endpoints {
+            if signed || mode.transformed {
+                ep[i] = sign_extend(ep[i], mode.delta_bits[c]);
+            }
+            if mode.transformed {
+                ep[i] = (ep[i] + ep[0]) & ((1 << mode.endpoint_bits) - 1);
+                if signed {
+                    ep[i] = sign_extend(ep[i], mode.endpoint_bits);
+                }
+            }
+        }
+        for value in ep[..num_endpoints].iter_mut() {
+            *value = unquantize(*value, mode.endpoint_bits, signed);
+        }
+    }
+
+    let mut out = [[0u16; 3]; 16];
+    for (i, px) in out.iter_mut().enumerate() {
+        let region = if mode.two_regions {((PARTITIONS[partition] >> i) & 1) as usize} else {0};
+        // anchor texels have an implicit leading zero bit.
+        let is_anchor = i == 0 || (region == 1 && i == ANCHORS[partition]);
+        let index_bits = if mode.two_regions {3} else {4};
+        let index = reader.read(index_bits - is_anchor as u32) as usize;
+        let w = if mode.two_regions {WEIGHTS_3[index]} else {WEIGHTS_4[index]};
+        for (c, out) in px.iter_mut().enumerate() {
+            let e0 = e[c * 4 + region * 2];
+            let e1 = e[c * 4 + region * 2 + 1];
+            *out = finish_unquantize((e0 * (64 - w) + e1 * w + 32) >> 6, signed);
+        }
+    }
+    out
+}
+
+/// Decompress BC6H blocks (in row-major block order) into texels, using a function to convert half-float bits to the output type.
+fn decode_bc6h_with<T: Pod, F: Fn([u16; 3]) -> T>(extent: Extent2D, blocks: &[BC6HBlock], signed: bool, convert: F) -> Box<[T]> {
+    assert_eq!(blocks.len(), bc6h_block_count(extent), "block count does not match image dimensions");
+    let (width, height) = (extent.width() as usize, extent.height() as usize);
+    let blocks_x = width.div_ceil(4);
+    let mut out = bytemuck::allocation::zeroed_slice_box::<T>(extent.texel_count());
+    for (b, block) in blocks.iter().enumerate() {
+        let texels = decode_block(block, signed);
+        let (bx, by) = (b % blocks_x * 4, b / blocks_x * 4);
+        for (i, texel) in texels.into_iter().enumerate() {
+            let (x, y) = (bx + i % 4, by + i / 4);
+            // skip padding in partial blocks
+            if x < width && y < height {
+                out[y * width + x] = convert(texel);
+            }
+        }
+    }
+    out
+}
+
+/// Decompress BC6H blocks (in row-major block order) into [RGBA16F] texels with an alpha of 1.
+/// `signed` selects between the `BC6H_SF16` and `BC6H_UF16` variants of the format.
+///
+/// All 14 modes of the format are supported, and blocks using reserved modes decode to black.
+pub fn decode_bc6h(extent: Extent2D, blocks: &[BC6HBlock], signed: bool) -> Box<[RGBA16F]> {
+    decode_bc6h_with(extent, blocks, signed, |[r, g, b]| {
+        RGBA16F{r: f16::from_bits(r), g: f16::from_bits(g), b: f16::from_bits(b), a: f16::ONE}
+    })
+}
+
+/// Decompress BC6H blocks (in row-major block order) into [RGB9E5] texels.
+/// Negative values (from signed blocks) are clamped to zero.
+pub fn decode_bc6h_as_rgb9e5(extent: Extent2D, blocks: &[BC6HBlock], signed: bool) -> Box<[RGB9E5]> {
+    decode_bc6h_with(extent, blocks, signed, |rgb| {
+        RGB9E5::pack(rgb.map(|c| f16::from_bits(c).to_f32()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_round_trip_partial_blocks() {
+        // 7x5 covers a partial block on both the right and bottom edges
+        let extent = Extent2D::new(7, 5).unwrap();
+        let colours: Vec<[f32; 3]> = (0..35).map(|i| {
+            let (x, y) = ((i % 7) as f32, (i / 7) as f32);
+            let brightness = 0.5 + 0.25 * x + 0.5 * y;
+            [4.0 * brightness, 2.0 * brightness, brightness]
+        }).collect();
+        let data: Vec<RGBA16F> = colours.iter().map(|&[r, g, b]| RGBA16F::from_f32([r, g, b, 1.0])).collect();
+        let blocks = encode_bc6h(extent, &data);
+        assert_eq!(blocks.len(), 4);
+        let decoded = decode_bc6h(extent, &blocks, false);
+        assert_eq!(decoded.len(), 35);
+        for (colour, texel) in colours.iter().zip(decoded.iter()) {
+            let texel = [texel.r, texel.g, texel.b].map(f16::to_f32);
+            // 4-bit indices over a range of about 2.5 stops per block
+            for (c, d) in colour.iter().zip(texel) {
+                assert!((c - d).abs() <= 0.08 * c, "{colour:?} decoded as {texel:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn decode_mode_11_block() {
+        // mode 11 (10-bit endpoints, one region): W = 0 and X = 1023 in every channel.
+        // The anchor texel has a 3-bit index of 7 and the rest use the largest 4-bit index.
+        let mut bits = 0b00011u128 | (1023 << 35) | (1023 << 45) | (1023 << 55) | (7 << 65);
+        for i in 1..16 {
+            bits |= 15 << (68 + 4 * (i - 1));
+        }
+        let extent = Extent2D::new(4, 4).unwrap();
+        let blocks = [BC6HBlock(bits.to_le_bytes())];
+        let decoded = decode_bc6h(extent, &blocks, false);
+        // weight 30 of 64 between 0 and 0xFFFF, then scaled by 31/64: 14880 = 0x3A20
+        assert_eq!(decoded[0], RGBA16F{r: f16::from_bits(0x3A20), g: f16::from_bits(0x3A20), b: f16::from_bits(0x3A20), a: f16::ONE});
+        for texel in &decoded[1..] {
+            assert_eq!([texel.r, texel.g, texel.b], [f16::MAX; 3]);
+        }
+        let rgb9e5 = decode_bc6h_as_rgb9e5(extent, &blocks, false);
+        assert_eq!(rgb9e5[0].unpack(), [0.765625; 3]);
+    }
+
+    #[test]
+    fn decode_reserved_and_empty_blocks() {
+        let extent = Extent2D::new(3, 2).unwrap();
+        // reserved mode 0b10011
+        for block in [BC6HBlock([0; 16]), BC6HBlock([0x13, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF])] {
+            for signed in [false, true] {
+                let decoded = decode_bc6h(extent, &[block], signed);
+                assert_eq!(decoded.len(), 6);
+                assert!(decoded.iter().all(|t| t.r.to_f32() == 0.0 && t.g.to_f32() == 0.0 && t.b.to_f32() == 0.0 && t.a == f16::ONE), "{block:?}");
+            }
+        }
+    }
+}
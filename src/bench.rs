@@ -0,0 +1,173 @@
+use crate::types::*;
+
+use bytemuck::Pod;
+use std::{hint::black_box, time::{Duration, Instant}};
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+/// Texel conversions measured by [bench_conversions] and `bench_parallel`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+pub enum BenchOp {
+    /// [RGBE8::pack] from `[f32;3]`.
+    PackRgbe8,
+    /// [RGBE8::unpack] to `[f32;3]`.
+    UnpackRgbe8,
+    /// [RGB9E5::pack] from `[f32;3]`.
+    PackRgb9e5,
+    /// [RGB9E5::unpack] to `[f32;3]`.
+    UnpackRgb9e5,
+    /// [RGBE8::repack_rgb9e5], without going through `f32`.
+    Rgbe8ToRgb9e5,
+    /// [RGBA16F::from_f32] from `[f32;4]`.
+    PackRgba16f,
+}
+
+impl BenchOp {
+    /// Every conversion, in the order [bench_conversions] measures them.
+    pub const ALL: [BenchOp; 6] = [
+        BenchOp::PackRgbe8, BenchOp::UnpackRgbe8, BenchOp::PackRgb9e5, BenchOp::UnpackRgb9e5, BenchOp::Rgbe8ToRgb9e5, BenchOp::PackRgba16f,
+    ];
+
+    /// Short name for reports, such as `pack-rgbe8`.
+    pub fn name(self) -> &'static str {
+        match self {
+            BenchOp::PackRgbe8 => "pack-rgbe8",
+            BenchOp::UnpackRgbe8 => "unpack-rgbe8",
+            BenchOp::PackRgb9e5 => "pack-rgb9e5",
+            BenchOp::UnpackRgb9e5 => "unpack-rgb9e5",
+            BenchOp::Rgbe8ToRgb9e5 => "rgbe8-to-rgb9e5",
+            BenchOp::PackRgba16f => "pack-rgba16f",
+        }
+    }
+}
+
+/// Time taken by one conversion of a buffer of texels.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct BenchResult {
+    pub op: BenchOp,
+    /// Number of texels converted in each pass.
+    pub texels: usize,
+    /// Number of texels given to each task, which is all of them for single-threaded runs.
+    pub chunk_size: usize,
+    /// Number of threads the conversion ran on.
+    pub threads: usize,
+    /// Average time of one pass over all of the texels.
+    pub elapsed: Duration,
+}
+
+impl BenchResult {
+    /// Throughput in texels per second.
+    pub fn texels_per_second(&self) -> f64 {
+        self.texels as f64 / self.elapsed.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+}
+
+/// The result with the highest throughput, such as to choose the chunk size and thread count for a pipeline.
+pub fn fastest(results: &[BenchResult]) -> Option<BenchResult> {
+    results.iter().copied().max_by(|a, b| a.texels_per_second().total_cmp(&b.texels_per_second()))
+}
+
+/// Input buffers for every conversion, filled with colours spread over a wide range of exponents
+/// so that every code path of the packing functions is exercised.
+struct BenchInputs {
+    rgb: Vec<[f32;3]>,
+    rgba: Vec<[f32;4]>,
+    rgbe8: Vec<RGBE8>,
+    rgb9e5: Vec<RGB9E5>,
+}
+
+impl BenchInputs {
+    fn new(texels: usize) -> Self {
+        let mut rng = SeededRng::new(texels as u32);
+        let rgb: Vec<[f32;3]> = (0..texels).map(|_| core::array::from_fn(|_| (rng.next_f32() * 24.0 - 12.0).exp2())).collect();
+        let rgba = rgb.iter().map(|&[r, g, b]| [r, g, b, 1.0]).collect();
+        let rgbe8 = rgb.iter().map(|&c| RGBE8::pack(c)).collect();
+        let rgb9e5 = rgb.iter().map(|&c| RGB9E5::pack(c)).collect();
+        BenchInputs{rgb, rgba, rgbe8, rgb9e5}
+    }
+
+    /// Average time of converting every texel with `op`, either on this thread or in parallel chunks of `chunk_size`.
+    fn time_op(&self, op: BenchOp, chunk_size: usize, parallel: bool, min_duration: Duration) -> Duration {
+        match op {
+            BenchOp::PackRgbe8 => time_conversion(&self.rgb, RGBE8::pack, chunk_size, parallel, min_duration),
+            BenchOp::UnpackRgbe8 => time_conversion(&self.rgbe8, RGBE8::unpack, chunk_size, parallel, min_duration),
+            BenchOp::PackRgb9e5 => time_conversion(&self.rgb, RGB9E5::pack, chunk_size, parallel, min_duration),
+            BenchOp::UnpackRgb9e5 => time_conversion(&self.rgb9e5, RGB9E5::unpack, chunk_size, parallel, min_duration),
+            BenchOp::Rgbe8ToRgb9e5 => time_conversion(&self.rgbe8, RGBE8::repack_rgb9e5, chunk_size, parallel, min_duration),
+            BenchOp::PackRgba16f => time_conversion(&self.rgba, RGBA16F::from_f32, chunk_size, parallel, min_duration),
+        }
+    }
+}
+
+/// Runs `pass` repeatedly for at least `min_duration` (and at least once), returning the average time of a pass.
+fn time_passes(min_duration: Duration, mut pass: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    let mut passes = 0;
+    while passes == 0 || start.elapsed() < min_duration {
+        pass();
+        passes += 1;
+    }
+    start.elapsed() / passes
+}
+
+/// Average time of converting `input` into a preallocated buffer with `f`.
+#[cfg_attr(not(feature = "rayon"), allow(unused_variables))]
+fn time_conversion<I: Copy + Sync, O: Pod + Send>(input: &[I], f: impl Fn(I) -> O + Sync, chunk_size: usize, parallel: bool, min_duration: Duration) -> Duration {
+    let mut output = vec![O::zeroed(); input.len()];
+    #[cfg(feature = "rayon")]
+    if parallel {
+        return time_passes(min_duration, || {
+            output.par_chunks_mut(chunk_size).zip(input.par_chunks(chunk_size)).for_each(|(out, chunk)| {
+                for (o, &i) in out.iter_mut().zip(chunk) {
+                    *o = f(i);
+                }
+            });
+            black_box(&mut output);
+        });
+    }
+    time_passes(min_duration, || {
+        for (o, &i) in output.iter_mut().zip(black_box(input)) {
+            *o = f(i);
+        }
+        black_box(&mut output);
+    })
+}
+
+/// Measures the single-threaded throughput of every [BenchOp] on this machine, converting buffers of `texels` texels
+/// repeatedly for at least `min_duration` each.
+///
+/// Buffers which fit in the CPU caches measure the conversions themselves, while larger ones (millions of texels)
+/// are closer to the throughput of converting whole images. Panics if `texels` is zero.
+pub fn bench_conversions(texels: usize, min_duration: Duration) -> Vec<BenchResult> {
+    assert!(texels > 0, "benchmark needs at least one texel");
+    let inputs = BenchInputs::new(texels);
+    BenchOp::ALL.iter().map(|&op| {
+        let elapsed = inputs.time_op(op, texels, false, min_duration);
+        BenchResult{op, texels, chunk_size: texels, threads: 1, elapsed}
+    }).collect()
+}
+
+/// Measures the throughput of `op` on rayon thread pools of each of `thread_counts` threads (0 for one per CPU),
+/// splitting buffers of `texels` texels into tasks of each of `chunk_sizes` texels, so that pipelines can pick
+/// the fastest combination for this machine with [fastest].
+///
+/// Panics if `texels` or any chunk size is zero.
+#[cfg(feature = "rayon")]
+pub fn bench_parallel(op: BenchOp, texels: usize, chunk_sizes: &[usize], thread_counts: &[usize], min_duration: Duration) -> Result<Vec<BenchResult>, rayon::ThreadPoolBuildError> {
+    assert!(texels > 0, "benchmark needs at least one texel");
+    assert!(!chunk_sizes.contains(&0), "chunk sizes must be greater than zero");
+    let inputs = BenchInputs::new(texels);
+    let mut results = Vec::with_capacity(chunk_sizes.len() * thread_counts.len());
+    for &threads in thread_counts {
+        let timings = crate::ops::with_thread_count(threads, || {
+            let threads = rayon::current_num_threads();
+            chunk_sizes.iter().map(|&chunk_size| {
+                let elapsed = inputs.time_op(op, chunk_size, true, min_duration);
+                BenchResult{op, texels, chunk_size, threads, elapsed}
+            }).collect::<Vec<_>>()
+        })?;
+        results.extend(timings);
+    }
+    Ok(results)
+}
@@ -0,0 +1,48 @@
+use crate::types::*;
+use crate::load::*;
+
+use bevy_app::{App, Plugin};
+use bevy_asset::{io::Reader, AssetApp, AssetLoader, LoadContext, RenderAssetUsages};
+use bevy_image::Image;
+use image::ImageError;
+use wgpu_types::{Extent3d, TextureDimension, TextureFormat};
+
+/// Bevy [AssetLoader] for RGBE8 PNG files with the extension `.rgbe.png`,
+/// which loads them as [Image] assets with format `Rgb9e5Ufloat`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RgbePngLoader;
+
+impl AssetLoader for RgbePngLoader {
+    type Asset = Image;
+    type Settings = ();
+    type Error = ImageError;
+
+    async fn load(&self, reader: &mut dyn Reader, _settings: &(), _load_context: &mut LoadContext<'_>) -> Result<Image, ImageError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await.map_err(ImageError::IoError)?;
+        // decoding from memory verifies the texel checksum, if the file has one
+        let image = decode_rgbe8_png_from_bytes(&bytes)?.convert::<RGB9E5>();
+        let extent = image.extent();
+        Ok(Image::new(
+            Extent3d{width: extent.width(), height: extent.height(), depth_or_array_layers: 1},
+            TextureDimension::D2,
+            bytemuck::cast_slice(image.pixels()).to_vec(),
+            TextureFormat::Rgb9e5Ufloat,
+            RenderAssetUsages::default(),
+        ))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["rgbe.png"]
+    }
+}
+
+/// Registers [RgbePngLoader], so that `asset_server.load("sky.rgbe.png")` returns an RGB9E5 [Image].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RgbePlugin;
+
+impl Plugin for RgbePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset_loader::<RgbePngLoader>();
+    }
+}
@@ -0,0 +1,530 @@
+//! Code shared by the command-line tools.
+
+// each tool uses a different subset of the config.
+#![allow(dead_code)]
+
+use rgbe::Extent2D;
+use std::{env, fmt::Write, fs, io, path::{Path, PathBuf}, process::{self, ExitCode}, sync::{atomic::{AtomicUsize, Ordering}, Condvar, Mutex}, thread, time::{Duration, Instant}};
+
+/// Description of the config file for usage messages.
+pub const CONFIG_HELP: &str = "\
+Defaults for some options can be set in $XDG_CONFIG_HOME/rgbe/config.toml (usually ~/.config/rgbe/config.toml):
+  output_dir = \"renders\"    Directory for output files when --output is not given
+  compression = \"best\"      PNG compression level for RGBE8 PNG output
+  jobs = 4                  Number of images to load in parallel
+  nice = 10                 Scheduling priority to lower batch commands to, from 0 (normal) to 19 (only idle CPU time)
+  max_memory = \"8G\"         Approximate memory limit for images being processed at once (K, M, or G suffix)
+  post_process = \"cmd\"      Shell command to run on each output file when --post-process is not given
+
+Output profiles for hdr2rgbe-png --profile, each writing every input in a format for one platform, go in a [profiles] table:
+  [profiles]
+  pc = \"bc6h-ktx2\"
+  web = \"rgbe-png\"
+The formats are rgbe-png, rgb9e5-ktx2, bc6h-ktx2, rgb9e5-dds, rgba16f-dds, bc6h-dds, and exr
+(those other than rgbe-png need hdr2rgbe-png to be built with the corresponding features).
+
+Post-processing commands are run with the environment variables RGBE_OUTPUT (the file just written)
+and RGBE_INPUT (the file it was made from, if there was a single input) set, so that further tools can be chained.
+Single-quoted strings can be used for commands containing double quotes, e.g.
+  post_process = 'studio-pack \"$RGBE_OUTPUT\"'";
+
+/// Defaults for command-line options, which are overridden by flags.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    pub output_dir: Option<PathBuf>,
+    pub compression: Option<String>,
+    pub jobs: Option<usize>,
+    pub nice: Option<u32>,
+    pub max_memory: Option<u64>,
+    pub post_process: Option<String>,
+    /// Output profiles as (name, format) pairs, in the order they were given.
+    pub profiles: Vec<(String, String)>,
+}
+
+/// Location of the config file, following the XDG base directory spec.
+fn config_path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()).map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_home.join("rgbe").join("config.toml"))
+}
+
+/// Removes a trailing comment from a line, ignoring `#` characters within strings.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' | '\'' if quote.is_none() => quote = Some(c),
+            _ if quote == Some(c) => quote = None,
+            '#' if quote.is_none() => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Parses a string value, which must be in double quotes or (as a literal string) single quotes.
+fn parse_string(key: &str, value: &str) -> Result<String, String> {
+    ['"', '\''].into_iter()
+        .find_map(|quote| value.strip_prefix(quote).and_then(|v| v.strip_suffix(quote)))
+        .map(str::to_string)
+        .ok_or_else(|| format!("{key} must be a quoted string"))
+}
+
+/// Parses a number of bytes with an optional `K`, `M`, or `G` suffix (in powers of 1024).
+pub fn parse_memory_size(size: &str) -> Result<u64, String> {
+    let (digits, scale) = match size.trim().to_ascii_uppercase() {
+        s if s.ends_with('K') => (s[..s.len() - 1].to_string(), 1 << 10),
+        s if s.ends_with('M') => (s[..s.len() - 1].to_string(), 1 << 20),
+        s if s.ends_with('G') => (s[..s.len() - 1].to_string(), 1 << 30),
+        s => (s, 1),
+    };
+    digits.trim().parse::<u64>().ok()
+        .and_then(|n| n.checked_mul(scale))
+        .ok_or_else(|| format!("invalid memory size '{size}' (expected a number of bytes with an optional K, M, or G suffix)"))
+}
+
+/// Parses a niceness for [set_nice], from 0 to 19.
+pub fn parse_nice(nice: &str) -> Result<u32, String> {
+    nice.trim().parse().ok().filter(|&n| n <= 19)
+        .ok_or_else(|| format!("invalid niceness '{nice}' (expected a number from 0 to 19)"))
+}
+
+/// Lowers the scheduling priority of the process to a niceness from 0 to 19, as with the `nice` command,
+/// so that long batches leave the CPU to interactive applications running alongside them.
+/// This must be called before starting any threads, which inherit the priority on Linux.
+///
+/// On Windows, niceness from 1 to 9 selects the below normal priority class, and 10 or more the idle class.
+pub fn set_nice(nice: u32) -> Result<(), String> {
+    #[cfg(unix)]
+    {
+        extern "C" {
+            fn setpriority(which: std::ffi::c_int, who: std::ffi::c_uint, prio: std::ffi::c_int) -> std::ffi::c_int;
+        }
+        const PRIO_PROCESS: std::ffi::c_int = 0;
+        // SAFETY: setpriority only reads its integer arguments, and a `who` of 0 is the calling process
+        if unsafe { setpriority(PRIO_PROCESS, 0, nice as std::ffi::c_int) } != 0 {
+            return Err(format!("failed to set the niceness to {nice}: {}", io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+    #[cfg(windows)]
+    {
+        extern "system" {
+            fn GetCurrentProcess() -> *mut std::ffi::c_void;
+            fn SetPriorityClass(process: *mut std::ffi::c_void, class: u32) -> i32;
+        }
+        let class = match nice {
+            0 => 0x20, // NORMAL_PRIORITY_CLASS
+            1..=9 => 0x4000, // BELOW_NORMAL_PRIORITY_CLASS
+            _ => 0x40, // IDLE_PRIORITY_CLASS
+        };
+        // SAFETY: the pseudo-handle of the current process is always valid
+        if unsafe { SetPriorityClass(GetCurrentProcess(), class) } == 0 {
+            return Err(format!("failed to set the process priority: {}", io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        Err(format!("setting the niceness to {nice} is not supported on this platform"))
+    }
+}
+
+/// Splits command-line arguments into flags and their values, accepting both `--flag value` and `--flag=value`.
+///
+/// Each argument (flag or positional) is returned by [ArgParser::next_arg], after which the value of a flag is taken with [ArgParser::value].
+pub struct ArgParser<I> {
+    args: I,
+    /// The flag most recently returned, for error messages.
+    flag: String,
+    /// The value given to that flag after `=`, until it is taken.
+    inline_value: Option<String>,
+}
+
+impl<I: Iterator<Item = String>> ArgParser<I> {
+    pub fn new(args: I) -> Self {
+        ArgParser{args, flag: String::new(), inline_value: None}
+    }
+
+    /// Returns the next flag (without any `=value`) or positional argument.
+    /// Fails if the previous flag was given a value with `=` but does not take one.
+    pub fn next_arg(&mut self) -> Result<Option<String>, String> {
+        if self.inline_value.is_some() {
+            return Err(format!("{} does not take a value", self.flag));
+        }
+        let Some(arg) = self.args.next() else {
+            return Ok(None);
+        };
+        self.flag = match arg.split_once('=') {
+            Some((flag, value)) if arg.starts_with("--") => {
+                self.inline_value = Some(value.to_string());
+                flag.to_string()
+            }
+            _ => arg,
+        };
+        Ok(Some(self.flag.clone()))
+    }
+
+    /// Takes the value of the current flag, which is either given after `=` or the next argument.
+    pub fn value(&mut self, name: &str) -> Result<String, String> {
+        self.inline_value.take().or_else(|| self.args.next()).ok_or_else(|| format!("{name} requires a value"))
+    }
+}
+
+impl Config {
+    /// Reads the config file, if there is one.
+    pub fn load() -> Result<Config, String> {
+        let Some(path) = config_path() else {
+            return Ok(Config::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => Config::parse(&text).map_err(|msg| format!("{}: {msg}", path.display())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(format!("failed to read {}: {e}", path.display())),
+        }
+    }
+
+    /// Parses the simple subset of TOML used by the config file: `key = value` pairs, the `[profiles]` table, and comments.
+    fn parse(text: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        let mut in_profiles = false;
+        for (n, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(table) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                if table.trim() != "profiles" {
+                    return Err(format!("line {}: unknown table '{}'", n + 1, table.trim()));
+                }
+                in_profiles = true;
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| format!("line {}: expected key = value", n + 1))?;
+            let (key, value) = (key.trim(), value.trim());
+            if in_profiles {
+                // profile names become part of output file names
+                if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                    return Err(format!("line {}: invalid profile name '{key}' (use letters, digits, '-', and '_')", n + 1));
+                }
+                config.profiles.push((key.to_string(), parse_string(key, value)?));
+                continue;
+            }
+            match key {
+                "output_dir" => config.output_dir = Some(PathBuf::from(parse_string(key, value)?)),
+                "compression" => config.compression = Some(parse_string(key, value)?),
+                "max_memory" => config.max_memory = Some(parse_memory_size(&parse_string(key, value)?).map_err(|msg| format!("line {}: {msg}", n + 1))?),
+                "post_process" => config.post_process = Some(parse_string(key, value)?),
+                "jobs" => config.jobs = Some(value.parse().map_err(|_| format!("line {}: jobs must be a number", n + 1))?),
+                "nice" => config.nice = Some(parse_nice(value).map_err(|msg| format!("line {}: {msg}", n + 1))?),
+                _ => return Err(format!("line {}: unknown key '{key}'", n + 1)),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Path of an output file whose path was not given explicitly, placed in the configured output directory if there is one.
+    pub fn default_output(&self, path: PathBuf) -> PathBuf {
+        match (&self.output_dir, path.file_name()) {
+            (Some(dir), Some(name)) => dir.join(name),
+            _ => path,
+        }
+    }
+
+    /// Runs the post-processing command (from `--post-process` if given, or the config file otherwise) on an output file.
+    pub fn post_process(&self, command: Option<&str>, input: Option<&Path>, output: &Path) -> Result<(), String> {
+        match command.or(self.post_process.as_deref()) {
+            Some(command) => run_post_process(command, input, output),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Runs a command through the platform shell with `RGBE_INPUT` and `RGBE_OUTPUT` set,
+/// failing if it exits unsuccessfully.
+pub fn run_post_process(command: &str, input: Option<&Path>, output: &Path) -> Result<(), String> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = process::Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = process::Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell.arg(command).env("RGBE_OUTPUT", output);
+    match input {
+        Some(input) => shell.env("RGBE_INPUT", input),
+        None => shell.env_remove("RGBE_INPUT"),
+    };
+    let status = shell.status().map_err(|e| format!("failed to run post-processing command: {e}"))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("post-processing command failed on {} ({status})", output.display()))
+    }
+}
+
+/// Verbosity of progress messages, from least to most verbose.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl LogLevel {
+    pub fn parse(level: &str) -> Result<LogLevel, String> {
+        match level {
+            "error" => Ok(LogLevel::Error),
+            "warn" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            _ => Err(format!("invalid log level '{level}' (expected error, warn, info, or debug)")),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+        }
+    }
+}
+
+/// Time spent on each stage of processing one file, and the sizes of its input and output,
+/// for finding the slow assets in large batches.
+#[derive(Clone, Debug)]
+pub struct FileStats {
+    pub input: PathBuf,
+    pub input_bytes: u64,
+    /// Path and size of the file written, if any.
+    pub output: Option<(PathBuf, u64)>,
+    pub extent: Option<Extent2D>,
+    pub stages: Vec<(&'static str, Duration)>,
+}
+
+impl FileStats {
+    pub fn new(input: &Path) -> Self {
+        FileStats{input: input.to_path_buf(), input_bytes: 0, output: None, extent: None, stages: Vec::new()}
+    }
+
+    /// Runs one stage of processing, recording how long it took.
+    pub fn time<T>(&mut self, stage: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.stages.push((stage, start.elapsed()));
+        result
+    }
+}
+
+/// Quotes a string for JSON output.
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if c.is_control() => { let _ = write!(out, "\\u{:04x}", c as u32); }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Prints progress messages and per-file statistics, either as text or as one JSON object per line.
+#[derive(Clone, Copy, Debug)]
+pub struct Logger {
+    pub level: LogLevel,
+    pub json: bool,
+}
+
+impl Default for Logger {
+    fn default() -> Self {
+        Logger{level: LogLevel::Info, json: false}
+    }
+}
+
+impl Logger {
+    fn message(&self, level: LogLevel, msg: &str) {
+        if self.level < level {
+            return;
+        }
+        if self.json {
+            println!("{{\"level\": \"{}\", \"message\": {}}}", level.name(), json_string(msg));
+        } else if level <= LogLevel::Warn {
+            eprintln!("{}: {msg}", level.name());
+        } else {
+            println!("{msg}");
+        }
+    }
+
+    pub fn error(&self, msg: &str) {
+        self.message(LogLevel::Error, msg);
+    }
+
+    pub fn warn(&self, msg: &str) {
+        self.message(LogLevel::Warn, msg);
+    }
+
+    pub fn info(&self, msg: &str) {
+        self.message(LogLevel::Info, msg);
+    }
+
+    pub fn debug(&self, msg: &str) {
+        self.message(LogLevel::Debug, msg);
+    }
+
+    /// Logs the statistics of one file.
+    ///
+    /// In JSON, every file is logged as an info message. In text, files which were converted are logged as
+    /// `INPUT -> OUTPUT (WxH)` at the info level, and timings are only shown at the debug level.
+    pub fn file(&self, stats: &FileStats) {
+        if self.json {
+            if self.level < LogLevel::Info {
+                return;
+            }
+            let mut line = format!("{{\"level\": \"info\", \"input\": {}, \"input_bytes\": {}", json_string(&stats.input.to_string_lossy()), stats.input_bytes);
+            if let Some((output, bytes)) = &stats.output {
+                let _ = write!(line, ", \"output\": {}, \"output_bytes\": {bytes}", json_string(&output.to_string_lossy()));
+            }
+            if let Some(extent) = stats.extent {
+                let _ = write!(line, ", \"width\": {}, \"height\": {}", extent.width(), extent.height());
+            }
+            for (stage, time) in &stats.stages {
+                let _ = write!(line, ", \"{stage}_ms\": {:.3}", time.as_secs_f64() * 1000.0);
+            }
+            println!("{line}}}");
+            return;
+        }
+
+        if let (Some((output, _)), Some(extent)) = (&stats.output, stats.extent) {
+            self.info(&format!("{} -> {} ({}x{})", stats.input.display(), output.display(), extent.width(), extent.height()));
+        }
+        if self.level >= LogLevel::Debug {
+            let stages: Vec<String> = stats.stages.iter()
+                .map(|(stage, time)| format!("{stage} {:.1} ms", time.as_secs_f64() * 1000.0))
+                .collect();
+            let sizes = match &stats.output {
+                Some((_, bytes)) => format!("{} -> {bytes} bytes", stats.input_bytes),
+                None => format!("{} bytes", stats.input_bytes),
+            };
+            println!("  {}: {} ({sizes})", stats.input.display(), stages.join(", "));
+        }
+    }
+}
+
+/// Exit code when some, but not all, of the files in a batch failed.
+pub const EXIT_PARTIAL_FAILURE: u8 = 3;
+
+/// Failures collected while processing a batch of files, so that one corrupt input does not abort the whole run.
+#[derive(Clone, Debug, Default)]
+pub struct BatchFailures {
+    pub total: usize,
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+impl BatchFailures {
+    /// Records the result of processing one file. Errors should already have been logged when they happened.
+    pub fn record<T>(&mut self, path: &Path, result: Result<T, String>) -> Option<T> {
+        self.total += 1;
+        match result {
+            Ok(value) => Some(value),
+            Err(msg) => {
+                self.failed.push((path.to_path_buf(), msg));
+                None
+            }
+        }
+    }
+
+    /// Logs a summary of any failures and returns the exit code for the run:
+    /// success if every file succeeded, [EXIT_PARTIAL_FAILURE] if some failed, and failure if all of them did.
+    pub fn finish(&self, log: &Logger) -> ExitCode {
+        if self.failed.is_empty() {
+            return ExitCode::SUCCESS;
+        }
+        let paths: Vec<String> = self.failed.iter().map(|(path, _)| path.display().to_string()).collect();
+        log.error(&format!("{} of {} files failed: {}", self.failed.len(), self.total, paths.join(", ")));
+        if self.failed.len() == self.total {
+            ExitCode::FAILURE
+        } else {
+            ExitCode::from(EXIT_PARTIAL_FAILURE)
+        }
+    }
+}
+
+/// A limit on the estimated memory used by jobs running at the same time.
+#[derive(Debug)]
+pub struct MemoryBudget {
+    limit: Option<u64>,
+    used: Mutex<u64>,
+    released: Condvar,
+}
+
+impl MemoryBudget {
+    pub fn new(limit: Option<u64>) -> Self {
+        MemoryBudget{limit, used: Mutex::new(0), released: Condvar::new()}
+    }
+
+    /// Blocks until `bytes` fit within the budget, then reserves them until the returned guard is dropped.
+    /// Jobs larger than the whole budget run once nothing else is running, rather than never.
+    pub fn reserve(&self, bytes: u64) -> MemoryReservation<'_> {
+        let mut used = self.used.lock().unwrap();
+        if let Some(limit) = self.limit {
+            while *used > 0 && *used + bytes > limit {
+                used = self.released.wait(used).unwrap();
+            }
+        }
+        *used += bytes;
+        MemoryReservation{budget: self, bytes}
+    }
+}
+
+/// Memory reserved from a [MemoryBudget], which is released when dropped.
+#[derive(Debug)]
+pub struct MemoryReservation<'a> {
+    budget: &'a MemoryBudget,
+    bytes: u64,
+}
+
+impl Drop for MemoryReservation<'_> {
+    fn drop(&mut self) {
+        *self.budget.used.lock().unwrap() -= self.bytes;
+        self.budget.released.notify_all();
+    }
+}
+
+/// Runs `f` on every item using up to `jobs` threads, returning the results in order.
+///
+/// Before each item is started, its peak memory use is estimated with `estimate`
+/// and reserved from `budget`, so fewer jobs run at once when the items are large.
+pub fn run_batch<T: Sync, R: Send>(
+    items: &[T],
+    jobs: usize,
+    budget: &MemoryBudget,
+    estimate: impl Fn(&T) -> u64 + Sync,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new(items.iter().map(|_| None).collect());
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, items.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(i) else {
+                    break;
+                };
+                let _reservation = budget.reserve(estimate(item));
+                let result = f(item);
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+    results.into_inner().unwrap().into_iter().map(|result| result.expect("batch job did not finish")).collect()
+}
@@ -1,13 +1,447 @@
-use image::{ImageError, ImageResult};
-use std::{env, io, path};
-
-pub fn main() -> ImageResult<()> {
-    let args: Box<[String]> = env::args().collect();
-    if args.len() < 2 {
-        return Err(ImageError::IoError(io::Error::new(io::ErrorKind::InvalidInput, "A filename is required")));
-    }
-    let path = path::Path::new(&args[1]);
-    let (width, height, data) = rgbe::load_radiance_file(path)?;
-    let outpath = path.with_extension("rgbe.png");
-    rgbe::save_rgbe8_png_file(&outpath, width, height, &data)
-}
\ No newline at end of file
+mod common;
+
+use common::{ArgParser, BatchFailures, Config, FileStats, LogLevel, Logger, MemoryBudget};
+use image::{codecs::png::CompressionType, ImageResult};
+use rgbe::{HdrImage, RGBE8};
+use std::{env, fs, path::{Path, PathBuf}, process::ExitCode, thread};
+
+const USAGE: &str = "\
+Usage: hdr2rgbe-png [OPTIONS] <INPUTS>...
+
+Converts Radiance HDR images into RGBE8 PNGs (with the exponent in the alpha channel),
+or into the formats of the output profiles defined in the config file.
+
+Options:
+  -o, --output <PATH>        Output file, for a single input [default: <INPUT> with the extension .rgbe.png]
+      --preview <PATH>       Also write a tone-mapped sRGB PNG preview, for a single input
+  -p, --profile <NAME>       Write the output of a profile from the config file, as <INPUT>.<NAME>.<EXT> (can be repeated)
+      --all-profiles         Write the outputs of every profile in the config file
+  -f, --force                Overwrite the output file if it already exists
+  -j, --jobs <N>             Number of files to convert in parallel [default: number of CPUs]
+      --threads <N>          Same as --jobs, as each file is converted on one thread
+      --nice <LEVEL>         Lower the scheduling priority to LEVEL, from 0 (normal) to 19 (only idle CPU time),
+                             so that the conversion does not slow down other applications
+      --checkpoint <PATH>    Record finished files in PATH, and skip files already recorded there when resuming
+      --max-memory <SIZE>    Run fewer jobs at once to keep the estimated memory use of images below SIZE (e.g. 8G)
+      --absolute             Divide the texels by the EXPOSURE header, so that they are in absolute radiance units
+      --white-balance <FROM[:TO]>
+                             Neutralize light of the FROM white point (a temperature such as 3200K, or d65, d50, aces, a, or e)
+                             with a Bradford chromatic adaptation to TO [default: d65], before converting the primaries
+      --primaries <FROM:TO>  Convert colours between primaries: rec709, rec2020, p3, ap0, or acescg (e.g. acescg:rec709)
+      --gamut-mapping <MODE> How --primaries brings colours into the target gamut: clip, or compress to desaturate
+                             colours near the boundary smoothly instead of shifting their hue [default: clip]
+      --color-transform <T>  Convert colours between scene-linear colour spaces named as in the ACES and CG OpenColorIO configs,
+                             e.g. 'ACEScg -> Linear Rec.709 (sRGB)' (a change of primaries; OpenColorIO itself is not used)
+      --soft-clip <THRESHOLD>
+                             Compress values above THRESHOLD smoothly into the range of RGB9E5 outputs instead of clipping them,
+                             recording the threshold in KTX2 files so that it can be undone
+  -c, --compression <LEVEL>  PNG compression level: fast, default, or best [default: best]
+      --checksum             Store a CRC-32 of the texels in RGBE8 PNGs, which rgbe checks when loading them
+      --post-process <CMD>   Shell command to run on the output file, with its path in $RGBE_OUTPUT
+  -q, --quiet                Do not print progress messages (same as --log-level=warn)
+      --log-level <LEVEL>    Verbosity: error, warn, info, or debug (which adds per-file timings) [default: info]
+      --log-json             Print progress and per-file timings and sizes as one JSON object per line
+  -h, --help                 Print this help message
+
+If some inputs fail to convert, the rest are still converted and the exit code is 3.
+An interrupted batch can be resumed by running it again with the same --checkpoint file
+(and --force if a file was being written when it was interrupted).
+
+Defaults for the output directory, compression level, and post-processing command, as well as output profiles,
+can be set in a config file; run `rgbe help config` for details.";
+
+struct Args {
+    inputs: Vec<PathBuf>,
+    output: Option<PathBuf>,
+    preview: Option<PathBuf>,
+    profiles: Vec<String>,
+    all_profiles: bool,
+    force: bool,
+    jobs: Option<usize>,
+    nice: Option<u32>,
+    max_memory: Option<u64>,
+    checkpoint: Option<PathBuf>,
+    absolute: bool,
+    white_balance: Option<(rgbe::WhitePoint, rgbe::WhitePoint)>,
+    primaries: Option<(rgbe::Primaries, rgbe::Primaries)>,
+    gamut_mapping: rgbe::GamutMapping,
+    transform: Option<rgbe::NamedPrimariesTransform>,
+    soft_clip: Option<rgbe::SoftClip>,
+    compression: Option<CompressionType>,
+    checksum: bool,
+    post_process: Option<String>,
+    log: Logger,
+}
+
+enum Command {
+    Convert(Box<Args>),
+    Help,
+}
+
+/// File format written for an output profile.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum OutputFormat {
+    RgbePng,
+    Rgb9e5Ktx2,
+    Bc6hKtx2,
+    Rgb9e5Dds,
+    Rgba16fDds,
+    Bc6hDds,
+    Exr,
+}
+
+impl OutputFormat {
+    fn parse(name: &str) -> Result<OutputFormat, String> {
+        let (format, features, enabled) = match name {
+            "rgbe-png" => (OutputFormat::RgbePng, "std", true),
+            "rgb9e5-ktx2" => (OutputFormat::Rgb9e5Ktx2, "ktx2", cfg!(feature = "ktx2")),
+            "bc6h-ktx2" => (OutputFormat::Bc6hKtx2, "bc6h and ktx2", cfg!(all(feature = "bc6h", feature = "ktx2"))),
+            "rgb9e5-dds" => (OutputFormat::Rgb9e5Dds, "dds", cfg!(feature = "dds")),
+            "rgba16f-dds" => (OutputFormat::Rgba16fDds, "dds", cfg!(feature = "dds")),
+            "bc6h-dds" => (OutputFormat::Bc6hDds, "bc6h and dds", cfg!(all(feature = "bc6h", feature = "dds"))),
+            "exr" => (OutputFormat::Exr, "exr", cfg!(feature = "exr")),
+            "astc-ktx2" => return Err("astc-ktx2 is not supported, as there is no ASTC encoder".into()),
+            _ => return Err(format!(
+                "unknown output format '{name}' (expected rgbe-png, rgb9e5-ktx2, bc6h-ktx2, rgb9e5-dds, rgba16f-dds, bc6h-dds, or exr)"
+            )),
+        };
+        if enabled {
+            Ok(format)
+        } else {
+            Err(format!("{name} output requires hdr2rgbe-png to be built with the {features} features"))
+        }
+    }
+
+    /// File extension of the format, without the leading dot.
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::RgbePng => "rgbe.png",
+            OutputFormat::Rgb9e5Ktx2 | OutputFormat::Bc6hKtx2 => "ktx2",
+            OutputFormat::Rgb9e5Dds | OutputFormat::Rgba16fDds | OutputFormat::Bc6hDds => "dds",
+            OutputFormat::Exr => "exr",
+        }
+    }
+
+    #[cfg_attr(not(any(feature = "ktx2", feature = "dds")), allow(unused_variables))]
+    fn encode(self, image: &HdrImage<RGBE8>, compression: CompressionType, checksum: bool, soft_clip: Option<rgbe::SoftClip>) -> ImageResult<Vec<u8>> {
+        let mut out = Vec::new();
+        let extent = image.extent();
+        // shoulder highlights before packing into RGB9E5, whose range is much smaller than that of RGBE8
+        #[cfg(any(feature = "ktx2", feature = "dds"))]
+        let to_rgb9e5 = || match soft_clip {
+            Some(clip) => {
+                let mut linear = image.convert::<rgbe::RGB32F>();
+                rgbe::soft_clip(linear.pixels_mut(), clip);
+                linear.convert::<rgbe::RGB9E5>()
+            }
+            None => image.convert::<rgbe::RGB9E5>(),
+        };
+        match self {
+            OutputFormat::RgbePng if checksum => rgbe::encode_rgbe8_png_with_checksum(extent, image.pixels(), &mut out, compression)?,
+            OutputFormat::RgbePng => rgbe::encode_rgbe8_png_with_compression(extent, image.pixels(), &mut out, compression)?,
+            #[cfg(feature = "ktx2")]
+            OutputFormat::Rgb9e5Ktx2 => match soft_clip {
+                Some(clip) => rgbe::encode_rgb9e5_ktx2_with_soft_clip(extent, to_rgb9e5().pixels(), clip, &mut out)?,
+                None => rgbe::encode_rgb9e5_ktx2(extent, to_rgb9e5().pixels(), &mut out)?,
+            },
+            #[cfg(all(feature = "bc6h", feature = "ktx2"))]
+            OutputFormat::Bc6hKtx2 => rgbe::encode_bc6h_ktx2(extent, &rgbe::encode_rgbe8_bc6h(extent, image.pixels()), &mut out)?,
+            #[cfg(feature = "dds")]
+            OutputFormat::Rgb9e5Dds => rgbe::encode_rgb9e5_dds(extent, to_rgb9e5().pixels(), &mut out)?,
+            #[cfg(feature = "dds")]
+            OutputFormat::Rgba16fDds => rgbe::encode_rgba16f_dds(extent, image.convert::<rgbe::RGBA16F>().pixels(), &mut out)?,
+            #[cfg(all(feature = "bc6h", feature = "dds"))]
+            OutputFormat::Bc6hDds => rgbe::encode_bc6h_dds(extent, &rgbe::encode_rgbe8_bc6h(extent, image.pixels()), &mut out)?,
+            #[cfg(feature = "exr")]
+            OutputFormat::Exr => rgbe::encode_exr(extent, image.pixels(), &mut out)?,
+            #[allow(unreachable_patterns)]
+            _ => unreachable!("formats of disabled features are rejected when parsed"),
+        }
+        Ok(out)
+    }
+}
+
+fn parse_primaries(spec: &str) -> Result<(rgbe::Primaries, rgbe::Primaries), String> {
+    let (from, to) = spec.split_once(':').ok_or_else(|| format!("invalid value '{spec}' for --primaries (expected FROM:TO)"))?;
+    let lookup = |name: &str| rgbe::Primaries::from_name(name)
+        .ok_or_else(|| format!("unknown primaries '{name}' (expected rec709, rec2020, p3, ap0, or acescg)"));
+    Ok((lookup(from)?, lookup(to)?))
+}
+
+fn parse_white_point(name: &str) -> Result<rgbe::WhitePoint, String> {
+    let name = name.trim().to_ascii_lowercase();
+    match name.as_str() {
+        "d65" => Ok(rgbe::WhitePoint::D65),
+        "d50" => Ok(rgbe::WhitePoint::D50),
+        "aces" | "d60" => Ok(rgbe::WhitePoint::ACES),
+        "a" => Ok(rgbe::WhitePoint::A),
+        "e" => Ok(rgbe::WhitePoint::E),
+        _ => name.strip_suffix('k').unwrap_or(&name).parse::<f32>().ok()
+            .filter(|kelvin| kelvin.is_finite() && *kelvin > 0.0)
+            .map(rgbe::WhitePoint::from_kelvin)
+            .ok_or_else(|| format!("unknown white point '{name}' (expected a temperature such as 3200K, or d65, d50, aces, a, or e)")),
+    }
+}
+
+fn parse_white_balance(spec: &str) -> Result<(rgbe::WhitePoint, rgbe::WhitePoint), String> {
+    match spec.split_once(':') {
+        Some((from, to)) => Ok((parse_white_point(from)?, parse_white_point(to)?)),
+        None => Ok((parse_white_point(spec)?, rgbe::WhitePoint::D65)),
+    }
+}
+
+fn parse_gamut_mapping(mode: &str) -> Result<rgbe::GamutMapping, String> {
+    match mode {
+        "clip" => Ok(rgbe::GamutMapping::Clip),
+        "compress" => Ok(rgbe::GamutMapping::Compress),
+        _ => Err(format!("invalid gamut mapping '{mode}' (expected clip or compress)")),
+    }
+}
+
+fn parse_compression(level: &str) -> Result<CompressionType, String> {
+    match level {
+        "fast" => Ok(CompressionType::Fast),
+        "default" => Ok(CompressionType::Default),
+        "best" => Ok(CompressionType::Best),
+        _ => Err(format!("invalid compression level '{level}' (expected fast, default, or best)")),
+    }
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut inputs = Vec::new();
+    let mut output = None;
+    let mut preview = None;
+    let mut profiles = Vec::new();
+    let mut all_profiles = false;
+    let mut force = false;
+    let mut jobs = None;
+    let mut nice = None;
+    let mut max_memory = None;
+    let mut checkpoint = None;
+    let mut absolute = false;
+    let mut white_balance = None;
+    let mut primaries = None;
+    let mut gamut_mapping = rgbe::GamutMapping::default();
+    let mut transform = None;
+    let mut soft_clip = None;
+    let mut compression = None;
+    let mut checksum = false;
+    let mut post_process = None;
+    let mut log = Logger::default();
+
+    let mut args = ArgParser::new(args);
+    while let Some(arg) = args.next_arg()? {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-o" | "--output" => output = Some(PathBuf::from(args.value("--output")?)),
+            "--preview" => preview = Some(PathBuf::from(args.value("--preview")?)),
+            "-p" | "--profile" => profiles.push(args.value("--profile")?),
+            "--all-profiles" => all_profiles = true,
+            "-f" | "--force" => force = true,
+            "-j" | "--jobs" | "--threads" => {
+                let n = args.value(&arg)?;
+                jobs = Some(n.parse().map_err(|_| format!("invalid value '{n}' for {arg}"))?);
+            }
+            "--nice" => nice = Some(common::parse_nice(&args.value("--nice")?)?),
+            "--checkpoint" => checkpoint = Some(PathBuf::from(args.value("--checkpoint")?)),
+            "--max-memory" => max_memory = Some(common::parse_memory_size(&args.value("--max-memory")?)?),
+            "--absolute" => absolute = true,
+            "--white-balance" => white_balance = Some(parse_white_balance(&args.value("--white-balance")?)?),
+            "--primaries" => primaries = Some(parse_primaries(&args.value("--primaries")?)?),
+            "--gamut-mapping" => gamut_mapping = parse_gamut_mapping(&args.value("--gamut-mapping")?)?,
+            "--color-transform" => {
+                let spec = args.value("--color-transform")?;
+                transform = Some(rgbe::NamedPrimariesTransform::parse(&spec).map_err(|e| e.to_string())?);
+            }
+            "--soft-clip" => {
+                let threshold = args.value("--soft-clip")?;
+                match threshold.parse::<f32>() {
+                    Ok(t) if t > 0.0 && t < rgbe::RGB9E5::MAX => soft_clip = Some(rgbe::SoftClip::rgb9e5(t)),
+                    _ => return Err(format!("invalid value '{threshold}' for --soft-clip (expected a value between 0 and {})", rgbe::RGB9E5::MAX)),
+                }
+            }
+            "-c" | "--compression" => compression = Some(parse_compression(&args.value("--compression")?)?),
+            "--checksum" => checksum = true,
+            "--post-process" => post_process = Some(args.value("--post-process")?),
+            "-q" | "--quiet" => log.level = LogLevel::Warn,
+            "--log-level" => log.level = LogLevel::parse(&args.value("--log-level")?)?,
+            "--log-json" => log.json = true,
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{arg}'")),
+            _ => inputs.push(PathBuf::from(arg)),
+        }
+    }
+
+    if inputs.is_empty() {
+        return Err("at least one input file is required".into());
+    }
+    if (output.is_some() || preview.is_some()) && inputs.len() > 1 {
+        return Err("--output and --preview cannot be used with multiple inputs".into());
+    }
+    if output.is_some() && (all_profiles || !profiles.is_empty()) {
+        return Err("--output cannot be used with --profile or --all-profiles".into());
+    }
+    Ok(Command::Convert(Box::new(Args{
+        inputs, output, preview, profiles, all_profiles, force, jobs, nice, max_memory, checkpoint,
+        absolute, white_balance, primaries, gamut_mapping, transform, soft_clip, compression, checksum, post_process, log,
+    })))
+}
+
+/// Converts a single file to each of its outputs, returning the statistics of each.
+/// Reading and decoding the input are only counted in the statistics of the first output.
+fn convert(input: &Path, outputs: &[(PathBuf, OutputFormat)], compression: CompressionType, args: &Args) -> Result<Vec<FileStats>, String> {
+    let output_paths = outputs.iter().map(|(path, _)| path.as_path());
+    for path in output_paths.chain(args.preview.as_deref()) {
+        if !args.force && path.exists() {
+            return Err(format!("{} already exists (use --force to overwrite)", path.display()));
+        }
+    }
+
+    let mut stats = FileStats::new(input);
+    let bytes = stats.time("read", || fs::read(input)).map_err(|e| format!("failed to read {}: {e}", input.display()))?;
+    stats.input_bytes = bytes.len() as u64;
+    let mut image = stats.time("decode", || if args.absolute {
+        rgbe::decode_radiance_absolute(&bytes[..], rgbe::Primaries::Rec709).map(|(image, _)| image)
+    } else {
+        rgbe::decode_radiance_from_bytes(&bytes)
+    })
+        .map_err(|e| format!("failed to load {}: {e}", input.display()))?;
+    if let Some((from, to)) = args.white_balance {
+        let primaries = args.primaries.map_or(rgbe::Primaries::Rec709, |(primaries, _)| primaries);
+        stats.time("transform", || rgbe::white_balance(image.pixels_mut(), from, to, rgbe::ChromaticAdaptation::Bradford, primaries));
+    }
+    if let Some((from, to)) = args.primaries {
+        stats.time("transform", || rgbe::convert_primaries_with_gamut_mapping(image.pixels_mut(), from, to, args.gamut_mapping));
+    }
+    if let Some(transform) = args.transform {
+        stats.time("transform", || transform.apply(&mut image));
+    }
+    stats.extent = Some(image.extent());
+    if let Some(preview) = &args.preview {
+        stats.time("preview", || {
+            let pixels = rgbe::tonemap_srgb8(image.pixels(), 0.0);
+            rgbe::save_rgb8_png_file(preview, image.extent(), &pixels)
+        }).map_err(|e| format!("failed to save {}: {e}", preview.display()))?;
+    }
+
+    let mut all_stats = Vec::with_capacity(outputs.len());
+    for (output, format) in outputs {
+        let mut output_stats = stats.clone();
+        if !all_stats.is_empty() {
+            output_stats.stages.clear();
+        }
+        let bytes = output_stats.time("encode", || format.encode(&image, compression, args.checksum, args.soft_clip))
+            .map_err(|e| format!("failed to encode {}: {e}", output.display()))?;
+        output_stats.time("write", || fs::write(output, &bytes)).map_err(|e| format!("failed to save {}: {e}", output.display()))?;
+        output_stats.output = Some((output.clone(), bytes.len() as u64));
+        all_stats.push(output_stats);
+    }
+    Ok(all_stats)
+}
+
+/// Output profiles selected on the command line, with their formats.
+fn selected_profiles(args: &Args, config: &Config) -> Result<Vec<(String, OutputFormat)>, String> {
+    if args.all_profiles && config.profiles.is_empty() {
+        return Err("--all-profiles was given, but the config file does not define any profiles".into());
+    }
+    let names: Vec<&str> = if args.all_profiles {
+        config.profiles.iter().map(|(name, _)| name.as_str()).collect()
+    } else {
+        args.profiles.iter().map(String::as_str).collect()
+    };
+    names.into_iter().map(|name| {
+        let (_, format) = config.profiles.iter().find(|(profile, _)| profile == name)
+            .ok_or_else(|| format!("unknown profile '{name}' (profiles are defined in the config file; run `rgbe help config` for details)"))?;
+        let format = OutputFormat::parse(format).map_err(|msg| format!("profile '{name}': {msg}"))?;
+        Ok((name.to_string(), format))
+    }).collect()
+}
+
+/// Estimated peak memory for converting a file: the file itself, the decoded texels, the encoded PNG, and the preview.
+fn estimate_memory(input: &Path, args: &Args) -> u64 {
+    let file_size = fs::metadata(input).map_or(0, |metadata| metadata.len());
+    let texels = rgbe::peek_dimensions(input).map_or(0, |(width, height, _)| width as u64 * height as u64);
+    let bytes_per_texel = if args.preview.is_some() {4 + 4 + 3} else {4 + 4};
+    let estimate = file_size + texels * bytes_per_texel;
+    args.log.debug(&format!("{}: estimated {} MiB", input.display(), estimate.div_ceil(1 << 20)));
+    estimate
+}
+
+/// Converts every input in parallel, continuing past any which fail.
+fn run(args: &Args) -> Result<BatchFailures, String> {
+    let config = Config::load()?;
+    let compression = match (args.compression, &config.compression) {
+        (Some(compression), _) => compression,
+        (None, Some(level)) => parse_compression(level)?,
+        (None, None) => CompressionType::Best,
+    };
+    if let Some(nice) = args.nice.or(config.nice) {
+        common::set_nice(nice)?;
+    }
+    let jobs = args.jobs.or(config.jobs)
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+    let budget = MemoryBudget::new(args.max_memory.or(config.max_memory));
+    let profiles = selected_profiles(args, &config)?;
+    let checkpoint = args.checkpoint.as_deref().map(|path| {
+        rgbe::BatchCheckpoint::open(path).map_err(|e| format!("failed to open {}: {e}", path.display()))
+    }).transpose()?;
+
+    let results = common::run_batch(&args.inputs, jobs, &budget, |input| estimate_memory(input, args), |input| {
+        let mut outputs = if profiles.is_empty() {
+            let output = args.output.clone().unwrap_or_else(|| config.default_output(input.with_extension("rgbe.png")));
+            vec![(output, OutputFormat::RgbePng)]
+        } else {
+            profiles.iter().map(|(name, format)| {
+                (config.default_output(input.with_extension(format!("{name}.{}", format.extension()))), *format)
+            }).collect()
+        };
+        outputs.retain(|(output, _)| {
+            let done = checkpoint.as_ref().is_some_and(|checkpoint| checkpoint.is_done(input, output));
+            if done {
+                args.log.info(&format!("{} -> {} (already done)", input.display(), output.display()));
+            }
+            !done
+        });
+        if outputs.is_empty() {
+            return Ok(());
+        }
+        let result = convert(input, &outputs, compression, args).and_then(|all_stats| {
+            for (stats, (output, _)) in all_stats.iter().zip(&outputs) {
+                args.log.file(stats);
+                config.post_process(args.post_process.as_deref(), Some(input), output)?;
+                if let Some(checkpoint) = &checkpoint {
+                    checkpoint.record(input, output).map_err(|e| format!("failed to save checkpoint: {e}"))?;
+                }
+            }
+            Ok(())
+        });
+        if let Err(msg) = &result {
+            args.log.error(msg);
+        }
+        result
+    });
+    let mut failures = BatchFailures::default();
+    for (input, result) in args.inputs.iter().zip(results) {
+        failures.record(input, result);
+    }
+    Ok(failures)
+}
+
+pub fn main() -> ExitCode {
+    match parse_args(env::args().skip(1)) {
+        Ok(Command::Help) => {
+            println!("{USAGE}");
+            ExitCode::SUCCESS
+        }
+        Ok(Command::Convert(args)) => match run(&args) {
+            Ok(failures) => failures.finish(&args.log),
+            Err(msg) => {
+                eprintln!("error: {msg}");
+                ExitCode::FAILURE
+            }
+        },
+        Err(msg) => {
+            eprintln!("error: {msg}\n\n{USAGE}");
+            ExitCode::from(2)
+        }
+    }
+}
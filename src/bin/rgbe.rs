@@ -0,0 +1,1345 @@
+mod common;
+
+use common::{ArgParser, BatchFailures, Config, FileStats, LogLevel, Logger, MemoryBudget, CONFIG_HELP};
+use rgbe::{AnyHdrImage, ContactSheetEntry, ContactSheetOptions, DisplayTarget, Extent2D, HdrImage, RgbTexel, ToneMapOperator, Zebra, RGBE8};
+use std::{env, fmt::Write, fs, path::{Path, PathBuf}, process::ExitCode, thread, time::Instant};
+
+const USAGE: &str = "\
+Usage: rgbe <COMMAND> [OPTIONS]
+
+Tools for working with RGBE HDR images.
+
+Commands:
+  contactsheet  Tile tone-mapped thumbnails of many HDR images into one PNG
+  tonemap       Convert an HDR image into an sRGB PNG preview
+  evstrip       Render an HDR image at several exposures side by side
+  preview       Print a tone-mapped HDR image to the terminal and inspect its pixels
+  compare       Print the error of a converted HDR image relative to the original
+  sweep         Compare the size and error of an HDR image in every storage format
+  cookie        Generate an HDR light cookie for a spot light
+  atlas         Pack many HDR images into one texture atlas without losing their range
+  completions   Print a shell completion script for bash, zsh, or fish
+
+Run `rgbe <COMMAND> --help` for the options of each command.
+Defaults for some options can be set in a config file; run `rgbe help config` for details.";
+
+const CONTACTSHEET_USAGE: &str = "\
+Usage: rgbe contactsheet [OPTIONS] <INPUTS>...
+
+Tiles tone-mapped thumbnails of Radiance HDR or RGBE8 PNG images into a single sRGB PNG,
+labelled with their file names and luminance statistics.
+
+Images which fail to load are left out, and the exit code is 3 if there were any.
+
+Options:
+  -o, --output <PATH>     Output file [default: contactsheet.png]
+  -j, --jobs <N>          Number of images to load in parallel [default: number of CPUs]
+      --threads <N>       Same as --jobs, as each image is loaded on one thread
+      --nice <LEVEL>      Lower the scheduling priority to LEVEL, from 0 (normal) to 19 (only idle CPU time)
+      --max-memory <SIZE> Load fewer images at once to keep the estimated memory use below SIZE (e.g. 8G)
+  -f, --force             Overwrite the output file if it already exists
+  -s, --size <PIXELS>     Maximum size of each thumbnail [default: 256]
+      --columns <N>       Number of thumbnails per row [default: 4]
+  -e, --exposure <STOPS>  Exposure adjustment [default: 0]
+      --no-auto-exposure  Do not normalize the exposure of each thumbnail
+      --zebra             Stripe pixels which are too bright for RGB9E5
+      --zebra-threshold <VALUE>
+                          Stripe pixels with any channel above VALUE instead
+      --post-process <CMD>
+                          Shell command to run on the output file, with its path in $RGBE_OUTPUT
+      --log-level <LEVEL> Verbosity: error, warn, info, or debug (which adds per-file timings) [default: info]
+      --log-json          Print progress and per-file timings and sizes as one JSON object per line
+  -h, --help              Print this help message";
+
+const TONEMAP_USAGE: &str = "\
+Usage: rgbe tonemap [OPTIONS] <INPUT>
+
+Converts a Radiance HDR or RGBE8 PNG image into a tone-mapped sRGB PNG,
+or a false colour map of its luminance for checking exposure.
+
+With --target p3 or pq, the preview is encoded for Display P3 or Rec.2100 PQ (HDR) displays instead.
+PQ previews are saved as 16-bit PNGs, which are not tagged as PQ, so most viewers will show them washed out.
+
+False colours are in stops relative to middle grey (18%): purple and blue are underexposed,
+green is middle grey, and yellow through red are highlights, with white at +6 stops and above.
+
+Options:
+  -o, --output <PATH>     Output file [default: <INPUT>.preview.png]
+  -f, --force             Overwrite the output file if it already exists
+  -e, --exposure <STOPS>  Exposure adjustment [default: 0]
+      --operator <NAME>   Tone mapping operator: reinhard, aces, or agx [default: reinhard]
+      --target <DISPLAY>  Display to encode for: srgb, p3, or pq [default: srgb]
+      --peak-nits <NITS>  Peak luminance of the display for --target pq [default: 1000]
+      --false-color       Render luminance as a false colour map by stops
+      --zebra             Stripe pixels which are too bright for RGB9E5
+      --zebra-threshold <VALUE>
+                          Stripe pixels with any channel above VALUE instead
+      --post-process <CMD>
+                          Shell command to run on the output file, with its path in $RGBE_OUTPUT
+  -h, --help              Print this help message";
+
+const EVSTRIP_USAGE: &str = "\
+Usage: rgbe evstrip [OPTIONS] <INPUT>
+
+Renders a Radiance HDR or RGBE8 PNG image at several exposures side by side in a single sRGB PNG,
+for evaluating HDR merges.
+
+Options:
+  -o, --output <PATH>     Output file [default: <INPUT>.evstrip.png]
+  -f, --force             Overwrite the output file if it already exists
+  -s, --size <PIXELS>     Maximum size of each frame [default: 256]
+      --stops <LIST>      Comma-separated exposures in stops [default: -4,-3,-2,-1,0,1,2,3,4]
+      --post-process <CMD>
+                          Shell command to run on the output file, with its path in $RGBE_OUTPUT
+  -h, --help              Print this help message";
+
+const PREVIEW_USAGE: &str = "\
+Usage: rgbe preview [OPTIONS] <INPUT> [<COMPARE>]
+
+Prints a tone-mapped Radiance HDR, RGBE8 PNG, or (with the ktx2 feature) RGB9E5 KTX2 image
+to the terminal using 24-bit colour, for checking conversions without exporting them to other programs.
+This is a static preview at the resolution of the terminal, not an interactive viewer.
+
+Given a second image of the same size, such as a converted or compressed copy, the two are compared:
+by default the view is split at a fixed position, with the first image on the left and the second on the right,
+and the error of the second image relative to the first is printed.
+
+With --gpu-check, the image is instead packed as RGB9E5 and compared with what the GPU decodes it to
+(shown as a difference by default), to catch drivers or shaders which decode the format differently from this crate.
+
+Options:
+  -e, --exposure <STOPS>  Exposure adjustment [default: 0]
+      --operator <NAME>   Tone mapping operator: reinhard, aces, or agx [default: reinhard]
+      --false-color       Show luminance as a false colour map by stops
+  -w, --width <COLUMNS>   Width of the view in terminal columns [default: $COLUMNS, or 80]
+  -i, --inspect <X,Y>     Print the stored and linear values of the pixel at X,Y (can be repeated)
+      --split <FRACTION>  Position of the split across the view, from 0 to 1 [default: 0.5]
+      --flicker           Alternate between the two images every half second until interrupted
+      --diff              Show the relative error of the second image as a heat map
+      --diff-gain <N>     Show errors of 1/N and above as white in the heat map [default: 100]
+      --gpu-check         Compare the RGB9E5 decoding of the GPU with the reference decoding (requires the wgpu feature)
+  -h, --help              Print this help message";
+
+const COMPARE_USAGE: &str = "\
+Usage: rgbe compare [OPTIONS] <REFERENCE> <TEST>
+
+Prints the relative error and log-domain PSNR of TEST compared to REFERENCE,
+which can each be a Radiance HDR, RGBE8 PNG, or (with the ktx2 feature) RGB9E5 KTX2 image.
+
+The exit code is 1 if the error is above the --max-error or --min-psnr thresholds,
+so that conversion quality can be checked in CI.
+
+Options:
+      --resize            Resample TEST to the size of REFERENCE if they differ, instead of failing
+      --max-error <FRACTION>
+                          Fail if the relative error of any channel is above FRACTION (e.g. 0.01)
+      --min-psnr <DB>     Fail if the overall log PSNR is below DB
+      --json              Print the metrics as a JSON object
+  -h, --help              Print this help message";
+
+const SWEEP_USAGE: &str = "\
+Usage: rgbe sweep [OPTIONS] <INPUT>
+
+Encodes an HDR image in each storage format and compression level, and prints a table of the size of each
+and its error relative to the input, for choosing the formats which assets are stored in.
+
+The formats are rgbe-png-fast, rgbe-png-default, rgbe-png-best, rgbe-png-dithered, rgb9e5-raw, r11g11b10f-raw,
+rgba16f-raw, rgb9e5-ktx2 (with the ktx2 feature), bc6h-ktx2 (with the bc6h and ktx2 features), and exr (with the exr feature).
+Raw formats use the minimal container of `rgbe::save_raw`.
+
+Options:
+      --formats <LIST>    Comma-separated formats to compare [default: all formats which are built in]
+      --seed <N>          Seed of the dither pattern for rgbe-png-dithered [default: 0]
+      --json              Print the results as one JSON object per format
+  -h, --help              Print this help message";
+
+const COOKIE_USAGE: &str = "\
+Usage: rgbe cookie [OPTIONS] <OUTPUT>
+
+Generates a light cookie for a spot light, with a smooth falloff between two cone angles, a table of intensities by angle,
+or the photometric data of a light fixture from an IES file, as an RGBE8 PNG or (with the ktx2 feature, for outputs ending in .ktx2) an RGB9E5 KTX2 file.
+
+Options:
+  -s, --size <PIXELS>     Width and height of the cookie [default: 256]
+      --inner <DEGREES>   Angle from the axis within which the spot has full intensity [default: 30]
+      --outer <DEGREES>   Angle from the axis at which the intensity falls to zero [default: 45]
+      --profile <LIST>    Comma-separated ANGLE:INTENSITY pairs, with angles in degrees in increasing order,
+                          to use instead of --inner and --outer
+      --ies <PATH>        IES file to take the profile from, averaged around the axis and normalized to a peak of 1
+      --latlong           Bake the full IES profile into a latitude-longitude texture of SIZE by SIZE/2 texels instead,
+                          with rows running from straight down to straight up
+      --half-angle <DEGREES>
+                          Angle from the axis to the edges of the cookie [default: the outer or last profile angle]
+      --intensity <SCALE> Multiply the intensity of the cookie by SCALE [default: 1]
+  -f, --force             Overwrite the output file if it already exists
+  -h, --help              Print this help message";
+
+const ATLAS_USAGE: &str = "\
+Usage: rgbe atlas [OPTIONS] <INPUTS>...
+
+Packs Radiance HDR or RGBE8 PNG images (such as small emissive textures) into a single texture atlas,
+as an RGBE8 PNG or (with the ktx2 feature, for outputs ending in .ktx2) an RGB9E5 KTX2 file,
+and writes the placement of each image in texels as JSON.
+
+Options:
+  -o, --output <PATH>     Output file [default: atlas.rgbe.png]
+      --index <PATH>      JSON file listing the placement of each image [default: <OUTPUT> with the extension .json]
+      --padding <TEXELS>  Texels copied from the edges of each image around it, to stop filtering from bleeding [default: 1]
+      --max-size <TEXELS> Largest allowed width and height of the atlas [default: 4096]
+      --power-of-two      Round the width and height of the atlas up to powers of two
+  -f, --force             Overwrite the output files if they already exist
+  -h, --help              Print this help message";
+
+const COMPLETIONS_USAGE: &str = "\
+Usage: rgbe completions <SHELL>
+
+Prints a completion script for bash, zsh, or fish. For example:
+  rgbe completions bash > ~/.local/share/bash-completion/completions/rgbe
+  rgbe completions zsh > ~/.zfunc/_rgbe
+  rgbe completions fish > ~/.config/fish/completions/rgbe.fish
+
+Options:
+  -h, --help              Print this help message";
+
+struct ContactSheetArgs {
+    inputs: Vec<PathBuf>,
+    output: Option<PathBuf>,
+    jobs: Option<usize>,
+    nice: Option<u32>,
+    max_memory: Option<u64>,
+    force: bool,
+    post_process: Option<String>,
+    log: Logger,
+    options: ContactSheetOptions,
+}
+
+struct TonemapArgs {
+    input: PathBuf,
+    output: Option<PathBuf>,
+    force: bool,
+    exposure: f32,
+    operator: ToneMapOperator,
+    target: DisplayTarget,
+    false_color: bool,
+    zebra: Option<Zebra>,
+    post_process: Option<String>,
+}
+
+struct EvStripArgs {
+    input: PathBuf,
+    output: Option<PathBuf>,
+    force: bool,
+    frame_size: u32,
+    stops: Vec<f32>,
+    post_process: Option<String>,
+}
+
+/// How `rgbe preview` shows two images.
+#[derive(Clone, Copy)]
+enum CompareMode {
+    /// Split at a fraction of the width.
+    Split(f32),
+    Flicker,
+    /// Heat map of the relative error.
+    Difference,
+}
+
+struct PreviewArgs {
+    input: PathBuf,
+    compare: Option<PathBuf>,
+    mode: CompareMode,
+    diff_gain: f32,
+    gpu_check: bool,
+    exposure: f32,
+    operator: ToneMapOperator,
+    false_color: bool,
+    width: Option<u32>,
+    inspect: Vec<(u32, u32)>,
+}
+
+struct CompareArgs {
+    reference: PathBuf,
+    test: PathBuf,
+    resize: bool,
+    max_error: Option<f32>,
+    min_psnr: Option<f32>,
+    json: bool,
+}
+
+struct SweepArgs {
+    input: PathBuf,
+    formats: Vec<&'static str>,
+    seed: u32,
+    json: bool,
+}
+
+struct CookieArgs {
+    output: PathBuf,
+    force: bool,
+    size: u32,
+    profile: rgbe::CookieProfile,
+    ies: Option<PathBuf>,
+    latlong: bool,
+    half_angle: Option<f32>,
+    intensity: f32,
+}
+
+struct AtlasArgs {
+    inputs: Vec<PathBuf>,
+    output: PathBuf,
+    index: Option<PathBuf>,
+    force: bool,
+    options: rgbe::AtlasOptions,
+}
+
+enum Command {
+    ContactSheet(ContactSheetArgs),
+    Tonemap(TonemapArgs),
+    EvStrip(EvStripArgs),
+    Preview(PreviewArgs),
+    Compare(CompareArgs),
+    Sweep(SweepArgs),
+    Cookie(CookieArgs),
+    Atlas(AtlasArgs),
+    Completions(String),
+    Help(&'static str),
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid value '{value}' for {flag}"))
+}
+
+fn parse_operator(name: &str) -> Result<ToneMapOperator, String> {
+    match name {
+        "reinhard" => Ok(ToneMapOperator::Reinhard),
+        "aces" => Ok(ToneMapOperator::Aces),
+        "agx" => Ok(ToneMapOperator::AgX),
+        _ => Err(format!("invalid tone mapping operator '{name}' (expected reinhard, aces, or agx)")),
+    }
+}
+
+fn parse_target(name: &str, peak_nits: f32) -> Result<DisplayTarget, String> {
+    match name {
+        "srgb" => Ok(DisplayTarget::Srgb),
+        "p3" => Ok(DisplayTarget::DisplayP3),
+        "pq" => Ok(DisplayTarget::Rec2100Pq{peak_nits}),
+        _ => Err(format!("invalid display target '{name}' (expected srgb, p3, or pq)")),
+    }
+}
+
+fn parse_contactsheet_args(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut inputs = Vec::new();
+    let mut output = None;
+    let mut jobs = None;
+    let mut nice = None;
+    let mut post_process = None;
+    let mut max_memory = None;
+    let mut force = false;
+    let mut log = Logger::default();
+    let mut options = ContactSheetOptions::default();
+
+    let mut args = ArgParser::new(args);
+    while let Some(arg) = args.next_arg()? {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(CONTACTSHEET_USAGE)),
+            "-o" | "--output" => output = Some(PathBuf::from(args.value("--output")?)),
+            "-j" | "--jobs" | "--threads" => jobs = Some(parse_number(&arg, &args.value(&arg)?)?),
+            "--nice" => nice = Some(common::parse_nice(&args.value("--nice")?)?),
+            "--max-memory" => max_memory = Some(common::parse_memory_size(&args.value("--max-memory")?)?),
+            "-f" | "--force" => force = true,
+            "-s" | "--size" => options.thumbnail_size = parse_number("--size", &args.value("--size")?)?,
+            "--columns" => options.columns = parse_number("--columns", &args.value("--columns")?)?,
+            "-e" | "--exposure" => options.exposure = parse_number("--exposure", &args.value("--exposure")?)?,
+            "--no-auto-exposure" => options.auto_exposure = false,
+            "--zebra" => options.zebra = Some(Zebra::default()),
+            "--zebra-threshold" => options.zebra = Some(Zebra::new(parse_number("--zebra-threshold", &args.value("--zebra-threshold")?)?)),
+            "--post-process" => post_process = Some(args.value("--post-process")?),
+            "--log-level" => log.level = LogLevel::parse(&args.value("--log-level")?)?,
+            "--log-json" => log.json = true,
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{arg}'")),
+            _ => inputs.push(PathBuf::from(arg)),
+        }
+    }
+
+    if inputs.is_empty() {
+        return Err("at least one input file is required".into());
+    }
+    if options.thumbnail_size == 0 || options.columns == 0 {
+        return Err("--size and --columns must be greater than zero".into());
+    }
+    Ok(Command::ContactSheet(ContactSheetArgs{inputs, output, jobs, nice, max_memory, force, post_process, log, options}))
+}
+
+fn parse_tonemap_args(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut input = None;
+    let mut output = None;
+    let mut post_process = None;
+    let mut force = false;
+    let mut exposure = 0.0;
+    let mut operator = ToneMapOperator::default();
+    let mut target = String::from("srgb");
+    let mut peak_nits = 1000.0;
+    let mut false_color = false;
+    let mut zebra = None;
+
+    let mut args = ArgParser::new(args);
+    while let Some(arg) = args.next_arg()? {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(TONEMAP_USAGE)),
+            "-o" | "--output" => output = Some(PathBuf::from(args.value("--output")?)),
+            "-f" | "--force" => force = true,
+            "-e" | "--exposure" => exposure = parse_number("--exposure", &args.value("--exposure")?)?,
+            "--operator" => operator = parse_operator(&args.value("--operator")?)?,
+            "--target" => target = args.value("--target")?,
+            "--peak-nits" => peak_nits = parse_number("--peak-nits", &args.value("--peak-nits")?)?,
+            "--false-color" => false_color = true,
+            "--zebra" => zebra = Some(Zebra::default()),
+            "--zebra-threshold" => zebra = Some(Zebra::new(parse_number("--zebra-threshold", &args.value("--zebra-threshold")?)?)),
+            "--post-process" => post_process = Some(args.value("--post-process")?),
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{arg}'")),
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument '{arg}'")),
+        }
+    }
+
+    let input = input.ok_or("an input file is required")?;
+    if peak_nits <= 0.0 {
+        return Err("--peak-nits must be positive".into());
+    }
+    let target = parse_target(&target, peak_nits)?;
+    if target.is_hdr() && (false_color || zebra.is_some()) {
+        return Err("--false-color and --zebra cannot be used with HDR targets".into());
+    }
+    Ok(Command::Tonemap(TonemapArgs{input, output, force, exposure, operator, target, false_color, zebra, post_process}))
+}
+
+fn parse_evstrip_args(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut input = None;
+    let mut output = None;
+    let mut post_process = None;
+    let mut force = false;
+    let mut frame_size = 256;
+    let mut stops = rgbe::DEFAULT_EV_STOPS.to_vec();
+
+    let mut args = ArgParser::new(args);
+    while let Some(arg) = args.next_arg()? {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(EVSTRIP_USAGE)),
+            "-o" | "--output" => output = Some(PathBuf::from(args.value("--output")?)),
+            "-f" | "--force" => force = true,
+            "-s" | "--size" => frame_size = parse_number("--size", &args.value("--size")?)?,
+            "--stops" => {
+                stops = args.value("--stops")?.split(',')
+                    .map(|stop| parse_number("--stops", stop.trim()))
+                    .collect::<Result<_, _>>()?;
+            }
+            "--post-process" => post_process = Some(args.value("--post-process")?),
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{arg}'")),
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument '{arg}'")),
+        }
+    }
+
+    let input = input.ok_or("an input file is required")?;
+    if frame_size == 0 {
+        return Err("--size must be greater than zero".into());
+    }
+    Ok(Command::EvStrip(EvStripArgs{input, output, force, frame_size, stops, post_process}))
+}
+
+fn parse_preview_args(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut input = None;
+    let mut exposure = 0.0;
+    let mut operator = ToneMapOperator::default();
+    let mut false_color = false;
+    let mut width = None;
+    let mut inspect = Vec::new();
+    let mut compare = None;
+    let mut mode = None;
+    let mut diff_gain = 100.0;
+    let mut gpu_check = false;
+
+    let mut args = ArgParser::new(args);
+    while let Some(arg) = args.next_arg()? {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(PREVIEW_USAGE)),
+            "-e" | "--exposure" => exposure = parse_number("--exposure", &args.value("--exposure")?)?,
+            "--operator" => operator = parse_operator(&args.value("--operator")?)?,
+            "--false-color" => false_color = true,
+            "-w" | "--width" => width = Some(parse_number("--width", &args.value("--width")?)?),
+            "-i" | "--inspect" => {
+                let point = args.value("--inspect")?;
+                let (x, y) = point.split_once(',').ok_or_else(|| format!("invalid value '{point}' for --inspect (expected X,Y)"))?;
+                inspect.push((parse_number("--inspect", x.trim())?, parse_number("--inspect", y.trim())?));
+            }
+            "--split" => {
+                let fraction: f32 = parse_number("--split", &args.value("--split")?)?;
+                if !(0.0..=1.0).contains(&fraction) {
+                    return Err("--split must be between 0 and 1".into());
+                }
+                mode = Some(CompareMode::Split(fraction));
+            }
+            "--flicker" => mode = Some(CompareMode::Flicker),
+            "--diff" => mode = Some(CompareMode::Difference),
+            "--diff-gain" => diff_gain = parse_number("--diff-gain", &args.value("--diff-gain")?)?,
+            "--gpu-check" if cfg!(feature = "wgpu") => gpu_check = true,
+            "--gpu-check" => return Err("--gpu-check requires rgbe to be built with the wgpu feature".into()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{arg}'")),
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ if compare.is_none() => compare = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument '{arg}'")),
+        }
+    }
+
+    let input = input.ok_or("an input file is required")?;
+    if width == Some(0) {
+        return Err("--width must be greater than zero".into());
+    }
+    if gpu_check && compare.is_some() {
+        return Err("--gpu-check cannot be used with a second image".into());
+    }
+    if mode.is_some() && compare.is_none() && !gpu_check {
+        return Err("--split, --flicker, and --diff need a second image to compare with".into());
+    }
+    if diff_gain <= 0.0 {
+        return Err("--diff-gain must be positive".into());
+    }
+    let mode = mode.unwrap_or(if gpu_check { CompareMode::Difference } else { CompareMode::Split(0.5) });
+    Ok(Command::Preview(PreviewArgs{input, compare, mode, diff_gain, gpu_check, exposure, operator, false_color, width, inspect}))
+}
+
+fn parse_compare_args(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut reference = None;
+    let mut test = None;
+    let mut resize = false;
+    let mut max_error = None;
+    let mut min_psnr = None;
+    let mut json = false;
+
+    let mut args = ArgParser::new(args);
+    while let Some(arg) = args.next_arg()? {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(COMPARE_USAGE)),
+            "--resize" => resize = true,
+            "--max-error" => max_error = Some(parse_number("--max-error", &args.value("--max-error")?)?),
+            "--min-psnr" => min_psnr = Some(parse_number("--min-psnr", &args.value("--min-psnr")?)?),
+            "--json" => json = true,
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{arg}'")),
+            _ if reference.is_none() => reference = Some(PathBuf::from(arg)),
+            _ if test.is_none() => test = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument '{arg}'")),
+        }
+    }
+
+    let (Some(reference), Some(test)) = (reference, test) else {
+        return Err("a reference and a test image are required".into());
+    };
+    if max_error.is_some_and(|e: f32| e < 0.0 || e.is_nan()) {
+        return Err("--max-error must not be negative".into());
+    }
+    Ok(Command::Compare(CompareArgs{reference, test, resize, max_error, min_psnr, json}))
+}
+
+/// Formats compared by `rgbe sweep`, and whether each is built in.
+const SWEEP_FORMATS: &[(&str, bool)] = &[
+    ("rgbe-png-fast", true),
+    ("rgbe-png-default", true),
+    ("rgbe-png-best", true),
+    ("rgbe-png-dithered", true),
+    ("rgb9e5-raw", true),
+    ("r11g11b10f-raw", true),
+    ("rgba16f-raw", true),
+    ("rgb9e5-ktx2", cfg!(feature = "ktx2")),
+    ("bc6h-ktx2", cfg!(all(feature = "bc6h", feature = "ktx2"))),
+    ("exr", cfg!(feature = "exr")),
+];
+
+fn parse_sweep_args(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut input = None;
+    let mut formats = None;
+    let mut seed = 0;
+    let mut json = false;
+
+    let mut args = ArgParser::new(args);
+    while let Some(arg) = args.next_arg()? {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(SWEEP_USAGE)),
+            "--formats" => {
+                let list = args.value("--formats")?;
+                let selected = list.split(',').map(|name| {
+                    let name = name.trim();
+                    match SWEEP_FORMATS.iter().find(|(format, _)| *format == name) {
+                        Some((format, true)) => Ok(*format),
+                        Some((_, false)) => Err(format!("{name} is not built into this version of rgbe")),
+                        None => Err(format!("unknown format '{name}' in --formats")),
+                    }
+                }).collect::<Result<Vec<_>, _>>()?;
+                formats = Some(selected);
+            }
+            "--seed" => {
+                let n = args.value("--seed")?;
+                seed = n.parse().map_err(|_| format!("invalid value '{n}' for --seed"))?;
+            }
+            "--json" => json = true,
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{arg}'")),
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument '{arg}'")),
+        }
+    }
+
+    let input = input.ok_or("an input file is required")?;
+    let formats = formats.unwrap_or_else(|| SWEEP_FORMATS.iter().filter(|(_, enabled)| *enabled).map(|(format, _)| *format).collect());
+    Ok(Command::Sweep(SweepArgs{input, formats, seed, json}))
+}
+
+fn parse_cookie_args(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut output = None;
+    let mut force = false;
+    let mut size = 256;
+    let mut inner = 30.0f32;
+    let mut outer = 45.0f32;
+    let mut samples = None;
+    let mut ies = None;
+    let mut latlong = false;
+    let mut half_angle = None;
+    let mut intensity = 1.0;
+
+    let mut args = ArgParser::new(args);
+    while let Some(arg) = args.next_arg()? {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(COOKIE_USAGE)),
+            "-s" | "--size" => size = parse_number("--size", &args.value("--size")?)?,
+            "--inner" => inner = parse_number("--inner", &args.value("--inner")?)?,
+            "--outer" => outer = parse_number("--outer", &args.value("--outer")?)?,
+            "--profile" => {
+                let list = args.value("--profile")?;
+                let pairs = list.split(',').map(|pair| {
+                    let (angle, value) = pair.split_once(':').ok_or_else(|| format!("invalid pair '{pair}' in --profile (expected ANGLE:INTENSITY)"))?;
+                    Ok((parse_number::<f32>("--profile", angle.trim())?.to_radians(), parse_number("--profile", value.trim())?))
+                }).collect::<Result<Vec<(f32, f32)>, String>>()?;
+                if pairs.windows(2).any(|pair| pair[1].0 < pair[0].0) {
+                    return Err("the angles in --profile must be in increasing order".into());
+                }
+                samples = Some(pairs);
+            }
+            "--ies" => ies = Some(PathBuf::from(args.value("--ies")?)),
+            "--latlong" => latlong = true,
+            "--half-angle" => half_angle = Some(parse_number::<f32>("--half-angle", &args.value("--half-angle")?)?.to_radians()),
+            "--intensity" => intensity = parse_number("--intensity", &args.value("--intensity")?)?,
+            "-f" | "--force" => force = true,
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{arg}'")),
+            _ if output.is_none() => output = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument '{arg}'")),
+        }
+    }
+
+    let output = output.ok_or("an output file is required")?;
+    if size == 0 {
+        return Err("--size must be greater than zero".into());
+    }
+    if inner > outer {
+        return Err("--inner must not be greater than --outer".into());
+    }
+    if ies.is_some() && samples.is_some() {
+        return Err("--ies and --profile cannot be used together".into());
+    }
+    if latlong && (ies.is_none() || size < 2) {
+        return Err("--latlong requires --ies and a --size of at least 2".into());
+    }
+    let profile = match samples {
+        Some(samples) => rgbe::CookieProfile::Angular(samples),
+        None => rgbe::CookieProfile::Spot{inner_angle: inner.to_radians(), outer_angle: outer.to_radians()},
+    };
+    Ok(Command::Cookie(CookieArgs{output, force, size, profile, ies, latlong, half_angle, intensity}))
+}
+
+fn parse_atlas_args(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut inputs = Vec::new();
+    let mut output = None;
+    let mut index = None;
+    let mut force = false;
+    let mut options = rgbe::AtlasOptions::default();
+
+    let mut args = ArgParser::new(args);
+    while let Some(arg) = args.next_arg()? {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(ATLAS_USAGE)),
+            "-o" | "--output" => output = Some(PathBuf::from(args.value("--output")?)),
+            "--index" => index = Some(PathBuf::from(args.value("--index")?)),
+            "--padding" => options.padding = parse_number("--padding", &args.value("--padding")?)?,
+            "--max-size" => options.max_size = parse_number("--max-size", &args.value("--max-size")?)?,
+            "--power-of-two" => options.power_of_two = true,
+            "-f" | "--force" => force = true,
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{arg}'")),
+            _ => inputs.push(PathBuf::from(arg)),
+        }
+    }
+
+    if inputs.is_empty() {
+        return Err("at least one input file is required".into());
+    }
+    if options.max_size == 0 {
+        return Err("--max-size must be greater than zero".into());
+    }
+    let output = output.unwrap_or_else(|| PathBuf::from("atlas.rgbe.png"));
+    Ok(Command::Atlas(AtlasArgs{inputs, output, index, force, options}))
+}
+
+fn parse_completions_args(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut shell = None;
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(COMPLETIONS_USAGE)),
+            "bash" | "zsh" | "fish" if shell.is_none() => shell = Some(arg),
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{arg}'")),
+            _ => return Err(format!("unsupported shell '{arg}' (expected bash, zsh, or fish)")),
+        }
+    }
+    shell.map(Command::Completions).ok_or_else(|| "a shell is required".into())
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    match args.next().as_deref() {
+        None | Some("-h") | Some("--help") => Ok(Command::Help(USAGE)),
+        Some("help") => match args.next().as_deref() {
+            Some("config") => Ok(Command::Help(CONFIG_HELP)),
+            _ => Ok(Command::Help(USAGE)),
+        },
+        Some("contactsheet") => parse_contactsheet_args(args),
+        Some("tonemap") => parse_tonemap_args(args),
+        Some("evstrip") => parse_evstrip_args(args),
+        Some("preview") => parse_preview_args(args),
+        Some("compare") => parse_compare_args(args),
+        Some("sweep") => parse_sweep_args(args),
+        Some("cookie") => parse_cookie_args(args),
+        Some("atlas") => parse_atlas_args(args),
+        Some("completions") => parse_completions_args(args),
+        Some(other) => Err(format!("unknown command '{other}'")),
+    }
+}
+
+/// Loads a Radiance HDR file or an RGBE8 PNG.
+fn load_hdr(path: &Path) -> Result<HdrImage<RGBE8>, String> {
+    let image = rgbe::load_hdr_file(path).map_err(|e| format!("failed to load {}: {e}", path.display()))?;
+    Ok(image.into_rgbe8())
+}
+
+/// Loads an image, logging how long it took.
+fn load_hdr_logged(path: &Path, log: &Logger) -> Result<HdrImage<RGBE8>, String> {
+    let mut stats = FileStats::new(path);
+    stats.input_bytes = fs::metadata(path).map_or(0, |metadata| metadata.len());
+    let image = stats.time("decode", || load_hdr(path)).inspect_err(|msg| log.error(msg))?;
+    stats.extent = Some(image.extent());
+    log.file(&stats);
+    Ok(image)
+}
+
+/// Loads many images using up to `jobs` threads, keeping the estimated memory use of those being decoded within `budget`.
+/// Returns the result of loading each image, in order.
+fn load_all(paths: &[PathBuf], jobs: usize, budget: &MemoryBudget, log: &Logger) -> Vec<Result<HdrImage<RGBE8>, String>> {
+    // the file, the decoded texels, and (for PNGs) a copy while they are converted
+    let estimate = |path: &PathBuf| {
+        let file_size = fs::metadata(path).map_or(0, |metadata| metadata.len());
+        let texels = rgbe::peek_dimensions(path).map_or(0, |(width, height, _)| width as u64 * height as u64);
+        file_size + 8 * texels
+    };
+    common::run_batch(paths, jobs, budget, estimate, |path| load_hdr_logged(path, log))
+}
+
+/// Makes a contact sheet of every input which can be loaded, skipping any which fail.
+fn run_contactsheet(args: ContactSheetArgs, config: &Config) -> Result<BatchFailures, String> {
+    let output = args.output.unwrap_or_else(|| config.default_output(PathBuf::from("contactsheet.png")));
+    if !args.force && output.exists() {
+        return Err(format!("{} already exists (use --force to overwrite)", output.display()));
+    }
+
+    if let Some(nice) = args.nice.or(config.nice) {
+        common::set_nice(nice)?;
+    }
+    let jobs = args.jobs.or(config.jobs)
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+    let mut failures = BatchFailures::default();
+    let mut images = Vec::with_capacity(args.inputs.len());
+    let mut labels = Vec::with_capacity(args.inputs.len());
+    let budget = MemoryBudget::new(args.max_memory.or(config.max_memory));
+    for (path, result) in args.inputs.iter().zip(load_all(&args.inputs, jobs, &budget, &args.log)) {
+        if let Some(image) = failures.record(path, result) {
+            images.push(image);
+            labels.push(path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().into_owned());
+        }
+    }
+    if images.is_empty() {
+        return Err("none of the input images could be loaded".into());
+    }
+    let entries: Vec<ContactSheetEntry<RGBE8>> = images.iter().zip(&labels)
+        .map(|(image, label)| ContactSheetEntry{label, image})
+        .collect();
+
+    let start = Instant::now();
+    let (extent, pixels) = rgbe::contact_sheet(&entries, &args.options);
+    args.log.debug(&format!("rendered contact sheet in {:.1} ms", start.elapsed().as_secs_f64() * 1000.0));
+    rgbe::save_rgb8_png_file(&output, extent, &pixels)
+        .map_err(|e| format!("failed to save {}: {e}", output.display()))?;
+    args.log.info(&format!("{} images -> {} ({}x{})", entries.len(), output.display(), extent.width(), extent.height()));
+    config.post_process(args.post_process.as_deref(), None, &output)?;
+    Ok(failures)
+}
+
+fn run_tonemap(args: TonemapArgs, config: &Config) -> Result<(), String> {
+    let output = args.output.unwrap_or_else(|| config.default_output(args.input.with_extension("preview.png")));
+    if !args.force && output.exists() {
+        return Err(format!("{} already exists (use --force to overwrite)", output.display()));
+    }
+
+    let image = load_hdr(&args.input)?;
+    let saved = if args.target.is_hdr() {
+        let pixels = rgbe::tonemap_rgb16(image.pixels(), args.exposure, args.operator, args.target);
+        rgbe::save_rgb16_png_file(&output, image.extent(), &pixels)
+    } else {
+        let mut pixels = if args.false_color {
+            rgbe::false_color(image.pixels(), args.exposure)
+        } else {
+            rgbe::tonemap_rgb8(image.pixels(), args.exposure, args.operator, args.target)
+        };
+        if let Some(zebra) = &args.zebra {
+            rgbe::zebra_overlay(image.extent(), image.pixels(), &mut pixels, zebra);
+        }
+        rgbe::save_rgb8_png_file(&output, image.extent(), &pixels)
+    };
+    saved.map_err(|e| format!("failed to save {}: {e}", output.display()))?;
+    println!("{} -> {}", args.input.display(), output.display());
+    config.post_process(args.post_process.as_deref(), Some(&args.input), &output)
+}
+
+fn run_evstrip(args: EvStripArgs, config: &Config) -> Result<(), String> {
+    let output = args.output.unwrap_or_else(|| config.default_output(args.input.with_extension("evstrip.png")));
+    if !args.force && output.exists() {
+        return Err(format!("{} already exists (use --force to overwrite)", output.display()));
+    }
+
+    let image = load_hdr(&args.input)?;
+    let (extent, pixels) = rgbe::ev_strip(&image, &args.stops, args.frame_size);
+    rgbe::save_rgb8_png_file(&output, extent, &pixels)
+        .map_err(|e| format!("failed to save {}: {e}", output.display()))?;
+    println!("{} -> {} ({}x{})", args.input.display(), output.display(), extent.width(), extent.height());
+    config.post_process(args.post_process.as_deref(), Some(&args.input), &output)
+}
+
+/// Renders sRGB pixels as lines of text using half-block characters with 24-bit colour,
+/// so that each character shows two vertically adjacent pixels.
+fn terminal_image(extent: Extent2D, pixels: &[[u8;3]]) -> String {
+    let (width, height) = (extent.width() as usize, extent.height() as usize);
+    let mut out = String::new();
+    for y in (0..height).step_by(2) {
+        for x in 0..width {
+            let [r, g, b] = pixels[y * width + x];
+            write!(out, "\x1b[38;2;{r};{g};{b}m").unwrap();
+            if y + 1 < height {
+                let [r, g, b] = pixels[(y + 1) * width + x];
+                write!(out, "\x1b[48;2;{r};{g};{b}m\u{2580}").unwrap();
+            } else {
+                // an odd last row leaves the lower half empty
+                out.push_str("\x1b[49m\u{2580}");
+            }
+        }
+        out.push_str("\x1b[0m\n");
+    }
+    out
+}
+
+/// Describes a texel as it is stored, along with its linear colour and exposure.
+fn inspect_texel(image: &AnyHdrImage, index: usize) -> String {
+    let (stored, rgb) = match image {
+        AnyHdrImage::Rgbe8(image) => {
+            let texel = image.pixels()[index];
+            (format!("RGBE8 ({}, {}, {}, {})", texel.r, texel.g, texel.b, texel.e), texel.to_rgb())
+        }
+        AnyHdrImage::Rgb9e5(image) => {
+            let texel = image.pixels()[index];
+            (format!("RGB9E5 0x{:08x}", texel.0), texel.to_rgb())
+        }
+        _ => {
+            let texel = image.to_rgba32f().pixels()[index];
+            (format!("{:?}", image.format()), texel.to_rgb())
+        }
+    };
+    let lum = rgbe::luminance(rgb);
+    let stops = if lum > 0.0 { format!("{:+.2} stops", (lum / rgbe::MIDDLE_GREY).log2()) } else { "black".into() };
+    format!("{stored}, linear [{:.6}, {:.6}, {:.6}], luminance {lum:.6} ({stops} from middle grey)", rgb[0], rgb[1], rgb[2])
+}
+
+/// Colour of a value from 0 to 1 in a heat map going from black through red and yellow to white.
+fn heat_color(v: f32) -> [u8;3] {
+    let v = 3.0 * v.clamp(0.0, 1.0);
+    [v, v - 1.0, v - 2.0].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+/// Summary of the error of a test image relative to a reference image.
+fn metrics_summary(metrics: &rgbe::ErrorMetrics) -> String {
+    let [r, g, b] = metrics.mean_relative_error.map(|e| e * 100.0);
+    format!(
+        "max error {:.4}%, mean error {r:.4}% / {g:.4}% / {b:.4}% (R/G/B), log PSNR {:.2} dB",
+        metrics.max_error() * 100.0, metrics.overall_log_psnr(),
+    )
+}
+
+/// Wakes a thread parked by [block_on].
+#[cfg(feature = "wgpu")]
+struct ThreadWaker(thread::Thread);
+
+#[cfg(feature = "wgpu")]
+impl std::task::Wake for ThreadWaker {
+    fn wake(self: std::sync::Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Runs a future to completion on this thread, parking it until the future is woken
+/// (the adapter and device requests are made before there is a device to poll).
+#[cfg(feature = "wgpu")]
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    let mut future = std::pin::pin!(future);
+    let waker = std::sync::Arc::new(ThreadWaker(thread::current())).into();
+    let mut context = std::task::Context::from_waker(&waker);
+    loop {
+        if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+        thread::park();
+    }
+}
+
+/// Decodes an RGB9E5 image on the default GPU, returning the name of the GPU and the decoded image.
+#[cfg(feature = "wgpu")]
+fn gpu_decode(image: &HdrImage<rgbe::RGB9E5>) -> Result<(String, HdrImage<rgbe::RGB32F>), String> {
+    let instance = wgpu::Instance::default();
+    let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default())).ok_or("no GPU adapter is available")?;
+    let name = adapter.get_info().name;
+    let (device, queue) = block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None))
+        .map_err(|e| format!("failed to open {name}: {e}"))?;
+    let texels = rgbe::decode_rgb9e5_on_gpu(&device, &queue, image.extent(), image.pixels());
+    Ok((name, HdrImage::new(image.extent(), texels.iter().map(|&[r, g, b]| rgbe::RGB32F{r, g, b}).collect())))
+}
+
+#[cfg(not(feature = "wgpu"))]
+fn gpu_decode(_image: &HdrImage<rgbe::RGB9E5>) -> Result<(String, HdrImage<rgbe::RGB32F>), String> {
+    Err("--gpu-check requires rgbe to be built with the wgpu feature".into())
+}
+
+fn run_preview(args: PreviewArgs) -> Result<(), String> {
+    let load = |path: &Path| rgbe::load_hdr_file(path).map_err(|e| format!("failed to load {}: {e}", path.display()));
+    let mut image = load(&args.input)?;
+    let mut other = args.compare.as_deref().map(load).transpose()?;
+    let input_label = args.input.display().to_string();
+    let mut compare_label = args.compare.as_ref().map(|path| path.display().to_string());
+    let extent = image.extent();
+    if let (Some(label), Some(other)) = (&compare_label, &other) {
+        if other.extent() != extent {
+            return Err(format!(
+                "{input_label} is {}x{} but {label} is {}x{}", extent.width(), extent.height(),
+                other.extent().width(), other.extent().height(),
+            ));
+        }
+    }
+    if args.gpu_check {
+        let packed = image.into_rgb9e5();
+        let (gpu_name, decoded) = gpu_decode(&packed)?;
+        let mismatches = packed.pixels().iter().zip(decoded.pixels()).filter(|(a, b)| a.to_rgb() != b.to_rgb()).count();
+        println!("{mismatches} of {} texels decoded differently by {gpu_name}", extent.texel_count());
+        image = packed.into();
+        other = Some(decoded.into());
+        compare_label = Some(format!("GPU decode ({gpu_name})"));
+    }
+    for &(x, y) in &args.inspect {
+        if x >= extent.width() || y >= extent.height() {
+            return Err(format!("--inspect {x},{y} is outside the {}x{} image", extent.width(), extent.height()));
+        }
+    }
+
+    let columns = args.width.or_else(|| env::var("COLUMNS").ok()?.parse().ok()).unwrap_or(80).max(1);
+    let width = columns.min(extent.width());
+    let height = (extent.height() as u64 * width as u64 / extent.width() as u64).max(1) as u32;
+    let view_extent = Extent2D::new(width, height).unwrap();
+    let render = |image: &AnyHdrImage| {
+        let linear: Box<[[f32;3]]> = image.to_rgba32f().pixels().iter().map(|texel| texel.to_rgb()).collect();
+        let small = rgbe::resize(extent, &linear, view_extent, &rgbe::BoxKernel);
+        if args.false_color {
+            rgbe::false_color(&small, args.exposure)
+        } else {
+            rgbe::tonemap_srgb8_with_operator(&small, args.exposure, args.operator)
+        }
+    };
+
+    let describe = |label: &str, image: &AnyHdrImage| format!("{label}: {}x{} {:?}", extent.width(), extent.height(), image.format());
+    let (Some(other), Some(compare_label)) = (other, compare_label) else {
+        print!("{}", terminal_image(view_extent, &render(&image)));
+        println!("{}", describe(&input_label, &image));
+        for &(x, y) in &args.inspect {
+            let index = y as usize * extent.width() as usize + x as usize;
+            println!("({x}, {y}): {}", inspect_texel(&image, index));
+        }
+        return Ok(());
+    };
+
+    let reference = image.to_rgba32f();
+    let test = other.to_rgba32f();
+    match args.mode {
+        CompareMode::Split(fraction) => {
+            let mut pixels = render(&image);
+            let right = render(&other);
+            let split = (fraction * width as f32).round() as usize;
+            for (i, (px, right)) in pixels.iter_mut().zip(right.iter()).enumerate() {
+                let x = i % width as usize;
+                if x == split {
+                    *px = [255, 255, 255];
+                } else if x > split {
+                    *px = *right;
+                }
+            }
+            print!("{}", terminal_image(view_extent, &pixels));
+        }
+        CompareMode::Flicker => {
+            let frames = [
+                (terminal_image(view_extent, &render(&image)), describe(&input_label, &image)),
+                (terminal_image(view_extent, &render(&other)), describe(&compare_label, &other)),
+            ];
+            // move back up over the image and its caption before drawing the next frame
+            let rows = height.div_ceil(2) + 1;
+            for (i, (frame, caption)) in frames.iter().cycle().enumerate() {
+                if i > 0 {
+                    print!("\x1b[{rows}A");
+                }
+                println!("{frame}\x1b[2K{caption}");
+                std::io::Write::flush(&mut std::io::stdout()).map_err(|e| e.to_string())?;
+                thread::sleep(std::time::Duration::from_millis(500));
+            }
+        }
+        CompareMode::Difference => {
+            let errors: Box<[[f32;3]]> = rgbe::relative_error_map(reference.pixels(), test.pixels()).iter().map(|&e| [e; 3]).collect();
+            let small = rgbe::resize(extent, &errors, view_extent, &rgbe::BoxKernel);
+            let pixels: Box<[[u8;3]]> = small.iter().map(|e| heat_color(e[0] * args.diff_gain)).collect();
+            print!("{}", terminal_image(view_extent, &pixels));
+        }
+    }
+    println!("{}", describe(&input_label, &image));
+    println!("{}", describe(&compare_label, &other));
+    println!("{}", metrics_summary(&rgbe::error_metrics(reference.pixels(), test.pixels())));
+    for &(x, y) in &args.inspect {
+        let index = y as usize * extent.width() as usize + x as usize;
+        println!("({x}, {y}): {}", inspect_texel(&image, index));
+        println!("{:>w$}  {}", "", inspect_texel(&other, index), w = format!("({x}, {y}):").len());
+    }
+    Ok(())
+}
+
+fn run_compare(args: CompareArgs) -> Result<(), String> {
+    let load = |path: &Path| rgbe::load_hdr_file(path).map_err(|e| format!("failed to load {}: {e}", path.display()));
+    let reference = load(&args.reference)?;
+    let test = load(&args.test)?;
+    let extent = reference.extent();
+    let reference: Box<[[f32;3]]> = reference.to_rgba32f().pixels().iter().map(|texel| texel.to_rgb()).collect();
+    let mut test_pixels: Box<[[f32;3]]> = test.to_rgba32f().pixels().iter().map(|texel| texel.to_rgb()).collect();
+    if test.extent() != extent {
+        let size = |e: Extent2D| format!("{}x{}", e.width(), e.height());
+        if !args.resize {
+            return Err(format!(
+                "{} is {} but {} is {} (use --resize to resample it)",
+                args.reference.display(), size(extent), args.test.display(), size(test.extent()),
+            ));
+        }
+        eprintln!("resampling {} from {} to {}", args.test.display(), size(test.extent()), size(extent));
+        test_pixels = rgbe::resize(test.extent(), &test_pixels, extent, &rgbe::BoxKernel);
+    }
+
+    let metrics = rgbe::error_metrics(&reference, &test_pixels);
+    if args.json {
+        // JSON has no infinity, which is the PSNR of identical images
+        let number = |v: f32| if v.is_finite() { format!("{v}") } else { "null".into() };
+        let list = |v: [f32;3]| format!("[{}, {}, {}]", number(v[0]), number(v[1]), number(v[2]));
+        println!(
+            "{{\"reference\": {}, \"test\": {}, \"max_relative_error\": {}, \"mean_relative_error\": {}, \"log_psnr\": {}, \"overall_log_psnr\": {}}}",
+            common::json_string(&args.reference.to_string_lossy()), common::json_string(&args.test.to_string_lossy()),
+            list(metrics.max_relative_error), list(metrics.mean_relative_error), list(metrics.log_psnr), number(metrics.overall_log_psnr()),
+        );
+    } else {
+        println!("{}", metrics_summary(&metrics));
+    }
+
+    if let Some(max_error) = args.max_error.filter(|&max_error| metrics.max_error() > max_error) {
+        return Err(format!("max error {:.4}% is above --max-error {:.4}%", metrics.max_error() * 100.0, max_error * 100.0));
+    }
+    if let Some(min_psnr) = args.min_psnr.filter(|&min_psnr| metrics.overall_log_psnr() < min_psnr) {
+        return Err(format!("log PSNR {:.2} dB is below --min-psnr {min_psnr} dB", metrics.overall_log_psnr()));
+    }
+    Ok(())
+}
+
+/// Encodes an image in one of the [SWEEP_FORMATS], returning the encoded file and the texels it stores.
+fn sweep_encode(format: &str, image: &AnyHdrImage, seed: u32) -> image::ImageResult<(Vec<u8>, AnyHdrImage)> {
+    use image::codecs::png::CompressionType;
+    use rgbe::TexelFormat;
+
+    let mut out = Vec::new();
+    let extent = image.extent();
+    let stored = match format {
+        "rgbe-png-fast" | "rgbe-png-default" | "rgbe-png-best" | "rgbe-png-dithered" => {
+            let (compression, packed) = match format {
+                "rgbe-png-fast" => (CompressionType::Fast, image.clone().into_rgbe8()),
+                "rgbe-png-default" => (CompressionType::Default, image.clone().into_rgbe8()),
+                "rgbe-png-best" => (CompressionType::Best, image.clone().into_rgbe8()),
+                _ => (CompressionType::Best, image.clone().into_rgbe8_dithered_with_seed(seed)),
+            };
+            rgbe::encode_rgbe8_png_with_compression(extent, packed.pixels(), &mut out, compression)?;
+            AnyHdrImage::Rgbe8(packed)
+        }
+        "rgb9e5-raw" | "r11g11b10f-raw" | "rgba16f-raw" => {
+            let stored = image.convert(match format {
+                "rgb9e5-raw" => TexelFormat::Rgb9e5,
+                "r11g11b10f-raw" => TexelFormat::R11g11b10f,
+                _ => TexelFormat::Rgba16f,
+            });
+            rgbe::encode_raw(std::slice::from_ref(&stored), &mut out)?;
+            stored
+        }
+        #[cfg(feature = "ktx2")]
+        "rgb9e5-ktx2" => {
+            let packed = image.clone().into_rgb9e5();
+            rgbe::encode_rgb9e5_ktx2(extent, packed.pixels(), &mut out)?;
+            AnyHdrImage::Rgb9e5(packed)
+        }
+        #[cfg(all(feature = "bc6h", feature = "ktx2"))]
+        "bc6h-ktx2" => {
+            let blocks = rgbe::encode_bc6h(extent, image.clone().into_rgba16f().pixels());
+            rgbe::encode_bc6h_ktx2(extent, &blocks, &mut out)?;
+            AnyHdrImage::Rgba16f(HdrImage::new(extent, rgbe::decode_bc6h(extent, &blocks, false)))
+        }
+        #[cfg(feature = "exr")]
+        "exr" => {
+            let packed = image.clone().into_rgba16f();
+            rgbe::encode_exr(extent, packed.pixels(), &mut out)?;
+            AnyHdrImage::Rgba16f(packed)
+        }
+        _ => unreachable!("formats which are not built in are rejected when parsed"),
+    };
+    Ok((out, stored))
+}
+
+fn run_sweep(args: SweepArgs) -> Result<(), String> {
+    let image = rgbe::load_hdr_file(&args.input).map_err(|e| format!("failed to load {}: {e}", args.input.display()))?;
+    let reference = image.to_rgba32f();
+    let texels = image.extent().texel_count() as f64;
+    if !args.json {
+        println!("{:<18} {:>12} {:>10} {:>10} {:>10} {:>10} {:>10}", "FORMAT", "BYTES", "BITS/TEXEL", "MAX ERROR", "MEAN ERROR", "LOG PSNR", "ENCODE");
+    }
+    for format in &args.formats {
+        let start = Instant::now();
+        let (bytes, stored) = sweep_encode(format, &image, args.seed).map_err(|e| format!("failed to encode {format}: {e}"))?;
+        let encode_ms = start.elapsed().as_secs_f64() * 1000.0;
+        let metrics = rgbe::error_metrics(reference.pixels(), stored.to_rgba32f().pixels());
+        let bits_per_texel = bytes.len() as f64 * 8.0 / texels;
+        let mean_error = metrics.mean_relative_error.iter().sum::<f32>() / 3.0;
+        let psnr = metrics.overall_log_psnr();
+        if args.json {
+            // JSON has no infinity, which is the PSNR of lossless formats
+            let psnr = if psnr.is_finite() { psnr.to_string() } else { "null".into() };
+            println!(
+                "{{\"format\": \"{format}\", \"bytes\": {}, \"bits_per_texel\": {bits_per_texel:.4}, \"max_relative_error\": {}, \"mean_relative_error\": {mean_error}, \"log_psnr\": {psnr}, \"encode_ms\": {encode_ms:.3}}}",
+                bytes.len(), metrics.max_error(),
+            );
+        } else {
+            println!(
+                "{format:<18} {:>12} {bits_per_texel:>10.3} {:>9.4}% {:>9.4}% {:>7.2} dB {encode_ms:>7.1} ms",
+                bytes.len(), metrics.max_error() * 100.0, mean_error * 100.0, psnr,
+            );
+        }
+    }
+    Ok(())
+}
+
+fn run_cookie(args: CookieArgs) -> Result<(), String> {
+    if !args.force && args.output.exists() {
+        return Err(format!("{} already exists (use --force to overwrite)", args.output.display()));
+    }
+    let ies = match &args.ies {
+        Some(path) => Some(rgbe::load_ies_file(path).map_err(|e| format!("failed to load {}: {e}", path.display()))?),
+        None => None,
+    };
+    let (mut cookie, peak) = match &ies {
+        Some(ies) if args.latlong => (rgbe::ies_to_equirect(ies, args.size), ies.max_candela()),
+        _ => {
+            let profile = ies.as_ref().map_or_else(|| args.profile.clone(), rgbe::IesProfile::to_cookie_profile);
+            let half_angle = args.half_angle.unwrap_or_else(|| profile.max_angle());
+            if !(half_angle > 0.0 && half_angle < std::f32::consts::FRAC_PI_2) {
+                return Err("the cookie must cover an angle between 0 and 90 degrees from the axis (set it with --half-angle)".into());
+            }
+            let peak = match &profile {
+                rgbe::CookieProfile::Angular(samples) if ies.is_some() => samples.iter().fold(0.0, |max, &(_, i)| f32::max(max, i)),
+                _ => 1.0,
+            };
+            (rgbe::light_cookie(args.size, &profile, half_angle), peak)
+        }
+    };
+    if peak <= 0.0 {
+        return Err("the IES profile has no light".into());
+    }
+    rgbe::mul_color(cookie.pixels_mut(), [args.intensity / peak; 3]);
+    let extent = cookie.extent();
+    let is_ktx2 = args.output.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("ktx2"));
+    let result = if is_ktx2 {
+        save_ktx2(&args.output, &cookie.convert())
+    } else {
+        rgbe::save_rgbe8_png_file(&args.output, extent, cookie.convert::<RGBE8>().pixels()).map_err(|e| e.to_string())
+    };
+    result.map_err(|e| format!("failed to save {}: {e}", args.output.display()))?;
+    println!("{} ({}x{})", args.output.display(), extent.width(), extent.height());
+    Ok(())
+}
+
+#[cfg(feature = "ktx2")]
+fn save_ktx2(path: &Path, image: &HdrImage<rgbe::RGB9E5>) -> Result<(), String> {
+    rgbe::save_rgb9e5_ktx2(path, image.extent(), image.pixels()).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "ktx2"))]
+fn save_ktx2(_path: &Path, _image: &HdrImage<rgbe::RGB9E5>) -> Result<(), String> {
+    Err("KTX2 output requires rgbe to be built with the ktx2 feature".into())
+}
+
+fn run_atlas(args: AtlasArgs) -> Result<(), String> {
+    let index = args.index.unwrap_or_else(|| args.output.with_extension("json"));
+    for path in [&args.output, &index] {
+        if !args.force && path.exists() {
+            return Err(format!("{} already exists (use --force to overwrite)", path.display()));
+        }
+    }
+    let images = args.inputs.iter().map(|path| load_hdr(path)).collect::<Result<Vec<_>, _>>()?;
+    let refs: Vec<&HdrImage<RGBE8>> = images.iter().collect();
+    let atlas = rgbe::pack_texture_atlas(&refs, &args.options)
+        .ok_or_else(|| format!("the images do not fit in an atlas of {0}x{0} texels (raise it with --max-size)", args.options.max_size))?;
+    let extent = atlas.image.extent();
+
+    let is_ktx2 = args.output.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("ktx2"));
+    let result = if is_ktx2 {
+        save_ktx2(&args.output, &atlas.image.convert())
+    } else {
+        rgbe::save_rgbe8_png_file(&args.output, extent, atlas.image.pixels()).map_err(|e| e.to_string())
+    };
+    result.map_err(|e| format!("failed to save {}: {e}", args.output.display()))?;
+
+    let entries: Vec<String> = args.inputs.iter().zip(&atlas.placements).map(|(path, p)| format!(
+        "{{\"file\": {}, \"x\": {}, \"y\": {}, \"width\": {}, \"height\": {}}}",
+        common::json_string(&path.display().to_string()), p.x, p.y, p.width, p.height
+    )).collect();
+    let json = format!("{{\"width\": {}, \"height\": {}, \"images\": [\n  {}\n]}}\n", extent.width(), extent.height(), entries.join(",\n  "));
+    fs::write(&index, json).map_err(|e| format!("failed to save {}: {e}", index.display()))?;
+    println!("{} images -> {} ({}x{}), {}", images.len(), args.output.display(), extent.width(), extent.height(), index.display());
+    Ok(())
+}
+
+/// Usage message of each command, for generating completions.
+const COMMANDS: &[(&str, &str)] = &[
+    ("contactsheet", CONTACTSHEET_USAGE),
+    ("tonemap", TONEMAP_USAGE),
+    ("evstrip", EVSTRIP_USAGE),
+    ("preview", PREVIEW_USAGE),
+    ("compare", COMPARE_USAGE),
+    ("sweep", SWEEP_USAGE),
+    ("cookie", COOKIE_USAGE),
+    ("atlas", ATLAS_USAGE),
+    ("completions", COMPLETIONS_USAGE),
+];
+
+/// Long flags listed in the options section of a usage message.
+fn long_flags(usage: &str) -> Vec<&str> {
+    usage.lines()
+        .skip_while(|line| *line != "Options:")
+        .filter_map(|line| line.split_whitespace().find(|word| word.starts_with("--")))
+        .map(|flag| flag.trim_end_matches(','))
+        .collect()
+}
+
+/// Description of a command from the top-level usage message.
+fn command_description(name: &str) -> &'static str {
+    USAGE.lines()
+        .find_map(|line| line.trim_start().strip_prefix(name).filter(|rest| rest.starts_with("  ")))
+        .map_or("", str::trim)
+}
+
+/// Generates a completion script for `shell`, which has already been validated.
+fn completions(shell: &str) -> String {
+    let names: Vec<&str> = COMMANDS.iter().map(|(name, _)| *name).collect();
+    let mut script = String::new();
+    match shell {
+        "bash" => {
+            script.push_str("_rgbe() {\n    local cur=\"${COMP_WORDS[COMP_CWORD]}\" opts=\"\"\n");
+            script.push_str(&format!("    if [ \"$COMP_CWORD\" -eq 1 ]; then\n        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n        return\n    fi\n", names.join(" ")));
+            script.push_str("    case \"${COMP_WORDS[1]}\" in\n");
+            for (name, usage) in COMMANDS {
+                script.push_str(&format!("        {name}) opts=\"{}\" ;;\n", long_flags(usage).join(" ")));
+            }
+            script.push_str("    esac\n    if [[ \"$cur\" == -* ]]; then\n        COMPREPLY=($(compgen -W \"$opts\" -- \"$cur\"))\n");
+            script.push_str("    else\n        COMPREPLY=($(compgen -f -- \"$cur\"))\n    fi\n}\ncomplete -o filenames -F _rgbe rgbe\n");
+        }
+        "zsh" => {
+            script.push_str("#compdef rgbe\n\n_rgbe() {\n    local -a opts\n    if (( CURRENT == 2 )); then\n        local -a commands=(\n");
+            for name in &names {
+                script.push_str(&format!("            '{name}:{}'\n", command_description(name)));
+            }
+            script.push_str("        )\n        _describe command commands\n        return\n    fi\n    case $words[2] in\n");
+            for (name, usage) in COMMANDS {
+                script.push_str(&format!("        {name}) opts=({}) ;;\n", long_flags(usage).join(" ")));
+            }
+            script.push_str("    esac\n    if [[ $PREFIX == -* ]]; then\n        compadd -a opts\n    else\n        _files\n    fi\n}\n\n_rgbe \"$@\"\n");
+        }
+        _ => {
+            for name in &names {
+                script.push_str(&format!("complete -c rgbe -f -n __fish_use_subcommand -a {name} -d '{}'\n", command_description(name)));
+            }
+            for (name, usage) in COMMANDS {
+                for flag in long_flags(usage) {
+                    script.push_str(&format!("complete -c rgbe -n '__fish_seen_subcommand_from {name}' -l {}\n", &flag[2..]));
+                }
+            }
+        }
+    }
+    script
+}
+
+pub fn main() -> ExitCode {
+    let command = match parse_args(env::args().skip(1)) {
+        Ok(Command::Help(usage)) => {
+            println!("{usage}");
+            return ExitCode::SUCCESS;
+        }
+        Ok(Command::Completions(shell)) => {
+            print!("{}", completions(&shell));
+            return ExitCode::SUCCESS;
+        }
+        Ok(command) => command,
+        Err(msg) => {
+            eprintln!("error: {msg}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    let result = Config::load().and_then(|config| match command {
+        Command::ContactSheet(args) => {
+            let log = args.log;
+            run_contactsheet(args, &config).map(|failures| failures.finish(&log))
+        }
+        Command::Tonemap(args) => run_tonemap(args, &config).map(|()| ExitCode::SUCCESS),
+        Command::EvStrip(args) => run_evstrip(args, &config).map(|()| ExitCode::SUCCESS),
+        Command::Preview(args) => run_preview(args).map(|()| ExitCode::SUCCESS),
+        Command::Compare(args) => run_compare(args).map(|()| ExitCode::SUCCESS),
+        Command::Sweep(args) => run_sweep(args).map(|()| ExitCode::SUCCESS),
+        Command::Cookie(args) => run_cookie(args).map(|()| ExitCode::SUCCESS),
+        Command::Atlas(args) => run_atlas(args).map(|()| ExitCode::SUCCESS),
+        Command::Help(_) | Command::Completions(_) => unreachable!(),
+    });
+    match result {
+        Ok(code) => code,
+        Err(msg) => {
+            eprintln!("error: {msg}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use image::{ImageError, ImageResult};
+use std::{collections::HashMap, fmt::Write as _, fs::{self, File, OpenOptions}, io::{self, BufRead, BufReader, Write}, path::Path, sync::Mutex};
+
+/// 64-bit FNV-1a hash of a file's contents, for detecting changed files.
+fn hash_file(path: &Path) -> io::Result<u64> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hash = 0xcbf29ce484222325u64;
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(hash);
+        }
+        for &byte in buf {
+            hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+        }
+        let len = buf.len();
+        reader.consume(len);
+    }
+}
+
+/// Writes a path as text which contains no tabs or line breaks and can be compared exactly.
+///
+/// Control characters, `%`, and bytes which are not valid UTF-8 (from non-UTF-8 paths) are escaped as `%XX`,
+/// so the escaped forms of two paths are equal only if the paths are.
+fn escape_path(path: &Path) -> String {
+    let mut escaped = String::new();
+    for chunk in path.as_os_str().as_encoded_bytes().utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if c == '%' || c.is_control() {
+                let mut buf = [0; 4];
+                for byte in c.encode_utf8(&mut buf).bytes() {
+                    write!(escaped, "%{byte:02X}").unwrap();
+                }
+            } else {
+                escaped.push(c);
+            }
+        }
+        for byte in chunk.invalid() {
+            write!(escaped, "%{byte:02X}").unwrap();
+        }
+    }
+    escaped
+}
+
+/// Hashes of a completed file recorded in a [BatchCheckpoint].
+#[derive(Clone, Copy, Debug)]
+struct CheckpointEntry {
+    input_hash: u64,
+    output_hash: u64,
+}
+
+/// Progress of a batch conversion, saved after every file so that an interrupted run (such as an overnight bake of an HDRI library)
+/// can be resumed without redoing finished work.
+///
+/// The file has one line per completed file, containing the hashes of the input and output (in hex) and their paths, separated by tabs.
+/// Paths are escaped so that any path (including those with tabs or which are not valid UTF-8) is recorded exactly.
+/// A file is only skipped if neither its input nor its output has changed since it was recorded.
+///
+/// Files can be checked and recorded from several threads at once. [run_checkpointed_batch] drives a whole batch.
+#[derive(Debug)]
+pub struct BatchCheckpoint {
+    file: Mutex<File>,
+    /// Entries by escaped input and output path, as one input can have several outputs.
+    done: HashMap<(String, String), CheckpointEntry>,
+}
+
+impl BatchCheckpoint {
+    /// Opens a checkpoint file, creating it if it does not exist and reading any progress already recorded in it.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut done = HashMap::new();
+        match fs::read_to_string(path) {
+            Ok(text) => {
+                for line in text.lines() {
+                    // a line cut off by an interruption is ignored
+                    let mut fields = line.split('\t');
+                    let (Some(input_hash), Some(output_hash), Some(input), Some(output), None) = (fields.next(), fields.next(), fields.next(), fields.next(), fields.next()) else {
+                        continue;
+                    };
+                    let (Ok(input_hash), Ok(output_hash)) = (u64::from_str_radix(input_hash, 16), u64::from_str_radix(output_hash, 16)) else {
+                        continue;
+                    };
+                    done.insert((input.to_string(), output.to_string()), CheckpointEntry{input_hash, output_hash});
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(BatchCheckpoint{file: Mutex::new(file), done})
+    }
+
+    /// Whether `input` has already been converted to `output`, with neither changed since.
+    pub fn is_done(&self, input: &Path, output: &Path) -> bool {
+        self.done.get(&(escape_path(input), escape_path(output))).is_some_and(|entry| {
+            hash_file(input).is_ok_and(|hash| hash == entry.input_hash)
+                && hash_file(output).is_ok_and(|hash| hash == entry.output_hash)
+        })
+    }
+
+    /// Records that `input` has been converted to `output`, saving it immediately.
+    pub fn record(&self, input: &Path, output: &Path) -> io::Result<()> {
+        let input_hash = hash_file(input)?;
+        let output_hash = hash_file(output)?;
+        let line = format!("{input_hash:016x}\t{output_hash:016x}\t{}\t{}\n", escape_path(input), escape_path(output));
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes())?;
+        file.sync_data()
+    }
+}
+
+/// What happened to one file of a batch run by [run_checkpointed_batch].
+#[derive(Debug)]
+pub enum BatchItemStatus {
+    /// The output was already recorded in the checkpoint, and neither file has changed since.
+    Skipped,
+    /// The file was converted and recorded in the checkpoint.
+    Converted,
+    /// Converting the file (or recording it) failed. The rest of the batch is still run.
+    Failed(ImageError),
+}
+
+/// Runs `convert` on each input and output path pair which is not already recorded in `checkpoint`, recording each one which succeeds,
+/// so that running the same batch again after an interruption only converts the remaining files.
+///
+/// Returns the status of each pair, in order.
+pub fn run_checkpointed_batch<P: AsRef<Path>, Q: AsRef<Path>>(
+    checkpoint: &BatchCheckpoint, items: &[(P, Q)], mut convert: impl FnMut(&Path, &Path) -> ImageResult<()>,
+) -> Vec<BatchItemStatus> {
+    items.iter().map(|(input, output)| {
+        let (input, output) = (input.as_ref(), output.as_ref());
+        if checkpoint.is_done(input, output) {
+            return BatchItemStatus::Skipped;
+        }
+        match convert(input, output).and_then(|()| checkpoint.record(input, output).map_err(ImageError::IoError)) {
+            Ok(()) => BatchItemStatus::Converted,
+            Err(e) => BatchItemStatus::Failed(e),
+        }
+    }).collect()
+}
+
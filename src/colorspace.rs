@@ -0,0 +1,314 @@
+use crate::types::*;
+#[cfg(not(any(feature = "std", test)))]
+use crate::float::FloatExt;
+
+/// RGB colour primaries which linear texel data can be expressed in.
+///
+/// XYZ colours are relative to a D65 white point. The ACES primaries use the ACES white point (close to D60),
+/// so conversions to and from them include a Bradford chromatic adaptation, matching the standard ACES transforms.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum Primaries {
+    /// ITU-R BT.709 primaries, shared with sRGB. This is what most renderers expect.
+    #[default]
+    Rec709,
+    /// ITU-R BT.2020 wide-gamut primaries.
+    Rec2020,
+    /// DCI-P3 primaries with a D65 white point, as used by Display P3 monitors.
+    DisplayP3,
+    /// ACES AP0 primaries, used by ACES2065-1 for archival and interchange. These include colours outside the visible gamut.
+    AcesAp0,
+    /// ACES AP1 primaries, used by the ACEScg rendering working space.
+    AcesCg,
+}
+
+impl Primaries {
+    /// All supported primaries.
+    pub const ALL: [Primaries; 5] = [Primaries::Rec709, Primaries::Rec2020, Primaries::DisplayP3, Primaries::AcesAp0, Primaries::AcesCg];
+
+    /// Short lowercase name of the primaries, as accepted by [Primaries::from_name].
+    pub fn name(self) -> &'static str {
+        match self {
+            Primaries::Rec709 => "rec709",
+            Primaries::Rec2020 => "rec2020",
+            Primaries::DisplayP3 => "p3",
+            Primaries::AcesAp0 => "ap0",
+            Primaries::AcesCg => "acescg",
+        }
+    }
+
+    /// Look up primaries by their short [name](Primaries::name) (or `srgb`, `ap1`, or `aces2065-1`), ignoring case.
+    pub fn from_name(name: &str) -> Option<Primaries> {
+        let name = name.trim();
+        let alias = [("srgb", Primaries::Rec709), ("display-p3", Primaries::DisplayP3), ("ap1", Primaries::AcesCg), ("aces2065-1", Primaries::AcesAp0)];
+        Primaries::ALL.into_iter().map(|p| (p.name(), p)).chain(alias)
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, p)| p)
+    }
+
+    /// Matrix converting CIE XYZ to linear RGB in these primaries.
+    pub fn xyz_to_rgb_matrix(self) -> [[f32;3];3] {
+        match self {
+            Primaries::Rec709 => XYZ_TO_RGB,
+            Primaries::Rec2020 => [
+                [1.7166512, -0.3556708, -0.2533663],
+                [-0.6666844, 1.6164812, 0.0157685],
+                [0.0176399, -0.0427706, 0.9421031],
+            ],
+            Primaries::DisplayP3 => [
+                [2.493497, -0.9313836, -0.4027108],
+                [-0.829489, 1.7626641, 0.0236247],
+                [0.0358458, -0.0761724, 0.9568845],
+            ],
+            Primaries::AcesAp0 => [
+                [1.0634955, 0.0064089, -0.0158068],
+                [-0.4920741, 1.3682234, 0.0913371],
+                [-0.0028165, 0.0046442, 0.9164186],
+            ],
+            Primaries::AcesCg => [
+                [1.6605853, -0.3152956, -0.2415093],
+                [-0.6599261, 1.6083915, 0.0172986],
+                [0.0090026, -0.0035669, 0.9136433],
+            ],
+        }
+    }
+
+    /// Matrix converting linear RGB in these primaries to CIE XYZ.
+    pub fn rgb_to_xyz_matrix(self) -> [[f32;3];3] {
+        match self {
+            Primaries::Rec709 => RGB_TO_XYZ,
+            Primaries::Rec2020 => [
+                [0.636958, 0.1446169, 0.168881],
+                [0.2627002, 0.6779981, 0.0593017],
+                [0.0, 0.0280727, 1.0609851],
+            ],
+            Primaries::DisplayP3 => [
+                [0.4865709, 0.2656677, 0.1982173],
+                [0.2289746, 0.6917385, 0.0792869],
+                [0.0, 0.0451134, 1.0439444],
+            ],
+            Primaries::AcesAp0 => [
+                [0.9382798, -0.0044514, 0.0166275],
+                [0.3373689, 0.7295216, -0.0668905],
+                [0.0011740, -0.0037107, 1.0915945],
+            ],
+            Primaries::AcesCg => [
+                [0.6522375, 0.1282361, 0.1699822],
+                [0.2676722, 0.67434, 0.0579878],
+                [-0.0053818, 0.0013691, 1.0930705],
+            ],
+        }
+    }
+
+    /// Matrix converting linear RGB in these primaries to linear RGB in the `to` primaries.
+    pub fn conversion_matrix(self, to: Primaries) -> [[f32;3];3] {
+        mul_matrices(&to.xyz_to_rgb_matrix(), &self.rgb_to_xyz_matrix())
+    }
+
+    /// Convert a linear RGB colour in these primaries to the `to` primaries.
+    /// Colours outside the target gamut have negative components.
+    pub fn convert_rgb(self, rgb: [f32;3], to: Primaries) -> [f32;3] {
+        mul_matrix(&self.conversion_matrix(to), rgb)
+    }
+
+    /// Convert a CIE XYZ colour to linear RGB in these primaries.
+    /// Colours outside the gamut have negative components.
+    pub fn rgb_from_xyz(self, xyz: [f32;3]) -> [f32;3] {
+        mul_matrix(&self.xyz_to_rgb_matrix(), xyz)
+    }
+}
+
+fn mul_matrices(a: &[[f32;3];3], b: &[[f32;3];3]) -> [[f32;3];3] {
+    core::array::from_fn(|i| core::array::from_fn(|j| a[i][0] * b[0][j] + a[i][1] * b[1][j] + a[i][2] * b[2][j]))
+}
+
+/// A white point, as a CIE 1931 (x, y) chromaticity.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct WhitePoint {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl WhitePoint {
+    /// CIE standard illuminant D65 (average daylight), the white point of Rec.709, Rec.2020, and Display P3.
+    pub const D65: WhitePoint = WhitePoint{x: 0.3127, y: 0.3290};
+    /// CIE standard illuminant D50 (horizon light), used for print and ICC profiles.
+    pub const D50: WhitePoint = WhitePoint{x: 0.3457, y: 0.3585};
+    /// The ACES white point, close to D60.
+    pub const ACES: WhitePoint = WhitePoint{x: 0.32168, y: 0.33767};
+    /// CIE standard illuminant A (incandescent light at 2856 K).
+    pub const A: WhitePoint = WhitePoint{x: 0.44757, y: 0.40745};
+    /// The equal-energy white point.
+    pub const E: WhitePoint = WhitePoint{x: 1.0 / 3.0, y: 1.0 / 3.0};
+
+    /// White point of a light with a correlated colour temperature in kelvin, clamped to between 1667 K and 25000 K.
+    ///
+    /// Temperatures below 4000 K are on the Planckian locus (like incandescent lights) and those above on the CIE daylight locus,
+    /// so 6504 K gives D65.
+    pub fn from_kelvin(kelvin: f32) -> WhitePoint {
+        let t = kelvin.clamp(1667.0, 25000.0) as f64;
+        let (t2, t3) = (t * t, t * t * t);
+        let (x, y) = if t < 4000.0 {
+            let x = -0.2661239e9 / t3 - 0.2343589e6 / t2 + 0.8776956e3 / t + 0.179910;
+            let y = if t < 2222.0 {
+                -1.1063814 * x * x * x - 1.34811020 * x * x + 2.18555832 * x - 0.20219683
+            } else {
+                -0.9549476 * x * x * x - 1.37418593 * x * x + 2.09137015 * x - 0.16748867
+            };
+            (x, y)
+        } else {
+            let x = if t <= 7000.0 {
+                -4.6070e9 / t3 + 2.9678e6 / t2 + 0.09911e3 / t + 0.244063
+            } else {
+                -2.0064e9 / t3 + 1.9018e6 / t2 + 0.24748e3 / t + 0.237040
+            };
+            (x, -3.0 * x * x + 2.870 * x - 0.275)
+        };
+        WhitePoint{x: x as f32, y: y as f32}
+    }
+
+    /// White point of a linear colour in the given primaries, such as a grey card or white surface in a light probe,
+    /// so that it can be neutralized with [white_balance].
+    pub fn from_rgb(rgb: [f32;3], primaries: Primaries) -> WhitePoint {
+        let [x, y, z] = mul_matrix(&primaries.rgb_to_xyz_matrix(), rgb);
+        let sum = x + y + z;
+        WhitePoint{x: x / sum, y: y / sum}
+    }
+
+    /// CIE XYZ colour of the white point, with a luminance (Y) of 1.
+    pub fn to_xyz(self) -> [f32;3] {
+        [self.x / self.y, 1.0, (1.0 - self.x - self.y) / self.y]
+    }
+}
+
+/// Cone response model used for chromatic adaptation.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum ChromaticAdaptation {
+    /// The Bradford transform, used by ICC profiles and ACES.
+    #[default]
+    Bradford,
+    /// The CAT02 transform from the CIECAM02 colour appearance model.
+    Cat02,
+}
+
+impl ChromaticAdaptation {
+    /// Matrix converting CIE XYZ to cone responses, and its inverse.
+    fn cone_matrices(self) -> ([[f32;3];3], [[f32;3];3]) {
+        match self {
+            ChromaticAdaptation::Bradford => ([
+                [0.8951, 0.2664, -0.1614],
+                [-0.7502, 1.7135, 0.0367],
+                [0.0389, -0.0685, 1.0296],
+            ], [
+                [0.9869929, -0.1470543, 0.1599627],
+                [0.4323053, 0.5183603, 0.0492912],
+                [-0.0085287, 0.0400428, 0.9684867],
+            ]),
+            ChromaticAdaptation::Cat02 => ([
+                [0.7328, 0.4296, -0.1624],
+                [-0.7036, 1.6975, 0.0061],
+                [0.0030, 0.0136, 0.9834],
+            ], [
+                [1.0961238, -0.278869, 0.1827452],
+                [0.454369, 0.4735332, 0.0720978],
+                [-0.0096276, -0.0056980, 1.0153256],
+            ]),
+        }
+    }
+
+    /// Matrix adapting linear RGB colours in the given primaries from being lit by the `from` white point to the `to` white point.
+    pub fn matrix(self, from: WhitePoint, to: WhitePoint, primaries: Primaries) -> [[f32;3];3] {
+        let (cone, inverse) = self.cone_matrices();
+        let (source, target) = (mul_matrix(&cone, from.to_xyz()), mul_matrix(&cone, to.to_xyz()));
+        let scaled: [[f32;3];3] = core::array::from_fn(|i| cone[i].map(|c| c * target[i] / source[i]));
+        let xyz = mul_matrices(&inverse, &scaled);
+        mul_matrices(&primaries.xyz_to_rgb_matrix(), &mul_matrices(&xyz, &primaries.rgb_to_xyz_matrix()))
+    }
+}
+
+/// Adapt the colours of texels in the given primaries from being lit by the `from` white point to the `to` white point,
+/// such as to neutralize a light probe captured under tungsten light with `from` set to [WhitePoint::from_kelvin]`(3200.0)`
+/// and `to` set to [WhitePoint::D65] (the white point of every supported set of [Primaries]).
+///
+/// Colours which the adaptation pushes outside the gamut are clamped when packed into unsigned formats.
+pub fn white_balance<T: RgbTexel>(data: &mut [T], from: WhitePoint, to: WhitePoint, method: ChromaticAdaptation, primaries: Primaries) {
+    if from == to {
+        return;
+    }
+    apply_color_matrix(data, &method.matrix(from, to, primaries));
+}
+
+/// Convert the colours of texels (in any [RgbTexel] format) from one set of [Primaries] to another,
+/// such as to bring ACEScg renders into Rec.709 for storage as [RGBE8] and back.
+///
+/// Colours outside the target gamut are clamped when packed into unsigned formats.
+pub fn convert_primaries<T: RgbTexel>(data: &mut [T], from: Primaries, to: Primaries) {
+    if from == to {
+        return;
+    }
+    apply_color_matrix(data, &from.conversion_matrix(to));
+}
+
+/// How colours outside the target gamut are brought inside it when converting between [Primaries].
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum GamutMapping {
+    /// Negative components are clamped to zero, keeping in-gamut colours exactly but shifting the hue of saturated ones.
+    #[default]
+    Clip,
+    /// Colours near and beyond the gamut boundary are desaturated smoothly (as in the ACES reference gamut compression),
+    /// so every colour of the source gamut lands inside the target gamut with its hue roughly kept,
+    /// at the cost of slightly desaturating colours close to the boundary.
+    Compress,
+}
+
+/// Distance from the achromatic axis beyond which [GamutMapping::Compress] starts desaturating.
+const GAMUT_THRESHOLD: f32 = 0.8;
+const GAMUT_POWER: f32 = 1.2;
+
+/// Compress a distance from the achromatic axis so that `limit` maps to 1 (the gamut boundary), leaving distances below the threshold unchanged.
+fn compress_distance(d: f32, limit: f32) -> f32 {
+    if d < GAMUT_THRESHOLD || limit <= 1.0 {
+        return d;
+    }
+    let range = limit - GAMUT_THRESHOLD;
+    let scale = range / (((1.0 - GAMUT_THRESHOLD) / range).powf(-GAMUT_POWER) - 1.0).powf(1.0 / GAMUT_POWER);
+    let x = (d - GAMUT_THRESHOLD) / scale;
+    GAMUT_THRESHOLD + scale * x / (1.0 + x.powf(GAMUT_POWER)).powf(1.0 / GAMUT_POWER)
+}
+
+/// Convert the colours of texels from one set of [Primaries] to another as [convert_primaries] does,
+/// then bring colours outside the target gamut inside it using the given [GamutMapping],
+/// such as for baking Rec.709 sources into Rec.2020 textures (or back) without the hue shifts of clamping when packing.
+pub fn convert_primaries_with_gamut_mapping<T: RgbTexel>(data: &mut [T], from: Primaries, to: Primaries, mapping: GamutMapping) {
+    let matrix = from.conversion_matrix(to);
+    // largest distance of each channel from the achromatic axis over the source primaries, which compression maps onto the boundary
+    let limits: [f32;3] = core::array::from_fn(|i| {
+        (0..3).map(|j| {
+            let primary: [f32;3] = core::array::from_fn(|k| matrix[k][j]);
+            let ach = primary.into_iter().fold(f32::MIN, f32::max);
+            (ach - primary[i]) / ach.abs()
+        }).fold(0.0, f32::max)
+    });
+    for texel in data.iter_mut() {
+        let rgb = mul_matrix(&matrix, texel.to_rgb());
+        let rgb = match mapping {
+            GamutMapping::Clip => rgb.map(|c| c.max(0.0)),
+            GamutMapping::Compress => {
+                let ach = rgb[0].max(rgb[1]).max(rgb[2]);
+                if ach <= 0.0 {
+                    [0.0; 3]
+                } else {
+                    core::array::from_fn(|i| (ach - compress_distance((ach - rgb[i]) / ach, limits[i]) * ach).max(0.0))
+                }
+            }
+        };
+        *texel = texel.with_rgb(rgb);
+    }
+}
+
+/// Multiply the linear colour of every texel by a 3×3 matrix (in row-major order), repacking the results.
+/// This applies colour transforms other than those between the built-in [Primaries], such as camera matrices.
+pub fn apply_color_matrix<T: RgbTexel>(data: &mut [T], matrix: &[[f32;3];3]) {
+    for texel in data.iter_mut() {
+        *texel = texel.with_rgb(mul_matrix(matrix, texel.to_rgb()));
+    }
+}
@@ -0,0 +1,171 @@
+use crate::types::*;
+use crate::hdrimage::*;
+use crate::resample::*;
+use crate::font::*;
+use crate::tonemap::*;
+
+use bytemuck::Pod;
+
+/// One image to include in a contact sheet.
+#[derive(Clone, Copy, Debug)]
+pub struct ContactSheetEntry<'a, T: Pod> {
+    /// Text shown under the thumbnail, usually the file name.
+    pub label: &'a str,
+    pub image: &'a HdrImage<T>,
+}
+
+/// Layout and exposure settings for [contact_sheet].
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct ContactSheetOptions {
+    /// Maximum width and height of each thumbnail.
+    pub thumbnail_size: u32,
+    /// Number of thumbnails in each row.
+    pub columns: u32,
+    /// Exposure adjustment in stops, applied after any automatic exposure.
+    pub exposure: f32,
+    /// Expose each thumbnail so that its average luminance is middle grey, so that dark and bright images are both visible.
+    pub auto_exposure: bool,
+    /// Stripes drawn over clipping pixels of each thumbnail.
+    pub zebra: Option<Zebra>,
+}
+
+impl Default for ContactSheetOptions {
+    fn default() -> Self {
+        ContactSheetOptions{thumbnail_size: 256, columns: 4, exposure: 0.0, auto_exposure: true, zebra: None}
+    }
+}
+
+/// Luminance statistics of an HDR image, as shown on contact sheets.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct LuminanceStats {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    /// Geometric mean, which is more representative of perceived brightness than the mean for HDR images.
+    pub log_mean: f32,
+}
+
+impl LuminanceStats {
+    /// Calculate the statistics of texels in any [RgbTexel] format.
+    pub fn of<T: RgbTexel>(data: &[T]) -> Self {
+        // avoids taking the log of zero
+        const EPSILON: f32 = 1.0 / 65536.0;
+        let mut stats = LuminanceStats{min: f32::INFINITY, max: 0.0, mean: 0.0, log_mean: 0.0};
+        let mut log_sum = 0.0f64;
+        let mut sum = 0.0f64;
+        for texel in data {
+            let lum = luminance(texel.to_rgb()).max(0.0);
+            stats.min = stats.min.min(lum);
+            stats.max = stats.max.max(lum);
+            sum += lum as f64;
+            log_sum += (lum + EPSILON).ln() as f64;
+        }
+        let n = data.len().max(1) as f64;
+        stats.min = stats.min.min(stats.max);
+        stats.mean = (sum / n) as f32;
+        stats.log_mean = ((log_sum / n).exp() as f32 - EPSILON).max(0.0);
+        stats
+    }
+}
+
+/// Height of the label area under each thumbnail, which holds two lines of text.
+const LABEL_HEIGHT: u32 = 2 * (GLYPH_HEIGHT + 3);
+/// Space around each cell.
+const MARGIN: u32 = 8;
+const BACKGROUND: [u8;3] = [32, 32, 32];
+const TEXT_COLOR: [u8;3] = [230, 230, 230];
+const STATS_COLOR: [u8;3] = [160, 160, 160];
+
+/// Resize an image to fit in a square of `size` texels, preserving aspect ratio. Images are never enlarged.
+fn thumbnail<T: RgbTexel>(image: &HdrImage<T>, size: u32) -> (Extent2D, Box<[[f32;3]]>) {
+    let (w, h) = (image.width(), image.height());
+    let scale = (size as f32 / w.max(h) as f32).min(1.0);
+    let extent = Extent2D::new(((w as f32 * scale).round() as u32).max(1), ((h as f32 * scale).round() as u32).max(1)).unwrap();
+    let rgb: Box<[[f32;3]]> = image.pixels().iter().map(|texel| texel.to_rgb()).collect();
+    (extent, resize(image.extent(), &rgb, extent, &TriangleKernel))
+}
+
+/// Tile tone-mapped thumbnails of many HDR images into a single 8-bit sRGB image for reviewing a library of images at a glance.
+///
+/// Each thumbnail is labelled with its label, dimensions, and luminance statistics.
+/// Returns the dimensions and pixels of the sheet, which can be saved with [save_rgb8_png_file](crate::save_rgb8_png_file).
+/// Panics if there are no entries.
+pub fn contact_sheet<T: RgbTexel>(entries: &[ContactSheetEntry<T>], options: &ContactSheetOptions) -> (Extent2D, Box<[[u8;3]]>) {
+    assert!(!entries.is_empty(), "contact sheet must have at least one entry");
+    let thumb_size = options.thumbnail_size.max(1);
+    let columns = options.columns.clamp(1, entries.len() as u32);
+    let rows = (entries.len() as u32).div_ceil(columns);
+    let cell_width = thumb_size + MARGIN;
+    let cell_height = thumb_size + LABEL_HEIGHT + MARGIN;
+    let extent = Extent2D::new(columns * cell_width + MARGIN, rows * cell_height + MARGIN).expect("contact sheet is too large");
+    let width = extent.width() as usize;
+    let mut pixels = vec![BACKGROUND; extent.texel_count()].into_boxed_slice();
+
+    for (i, entry) in entries.iter().enumerate() {
+        let cell_x = MARGIN + (i as u32 % columns) * cell_width;
+        let cell_y = MARGIN + (i as u32 / columns) * cell_height;
+
+        let (w, h) = (entry.image.width(), entry.image.height());
+        let (thumb_extent, thumb) = thumbnail(entry.image, thumb_size);
+
+        let stats = LuminanceStats::of(entry.image.pixels());
+        let mut exposure = options.exposure.exp2();
+        if options.auto_exposure && stats.log_mean > 0.0 {
+            exposure *= 0.18 / stats.log_mean;
+        }
+        let x0 = cell_x + (thumb_size - thumb_extent.width()) / 2;
+        let y0 = cell_y + (thumb_size - thumb_extent.height()) / 2;
+        for (ty, row) in thumb.chunks_exact(thumb_extent.width() as usize).enumerate() {
+            for (tx, px) in row.iter().enumerate() {
+                let (x, y) = (x0 + tx as u32, y0 + ty as u32);
+                pixels[y as usize * width + x as usize] = match options.zebra {
+                    Some(zebra) if zebra.marks(*px, x, y) => zebra.color,
+                    _ => reinhard_srgb8(*px, exposure),
+                };
+            }
+        }
+
+        let max_chars = (thumb_size / GLYPH_ADVANCE) as usize;
+        let label: String = entry.label.chars().take(max_chars).collect();
+        let stats_line = format!("{w}X{h} MAX {:.2e} AVG {:.2e}", stats.max, stats.mean);
+        let stats_line: String = stats_line.chars().take(max_chars).collect();
+        let text_y = (cell_y + thumb_size + 3) as i32;
+        draw_text(extent, &mut pixels, cell_x as i32, text_y, &label, &TextStyle::new(TEXT_COLOR));
+        draw_text(extent, &mut pixels, cell_x as i32, text_y + (GLYPH_HEIGHT + 3) as i32, &stats_line, &TextStyle::new(STATS_COLOR));
+    }
+    (extent, pixels)
+}
+
+/// Exposures used by photographers to evaluate HDR merges, in stops.
+pub const DEFAULT_EV_STOPS: [f32; 9] = [-4.0, -3.0, -2.0, -1.0, 0.0, 1.0, 2.0, 3.0, 4.0];
+
+/// Render an HDR image at several exposures (in stops, such as [DEFAULT_EV_STOPS]) side by side in a single 8-bit sRGB image,
+/// with each frame labelled with its exposure.
+///
+/// Each frame is scaled to fit within `frame_size` texels and tone mapped with [reinhard_srgb8].
+/// Panics if `stops` is empty.
+pub fn ev_strip<T: RgbTexel>(image: &HdrImage<T>, stops: &[f32], frame_size: u32) -> (Extent2D, Box<[[u8;3]]>) {
+    assert!(!stops.is_empty(), "EV strip must have at least one exposure");
+    let (frame_extent, frame) = thumbnail(image, frame_size.max(1));
+    let (frame_width, frame_height) = (frame_extent.width(), frame_extent.height());
+    let label_height = GLYPH_HEIGHT + 3;
+    let extent = Extent2D::new(
+        stops.len() as u32 * (frame_width + MARGIN) + MARGIN,
+        frame_height + label_height + 2 * MARGIN,
+    ).expect("EV strip is too large");
+    let width = extent.width() as usize;
+    let mut pixels = vec![BACKGROUND; extent.texel_count()].into_boxed_slice();
+
+    for (i, &stop) in stops.iter().enumerate() {
+        let x0 = MARGIN + i as u32 * (frame_width + MARGIN);
+        let exposure = stop.exp2();
+        for (fy, row) in frame.chunks_exact(frame_width as usize).enumerate() {
+            for (fx, px) in row.iter().enumerate() {
+                pixels[(MARGIN as usize + fy) * width + x0 as usize + fx] = reinhard_srgb8(*px, exposure);
+            }
+        }
+        let label = format!("EV {stop:+}");
+        draw_text(extent, &mut pixels, x0 as i32, (MARGIN + frame_height + 3) as i32, &label, &TextStyle::new(TEXT_COLOR));
+    }
+    (extent, pixels)
+}
@@ -0,0 +1,67 @@
+use crate::types::*;
+use core::{iter::FusedIterator, marker::PhantomData, slice};
+
+/// Iterator which converts texels to another format as they are read, returned by [iter_converted].
+#[derive(Clone, Debug)]
+pub struct ConvertTexels<'a, T, U> {
+    inner: slice::Iter<'a, T>,
+    format: PhantomData<fn() -> U>,
+}
+
+impl<T: Copy, U: From<T>> Iterator for ConvertTexels<'_, T, U> {
+    type Item = U;
+
+    fn next(&mut self) -> Option<U> {
+        self.inner.next().map(|&texel| U::from(texel))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<U> {
+        self.inner.nth(n).map(|&texel| U::from(texel))
+    }
+}
+
+impl<T: Copy, U: From<T>> DoubleEndedIterator for ConvertTexels<'_, T, U> {
+    fn next_back(&mut self) -> Option<U> {
+        self.inner.next_back().map(|&texel| U::from(texel))
+    }
+}
+
+impl<T: Copy, U: From<T>> ExactSizeIterator for ConvertTexels<'_, T, U> {}
+
+impl<T: Copy, U: From<T>> FusedIterator for ConvertTexels<'_, T, U> {}
+
+/// Convert texels to another format lazily, without allocating a buffer for the result,
+/// so that large textures can be converted straight into their destination (such as with `extend` into GPU staging memory)
+/// without doubling the peak memory use.
+pub fn iter_converted<T: Copy, U: From<T>>(data: &[T]) -> ConvertTexels<'_, T, U> {
+    ConvertTexels{inner: data.iter(), format: PhantomData}
+}
+
+/// Convert texels (such as [RGBE8] loaded from a file) to [RGB9E5] lazily for uploading to the GPU (see [iter_converted]).
+pub fn iter_rgb9e5<T: Copy>(data: &[T]) -> ConvertTexels<'_, T, RGB9E5> where RGB9E5: From<T> {
+    iter_converted(data)
+}
+
+/// Convert texels to [RGBE8] lazily (see [iter_converted]).
+pub fn iter_rgbe8<T: Copy>(data: &[T]) -> ConvertTexels<'_, T, RGBE8> where RGBE8: From<T> {
+    iter_converted(data)
+}
+
+/// Convert texels to [RGBA16F] lazily (see [iter_converted]).
+pub fn iter_rgba16f<T: Copy>(data: &[T]) -> ConvertTexels<'_, T, RGBA16F> where RGBA16F: From<T> {
+    iter_converted(data)
+}
+
+/// Convert texels into an existing buffer, such as a mapped GPU staging buffer cast to texels with `bytemuck::cast_slice_mut`.
+///
+/// Panics if `out` is not the same length as `data`.
+pub fn convert_into<T: Copy, U: From<T>>(data: &[T], out: &mut [U]) {
+    assert_eq!(data.len(), out.len(), "output does not match the number of texels");
+    for (texel, out) in data.iter().zip(out) {
+        *out = U::from(*texel);
+    }
+}
@@ -0,0 +1,79 @@
+use crate::types::*;
+use crate::hdrimage::*;
+
+/// Angular intensity profile of a rotationally symmetric light, from which [light_cookie] generates a texture.
+#[derive(PartialEq, Clone, Debug)]
+pub enum CookieProfile {
+    /// A spot light with an intensity of 1 within `inner_angle` of its axis, falling off smoothly to 0 at `outer_angle`.
+    /// Angles are in radians from the axis.
+    Spot{inner_angle: f32, outer_angle: f32},
+    /// Intensities at increasing angles from the axis (in radians), such as the vertical angles of an IES profile,
+    /// interpolated linearly between them. The intensity is 0 beyond the last angle.
+    Angular(Vec<(f32, f32)>),
+}
+
+impl CookieProfile {
+    /// Intensity of the light at an angle of `theta` radians from its axis.
+    pub fn intensity(&self, theta: f32) -> f32 {
+        match self {
+            CookieProfile::Spot{inner_angle, outer_angle} => {
+                if theta >= *outer_angle {
+                    0.0
+                } else if theta <= *inner_angle {
+                    1.0
+                } else {
+                    let t = (outer_angle - theta) / (outer_angle - inner_angle);
+                    t * t * (3.0 - 2.0 * t)
+                }
+            }
+            CookieProfile::Angular(samples) => {
+                let Some(&(first_angle, first)) = samples.first() else { return 0.0 };
+                if theta <= first_angle {
+                    return first;
+                }
+                samples.windows(2).find(|pair| theta <= pair[1].0).map_or(0.0, |pair| {
+                    let ((a0, i0), (a1, i1)) = (pair[0], pair[1]);
+                    let t = if a1 > a0 { (theta - a0) / (a1 - a0) } else { 1.0 };
+                    i0 + (i1 - i0) * t
+                })
+            }
+        }
+    }
+
+    /// Largest angle from the axis at which the light may have a nonzero intensity, which cookies usually cover.
+    pub fn max_angle(&self) -> f32 {
+        match self {
+            CookieProfile::Spot{outer_angle, ..} => *outer_angle,
+            CookieProfile::Angular(samples) => samples.last().map_or(0.0, |&(angle, _)| angle),
+        }
+    }
+}
+
+/// Generates a square light cookie of `size` texels per side from an angular intensity profile, for projecting from spot lights.
+///
+/// The cookie covers the cone of directions within `half_angle` radians of the axis (horizontally and vertically),
+/// as projected onto a plane perpendicular to the axis, so the centre of the texture is along the axis.
+/// `half_angle` must be less than a right angle, and is usually the [max_angle](CookieProfile::max_angle) of the profile.
+/// Each texel averages 2x2 samples to soften hard edges. The intensity is the same in every channel,
+/// so the cookie can be stored as [RGBE8] or [RGB9E5] without colour shifts, and multiplied by a tint when shading.
+///
+/// Panics if `size` is zero.
+pub fn light_cookie(size: u32, profile: &CookieProfile, half_angle: f32) -> HdrImage<RGB32F> {
+    let extent = Extent2D::new(size, size).expect("cookie size must be greater than zero");
+    let tan_half = half_angle.tan();
+    let offsets = [0.25, 0.75];
+    let data = (0..size * size).map(|i| {
+        let (x, y) = (i % size, i / size);
+        let mut sum = 0.0;
+        for dy in offsets {
+            for dx in offsets {
+                let u = (x as f32 + dx) / size as f32 * 2.0 - 1.0;
+                let v = (y as f32 + dy) / size as f32 * 2.0 - 1.0;
+                let theta = (u.hypot(v) * tan_half).atan();
+                sum += profile.intensity(theta);
+            }
+        }
+        RGB32F::from([sum / 4.0; 3])
+    }).collect();
+    HdrImage::new(extent, data)
+}
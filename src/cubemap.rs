@@ -0,0 +1,369 @@
+use crate::types::*;
+use crate::hdrimage::*;
+use crate::resample::ResampleKernel;
+use crate::mipmap::{MipLevelRange, PackedMipChain};
+
+use bytemuck::Pod;
+use image::{error::{ParameterError, ParameterErrorKind}, ImageError, ImageResult};
+
+/// Faces of a cubemap, in the standard layer order used by Vulkan, Direct3D, and KTX.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+pub enum CubeFace {
+    PosX,
+    NegX,
+    PosY,
+    NegY,
+    PosZ,
+    NegZ,
+}
+
+impl CubeFace {
+    /// All faces in layer order.
+    pub const ALL: [CubeFace; 6] = [CubeFace::PosX, CubeFace::NegX, CubeFace::PosY, CubeFace::NegY, CubeFace::PosZ, CubeFace::NegZ];
+
+    /// Direction (not normalized) through the point `(s, t)` on this face,
+    /// where `s` and `t` range from -1 to 1 from the left and top of the face image respectively.
+    pub fn direction(self, s: f32, t: f32) -> [f32;3] {
+        match self {
+            CubeFace::PosX => [1.0, -t, -s],
+            CubeFace::NegX => [-1.0, -t, s],
+            CubeFace::PosY => [s, 1.0, t],
+            CubeFace::NegY => [s, -1.0, -t],
+            CubeFace::PosZ => [s, -t, 1.0],
+            CubeFace::NegZ => [-s, -t, -1.0],
+        }
+    }
+
+    /// Face which the direction `dir` (which need not be normalized) points through, and the point `(s, t)` on it.
+    /// This is the inverse of [CubeFace::direction]. The zero vector is treated as pointing through `+X`.
+    pub fn from_direction(dir: [f32;3]) -> (CubeFace, f32, f32) {
+        let [x, y, z] = dir;
+        let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+        if ax >= ay && ax >= az {
+            let m = ax.max(f32::MIN_POSITIVE);
+            if x >= 0.0 { (CubeFace::PosX, -z / m, -y / m) } else { (CubeFace::NegX, z / m, -y / m) }
+        } else if ay >= az {
+            if y >= 0.0 { (CubeFace::PosY, x / ay, z / ay) } else { (CubeFace::NegY, x / ay, -z / ay) }
+        } else if z >= 0.0 {
+            (CubeFace::PosZ, x / az, -y / az)
+        } else {
+            (CubeFace::NegZ, -x / az, -y / az)
+        }
+    }
+}
+
+/// Arrangement of the six faces of a cubemap in a single image, as cubemaps are often authored.
+///
+/// Faces are placed so that their edges meet as they do on the cube:
+/// ```text
+/// Horizontal:         Vertical:
+///     +Y                  +Y
+/// -X  +Z  +X  -Z      -X  +Z  +X
+///     -Y                  -Y
+///                         -Z
+/// ```
+/// which leaves every face upright except `-Z` in the vertical cross, which is rotated by 180 degrees.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+pub enum CrossLayout {
+    /// Four faces wide and three faces high.
+    Horizontal,
+    /// Three faces wide and four faces high.
+    Vertical,
+}
+
+impl CrossLayout {
+    /// Size of the layout in faces, as `(columns, rows)`.
+    pub fn grid(self) -> (u32, u32) {
+        match self {
+            CrossLayout::Horizontal => (4, 3),
+            CrossLayout::Vertical => (3, 4),
+        }
+    }
+
+    /// Detect the layout of a cross image from its dimensions, returning it with the face size.
+    pub fn from_extent(extent: Extent2D) -> Option<(Self, u32)> {
+        [CrossLayout::Horizontal, CrossLayout::Vertical].into_iter().find_map(|layout| {
+            let (columns, rows) = layout.grid();
+            let face_size = extent.width() / columns;
+            (extent.width() == face_size * columns && extent.height() == face_size * rows).then_some((layout, face_size))
+        })
+    }
+
+    /// Position of a face in the layout, as `(column, row)`, and whether it is rotated by 180 degrees.
+    pub fn face_cell(self, face: CubeFace) -> (u32, u32, bool) {
+        match (self, face) {
+            (_, CubeFace::PosY) => (1, 0, false),
+            (_, CubeFace::NegX) => (0, 1, false),
+            (_, CubeFace::PosZ) => (1, 1, false),
+            (_, CubeFace::PosX) => (2, 1, false),
+            (_, CubeFace::NegY) => (1, 2, false),
+            (CrossLayout::Horizontal, CubeFace::NegZ) => (3, 1, false),
+            (CrossLayout::Vertical, CubeFace::NegZ) => (1, 3, true),
+        }
+    }
+}
+
+/// The six square faces of a cubemap, each in row-major order with the orientation given by [CubeFace::direction].
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct HdrCubemap<T: Pod> {
+    face_size: u32,
+    faces: [Box<[T]>; 6],
+}
+
+impl<T: Pod> HdrCubemap<T> {
+    /// Faces are given in the layer order of [CubeFace::ALL].
+    /// Panics if `face_size` is zero or any face does not have `face_size * face_size` texels.
+    pub fn new(face_size: u32, faces: [Box<[T]>; 6]) -> Self {
+        assert!(face_size > 0, "face size is zero");
+        for face in &faces {
+            assert_eq!(face.len(), face_size as usize * face_size as usize, "data does not match face size");
+        }
+        HdrCubemap{face_size, faces}
+    }
+
+    /// Width and height of each face in texels.
+    pub fn face_size(&self) -> u32 {
+        self.face_size
+    }
+
+    pub fn face(&self, face: CubeFace) -> &[T] {
+        &self.faces[face as usize]
+    }
+
+    pub fn face_mut(&mut self, face: CubeFace) -> &mut [T] {
+        &mut self.faces[face as usize]
+    }
+
+    /// All faces in layer order, such as for uploading as a cube texture.
+    pub fn faces(&self) -> &[Box<[T]>; 6] {
+        &self.faces
+    }
+
+    /// Take the face buffers, in layer order.
+    pub fn into_faces(self) -> [Box<[T]>; 6] {
+        self.faces
+    }
+
+    /// Convert every texel to another format.
+    pub fn convert<U: Pod + From<T>>(&self) -> HdrCubemap<U> {
+        HdrCubemap{face_size: self.face_size, faces: self.faces.each_ref().map(|face| face.iter().map(|&texel| U::from(texel)).collect())}
+    }
+
+    /// Split a cross image into faces, detecting its layout from its dimensions.
+    /// Fails if the image is not 4:3 or 3:4 with square faces.
+    pub fn from_cross(image: &HdrImage<T>) -> ImageResult<Self> {
+        let (layout, _) = CrossLayout::from_extent(image.extent())
+            .ok_or(ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::DimensionMismatch)))?;
+        Self::from_cross_layout(image, layout)
+    }
+
+    /// Split a cross image with a known layout into faces, rotating them as necessary.
+    /// Fails if the dimensions of the image do not match the layout.
+    pub fn from_cross_layout(image: &HdrImage<T>, layout: CrossLayout) -> ImageResult<Self> {
+        let (columns, rows) = layout.grid();
+        let face_size = image.width() / columns;
+        if image.width() != face_size * columns || image.height() != face_size * rows {
+            return Err(ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::DimensionMismatch)));
+        }
+        let faces = CubeFace::ALL.map(|face| {
+            let (column, row, rotated) = layout.face_cell(face);
+            (0..face_size * face_size).map(|i| {
+                let index = cross_index(image.width(), face_size, column, row, rotated, i);
+                image.pixels()[index]
+            }).collect()
+        });
+        Ok(HdrCubemap{face_size, faces})
+    }
+
+    /// Arrange the faces into a cross image. The unused parts of the image are zero (black).
+    pub fn to_cross(&self, layout: CrossLayout) -> HdrImage<T> {
+        let (columns, rows) = layout.grid();
+        let extent = Extent2D::new(self.face_size * columns, self.face_size * rows).expect("cross image is too large");
+        let mut data = vec![T::zeroed(); extent.texel_count()].into_boxed_slice();
+        for face in CubeFace::ALL {
+            let (column, row, rotated) = layout.face_cell(face);
+            for (i, &texel) in self.face(face).iter().enumerate() {
+                data[cross_index(extent.width(), self.face_size, column, row, rotated, i as u32)] = texel;
+            }
+        }
+        HdrImage::new(extent, data)
+    }
+}
+
+/// Position in an equirectangular (latitude-longitude) image of the direction `dir` (which need not be normalized),
+/// as fractions of its width and height.
+///
+/// The centre of the image faces -Z with +X to its right, and the top edge is +Y,
+/// which is the usual convention for environment maps in a right-handed Y-up space.
+pub fn equirect_coord(dir: [f32;3]) -> (f32, f32) {
+    let [x, y, z] = dir;
+    let u = 0.5 + x.atan2(-z) / std::f32::consts::TAU;
+    let v = 0.5 - (y / (x * x + y * y + z * z).sqrt()).clamp(-1.0, 1.0).asin() / std::f32::consts::PI;
+    (u, v)
+}
+
+/// Filter taps (first texel and weights) for sampling at the continuous texel position `p` with `kernel`.
+fn sample_taps<K: ResampleKernel + ?Sized>(p: f32, kernel: &K) -> (i64, Vec<f32>) {
+    let support = kernel.support();
+    let start = (p - support).floor() as i64 + 1;
+    let end = (p + support).floor() as i64;
+    let mut weights: Vec<f32> = (start..=end).map(|i| kernel.eval(i as f32 - p)).collect();
+    let sum: f32 = weights.iter().sum();
+    if sum != 0.0 {
+        weights.iter_mut().for_each(|w| *w /= sum);
+        (start, weights)
+    } else {
+        (p.round() as i64, vec![1.0])
+    }
+}
+
+/// Project an equirectangular panorama (with the orientation of [equirect_coord]) onto the six faces of a cubemap
+/// with `face_size` texels per side, interpolating in linear light.
+///
+/// The kernel is evaluated in source texels, so [TriangleKernel](crate::TriangleKernel) gives bilinear sampling
+/// and [CubicKernel](crate::CubicKernel) gives bicubic sampling.
+/// Samples wrap around horizontally and are clamped at the poles.
+/// As each face texel takes a single interpolated sample, the panorama should be downsampled (such as with [resize](crate::resize))
+/// to about four times the face size in width first if it is much larger, to avoid aliasing.
+/// Negative values from kernels with negative lobes are clamped to zero.
+///
+/// Panics if `face_size` is zero.
+pub fn equirect_to_cubemap<T: RgbTexel, K: ResampleKernel + ?Sized>(image: &HdrImage<T>, face_size: u32, kernel: &K) -> HdrCubemap<RGB32F> {
+    assert!(face_size > 0, "face size is zero");
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    let pixels = image.pixels();
+    let faces = CubeFace::ALL.map(|face| {
+        (0..face_size * face_size).map(|i| {
+            let dir = face.direction(texel_face_coord(face_size, i % face_size), texel_face_coord(face_size, i / face_size));
+            let (u, v) = equirect_coord(dir);
+            let (x0, x_weights) = sample_taps(u * width as f32 - 0.5, kernel);
+            let (y0, y_weights) = sample_taps(v * height as f32 - 0.5, kernel);
+            let mut rgb = [0.0f32; 3];
+            for (dy, &wy) in y_weights.iter().enumerate() {
+                let y = (y0 + dy as i64).clamp(0, height - 1);
+                for (dx, &wx) in x_weights.iter().enumerate() {
+                    let x = (x0 + dx as i64).rem_euclid(width);
+                    let texel = pixels[(y * width + x) as usize].to_rgb();
+                    for c in 0..3 {
+                        rgb[c] += wx * wy * texel[c];
+                    }
+                }
+            }
+            RGB32F::from_f32(rgb.map(|c| c.max(0.0)))
+        }).collect()
+    });
+    HdrCubemap{face_size, faces}
+}
+
+/// Pack the mip levels of a cubemap (such as those from [prefilter_ggx](crate::prefilter_ggx)) into a single buffer for uploading.
+///
+/// As in a KTX2 file, levels are stored smallest first, and each level contains its six faces in layer order.
+///
+/// Panics if `levels` is empty or each level is not half the size of the previous one (rounding down, but at least 1).
+pub fn pack_cubemap_mipmaps<T: Pod>(levels: &[HdrCubemap<T>]) -> PackedMipChain {
+    let base = levels.first().expect("mip chain must have at least one level");
+    let total: usize = levels.iter().map(|level| 6 * level.faces[0].len() * size_of::<T>()).sum();
+    let mut data = Vec::with_capacity(total);
+    let mut ranges = vec![MipLevelRange{offset: 0, len: 0}; levels.len()];
+    for (i, level) in levels.iter().enumerate().rev() {
+        assert_eq!(level.face_size, (base.face_size >> i).max(1), "mip level {i} has the wrong dimensions");
+        let offset = data.len();
+        for face in &level.faces {
+            data.extend_from_slice(bytemuck::cast_slice(face));
+        }
+        ranges[i] = MipLevelRange{offset, len: data.len() - offset};
+    }
+    let extent = Extent2D::new(base.face_size, base.face_size).unwrap();
+    PackedMipChain{extent, data: data.into_boxed_slice(), levels: ranges}
+}
+
+/// Index in a cross image `width` texels wide of texel `i` of the face in the given cell.
+fn cross_index(width: u32, face_size: u32, column: u32, row: u32, rotated: bool, i: u32) -> usize {
+    let (mut x, mut y) = (i % face_size, i / face_size);
+    if rotated {
+        (x, y) = (face_size - 1 - x, face_size - 1 - y);
+    }
+    (row * face_size + y) as usize * width as usize + (column * face_size + x) as usize
+}
+
+/// Convert a texel index to face coordinates ranging from -1 to 1.
+pub fn texel_face_coord(face_size: u32, i: u32) -> f32 {
+    (2.0 * (i as f32 + 0.5) / face_size as f32) - 1.0
+}
+
+/// Exact solid angle (in steradians) subtended by texel `(x, y)` of a cubemap face with `face_size` texels per side.
+///
+/// Texels near the corners of a face subtend a significantly smaller solid angle than those in the centre
+/// (about a fifth of it for large faces), so convolutions which treat all texels equally overweight the corners.
+pub fn texel_solid_angle(face_size: u32, x: u32, y: u32) -> f32 {
+    // Integral of the projected area element from the face centre to (s, t).
+    fn area(s: f32, t: f32) -> f32 {
+        (s * t).atan2((s * s + t * t + 1.0).sqrt())
+    }
+    let texel = 2.0 / face_size as f32;
+    let s0 = x as f32 * texel - 1.0;
+    let t0 = y as f32 * texel - 1.0;
+    let s1 = s0 + texel;
+    let t1 = t0 + texel;
+    area(s0, t0) - area(s0, t1) - area(s1, t0) + area(s1, t1)
+}
+
+/// How cubemap texels are weighted when integrating over the sphere (such as when prefiltering probes).
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum CubemapWeighting {
+    /// Weight each texel by the solid angle it subtends. This is correct, and the difference is visible on low-resolution probes.
+    #[default]
+    SolidAngle,
+    /// Weight all texels equally. Only useful for matching the output of tools which do this.
+    Uniform,
+}
+
+impl CubemapWeighting {
+    /// Weight of texel `(x, y)` of a face with `face_size` texels per side.
+    /// Uniform weights are scaled so that both modes sum to 4π over the whole cube.
+    pub fn texel_weight(self, face_size: u32, x: u32, y: u32) -> f32 {
+        match self {
+            CubemapWeighting::SolidAngle => texel_solid_angle(face_size, x, y),
+            CubemapWeighting::Uniform => 4.0 * std::f32::consts::PI / (6 * face_size * face_size) as f32,
+        }
+    }
+
+    /// Weights for every texel of a face in row-major order.
+    /// These are the same for all six faces.
+    pub fn face_weights(self, face_size: u32) -> Box<[f32]> {
+        (0..face_size).flat_map(|y| (0..face_size).map(move |x| self.texel_weight(face_size, x, y))).collect()
+    }
+}
+
+/// Quality settings for image-based lighting bakes (specular prefiltering and irradiance).
+///
+/// Use [BakeQuality::FAST] for quick iteration and [BakeQuality::PRODUCTION] (the default) for shipping assets.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct BakeQuality {
+    /// Number of Monte-Carlo samples taken per output texel.
+    pub sample_count: u32,
+    /// Distribute samples according to the GGX lobe rather than uniformly over the hemisphere.
+    /// This greatly reduces noise for glossy surfaces at the same sample count.
+    pub importance_sampling: bool,
+    /// Compensate for the energy lost to multiple scattering at high roughness,
+    /// which otherwise makes rough surfaces appear too dark.
+    pub multiscatter_compensation: bool,
+    /// How the texels of the source cubemap are weighted when integrating over the sphere.
+    pub weighting: CubemapWeighting,
+    /// Seed of the [SeededRng] which randomizes the sample pattern.
+    /// Bakes with the same settings and seed are identical.
+    pub seed: u32,
+}
+
+impl BakeQuality {
+    /// Low sample counts for quick previews. Expect visible noise.
+    pub const FAST: Self = BakeQuality{sample_count: 64, importance_sampling: true, multiscatter_compensation: false, weighting: CubemapWeighting::SolidAngle, seed: 0};
+    /// High sample counts with all corrections enabled for final bakes.
+    pub const PRODUCTION: Self = BakeQuality{sample_count: 1024, importance_sampling: true, multiscatter_compensation: true, weighting: CubemapWeighting::SolidAngle, seed: 0};
+}
+
+impl Default for BakeQuality {
+    fn default() -> Self {
+        Self::PRODUCTION
+    }
+}
@@ -0,0 +1,188 @@
+use crate::types::*;
+use crate::load::DDS_MAGIC;
+#[cfg(feature = "bc6h")]
+use crate::bc6h::*;
+
+use image::{error::{LimitError, LimitErrorKind}, ImageError, ImageResult};
+use std::{fs::File, io::{BufWriter, Write}, path::Path};
+
+const DXGI_FORMAT_R16G16B16A16_FLOAT: u32 = 10;
+const DXGI_FORMAT_R9G9B9E5_SHAREDEXP: u32 = 67;
+#[cfg(feature = "bc6h")]
+const DXGI_FORMAT_BC6H_UF16: u32 = 95;
+
+/// Row pitch or total size of the top level, for the header.
+enum DdsSize {
+    /// Bytes per row of an uncompressed texture.
+    Pitch(u32),
+    /// Total bytes of a block-compressed texture.
+    #[cfg_attr(not(feature = "bc6h"), allow(dead_code))]
+    LinearSize(u32),
+}
+
+/// Size in bytes of `count` items of `item_bytes` each, for the header.
+/// Fails if it does not fit in the 32-bit field, rather than writing a truncated size.
+fn header_size(count: usize, item_bytes: usize) -> ImageResult<u32> {
+    count.checked_mul(item_bytes).and_then(|size| u32::try_from(size).ok())
+        .ok_or_else(|| ImageError::Limits(LimitError::from_kind(LimitErrorKind::DimensionError)))
+}
+
+/// Writes the DDS magic number, legacy header, and DX10 extension header for a 2D texture.
+fn write_dds_header<W: Write>(out: &mut W, extent: Extent2D, dxgi_format: u32, size: DdsSize) -> ImageResult<()> {
+    const DDSD_CAPS: u32 = 0x1;
+    const DDSD_HEIGHT: u32 = 0x2;
+    const DDSD_WIDTH: u32 = 0x4;
+    const DDSD_PITCH: u32 = 0x8;
+    const DDSD_PIXELFORMAT: u32 = 0x1000;
+    const DDSD_LINEARSIZE: u32 = 0x80000;
+    const DDPF_FOURCC: u32 = 0x4;
+    const DDSCAPS_TEXTURE: u32 = 0x1000;
+    const D3D10_RESOURCE_DIMENSION_TEXTURE2D: u32 = 3;
+
+    let mut header = Vec::with_capacity(4 + 124 + 20);
+    header.extend_from_slice(DDS_MAGIC);
+
+    let (size_flag, pitch_or_linear_size) = match size {
+        DdsSize::Pitch(pitch) => (DDSD_PITCH, pitch),
+        DdsSize::LinearSize(len) => (DDSD_LINEARSIZE, len),
+    };
+    let mut fields = vec![
+        124, // dwSize
+        DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT | size_flag,
+        extent.height(),
+        extent.width(),
+        pitch_or_linear_size,
+        0, // dwDepth
+        0, // dwMipMapCount
+    ];
+    fields.extend_from_slice(&[0; 11]); // dwReserved1
+    fields.extend_from_slice(&[
+        // DDS_PIXELFORMAT
+        32, // dwSize
+        DDPF_FOURCC,
+        u32::from_le_bytes(*b"DX10"),
+        0, 0, 0, 0, 0, // bit count and masks
+    ]);
+    fields.extend_from_slice(&[DDSCAPS_TEXTURE, 0, 0, 0, 0]); // dwCaps, dwCaps2-4, dwReserved2
+    fields.extend_from_slice(&[
+        // DDS_HEADER_DXT10
+        dxgi_format,
+        D3D10_RESOURCE_DIMENSION_TEXTURE2D,
+        0, // miscFlag
+        1, // arraySize
+        0, // miscFlags2
+    ]);
+    for field in fields {
+        header.extend_from_slice(&field.to_le_bytes());
+    }
+    out.write_all(&header).map_err(ImageError::IoError)
+}
+
+/// Encodes [RGB9E5] texel data as a DDS texture with format `DXGI_FORMAT_R9G9B9E5_SHAREDEXP`.
+pub fn encode_rgb9e5_dds<W: Write>(extent: Extent2D, data: &[RGB9E5], mut out: W) -> ImageResult<()> {
+    assert_eq!(data.len(), extent.texel_count(), "data does not match image dimensions");
+    write_dds_header(&mut out, extent, DXGI_FORMAT_R9G9B9E5_SHAREDEXP, DdsSize::Pitch(header_size(extent.width() as usize, 4)?))?;
+    for texel in data {
+        out.write_all(&texel.0.to_le_bytes()).map_err(ImageError::IoError)?;
+    }
+    out.flush().map_err(ImageError::IoError)
+}
+
+/// Encodes [RGBA16F] texel data as a DDS texture with format `DXGI_FORMAT_R16G16B16A16_FLOAT`.
+pub fn encode_rgba16f_dds<W: Write>(extent: Extent2D, data: &[RGBA16F], mut out: W) -> ImageResult<()> {
+    assert_eq!(data.len(), extent.texel_count(), "data does not match image dimensions");
+    write_dds_header(&mut out, extent, DXGI_FORMAT_R16G16B16A16_FLOAT, DdsSize::Pitch(header_size(extent.width() as usize, 8)?))?;
+    for texel in data {
+        for c in [texel.r, texel.g, texel.b, texel.a] {
+            out.write_all(&c.to_le_bytes()).map_err(ImageError::IoError)?;
+        }
+    }
+    out.flush().map_err(ImageError::IoError)
+}
+
+/// Saves [RGB9E5] texel data as a DDS file (with a DX10 header) for use with DirectX tooling.
+pub fn save_rgb9e5_dds(path: &Path, extent: Extent2D, data: &[RGB9E5]) -> ImageResult<()> {
+    let file = File::create(path).map_err(ImageError::IoError)?;
+    encode_rgb9e5_dds(extent, data, BufWriter::new(file))
+}
+
+/// Saves [RGBA16F] texel data as a DDS file (with a DX10 header) for use with DirectX tooling.
+pub fn save_rgba16f_dds(path: &Path, extent: Extent2D, data: &[RGBA16F]) -> ImageResult<()> {
+    let file = File::create(path).map_err(ImageError::IoError)?;
+    encode_rgba16f_dds(extent, data, BufWriter::new(file))
+}
+
+/// Encodes BC6H blocks (as produced by [encode_bc6h](crate::encode_bc6h)) as a DDS texture with format `DXGI_FORMAT_BC6H_UF16`.
+#[cfg(feature = "bc6h")]
+pub fn encode_bc6h_dds<W: Write>(extent: Extent2D, blocks: &[BC6HBlock], mut out: W) -> ImageResult<()> {
+    assert_eq!(blocks.len(), bc6h_block_count(extent), "block count does not match image dimensions");
+    write_dds_header(&mut out, extent, DXGI_FORMAT_BC6H_UF16, DdsSize::LinearSize(header_size(blocks.len(), 16)?))?;
+    out.write_all(bytemuck::cast_slice(blocks)).map_err(ImageError::IoError)?;
+    out.flush().map_err(ImageError::IoError)
+}
+
+/// Saves BC6H blocks as a DDS file (with a DX10 header) for use with DirectX tooling.
+#[cfg(feature = "bc6h")]
+pub fn save_bc6h_dds(path: &Path, extent: Extent2D, blocks: &[BC6HBlock]) -> ImageResult<()> {
+    let file = File::create(path).map_err(ImageError::IoError)?;
+    encode_bc6h_dds(extent, blocks, BufWriter::new(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// Header length including the magic number and DX10 header.
+    const HEADER_LEN: usize = 4 + 124 + 20;
+
+    /// The flags, height, width, pitch or linear size, and DXGI format from a header.
+    fn header_fields(bytes: &[u8]) -> (u32, u32, u32, u32, u32) {
+        assert_eq!(&bytes[..4], DDS_MAGIC);
+        assert_eq!(u32_at(bytes, 84), u32::from_le_bytes(*b"DX10"));
+        (u32_at(bytes, 8), u32_at(bytes, 12), u32_at(bytes, 16), u32_at(bytes, 20), u32_at(bytes, 128))
+    }
+
+    #[test]
+    fn rgb9e5_layout() {
+        let extent = Extent2D::new(5, 3).unwrap();
+        let data: Vec<RGB9E5> = (0..15).map(|i| RGB9E5::pack([i as f32, 1.0, 0.25])).collect();
+        let mut bytes = Vec::new();
+        encode_rgb9e5_dds(extent, &data, &mut bytes).unwrap();
+        assert_eq!(header_fields(&bytes), (0x100F, 3, 5, 20, DXGI_FORMAT_R9G9B9E5_SHAREDEXP));
+        let texels: Vec<RGB9E5> = bytes[HEADER_LEN..].chunks_exact(4).map(|c| RGB9E5(u32::from_le_bytes(c.try_into().unwrap()))).collect();
+        assert_eq!(texels, data);
+    }
+
+    #[test]
+    fn rgba16f_layout() {
+        let extent = Extent2D::new(3, 2).unwrap();
+        let data: Vec<RGBA16F> = (0..6).map(|i| RGBA16F::from_f32([i as f32, 0.5, 2.0, 1.0])).collect();
+        let mut bytes = Vec::new();
+        encode_rgba16f_dds(extent, &data, &mut bytes).unwrap();
+        assert_eq!(header_fields(&bytes), (0x100F, 2, 3, 24, DXGI_FORMAT_R16G16B16A16_FLOAT));
+        let expected: Vec<u8> = data.iter().flat_map(|t| [t.r, t.g, t.b, t.a]).flat_map(|c| c.to_le_bytes()).collect();
+        assert_eq!(&bytes[HEADER_LEN..], expected);
+    }
+
+    #[cfg(feature = "bc6h")]
+    #[test]
+    fn bc6h_layout() {
+        let extent = Extent2D::new(6, 5).unwrap();
+        let blocks: Vec<BC6HBlock> = (0..4).map(|i| BC6HBlock([i as u8; 16])).collect();
+        let mut bytes = Vec::new();
+        encode_bc6h_dds(extent, &blocks, &mut bytes).unwrap();
+        assert_eq!(header_fields(&bytes), (0x81007, 5, 6, 64, DXGI_FORMAT_BC6H_UF16));
+        assert_eq!(&bytes[HEADER_LEN..], bytemuck::cast_slice::<BC6HBlock, u8>(&blocks));
+    }
+
+    #[test]
+    fn header_size_overflow() {
+        assert_eq!(header_size(u32::MAX as usize / 8, 8).unwrap(), u32::MAX / 8 * 8);
+        assert!(header_size(u32::MAX as usize / 8 + 1, 8).is_err());
+        assert!(header_size(usize::MAX, 16).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+use crate::types::*;
+
+use half::f16;
+use image::{ImageError, ImageResult};
+use std::{fs::File, io::{BufWriter, Write}, path::Path};
+
+const EXR_MAGIC: [u8; 4] = [0x76, 0x2F, 0x31, 0x01];
+const EXR_VERSION: u32 = 2;
+const PIXEL_TYPE_HALF: u32 = 1;
+
+/// Appends a header attribute with the given name, type, and value.
+fn write_attribute(header: &mut Vec<u8>, name: &str, attr_type: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(attr_type.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+/// Writes an uncompressed scanline EXR file with half-float channels.
+/// `channels` must be sorted by name and `rows` yields the samples of each row for each channel in the same order.
+fn write_half_exr<W: Write>(mut out: W, extent: Extent2D, channels: &[&str], rows: impl Iterator<Item = Vec<f16>>) -> ImageResult<()> {
+    let (width, height) = (extent.width(), extent.height());
+    let mut header = Vec::new();
+    header.extend_from_slice(&EXR_MAGIC);
+    header.extend_from_slice(&EXR_VERSION.to_le_bytes());
+
+    let mut chlist = Vec::new();
+    for name in channels {
+        chlist.extend_from_slice(name.as_bytes());
+        chlist.push(0);
+        chlist.extend_from_slice(&PIXEL_TYPE_HALF.to_le_bytes());
+        chlist.extend_from_slice(&[0; 4]); // pLinear and reserved
+        chlist.extend_from_slice(&1u32.to_le_bytes()); // xSampling
+        chlist.extend_from_slice(&1u32.to_le_bytes()); // ySampling
+    }
+    chlist.push(0);
+    let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1].iter().flat_map(|v| v.to_le_bytes()).collect();
+
+    write_attribute(&mut header, "channels", "chlist", &chlist);
+    write_attribute(&mut header, "compression", "compression", &[0]); // NO_COMPRESSION
+    write_attribute(&mut header, "dataWindow", "box2i", &window);
+    write_attribute(&mut header, "displayWindow", "box2i", &window);
+    write_attribute(&mut header, "lineOrder", "lineOrder", &[0]); // INCREASING_Y
+    write_attribute(&mut header, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+    write_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    write_attribute(&mut header, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+    header.push(0);
+
+    // each scanline is stored as a chunk with its y coordinate and size.
+    let row_bytes = 2 * width as usize * channels.len();
+    let chunk_len = 8 + row_bytes;
+    let first_chunk = header.len() + 8 * height as usize;
+    for y in 0..height as usize {
+        header.extend_from_slice(&((first_chunk + y * chunk_len) as u64).to_le_bytes());
+    }
+    out.write_all(&header).map_err(ImageError::IoError)?;
+
+    let mut chunk = Vec::with_capacity(chunk_len);
+    for (y, row) in rows.enumerate() {
+        chunk.clear();
+        chunk.extend_from_slice(&(y as i32).to_le_bytes());
+        chunk.extend_from_slice(&(row_bytes as u32).to_le_bytes());
+        chunk.extend(row.iter().flat_map(|c| c.to_le_bytes()));
+        out.write_all(&chunk).map_err(ImageError::IoError)?;
+    }
+    out.flush().map_err(ImageError::IoError)
+}
+
+/// Encodes texel data (in any [RgbTexel] format) as an uncompressed half-float RGB OpenEXR image.
+///
+/// This is intended for inspecting intermediate results in compositing and painting tools.
+pub fn encode_exr<T: RgbTexel, W: Write>(extent: Extent2D, data: &[T], out: W) -> ImageResult<()> {
+    assert_eq!(data.len(), extent.texel_count(), "data does not match image dimensions");
+    let rows = data.chunks_exact(extent.width() as usize).map(|row| {
+        let rgb: Vec<[f32;3]> = row.iter().map(|texel| texel.to_rgb()).collect();
+        // channels are stored in alphabetical order
+        [2, 1, 0].iter().flat_map(|&c| rgb.iter().map(move |px| f16::from_f32(px[c]))).collect()
+    });
+    write_half_exr(out, extent, &["B", "G", "R"], rows)
+}
+
+/// Encodes [RGBA16F] texel data as an uncompressed half-float RGBA OpenEXR image without loss of precision.
+pub fn encode_rgba16f_exr<W: Write>(extent: Extent2D, data: &[RGBA16F], out: W) -> ImageResult<()> {
+    assert_eq!(data.len(), extent.texel_count(), "data does not match image dimensions");
+    let rows = data.chunks_exact(extent.width() as usize).map(|row| {
+        let a = row.iter().map(|px| px.a);
+        let b = row.iter().map(|px| px.b);
+        let g = row.iter().map(|px| px.g);
+        let r = row.iter().map(|px| px.r);
+        a.chain(b).chain(g).chain(r).collect()
+    });
+    write_half_exr(out, extent, &["A", "B", "G", "R"], rows)
+}
+
+/// Saves texel data (in any [RgbTexel] format) as a half-float RGB OpenEXR file.
+pub fn save_exr<T: RgbTexel>(path: &Path, extent: Extent2D, data: &[T]) -> ImageResult<()> {
+    let file = File::create(path).map_err(ImageError::IoError)?;
+    encode_exr(extent, data, BufWriter::new(file))
+}
+
+/// Saves [RGBA16F] texel data as a half-float RGBA OpenEXR file.
+pub fn save_rgba16f_exr(path: &Path, extent: Extent2D, data: &[RGBA16F]) -> ImageResult<()> {
+    let file = File::create(path).map_err(ImageError::IoError)?;
+    encode_rgba16f_exr(extent, data, BufWriter::new(file))
+}
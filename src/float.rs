@@ -0,0 +1,36 @@
+//! Float functions which are only inherent methods with `std`, implemented with libm for `no_std` builds.
+
+pub(crate) trait FloatExt {
+    fn floor(self) -> Self;
+    fn ceil(self) -> Self;
+    fn round(self) -> Self;
+    fn exp2(self) -> Self;
+    fn log2(self) -> Self;
+    fn powf(self, n: Self) -> Self;
+}
+
+impl FloatExt for f32 {
+    fn floor(self) -> f32 {
+        libm::floorf(self)
+    }
+
+    fn ceil(self) -> f32 {
+        libm::ceilf(self)
+    }
+
+    fn round(self) -> f32 {
+        libm::roundf(self)
+    }
+
+    fn exp2(self) -> f32 {
+        libm::exp2f(self)
+    }
+
+    fn log2(self) -> f32 {
+        libm::log2f(self)
+    }
+
+    fn powf(self, n: f32) -> f32 {
+        libm::powf(self, n)
+    }
+}
@@ -0,0 +1,104 @@
+use crate::types::*;
+
+/// Width of a glyph in the built-in bitmap font, in pixels.
+pub(crate) const GLYPH_WIDTH: u32 = 5;
+/// Height of a glyph in the built-in bitmap font, in pixels.
+pub(crate) const GLYPH_HEIGHT: u32 = 7;
+/// Horizontal distance between the starts of consecutive glyphs.
+pub(crate) const GLYPH_ADVANCE: u32 = GLYPH_WIDTH + 1;
+
+/// 5x7 glyphs for ASCII 0x20 to 0x5F, one byte per row with the leftmost pixel in bit 4.
+const GLYPHS: [[u8; 7]; 64] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04], [0x0A, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00], [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A],
+    [0x04, 0x0F, 0x14, 0x0E, 0x05, 0x1E, 0x04], [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03], [0x0C, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0D], [0x04, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02], [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08], [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00], [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00],
+    [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08], [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00], [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C], [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+    [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E], [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E], [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F], [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+    [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02], [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E], [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E], [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+    [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E], [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C], [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00], [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x04, 0x08],
+    [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02], [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00], [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08], [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    [0x0E, 0x11, 0x01, 0x0D, 0x15, 0x15, 0x0E], [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11], [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E], [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+    [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C], [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F], [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10], [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+    [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11], [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E], [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C], [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F], [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11], [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11], [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+    [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10], [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D], [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11], [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+    [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E], [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04], [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+    [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11], [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04], [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F], [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E],
+    [0x00, 0x10, 0x08, 0x04, 0x02, 0x01, 0x00], [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E], [0x04, 0x0A, 0x11, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+];
+
+/// Bitmap for a character. Lower case letters use the upper case glyphs and unsupported characters are shown as `?`.
+fn glyph(c: char) -> &'static [u8; 7] {
+    let c = c.to_ascii_uppercase();
+    let index = match c {
+        ' '..='_' => c as usize - 0x20,
+        _ => '?' as usize - 0x20,
+    };
+    &GLYPHS[index]
+}
+
+/// Appearance of text drawn with [draw_text].
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct TextStyle<T> {
+    pub color: T,
+    /// If set, the text is drawn on a solid box (with a margin of one font pixel) so that it is legible over any image.
+    pub background: Option<T>,
+    /// Each font pixel is drawn as a `scale` by `scale` square.
+    pub scale: u32,
+}
+
+impl<T> TextStyle<T> {
+    /// Text of the given colour at a scale of 1 with no background.
+    pub fn new(color: T) -> Self {
+        TextStyle{color, background: None, scale: 1}
+    }
+}
+
+/// Size in pixels of a single line of text drawn with [draw_text] at the given scale, excluding any background margin.
+pub fn text_size(text: &str, scale: u32) -> (u32, u32) {
+    let len = text.chars().count() as u32;
+    (len.saturating_mul(GLYPH_ADVANCE).saturating_sub(1) * scale, GLYPH_HEIGHT * scale)
+}
+
+/// Draw a single line of text in the built-in 5x7 bitmap font onto an image of any texel type, with its top left corner at `(x, y)`.
+///
+/// This is intended for stamping names, exposure values, and mip indices onto debug and preview images.
+/// Only upper case ASCII is supported (lower case is drawn as upper case), and text extending past the edges of the image is clipped.
+pub fn draw_text<T: Copy>(extent: Extent2D, pixels: &mut [T], x: i32, y: i32, text: &str, style: &TextStyle<T>) {
+    assert_eq!(pixels.len(), extent.texel_count(), "data does not match image dimensions");
+    let (width, height) = (extent.width() as i32, extent.height() as i32);
+    let scale = style.scale.max(1) as i32;
+    let mut set = |px: i32, py: i32, value: T| {
+        if (0..width).contains(&px) && (0..height).contains(&py) {
+            pixels[py as usize * width as usize + px as usize] = value;
+        }
+    };
+
+    let (x, y) = if let Some(background) = style.background {
+        let (text_width, text_height) = text_size(text, scale as u32);
+        for py in y..y + text_height as i32 + 2 * scale {
+            for px in x..x + text_width as i32 + 2 * scale {
+                set(px, py, background);
+            }
+        }
+        (x + scale, y + scale)
+    } else {
+        (x, y)
+    };
+
+    for (i, c) in text.chars().enumerate() {
+        let gx = x + i as i32 * GLYPH_ADVANCE as i32 * scale;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH as i32 {
+                if bits & (0x10 >> col) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        set(gx + col * scale + dx, y + row as i32 * scale + dy, style.color);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,353 @@
+use crate::types::*;
+use crate::hdrimage::*;
+use crate::tonemap::srgb_to_linear;
+
+use image::{error::{DecodingError, ImageFormatHint}, ImageError, ImageResult};
+
+/// Header of the APP1 segment holding XMP metadata in a JPEG.
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+/// Header of the APP2 segment holding ISO 21496-1 gain map metadata in a JPEG.
+const ISO_21496_1_HEADER: &[u8] = b"urn:iso:std:iso:ts:21496:-1\0";
+
+fn gain_map_error(msg: &str) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormatHint::Name("gain map JPEG".into()), msg.to_string()))
+}
+
+fn iso_metadata_error(msg: &str) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormatHint::Name("ISO 21496-1 gain map metadata".into()), msg.to_string()))
+}
+
+/// Reads the big-endian fields of binary ISO 21496-1 metadata.
+struct IsoReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl IsoReader<'_> {
+    fn bytes<const N: usize>(&mut self) -> ImageResult<[u8;N]> {
+        let (field, rest) = self.bytes.split_first_chunk().ok_or_else(|| iso_metadata_error("truncated metadata"))?;
+        self.bytes = rest;
+        Ok(*field)
+    }
+
+    fn u16(&mut self) -> ImageResult<u16> {
+        self.bytes().map(u16::from_be_bytes)
+    }
+
+    fn u32(&mut self) -> ImageResult<u32> {
+        self.bytes().map(u32::from_be_bytes)
+    }
+
+    fn i32(&mut self) -> ImageResult<i32> {
+        self.bytes().map(i32::from_be_bytes)
+    }
+
+    /// A fraction with a numerator read by `numerator`, and a denominator which is either shared or read after it.
+    fn fraction(&mut self, numerator: fn(&mut Self) -> ImageResult<f64>, common_denominator: Option<u32>) -> ImageResult<f32> {
+        let n = numerator(self)?;
+        let d = match common_denominator {
+            Some(d) => d,
+            None => self.u32()?,
+        };
+        if d == 0 {
+            return Err(iso_metadata_error("fraction has a zero denominator"));
+        }
+        Ok((n / d as f64) as f32)
+    }
+
+    fn signed(&mut self) -> ImageResult<f64> {
+        self.i32().map(f64::from)
+    }
+
+    fn unsigned(&mut self) -> ImageResult<f64> {
+        self.u32().map(f64::from)
+    }
+}
+
+/// Parameters for reconstructing an HDR image from an SDR base image and a gain map,
+/// as in the `hdrgm` XMP metadata of Adobe gain maps and Android Ultra HDR photos, or the binary ISO 21496-1 metadata.
+///
+/// Boosts and capacities are in stops (log2), and per-channel values are in RGB order
+/// (with all three the same for single-channel gain maps).
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct GainMapMetadata {
+    /// Boost of texels where the gain map is 0.
+    pub gain_map_min: [f32;3],
+    /// Boost of texels where the gain map is 1.
+    pub gain_map_max: [f32;3],
+    /// Gamma with which the gain map values are encoded.
+    pub gamma: [f32;3],
+    /// Offset added to the linear SDR colour before boosting it, which keeps black texels from staying black.
+    pub offset_sdr: [f32;3],
+    /// Offset subtracted from the boosted colour.
+    pub offset_hdr: [f32;3],
+    /// Display headroom at which the gain map starts to be applied.
+    pub hdr_capacity_min: f32,
+    /// Display headroom at which the gain map is fully applied.
+    pub hdr_capacity_max: f32,
+}
+
+/// Finds the values of an `hdrgm` property, either as an attribute or as an element containing an `rdf:Seq` of one value per channel.
+fn xmp_property(xmp: &str, name: &str) -> Option<Vec<f32>> {
+    if let Some(start) = xmp.find(&format!("hdrgm:{name}=\"")) {
+        let value = &xmp[start + name.len() + 8..];
+        return value[..value.find('"')?].trim().parse().ok().map(|v| vec![v]);
+    }
+    let start = xmp.find(&format!("<hdrgm:{name}>"))? + name.len() + 8;
+    let end = start + xmp[start..].find(&format!("</hdrgm:{name}>"))?;
+    let values = xmp[start..end].split("<rdf:li>").skip(1)
+        .map(|item| item.split("</rdf:li>").next().and_then(|v| v.trim().parse().ok()))
+        .collect::<Option<Vec<f32>>>()?;
+    (!values.is_empty()).then_some(values)
+}
+
+/// Reads a per-channel property, which may have one value for all channels or one for each.
+fn xmp_channels(xmp: &str, name: &str, default: Option<f32>) -> ImageResult<[f32;3]> {
+    match xmp_property(xmp, name).as_deref() {
+        Some(&[v]) => Ok([v; 3]),
+        Some(&[r, g, b]) => Ok([r, g, b]),
+        Some(_) => Err(gain_map_error(&format!("hdrgm:{name} must have one or three values"))),
+        None => default.map(|v| [v; 3]).ok_or_else(|| gain_map_error(&format!("missing hdrgm:{name}"))),
+    }
+}
+
+impl GainMapMetadata {
+    /// Parses the `hdrgm` properties from an XMP packet, using the defaults of the specification for optional properties.
+    ///
+    /// Fails if the required `GainMapMax` and `HDRCapacityMax` properties are missing,
+    /// or if the base image is the HDR rendition (`BaseRenditionIsHDR="True"`), which is not supported.
+    pub fn from_xmp(xmp: &str) -> ImageResult<Self> {
+        if xmp.contains("hdrgm:BaseRenditionIsHDR=\"True\"") {
+            return Err(gain_map_error("gain maps with an HDR base rendition are not supported"));
+        }
+        let single = |name: &str, default: Option<f32>| match xmp_property(xmp, name).as_deref() {
+            Some(&[v]) => Ok(v),
+            Some(_) => Err(gain_map_error(&format!("hdrgm:{name} must have a single value"))),
+            None => default.ok_or_else(|| gain_map_error(&format!("missing hdrgm:{name}"))),
+        };
+        Ok(GainMapMetadata{
+            gain_map_min: xmp_channels(xmp, "GainMapMin", Some(0.0))?,
+            gain_map_max: xmp_channels(xmp, "GainMapMax", None)?,
+            gamma: xmp_channels(xmp, "Gamma", Some(1.0))?,
+            offset_sdr: xmp_channels(xmp, "OffsetSDR", Some(1.0 / 64.0))?,
+            offset_hdr: xmp_channels(xmp, "OffsetHDR", Some(1.0 / 64.0))?,
+            hdr_capacity_min: single("HDRCapacityMin", Some(0.0))?,
+            hdr_capacity_max: single("HDRCapacityMax", None)?,
+        })
+    }
+
+    /// Parses binary ISO 21496-1 gain map metadata, starting with its `minimum_version` field,
+    /// as stored after the `urn:iso:std:iso:ts:21496:-1` header in the APP2 segment of the gain map of a JPEG.
+    ///
+    /// The headrooms of the base and alternate images become the HDR capacities, and their offsets become the SDR and HDR offsets.
+    /// Fails if the metadata needs a newer version of the specification, or if the base image is the HDR rendition
+    /// (the backward direction), which is not supported.
+    pub fn from_iso_21496_1(bytes: &[u8]) -> ImageResult<Self> {
+        let mut reader = IsoReader{bytes};
+        let minimum_version = reader.u16()?;
+        let _writer_version = reader.u16()?;
+        if minimum_version != 0 {
+            return Err(iso_metadata_error(&format!("unsupported metadata version {minimum_version}")));
+        }
+        let [flags] = reader.bytes()?;
+        let multichannel = flags & 0x80 != 0;
+        if flags & 0x04 != 0 {
+            return Err(iso_metadata_error("gain maps with an HDR base rendition are not supported"));
+        }
+        let common_denominator = if flags & 0x08 != 0 { Some(reader.u32()?) } else { None };
+        let hdr_capacity_min = reader.fraction(IsoReader::unsigned, common_denominator)?;
+        let hdr_capacity_max = reader.fraction(IsoReader::unsigned, common_denominator)?;
+
+        let mut channels = [[0.0; 5]; 3];
+        for channel in &mut channels[..if multichannel { 3 } else { 1 }] {
+            *channel = [
+                reader.fraction(IsoReader::signed, common_denominator)?,
+                reader.fraction(IsoReader::signed, common_denominator)?,
+                reader.fraction(IsoReader::unsigned, common_denominator)?,
+                reader.fraction(IsoReader::signed, common_denominator)?,
+                reader.fraction(IsoReader::signed, common_denominator)?,
+            ];
+        }
+        if !multichannel {
+            channels = [channels[0]; 3];
+        }
+        if channels.iter().any(|channel| channel[2] <= 0.0) {
+            return Err(iso_metadata_error("gain map gamma must be positive"));
+        }
+        let field = |i: usize| channels.map(|channel| channel[i]);
+        Ok(GainMapMetadata{
+            gain_map_min: field(0),
+            gain_map_max: field(1),
+            gamma: field(2),
+            offset_sdr: field(3),
+            offset_hdr: field(4),
+            hdr_capacity_min,
+            hdr_capacity_max,
+        })
+    }
+
+    /// Parses the payload of the `tmap` (tone map) item of an AVIF or HEIF file with a gain map,
+    /// which is a version byte followed by ISO 21496-1 metadata (see [GainMapMetadata::from_iso_21496_1]).
+    pub fn from_tmap(payload: &[u8]) -> ImageResult<Self> {
+        match payload.split_first() {
+            Some((0, metadata)) => Self::from_iso_21496_1(metadata),
+            Some((version, _)) => Err(iso_metadata_error(&format!("unsupported tmap version {version}"))),
+            None => Err(iso_metadata_error("empty tmap item")),
+        }
+    }
+
+    /// Weight with which to apply the gain map for a display with `headroom` stops above SDR white,
+    /// from 0 (showing the SDR image) to 1 (showing the full HDR rendition).
+    pub fn weight(&self, headroom: f32) -> f32 {
+        if self.hdr_capacity_max <= self.hdr_capacity_min {
+            return if headroom >= self.hdr_capacity_max { 1.0 } else { 0.0 };
+        }
+        ((headroom - self.hdr_capacity_min) / (self.hdr_capacity_max - self.hdr_capacity_min)).clamp(0.0, 1.0)
+    }
+}
+
+/// The parts of a JPEG with an embedded gain map, found by [split_gain_map_jpeg].
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct GainMapJpeg<'a> {
+    /// The primary (SDR) image, as a complete JPEG file.
+    pub base: &'a [u8],
+    /// The gain map, as a complete JPEG file.
+    pub gain_map: &'a [u8],
+    pub metadata: GainMapMetadata,
+}
+
+/// A marker segment of a JPEG file.
+struct JpegSegment<'a> {
+    marker: u8,
+    payload: &'a [u8],
+}
+
+/// Finds the segments of a JPEG before its image data,
+/// and the offset at which the image data starts (after the first start of scan segment).
+fn jpeg_header_segments(jpeg: &[u8]) -> ImageResult<(Vec<JpegSegment<'_>>, usize)> {
+    if !jpeg.starts_with(&[0xFF, 0xD8]) {
+        return Err(gain_map_error("not a JPEG file"));
+    }
+    let mut segments = Vec::new();
+    let mut pos = 2;
+    loop {
+        let (marker, payload_end) = jpeg_segment(jpeg, pos)?;
+        segments.push(JpegSegment{marker, payload: &jpeg[pos + 4..payload_end]});
+        pos = payload_end;
+        if marker == 0xDA {
+            return Ok((segments, pos));
+        }
+    }
+}
+
+/// Reads the marker and length of the segment at `pos`, returning the marker and the end of the segment.
+fn jpeg_segment(jpeg: &[u8], pos: usize) -> ImageResult<(u8, usize)> {
+    match jpeg.get(pos..pos + 4) {
+        Some(&[0xFF, marker, hi, lo]) => {
+            let end = pos + 2 + u16::from_be_bytes([hi, lo]) as usize;
+            if end > jpeg.len() || end < pos + 4 {
+                return Err(gain_map_error("truncated JPEG segment"));
+            }
+            Ok((marker, end))
+        }
+        _ => Err(gain_map_error("invalid JPEG marker")),
+    }
+}
+
+/// Finds the end of a JPEG file (just after its end of image marker), skipping over entropy-coded data
+/// and any segments between progressive scans.
+fn jpeg_end(jpeg: &[u8]) -> ImageResult<usize> {
+    let (_, mut pos) = jpeg_header_segments(jpeg)?;
+    while pos + 1 < jpeg.len() {
+        if jpeg[pos] != 0xFF {
+            pos += 1;
+            continue;
+        }
+        match jpeg[pos + 1] {
+            // stuffed zero bytes, restart markers, and fill bytes are part of the scan
+            0x00 | 0xD0..=0xD7 | 0xFF => pos += 1,
+            0xD9 => return Ok(pos + 2),
+            _ => pos = jpeg_segment(jpeg, pos)?.1,
+        }
+    }
+    Err(gain_map_error("JPEG has no end of image marker"))
+}
+
+/// Returns the XMP packet of a JPEG file, if it has one.
+pub fn jpeg_xmp(jpeg: &[u8]) -> ImageResult<Option<&str>> {
+    let (segments, _) = jpeg_header_segments(jpeg)?;
+    Ok(segments.into_iter()
+        .find_map(|segment| (segment.marker == 0xE1).then(|| segment.payload.strip_prefix(XMP_HEADER)).flatten())
+        .and_then(|xmp| std::str::from_utf8(xmp).ok()))
+}
+
+/// Returns the ISO 21496-1 metadata of a JPEG file (after the header of its APP2 segment), if it has any.
+/// The primary image of a gain map JPEG only records the version of the metadata here, so this is usually read from the gain map.
+pub fn jpeg_iso_21496_1(jpeg: &[u8]) -> ImageResult<Option<&[u8]>> {
+    let (segments, _) = jpeg_header_segments(jpeg)?;
+    Ok(segments.into_iter()
+        .find_map(|segment| (segment.marker == 0xE2).then(|| segment.payload.strip_prefix(ISO_21496_1_HEADER)).flatten()))
+}
+
+/// Splits a JPEG with an embedded gain map (such as an Android Ultra HDR or Adobe gain map photo)
+/// into its base image and gain map, and parses the gain map metadata.
+///
+/// The gain map is the JPEG file which follows the primary image. Its metadata is read from the binary ISO 21496-1 metadata
+/// of the gain map if it has any (see [GainMapMetadata::from_iso_21496_1]), and otherwise from the `hdrgm` properties
+/// in the XMP of the gain map (or of the primary image, if the gain map has none).
+/// Both images must be decoded with a JPEG decoder, and can then be combined with [apply_gain_map].
+///
+/// Gain maps in HEIC files must be extracted with a HEIF demuxer, which can pass the `tmap` item to [GainMapMetadata::from_tmap].
+/// Apple's own gain map metadata is not supported.
+pub fn split_gain_map_jpeg(jpeg: &[u8]) -> ImageResult<GainMapJpeg<'_>> {
+    let base_end = jpeg_end(jpeg)?;
+    let gain_start = jpeg[base_end..].windows(3).position(|w| w == [0xFF, 0xD8, 0xFF])
+        .ok_or_else(|| gain_map_error("JPEG does not contain a gain map"))? + base_end;
+    let gain_end = gain_start + jpeg_end(&jpeg[gain_start..])?;
+    let (base, gain_map) = (&jpeg[..base_end], &jpeg[gain_start..gain_end]);
+    // a segment of only the version numbers carries no metadata
+    if let Some(iso) = jpeg_iso_21496_1(gain_map)?.filter(|iso| iso.len() > 4) {
+        return Ok(GainMapJpeg{base, gain_map, metadata: GainMapMetadata::from_iso_21496_1(iso)?});
+    }
+    let xmp = [gain_map, base].into_iter()
+        .filter_map(|part| jpeg_xmp(part).ok().flatten())
+        .find(|xmp| xmp.contains("hdrgm:"))
+        .ok_or_else(|| gain_map_error("JPEG does not contain gain map metadata"))?;
+    Ok(GainMapJpeg{base, gain_map, metadata: GainMapMetadata::from_xmp(xmp)?})
+}
+
+/// Samples the gain map bilinearly at the centre of the texel `(x, y)` of an image of the given extent,
+/// as the gain map is often smaller than the base image.
+fn sample_gain_map(gain_map: &HdrImage<[u8;3]>, extent: Extent2D, x: u32, y: u32) -> [f32;3] {
+    let coord = |i: u32, size: u32, map_size: u32| {
+        let c = ((i as f32 + 0.5) * map_size as f32 / size as f32 - 0.5).clamp(0.0, (map_size - 1) as f32);
+        let i0 = c.floor() as u32;
+        (i0, (i0 + 1).min(map_size - 1), c - i0 as f32)
+    };
+    let (x0, x1, fx) = coord(x, extent.width(), gain_map.width());
+    let (y0, y1, fy) = coord(y, extent.height(), gain_map.height());
+    let texel = |x: u32, y: u32| gain_map.pixels()[(y * gain_map.width() + x) as usize].map(|v| v as f32 / 255.0);
+    let (a, b, c, d) = (texel(x0, y0), texel(x1, y0), texel(x0, y1), texel(x1, y1));
+    core::array::from_fn(|i| (a[i] * (1.0 - fx) + b[i] * fx) * (1.0 - fy) + (c[i] * (1.0 - fx) + d[i] * fx) * fy)
+}
+
+/// Reconstructs linear HDR colours from a decoded sRGB base image and gain map, applying the gain map with the given weight
+/// (1 for the full HDR rendition, or [GainMapMetadata::weight] for a particular display).
+///
+/// Single-channel gain maps should be expanded to RGB with the same value in each channel.
+/// The gain map is scaled to the size of the base image with bilinear filtering.
+/// Colours are relative to SDR white and in the primaries of the base image, which for phone photos is often Display P3
+/// (see [convert_primaries](crate::convert_primaries)).
+pub fn apply_gain_map(base: &HdrImage<[u8;3]>, gain_map: &HdrImage<[u8;3]>, metadata: &GainMapMetadata, weight: f32) -> HdrImage<RGB32F> {
+    let extent = base.extent();
+    let data = base.pixels().iter().enumerate().map(|(i, sdr)| {
+        let (x, y) = (i as u32 % extent.width(), i as u32 / extent.width());
+        let gain = sample_gain_map(gain_map, extent, x, y);
+        RGB32F::from(core::array::from_fn::<f32, 3, _>(|c| {
+            let recovery = gain[c].powf(1.0 / metadata.gamma[c]);
+            let log_boost = metadata.gain_map_min[c] * (1.0 - recovery) + metadata.gain_map_max[c] * recovery;
+            let linear = srgb_to_linear(sdr[c] as f32 / 255.0);
+            ((linear + metadata.offset_sdr[c]) * (log_boost * weight).exp2() - metadata.offset_hdr[c]).max(0.0)
+        }))
+    }).collect();
+    HdrImage::new(extent, data)
+}
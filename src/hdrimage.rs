@@ -0,0 +1,461 @@
+use crate::types::*;
+use crate::load::image_extent;
+
+use bytemuck::Pod;
+use image::{DynamicImage, ImageBuffer, ImageError, Rgb32FImage, Rgba32FImage};
+
+/// A 2D image with its dimensions and a buffer of texels in row-major order.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct HdrImage<T: Pod> {
+    extent: Extent2D,
+    data: Box<[T]>,
+}
+
+impl<T: Pod> HdrImage<T> {
+    /// Panics if the length of `data` does not match `extent`.
+    pub fn new(extent: Extent2D, data: Box<[T]>) -> Self {
+        assert_eq!(data.len(), extent.texel_count(), "data does not match image dimensions");
+        HdrImage{extent, data}
+    }
+
+    pub fn extent(&self) -> Extent2D {
+        self.extent
+    }
+
+    pub fn width(&self) -> u32 {
+        self.extent.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.extent.height()
+    }
+
+    /// Texels in row-major order.
+    pub fn pixels(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [T] {
+        &mut self.data
+    }
+
+    /// Texel in column `x` of row `y`, counting from the top left.
+    ///
+    /// Panics if the coordinates are outside the image.
+    pub fn get(&self, x: u32, y: u32) -> T {
+        self.data[self.index(x, y)]
+    }
+
+    /// Index of the texel `(x, y)` in the buffer.
+    fn index(&self, x: u32, y: u32) -> usize {
+        assert!(x < self.width() && y < self.height(), "texel ({x}, {y}) is outside the {}x{} image", self.width(), self.height());
+        y as usize * self.width() as usize + x as usize
+    }
+
+    /// Raw texel data, such as for uploading to the GPU.
+    pub fn as_bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(&self.data)
+    }
+
+    /// Take the texel buffer, discarding the dimensions.
+    pub fn into_pixels(self) -> Box<[T]> {
+        self.data
+    }
+
+    /// Convert every texel to another format.
+    pub fn convert<U: Pod + From<T>>(&self) -> HdrImage<U> {
+        HdrImage{extent: self.extent, data: self.data.iter().map(|&texel| U::from(texel)).collect()}
+    }
+
+    /// Copy the `width` by `height` region with its top left corner at `(x, y)` into a new image.
+    /// Texels are copied as they are, so there is no loss of precision in packed formats.
+    ///
+    /// Panics if the region is empty or extends outside the image.
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> HdrImage<T> {
+        let extent = Extent2D::new(width, height).expect("cropped region must not be empty");
+        assert!(x.checked_add(width).is_some_and(|right| right <= self.width()) && y.checked_add(height).is_some_and(|bottom| bottom <= self.height()),
+            "region at ({x}, {y}) of {width}x{height} extends outside the {}x{} image", self.width(), self.height());
+        let data = self.data.chunks_exact(self.width() as usize)
+            .skip(y as usize).take(height as usize)
+            .flat_map(|row| &row[x as usize..(x + width) as usize])
+            .copied().collect();
+        HdrImage{extent, data}
+    }
+
+    /// Mirror the image horizontally in place, swapping its left and right edges.
+    pub fn flip_x(&mut self) {
+        let width = self.width() as usize;
+        for row in self.data.chunks_exact_mut(width) {
+            row.reverse();
+        }
+    }
+
+    /// Mirror the image vertically in place, swapping its top and bottom edges
+    /// (such as for light probes stored with the opposite row order to the engine's convention).
+    pub fn flip_y(&mut self) {
+        let width = self.width() as usize;
+        let height = self.height() as usize;
+        for y in 0..height / 2 {
+            let (top, bottom) = self.data.split_at_mut((height - 1 - y) * width);
+            top[y * width..(y + 1) * width].swap_with_slice(&mut bottom[..width]);
+        }
+    }
+
+    /// Rotate the image by 180 degrees in place.
+    pub fn rotate180(&mut self) {
+        self.data.reverse();
+    }
+
+    /// Rotate the image by 90 degrees clockwise, swapping its width and height.
+    pub fn rotate90(&self) -> HdrImage<T> {
+        let (width, height) = (self.width() as usize, self.height() as usize);
+        // column x of the result is row (height - 1 - x) of the source
+        let data = (0..width).flat_map(|x| (0..height).rev().map(move |y| (x, y)))
+            .map(|(x, y)| self.data[y * width + x])
+            .collect();
+        HdrImage{extent: Extent2D::new(self.height(), self.width()).unwrap(), data}
+    }
+
+    /// Rotate the image by 90 degrees counterclockwise (270 degrees clockwise), swapping its width and height.
+    pub fn rotate270(&self) -> HdrImage<T> {
+        let (width, height) = (self.width() as usize, self.height() as usize);
+        let data = (0..width).rev().flat_map(|x| (0..height).map(move |y| (x, y)))
+            .map(|(x, y)| self.data[y * width + x])
+            .collect();
+        HdrImage{extent: Extent2D::new(self.height(), self.width()).unwrap(), data}
+    }
+}
+
+impl<T: RgbTexel> HdrImage<T> {
+    /// Linear colour of the texel in column `x` of row `y`, counting from the top left.
+    ///
+    /// Panics if the coordinates are outside the image.
+    pub fn get_unpacked(&self, x: u32, y: u32) -> [f32;3] {
+        self.get(x, y).to_rgb()
+    }
+
+    /// Bilinear sample at texture coordinates `(u, v)`, where (0, 0) is the top left corner of the image and (1, 1) the bottom right,
+    /// so that texel centres are at half-texel offsets as on the GPU. Coordinates outside the image are clamped to its edges.
+    pub fn sample(&self, u: f32, v: f32) -> [f32;3] {
+        let coord = |t: f32, size: u32| {
+            let c = (t * size as f32 - 0.5).clamp(0.0, (size - 1) as f32);
+            let i0 = c.floor() as u32;
+            (i0, (i0 + 1).min(size - 1), c - i0 as f32)
+        };
+        let (x0, x1, fx) = coord(u, self.width());
+        let (y0, y1, fy) = coord(v, self.height());
+        let (a, b, c, d) = (self.get_unpacked(x0, y0), self.get_unpacked(x1, y0), self.get_unpacked(x0, y1), self.get_unpacked(x1, y1));
+        core::array::from_fn(|i| (a[i] * (1.0 - fx) + b[i] * fx) * (1.0 - fy) + (c[i] * (1.0 - fx) + d[i] * fx) * fy)
+    }
+
+    /// Convert every texel to [RGBE8] with [triangular_dither], to avoid banding in smooth gradients.
+    pub fn to_rgbe8_dithered(&self) -> HdrImage<RGBE8> {
+        self.to_rgbe8_dithered_with_seed(0)
+    }
+
+    /// Convert every texel to [RGBE8] with the dither pattern of [triangular_dither_seeded] for `seed`.
+    pub fn to_rgbe8_dithered_with_seed(&self, seed: u32) -> HdrImage<RGBE8> {
+        pack_rgbe8_dithered(self.extent, self.data.iter().map(|texel| texel.to_rgb()), seed)
+    }
+}
+
+/// Pack colours in row-major order into an [RGBE8] image with [triangular_dither_seeded].
+pub(crate) fn pack_rgbe8_dithered(extent: Extent2D, colors: impl Iterator<Item = [f32;3]>, seed: u32) -> HdrImage<RGBE8> {
+    let width = extent.width() as usize;
+    let data = colors.enumerate().map(|(i, rgb)| {
+        RGBE8::pack_dithered(rgb, triangular_dither_seeded((i % width) as u32, (i / width) as u32, seed))
+    }).collect();
+    HdrImage::new(extent, data)
+}
+
+#[cfg(feature = "rayon")]
+impl<T: RgbTexel + Send> HdrImage<T> {
+    /// Apply a function to the colour of every texel in parallel, unpacking and repacking as necessary.
+    /// This is convenient for one-off adjustments which are not covered by functions such as [mul_color](crate::mul_color).
+    pub fn par_map_pixels(&mut self, f: impl Fn([f32;3]) -> [f32;3] + Sync) {
+        crate::ops::par_map_rgb(&mut self.data, f);
+    }
+}
+
+/// Layers of a 2D array texture with the same dimensions, stored one after another in a single buffer
+/// ready for uploading, such as one assembled by [load_rgb9e5_array_texture](crate::load_rgb9e5_array_texture).
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct ArrayTexture<T: Pod> {
+    extent: Extent2D,
+    layers: u32,
+    data: Box<[T]>,
+}
+
+impl<T: Pod> ArrayTexture<T> {
+    /// Panics if there are no layers, or if the length of `data` does not match `extent` and the number of layers.
+    pub fn new(extent: Extent2D, layers: u32, data: Box<[T]>) -> Self {
+        assert!(layers > 0, "array texture must have at least one layer");
+        assert_eq!(Some(data.len()), extent.texel_count().checked_mul(layers as usize), "data does not match array dimensions");
+        ArrayTexture{extent, layers, data}
+    }
+
+    /// Dimensions of each layer.
+    pub fn extent(&self) -> Extent2D {
+        self.extent
+    }
+
+    pub fn layer_count(&self) -> u32 {
+        self.layers
+    }
+
+    /// Texels of one layer in row-major order.
+    ///
+    /// Panics if `layer` is out of range.
+    pub fn layer(&self, layer: u32) -> &[T] {
+        assert!(layer < self.layers, "layer {layer} is out of range for {} layers", self.layers);
+        let len = self.extent.texel_count();
+        &self.data[layer as usize * len..][..len]
+    }
+
+    /// Texels of every layer in order.
+    pub fn pixels(&self) -> &[T] {
+        &self.data
+    }
+
+    /// Raw texel data of every layer, such as for uploading to the GPU in one call.
+    pub fn as_bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(&self.data)
+    }
+
+    /// Take the texel buffer, discarding the dimensions.
+    pub fn into_pixels(self) -> Box<[T]> {
+        self.data
+    }
+}
+
+/// Texel formats which can be stored in an [AnyHdrImage].
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+pub enum TexelFormat {
+    Rgbe8,
+    Rgb9e5,
+    R11g11b10f,
+    Rgba16f,
+    Rgb32f,
+    Rgba32f,
+}
+
+/// An image whose texel format is only known at runtime, such as one returned by [load_hdr_file](crate::load_hdr_file).
+#[derive(PartialEq, Clone, Debug)]
+pub enum AnyHdrImage {
+    Rgbe8(HdrImage<RGBE8>),
+    Rgb9e5(HdrImage<RGB9E5>),
+    R11g11b10f(HdrImage<R11G11B10F>),
+    Rgba16f(HdrImage<RGBA16F>),
+    Rgb32f(HdrImage<RGB32F>),
+    Rgba32f(HdrImage<RGBA32F>),
+}
+
+/// Convert texels through [RGBA32F], which all formats can be converted to and from while preserving alpha.
+fn convert_via_rgba32f<T, U>(image: &HdrImage<T>) -> HdrImage<U>
+where T: Pod + Into<RGBA32F>, U: Pod + From<RGBA32F> {
+    HdrImage::new(image.extent(), image.pixels().iter().map(|&texel| U::from(texel.into())).collect())
+}
+
+impl AnyHdrImage {
+    /// Format of the stored texels.
+    pub fn format(&self) -> TexelFormat {
+        match self {
+            AnyHdrImage::Rgbe8(_) => TexelFormat::Rgbe8,
+            AnyHdrImage::Rgb9e5(_) => TexelFormat::Rgb9e5,
+            AnyHdrImage::R11g11b10f(_) => TexelFormat::R11g11b10f,
+            AnyHdrImage::Rgba16f(_) => TexelFormat::Rgba16f,
+            AnyHdrImage::Rgb32f(_) => TexelFormat::Rgb32f,
+            AnyHdrImage::Rgba32f(_) => TexelFormat::Rgba32f,
+        }
+    }
+
+    pub fn extent(&self) -> Extent2D {
+        match self {
+            AnyHdrImage::Rgbe8(image) => image.extent(),
+            AnyHdrImage::Rgb9e5(image) => image.extent(),
+            AnyHdrImage::R11g11b10f(image) => image.extent(),
+            AnyHdrImage::Rgba16f(image) => image.extent(),
+            AnyHdrImage::Rgb32f(image) => image.extent(),
+            AnyHdrImage::Rgba32f(image) => image.extent(),
+        }
+    }
+
+    /// Raw texel data in the stored format.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            AnyHdrImage::Rgbe8(image) => image.as_bytes(),
+            AnyHdrImage::Rgb9e5(image) => image.as_bytes(),
+            AnyHdrImage::R11g11b10f(image) => image.as_bytes(),
+            AnyHdrImage::Rgba16f(image) => image.as_bytes(),
+            AnyHdrImage::Rgb32f(image) => image.as_bytes(),
+            AnyHdrImage::Rgba32f(image) => image.as_bytes(),
+        }
+    }
+
+    /// Convert to full-precision [RGBA32F], which is lossless for all formats.
+    pub fn to_rgba32f(&self) -> HdrImage<RGBA32F> {
+        match self {
+            AnyHdrImage::Rgbe8(image) => convert_via_rgba32f(image),
+            AnyHdrImage::Rgb9e5(image) => convert_via_rgba32f(image),
+            AnyHdrImage::R11g11b10f(image) => convert_via_rgba32f(image),
+            AnyHdrImage::Rgba16f(image) => convert_via_rgba32f(image),
+            AnyHdrImage::Rgb32f(image) => convert_via_rgba32f(image),
+            AnyHdrImage::Rgba32f(image) => image.clone(),
+        }
+    }
+
+    /// Convert to the requested format. The image is copied unchanged if it is already in that format.
+    pub fn convert(&self, format: TexelFormat) -> AnyHdrImage {
+        if format == self.format() {
+            return self.clone();
+        }
+        let full = self.to_rgba32f();
+        match format {
+            TexelFormat::Rgbe8 => AnyHdrImage::Rgbe8(convert_via_rgba32f(&full)),
+            TexelFormat::Rgb9e5 => AnyHdrImage::Rgb9e5(convert_via_rgba32f(&full)),
+            TexelFormat::R11g11b10f => AnyHdrImage::R11g11b10f(convert_via_rgba32f(&full)),
+            TexelFormat::Rgba16f => AnyHdrImage::Rgba16f(convert_via_rgba32f(&full)),
+            TexelFormat::Rgb32f => AnyHdrImage::Rgb32f(convert_via_rgba32f(&full)),
+            TexelFormat::Rgba32f => AnyHdrImage::Rgba32f(full),
+        }
+    }
+
+    /// Convert to [RGBE8], such as for saving as a PNG.
+    pub fn into_rgbe8(self) -> HdrImage<RGBE8> {
+        match self.convert(TexelFormat::Rgbe8) {
+            AnyHdrImage::Rgbe8(image) => image,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Convert to [RGBE8] with [triangular_dither], which hides banding in smooth gradients from higher-precision formats.
+    /// The image is returned unchanged if it is already RGBE8.
+    pub fn into_rgbe8_dithered(self) -> HdrImage<RGBE8> {
+        self.into_rgbe8_dithered_with_seed(0)
+    }
+
+    /// Convert to [RGBE8] with the dither pattern of [triangular_dither_seeded] for `seed`.
+    /// The image is returned unchanged if it is already RGBE8.
+    pub fn into_rgbe8_dithered_with_seed(self, seed: u32) -> HdrImage<RGBE8> {
+        match self {
+            AnyHdrImage::Rgbe8(image) => image,
+            AnyHdrImage::Rgb9e5(image) => image.to_rgbe8_dithered_with_seed(seed),
+            AnyHdrImage::R11g11b10f(image) => image.to_rgbe8_dithered_with_seed(seed),
+            AnyHdrImage::Rgba16f(image) => image.to_rgbe8_dithered_with_seed(seed),
+            AnyHdrImage::Rgb32f(image) => image.to_rgbe8_dithered_with_seed(seed),
+            AnyHdrImage::Rgba32f(image) => image.to_rgbe8_dithered_with_seed(seed),
+        }
+    }
+
+    /// Convert to [RGB9E5] for use on the GPU.
+    pub fn into_rgb9e5(self) -> HdrImage<RGB9E5> {
+        match self.convert(TexelFormat::Rgb9e5) {
+            AnyHdrImage::Rgb9e5(image) => image,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Convert to [RGBA16F].
+    pub fn into_rgba16f(self) -> HdrImage<RGBA16F> {
+        match self.convert(TexelFormat::Rgba16f) {
+            AnyHdrImage::Rgba16f(image) => image,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl From<HdrImage<RGBE8>> for AnyHdrImage {
+    fn from(image: HdrImage<RGBE8>) -> Self {
+        AnyHdrImage::Rgbe8(image)
+    }
+}
+
+impl From<HdrImage<RGB9E5>> for AnyHdrImage {
+    fn from(image: HdrImage<RGB9E5>) -> Self {
+        AnyHdrImage::Rgb9e5(image)
+    }
+}
+
+impl From<HdrImage<R11G11B10F>> for AnyHdrImage {
+    fn from(image: HdrImage<R11G11B10F>) -> Self {
+        AnyHdrImage::R11g11b10f(image)
+    }
+}
+
+impl From<HdrImage<RGBA16F>> for AnyHdrImage {
+    fn from(image: HdrImage<RGBA16F>) -> Self {
+        AnyHdrImage::Rgba16f(image)
+    }
+}
+
+impl From<HdrImage<RGB32F>> for AnyHdrImage {
+    fn from(image: HdrImage<RGB32F>) -> Self {
+        AnyHdrImage::Rgb32f(image)
+    }
+}
+
+impl From<HdrImage<RGBA32F>> for AnyHdrImage {
+    fn from(image: HdrImage<RGBA32F>) -> Self {
+        AnyHdrImage::Rgba32f(image)
+    }
+}
+
+// Interop with the buffers of the image crate, so that its resizing and filtering operations can be used on HDR data.
+
+impl<T: RgbTexel> From<&HdrImage<T>> for Rgb32FImage {
+    fn from(image: &HdrImage<T>) -> Self {
+        let data = image.pixels().iter().flat_map(|texel| texel.to_rgb()).collect();
+        ImageBuffer::from_raw(image.width(), image.height(), data).unwrap()
+    }
+}
+
+impl<T: RgbTexel> From<HdrImage<T>> for Rgb32FImage {
+    fn from(image: HdrImage<T>) -> Self {
+        Rgb32FImage::from(&image)
+    }
+}
+
+impl<T: RgbTexel> From<HdrImage<T>> for DynamicImage {
+    fn from(image: HdrImage<T>) -> Self {
+        DynamicImage::ImageRgb32F(image.into())
+    }
+}
+
+/// Alpha is kept by converting to [Rgba32FImage].
+impl From<&AnyHdrImage> for DynamicImage {
+    fn from(image: &AnyHdrImage) -> Self {
+        let full = image.to_rgba32f();
+        let data = full.pixels().iter().flat_map(|texel| [texel.r, texel.g, texel.b, texel.a]).collect();
+        DynamicImage::ImageRgba32F(Rgba32FImage::from_raw(full.width(), full.height(), data).unwrap())
+    }
+}
+
+/// Fails if the image is empty.
+impl<T: Pod + From<[f32;3]>> TryFrom<&Rgb32FImage> for HdrImage<T> {
+    type Error = ImageError;
+
+    fn try_from(image: &Rgb32FImage) -> Result<Self, ImageError> {
+        let extent = image_extent(image.width(), image.height())?;
+        let data = image.pixels().map(|px| T::from(px.0)).collect();
+        Ok(HdrImage{extent, data})
+    }
+}
+
+/// Fails if the image is empty.
+impl<T: Pod + From<[f32;3]>> TryFrom<Rgb32FImage> for HdrImage<T> {
+    type Error = ImageError;
+
+    fn try_from(image: Rgb32FImage) -> Result<Self, ImageError> {
+        HdrImage::try_from(&image)
+    }
+}
+
+/// Images in other formats are converted to [Rgb32FImage] first, which discards alpha and scales 8 and 16-bit values to the range 0 to 1
+/// without converting them from sRGB. Fails if the image is empty.
+impl<T: Pod + From<[f32;3]>> TryFrom<DynamicImage> for HdrImage<T> {
+    type Error = ImageError;
+
+    fn try_from(image: DynamicImage) -> Result<Self, ImageError> {
+        HdrImage::try_from(&image.into_rgb32f())
+    }
+}
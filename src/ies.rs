@@ -0,0 +1,169 @@
+use crate::types::*;
+use crate::hdrimage::*;
+use crate::cookie::CookieProfile;
+
+use image::{error::{DecodingError, ImageFormatHint}, ImageError, ImageResult};
+use std::{fs, path::Path};
+
+fn ies_error(msg: &str) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormatHint::Name("IES".into()), msg.to_string()))
+}
+
+/// Luminous intensity distribution of a light fixture, read from an IES LM-63 photometric file.
+///
+/// Only type C photometry (used by almost all architectural fixtures) is supported, where vertical angles run from
+/// 0° (straight down, along the axis of the fixture) to 180° (straight up), and horizontal angles run around the axis.
+#[derive(PartialEq, Clone, Debug)]
+pub struct IesProfile {
+    vertical_angles: Vec<f32>,
+    horizontal_angles: Vec<f32>,
+    /// Intensities in candela for each horizontal angle, each containing a value for every vertical angle.
+    candela: Vec<f32>,
+}
+
+impl IesProfile {
+    /// Parses the text of an IES file in any of the LM-63 versions (1986, 1991, 1995, or 2002).
+    ///
+    /// Lamp tilt data is skipped, as it only matters for fixtures whose lamps are tilted away from their design orientation.
+    /// The candela values are scaled by the multiplier in the file, but not by the ballast factor.
+    pub fn parse(text: &str) -> ImageResult<Self> {
+        let mut lines = text.lines();
+        let tilt = lines.by_ref().map(str::trim).find(|line| line.starts_with("TILT="))
+            .ok_or_else(|| ies_error("missing TILT line"))?;
+        let mut tokens = lines.flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|token| !token.is_empty())
+            .collect::<Vec<_>>()
+            .into_iter();
+        // counts are checked against the remaining tokens before allocating, so that a corrupt count cannot exhaust memory
+        let mut read = |count: usize| {
+            if count > tokens.len() {
+                return Err(ies_error("file is truncated"));
+            }
+            tokens.by_ref().take(count)
+                .map(|token| token.parse::<f32>().map_err(|_| ies_error(&format!("invalid number '{token}'"))))
+                .collect::<ImageResult<Vec<f32>>>()
+        };
+        let as_count = |value: f32| if value >= 0.0 && value.fract() == 0.0 && value <= u32::MAX as f32 {
+            Ok(value as usize)
+        } else {
+            Err(ies_error(&format!("invalid count '{value}'")))
+        };
+        if tilt == "TILT=INCLUDE" {
+            // lamp-to-luminaire geometry, then the number of tilt angles, the angles, and the multiplying factors
+            let count = as_count(read(2)?[1])?;
+            read(count.checked_mul(2).ok_or_else(|| ies_error("too many tilt angles"))?)?;
+        }
+
+        // lamp count, lumens per lamp, candela multiplier, vertical and horizontal angle counts, photometric type,
+        // units, width, length, height, ballast factor, future use (ballast-lamp factor), and input watts
+        let header = read(13)?;
+        let multiplier = header[2];
+        let (vertical_count, horizontal_count) = (as_count(header[3])?, as_count(header[4])?);
+        if header[5] != 1.0 {
+            return Err(ies_error("only type C photometry is supported"));
+        }
+        if vertical_count == 0 || horizontal_count == 0 {
+            return Err(ies_error("profile has no angles"));
+        }
+        let candela_count = vertical_count.checked_mul(horizontal_count).ok_or_else(|| ies_error("too many angles"))?;
+        let vertical_angles = read(vertical_count)?;
+        let horizontal_angles = read(horizontal_count)?;
+        let candela = read(candela_count)?.into_iter().map(|cd| cd * multiplier).collect();
+        let increasing = |angles: &[f32]| angles.windows(2).all(|pair| pair[0] < pair[1]);
+        if !increasing(&vertical_angles) || !increasing(&horizontal_angles) {
+            return Err(ies_error("angles are not in increasing order"));
+        }
+        Ok(IesProfile{vertical_angles, horizontal_angles, candela})
+    }
+
+    /// Vertical angles of the measurements in degrees, from the downward axis of the fixture.
+    pub fn vertical_angles(&self) -> &[f32] {
+        &self.vertical_angles
+    }
+
+    /// Horizontal angles of the measurements in degrees, around the axis of the fixture.
+    pub fn horizontal_angles(&self) -> &[f32] {
+        &self.horizontal_angles
+    }
+
+    /// Largest measured intensity in candela.
+    pub fn max_candela(&self) -> f32 {
+        self.candela.iter().copied().fold(0.0, f32::max)
+    }
+
+    /// Interpolates the measurements for one horizontal angle (given as its index) at a vertical angle in degrees.
+    fn interpolate_vertical(&self, row: usize, vertical: f32) -> f32 {
+        let values = &self.candela[row * self.vertical_angles.len()..][..self.vertical_angles.len()];
+        let angles = &self.vertical_angles;
+        if vertical < angles[0] || vertical > angles[angles.len() - 1] {
+            return 0.0;
+        }
+        if angles.len() == 1 {
+            return values[0];
+        }
+        let i = angles.partition_point(|&a| a <= vertical).clamp(1, angles.len() - 1);
+        let t = (vertical - angles[i - 1]) / (angles[i] - angles[i - 1]);
+        values[i - 1] + (values[i] - values[i - 1]) * t
+    }
+
+    /// Intensity in candela in the direction at `vertical` degrees from the downward axis and `horizontal` degrees around it,
+    /// interpolated linearly between the measurements.
+    ///
+    /// Profiles with measurements for a single horizontal angle, or for only 0–90° or 0–180°, are assumed to be symmetric
+    /// as the IES format specifies. The intensity is 0 outside the range of vertical angles.
+    pub fn candela(&self, vertical: f32, horizontal: f32) -> f32 {
+        let angles = &self.horizontal_angles;
+        if angles.len() == 1 {
+            return self.interpolate_vertical(0, vertical);
+        }
+        let mut h = horizontal.rem_euclid(360.0);
+        let last = angles[angles.len() - 1];
+        if last <= 180.0 && h > 180.0 {
+            h = 360.0 - h;
+        }
+        if last <= 90.0 && h > 90.0 {
+            h = 180.0 - h;
+        }
+        let h = h.clamp(angles[0], last);
+        let i = angles.partition_point(|&a| a <= h).clamp(1, angles.len() - 1);
+        let t = (h - angles[i - 1]) / (angles[i] - angles[i - 1]);
+        let (a, b) = (self.interpolate_vertical(i - 1, vertical), self.interpolate_vertical(i, vertical));
+        a + (b - a) * t
+    }
+
+    /// A rotationally symmetric [CookieProfile] with the intensity at each vertical angle up to 90° averaged around the axis,
+    /// for generating spot light cookies with [light_cookie](crate::light_cookie).
+    pub fn to_cookie_profile(&self) -> CookieProfile {
+        let samples = self.vertical_angles.iter().filter(|&&v| v < 90.0).map(|&v| {
+            let average = (0..360).map(|h| self.candela(v, h as f32)).sum::<f32>() / 360.0;
+            (v.to_radians(), average)
+        }).collect();
+        CookieProfile::Angular(samples)
+    }
+}
+
+/// Loads an IES photometric file (see [IesProfile::parse]).
+pub fn load_ies_file(path: &Path) -> ImageResult<IesProfile> {
+    let bytes = fs::read(path).map_err(ImageError::IoError)?;
+    // older files are often in Latin-1 rather than UTF-8, which only matters for the keyword lines
+    IesProfile::parse(&String::from_utf8_lossy(&bytes))
+}
+
+/// Bakes an IES profile into a latitude-longitude texture of `width` by `width / 2` texels holding the intensity in candela
+/// (in every channel), for sampling by direction in shaders.
+///
+/// Rows run from straight down (the axis of the fixture, at the top of the texture) to straight up,
+/// and columns run around the axis from the 0° horizontal angle. Values can be normalized for storage
+/// by scaling them by the reciprocal of [max_candela](IesProfile::max_candela) with [mul_color](crate::mul_color).
+///
+/// Panics if `width` is less than 2.
+pub fn ies_to_equirect(profile: &IesProfile, width: u32) -> HdrImage<RGB32F> {
+    let extent = Extent2D::new(width, width / 2).expect("IES texture must be at least 2 texels wide");
+    let height = extent.height();
+    let data = (0..width * height).map(|i| {
+        let horizontal = ((i % width) as f32 + 0.5) / width as f32 * 360.0;
+        let vertical = ((i / width) as f32 + 0.5) / height as f32 * 180.0;
+        RGB32F::from([profile.candela(vertical, horizontal); 3])
+    }).collect();
+    HdrImage::new(extent, data)
+}
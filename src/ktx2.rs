@@ -0,0 +1,340 @@
+use crate::types::*;
+use crate::hdrimage::*;
+use crate::mipmap::*;
+use crate::ops::SoftClip;
+use crate::load::KTX2_IDENTIFIER;
+#[cfg(feature = "bc6h")]
+use crate::bc6h::*;
+
+use image::{error::{DecodingError, ImageFormatHint}, ImageError, ImageResult};
+use std::{fs::File, io::{BufWriter, Write}, path::Path};
+
+const VK_FORMAT_E5B9G9R9_UFLOAT_PACK32: u32 = 123;
+#[cfg(feature = "bc6h")]
+const VK_FORMAT_BC6H_UFLOAT_BLOCK: u32 = 143;
+
+// Sizes of the fixed-length parts of the file.
+const HEADER_LEN: usize = 12 + 9 * 4;
+const INDEX_LEN: usize = 4 * 4 + 2 * 8;
+const LEVEL_INDEX_LEN: usize = 3 * 8;
+
+const KHR_DF_PRIMARIES_BT709: u32 = 1;
+const KHR_DF_TRANSFER_LINEAR: u32 = 1;
+
+/// Builds the Khronos Data Format Descriptor for E5B9G9R9,
+/// matching the one produced by the reference `ktx` tools.
+fn rgb9e5_dfd() -> Vec<u32> {
+    const KHR_DF_MODEL_RGBSDA: u32 = 1;
+    const KHR_DF_SAMPLE_DATATYPE_EXPONENT: u32 = 0x20;
+
+    // (bit offset, bit length, channel | qualifiers, lower, upper)
+    let mut samples = Vec::new();
+    for (channel, offset) in [(0, 0), (1, 9), (2, 18)] {
+        samples.push((offset, 9, channel, 0, 8448));
+        samples.push((27, 5, channel | KHR_DF_SAMPLE_DATATYPE_EXPONENT, 15, 31));
+    }
+
+    let block_size = 24 + 16 * samples.len() as u32;
+    let mut dfd = vec![
+        4 + block_size, // dfdTotalSize
+        0, // vendorId = Khronos, descriptorType = basic
+        2 | (block_size << 16), // versionNumber = 1.3
+        KHR_DF_MODEL_RGBSDA | (KHR_DF_PRIMARIES_BT709 << 8) | (KHR_DF_TRANSFER_LINEAR << 16),
+        0, // texelBlockDimension
+        4, // bytesPlane0
+        0,
+    ];
+    for (offset, bits, channel, lower, upper) in samples {
+        dfd.extend_from_slice(&[offset | ((bits - 1) << 16) | (channel << 24), 0, lower, upper]);
+    }
+    dfd
+}
+
+/// Builds the Khronos Data Format Descriptor for unsigned BC6H.
+#[cfg(feature = "bc6h")]
+fn bc6h_dfd() -> Vec<u32> {
+    const KHR_DF_MODEL_BC6H: u32 = 131;
+    const KHR_DF_SAMPLE_DATATYPE_FLOAT: u32 = 0x80;
+    const FLOAT_ONE: u32 = 0x3F800000;
+
+    let block_size = 24 + 16;
+    vec![
+        4 + block_size, // dfdTotalSize
+        0, // vendorId = Khronos, descriptorType = basic
+        2 | (block_size << 16), // versionNumber = 1.3
+        KHR_DF_MODEL_BC6H | (KHR_DF_PRIMARIES_BT709 << 8) | (KHR_DF_TRANSFER_LINEAR << 16),
+        3 | (3 << 8), // texelBlockDimension = 4x4
+        16, // bytesPlane0
+        0,
+        // single sample covering the whole block
+        (127 << 16) | (KHR_DF_SAMPLE_DATATYPE_FLOAT << 24), 0, 0, FLOAT_ONE,
+    ]
+}
+
+/// Key of the entry recording the [SoftClip] applied to the texels, as the threshold and maximum separated by a space.
+const SOFT_CLIP_KEY: &str = "rgbeSoftClip";
+
+/// Builds the key/value data block from the writer entry and any extra entries (which must be sorted by key and come after it),
+/// with each entry padded to 4 bytes.
+fn key_value_data(extra: &[(&str, String)]) -> Vec<u8> {
+    let mut kvd = Vec::new();
+    let writer = concat!("rgbe-rs v", env!("CARGO_PKG_VERSION"));
+    for (key, value) in [("KTXwriter", writer)].into_iter().chain(extra.iter().map(|(key, value)| (*key, value.as_str()))) {
+        let entry_len = key.len() + 1 + value.len() + 1;
+        kvd.extend_from_slice(&(entry_len as u32).to_le_bytes());
+        kvd.extend_from_slice(key.as_bytes());
+        kvd.push(0);
+        kvd.extend_from_slice(value.as_bytes());
+        kvd.push(0);
+        kvd.resize(kvd.len().next_multiple_of(4), 0);
+    }
+    kvd
+}
+
+/// Finds the value of an entry in the key/value data of a KTX2 file, without its terminating NUL.
+fn find_key_value<'a>(bytes: &'a [u8], key: &str) -> Option<&'a [u8]> {
+    let u32_at = |offset: usize| Some(u32::from_le_bytes(bytes.get(offset..offset + 4)?.try_into().unwrap()) as usize);
+    let (offset, len) = (u32_at(56)?, u32_at(60)?);
+    let mut kvd = bytes.get(offset..offset.checked_add(len)?)?;
+    while kvd.len() >= 4 {
+        let entry_len = u32::from_le_bytes(kvd[..4].try_into().unwrap()) as usize;
+        let entry = kvd.get(4..4 + entry_len)?;
+        if let Some(value) = entry.strip_prefix(key.as_bytes()).and_then(|rest| rest.strip_prefix(b"\0")) {
+            return Some(value.strip_suffix(b"\0").unwrap_or(value));
+        }
+        kvd = kvd.get((4 + entry_len).next_multiple_of(4)..).unwrap_or_default();
+    }
+    None
+}
+
+/// Format-dependent fields of a KTX2 file.
+struct Ktx2Format {
+    vk_format: u32,
+    type_size: u32,
+    /// Size of a texel (or compressed block), which determines the alignment of the image data.
+    block_bytes: usize,
+    dfd: Vec<u32>,
+}
+
+/// Writes a KTX2 file containing a 2D image and its mip levels with no supercompression.
+///
+/// `data` holds the levels smallest first (as they are stored in the file) and `levels` gives their locations in it, largest first.
+fn write_ktx2<W: Write>(mut out: W, format: &Ktx2Format, extent: Extent2D, data: &[u8], levels: &[MipLevelRange], metadata: &[(&str, String)]) -> ImageResult<()> {
+    let dfd = &format.dfd;
+    let kvd = key_value_data(metadata);
+    let dfd_offset = HEADER_LEN + INDEX_LEN + levels.len() * LEVEL_INDEX_LEN;
+    let kvd_offset = dfd_offset + dfd.len() * 4;
+    // level data must be aligned to both the block size and 4 bytes (all supported block sizes are powers of 2).
+    // Levels are a whole number of blocks, so only the start of the data needs padding.
+    let alignment = format.block_bytes.max(4);
+    let data_offset = (kvd_offset + kvd.len()).next_multiple_of(alignment);
+
+    let mut header = Vec::with_capacity(data_offset);
+    header.extend_from_slice(&KTX2_IDENTIFIER);
+    for field in [format.vk_format, format.type_size, extent.width(), extent.height(), 0, 0, 1, levels.len() as u32, 0] {
+        // vkFormat, typeSize, pixelWidth, pixelHeight, pixelDepth, layerCount, faceCount, levelCount, supercompressionScheme
+        header.extend_from_slice(&field.to_le_bytes());
+    }
+    for field in [dfd_offset, dfd.len() * 4, kvd_offset, kvd.len()] {
+        header.extend_from_slice(&(field as u32).to_le_bytes());
+    }
+    // no supercompression global data
+    header.extend_from_slice(&0u64.to_le_bytes());
+    header.extend_from_slice(&0u64.to_le_bytes());
+    // level index: byteOffset, byteLength, uncompressedByteLength
+    for level in levels {
+        for field in [data_offset + level.offset, level.len, level.len] {
+            header.extend_from_slice(&(field as u64).to_le_bytes());
+        }
+    }
+    for &word in dfd {
+        header.extend_from_slice(&word.to_le_bytes());
+    }
+    header.extend_from_slice(&kvd);
+    header.resize(data_offset, 0);
+
+    out.write_all(&header).map_err(ImageError::IoError)?;
+    out.write_all(data).map_err(ImageError::IoError)?;
+    out.flush().map_err(ImageError::IoError)?;
+    Ok(())
+}
+
+/// Level index for a file containing a single level.
+fn single_level(data: &[u8]) -> [MipLevelRange; 1] {
+    [MipLevelRange{offset: 0, len: data.len()}]
+}
+
+fn rgb9e5_format() -> Ktx2Format {
+    Ktx2Format{vk_format: VK_FORMAT_E5B9G9R9_UFLOAT_PACK32, type_size: 4, block_bytes: 4, dfd: rgb9e5_dfd()}
+}
+
+/// Encodes [RGB9E5] texel data as a KTX2 texture with format `VK_FORMAT_E5B9G9R9_UFLOAT_PACK32`.
+///
+/// The output contains a single 2D image with no mipmaps or supercompression,
+/// and can be uploaded directly by Vulkan or wgpu texture loaders.
+pub fn encode_rgb9e5_ktx2<W: Write>(extent: Extent2D, data: &[RGB9E5], out: W) -> ImageResult<()> {
+    assert_eq!(data.len(), extent.texel_count(), "data does not match image dimensions");
+    let level: Vec<u8> = data.iter().flat_map(|texel| texel.0.to_le_bytes()).collect();
+    write_ktx2(out, &rgb9e5_format(), extent, &level, &single_level(&level), &[])
+}
+
+/// Encodes [RGB9E5] texel data as [encode_rgb9e5_ktx2] does, recording the [SoftClip] which was applied to the texels
+/// before packing in an `rgbeSoftClip` key/value entry, so that loaders can undo it (see [ktx2_soft_clip]).
+pub fn encode_rgb9e5_ktx2_with_soft_clip<W: Write>(extent: Extent2D, data: &[RGB9E5], clip: SoftClip, out: W) -> ImageResult<()> {
+    assert_eq!(data.len(), extent.texel_count(), "data does not match image dimensions");
+    let level: Vec<u8> = data.iter().flat_map(|texel| texel.0.to_le_bytes()).collect();
+    let metadata = [(SOFT_CLIP_KEY, format!("{} {}", clip.threshold, clip.max))];
+    write_ktx2(out, &rgb9e5_format(), extent, &level, &single_level(&level), &metadata)
+}
+
+/// Reads the [SoftClip] recorded in a KTX2 file by [encode_rgb9e5_ktx2_with_soft_clip], if there is one.
+pub fn ktx2_soft_clip(bytes: &[u8]) -> Option<SoftClip> {
+    let value = std::str::from_utf8(find_key_value(bytes, SOFT_CLIP_KEY)?).ok()?;
+    let (threshold, max) = value.split_once(' ')?;
+    Some(SoftClip{threshold: threshold.parse().ok()?, max: max.parse().ok()?})
+}
+
+/// Saves [RGB9E5] texel data as a KTX2 file with format `VK_FORMAT_E5B9G9R9_UFLOAT_PACK32`.
+/// This allows textures to be loaded onto the GPU without any conversion at runtime.
+pub fn save_rgb9e5_ktx2(path: &Path, extent: Extent2D, data: &[RGB9E5]) -> ImageResult<()> {
+    let file = File::create(path).map_err(ImageError::IoError)?;
+    encode_rgb9e5_ktx2(extent, data, BufWriter::new(file))
+}
+
+/// Encodes an [RGB9E5] mip chain (as produced by [pack_mipmaps](crate::pack_mipmaps)) as a KTX2 texture
+/// with format `VK_FORMAT_E5B9G9R9_UFLOAT_PACK32`.
+pub fn encode_rgb9e5_ktx2_mipmaps<W: Write>(mips: &PackedMipChain, out: W) -> ImageResult<()> {
+    // texels are packed in native byte order
+    let data: Vec<u8> = mips.data.chunks_exact(4).flat_map(|c| u32::from_ne_bytes(c.try_into().unwrap()).to_le_bytes()).collect();
+    write_ktx2(out, &rgb9e5_format(), mips.extent, &data, &mips.levels, &[])
+}
+
+/// Saves a full [RGB9E5] mip chain for an image as a KTX2 file, generating the levels with [generate_mipmaps](crate::generate_mipmaps).
+pub fn save_rgb9e5_ktx2_mipmaps(path: &Path, image: &HdrImage<RGB9E5>) -> ImageResult<()> {
+    let mips = pack_mipmaps(&generate_mipmaps(image));
+    let file = File::create(path).map_err(ImageError::IoError)?;
+    encode_rgb9e5_ktx2_mipmaps(&mips, BufWriter::new(file))
+}
+
+fn ktx2_error(msg: &str) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormatHint::Name("KTX2".into()), msg))
+}
+
+/// Decodes the base level of a KTX2 texture with format `VK_FORMAT_E5B9G9R9_UFLOAT_PACK32`,
+/// such as one written by [encode_rgb9e5_ktx2]. Other mip levels, array layers, and cubemap faces are ignored.
+/// Supercompressed textures are not supported.
+pub fn decode_rgb9e5_ktx2(bytes: &[u8]) -> ImageResult<HdrImage<RGB9E5>> {
+    if bytes.len() < HEADER_LEN + INDEX_LEN + LEVEL_INDEX_LEN || !bytes.starts_with(&KTX2_IDENTIFIER) {
+        return Err(ktx2_error("not a KTX2 file"));
+    }
+    let u32_at = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+    let u64_at = |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+    if u32_at(12) != VK_FORMAT_E5B9G9R9_UFLOAT_PACK32 {
+        return Err(ktx2_error("texture format is not E5B9G9R9_UFLOAT_PACK32"));
+    }
+    if u32_at(44) != 0 {
+        return Err(ktx2_error("supercompressed textures are not supported"));
+    }
+    let extent = crate::load::image_extent(u32_at(20), u32_at(24).max(1))?;
+    // the level index follows the header and index, starting with the base level
+    let level_index = HEADER_LEN + INDEX_LEN;
+    let offset = usize::try_from(u64_at(level_index)).map_err(|_| ktx2_error("level offset out of range"))?;
+    let len = extent.texel_count() * 4;
+    let level = offset.checked_add(len).and_then(|end| bytes.get(offset..end))
+        .ok_or_else(|| ktx2_error("file is truncated"))?;
+    let data = level.chunks_exact(4).map(|c| RGB9E5(u32::from_le_bytes(c.try_into().unwrap()))).collect();
+    Ok(HdrImage::new(extent, data))
+}
+
+/// Loads the base level of an [RGB9E5] KTX2 file, as described in [decode_rgb9e5_ktx2].
+pub fn load_rgb9e5_ktx2_file(path: &Path) -> ImageResult<HdrImage<RGB9E5>> {
+    let bytes = std::fs::read(path).map_err(ImageError::IoError)?;
+    decode_rgb9e5_ktx2(&bytes)
+}
+
+/// Encodes BC6H blocks (as produced by [encode_bc6h](crate::encode_bc6h)) as a KTX2 texture with format `VK_FORMAT_BC6H_UFLOAT_BLOCK`.
+#[cfg(feature = "bc6h")]
+pub fn encode_bc6h_ktx2<W: Write>(extent: Extent2D, blocks: &[BC6HBlock], out: W) -> ImageResult<()> {
+    assert_eq!(blocks.len(), bc6h_block_count(extent), "block count does not match image dimensions");
+    let format = Ktx2Format{vk_format: VK_FORMAT_BC6H_UFLOAT_BLOCK, type_size: 1, block_bytes: 16, dfd: bc6h_dfd()};
+    let data = bytemuck::cast_slice(blocks);
+    write_ktx2(out, &format, extent, data, &single_level(data), &[])
+}
+
+/// Saves BC6H blocks as a KTX2 file with format `VK_FORMAT_BC6H_UFLOAT_BLOCK`.
+#[cfg(feature = "bc6h")]
+pub fn save_bc6h_ktx2(path: &Path, extent: Extent2D, blocks: &[BC6HBlock]) -> ImageResult<()> {
+    let file = File::create(path).map_err(ImageError::IoError)?;
+    encode_bc6h_ktx2(extent, blocks, BufWriter::new(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn u64_at(bytes: &[u8], offset: usize) -> usize {
+        u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap()) as usize
+    }
+
+    fn test_image(width: u32, height: u32) -> HdrImage<RGB9E5> {
+        let extent = Extent2D::new(width, height).unwrap();
+        let data = (0..extent.texel_count()).map(|i| RGB9E5::pack([i as f32, 0.5, 1.0 / (i + 1) as f32])).collect();
+        HdrImage::new(extent, data)
+    }
+
+    #[test]
+    fn rgb9e5_round_trip() {
+        let image = test_image(5, 3);
+        let mut bytes = Vec::new();
+        encode_rgb9e5_ktx2(image.extent(), image.pixels(), &mut bytes).unwrap();
+        assert_eq!(decode_rgb9e5_ktx2(&bytes).unwrap(), image);
+        assert_eq!(ktx2_soft_clip(&bytes), None);
+
+        let clip = SoftClip{threshold: 2.0, max: 8.0};
+        let mut bytes = Vec::new();
+        encode_rgb9e5_ktx2_with_soft_clip(image.extent(), image.pixels(), clip, &mut bytes).unwrap();
+        assert_eq!(decode_rgb9e5_ktx2(&bytes).unwrap(), image);
+        assert_eq!(ktx2_soft_clip(&bytes), Some(clip));
+
+        assert!(decode_rgb9e5_ktx2(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn rgb9e5_mip_chain() {
+        let levels = generate_mipmaps(&test_image(5, 3));
+        assert_eq!(levels.len(), 3);
+        let mut bytes = Vec::new();
+        encode_rgb9e5_ktx2_mipmaps(&pack_mipmaps(&levels), &mut bytes).unwrap();
+
+        assert_eq!((u32_at(&bytes, 20), u32_at(&bytes, 24), u32_at(&bytes, 40)), (5, 3, 3));
+        let mut previous_offset = bytes.len();
+        for (i, level) in levels.iter().enumerate() {
+            let entry = HEADER_LEN + INDEX_LEN + i * LEVEL_INDEX_LEN;
+            let (offset, len) = (u64_at(&bytes, entry), u64_at(&bytes, entry + 8));
+            // levels are stored smallest first, aligned to the texel size
+            assert!(offset + len <= previous_offset && offset % 4 == 0, "level {i} at {offset}");
+            previous_offset = offset;
+            let texels: Vec<RGB9E5> = bytes[offset..offset + len].chunks_exact(4).map(|c| RGB9E5(u32::from_le_bytes(c.try_into().unwrap()))).collect();
+            assert_eq!(texels, level.pixels(), "level {i}");
+        }
+        assert_eq!(decode_rgb9e5_ktx2(&bytes).unwrap(), levels[0]);
+    }
+
+    #[cfg(feature = "bc6h")]
+    #[test]
+    fn bc6h_blocks() {
+        let extent = Extent2D::new(6, 6).unwrap();
+        let blocks: Vec<BC6HBlock> = (0..4).map(|i| BC6HBlock([i as u8; 16])).collect();
+        let mut bytes = Vec::new();
+        encode_bc6h_ktx2(extent, &blocks, &mut bytes).unwrap();
+        assert_eq!((u32_at(&bytes, 12), u32_at(&bytes, 16), u32_at(&bytes, 20), u32_at(&bytes, 24)), (VK_FORMAT_BC6H_UFLOAT_BLOCK, 1, 6, 6));
+        let entry = HEADER_LEN + INDEX_LEN;
+        let (offset, len) = (u64_at(&bytes, entry), u64_at(&bytes, entry + 8));
+        assert_eq!(offset % 16, 0);
+        assert_eq!(&bytes[offset..offset + len], bytemuck::cast_slice::<BC6HBlock, u8>(&blocks));
+    }
+}
@@ -5,14 +5,261 @@
 //! Supports the [RGBE8] format which is storable in Radiance HDR and PNG files,
 //! as well as the [RGB9E5] GPU texture format.
 //!
+//! Banding in smooth gradients can be avoided by dithering when packing RGBE8, with [RGBE8::pack_dithered] and [triangular_dither]
+//! or in bulk with `HdrImage::to_rgbe8_dithered`.
+//! Stochastic operations such as dithering and prefiltering take an explicit seed and draw from the deterministic [SeededRng],
+//! so that bakes are reproducible.
+//! Texels can be converted between formats lazily with [iter_rgb9e5] and other [iter_converted] adapters,
+//! or into an existing buffer with [convert_into], to avoid holding a second copy of large textures.
+//! Packing clamps infinities and zeroes NaN channels, while `try_pack` can instead reject them
+//! (or black out the texel) according to a [NonFinitePolicy], so that fireflies in renders do not slip into assets unnoticed.
+//!
 //! An intended use case for this library is to store HDR textures as RGBE8 PNG files
-//! and convert them to RGB9E5 for the GPU when loading.
+//! and convert them to RGB9E5 for the GPU when loading,
+//! either one at a time or as the layers of an [ArrayTexture] with [load_rgb9e5_array_texture].
+//! These PNGs are tagged with a private `rgBE` chunk (see [png_rgbe_exponent_bias]) so that they can be told apart from ordinary RGBA images,
+//! which [load_png_auto] uses to load either kind of PNG correctly,
+//! and can carry a checksum of their texels with [encode_rgbe8_png_with_checksum], which the loaders verify to catch corrupted files.
+//! Converted textures can be cached in a minimal raw container with [save_raw] and [load_raw]
+//! (or [save_rgba16f_raw] and [load_rgba16f_raw] for working images passed between pipeline stages).
+//! Interleaved RGBA f32 data such as render target readbacks can be converted to and from [RGBA16F] texels in bulk
+//! with [RGBA16F::slice_from_f32] and [RGBA16F::slice_to_f32].
+//! For archival, [split_rgb9e5_residual] packs [RGBA16F] images into RGB9E5 along with a residual (saved with [save_residual_png])
+//! from which [reconstruct_rgba16f] restores the original texels exactly.
+//! Engines with their own allocators or frame arenas can decode and convert straight into them with [decode_radiance_in],
+//! [decode_rgbe8_png_in], and [convert_in], which take any [TexelAllocator] (such as a [TexelArena] over a borrowed block of memory).
+//! Long batch conversions can record their progress in a [BatchCheckpoint] (or be driven by [run_checkpointed_batch])
+//! so that an interrupted run resumes where it left off (also available as `hdr2rgbe-png --checkpoint`).
+//!
+//! With the `ktx2` feature, RGB9E5 textures can also be saved as KTX2 files
+//! which can be uploaded to the GPU directly, and with the `dds` feature,
+//! RGB9E5 and RGBA16F textures can be saved as DDS files for use with DirectX tooling.
+//! The `bc6h` feature adds a BC6H compressor and decompressor for block-compressed HDR textures.
+//! The `exr` feature adds a half-float OpenEXR writer for inspecting images in compositing tools.
+//! The `wgpu` feature adds helpers for creating RGB9E5 textures and uploading texel data to them with wgpu
+//! (and `decode_rgb9e5_on_gpu` for checking that the GPU decodes them like [RGB9E5::to_rgb] does),
+//! and the `bevy` feature adds `RgbePlugin`, which loads `.rgbe.png` files as RGB9E5 `Image` assets in Bevy.
+//! The `serde` feature serializes [RGBE8], [RGB9E5], and [RGBA16F] texels as their packed bits, for storing colours in config files.
+//! The `mmap` feature adds `MappedRgb9e5`, which exposes raw RGB9E5 files as texel slices without copying them.
+//! The `ndarray` feature adds `HdrImage::to_ndarray` and `HdrImage::from_ndarray` for analysis in the ndarray ecosystem.
+//! The `bench` feature adds `bench_conversions` (and `bench_parallel` with `rayon`), which measure texel conversion throughput
+//! on the current machine so that pipelines can choose chunk sizes and thread counts at startup.
+//!
+//! Cubemaps authored as horizontal or vertical crosses can be split into an [HdrCubemap] of six faces (and back) with [CrossLayout],
+//! and equirectangular panoramas can be projected onto cubemaps with [equirect_to_cubemap].
+//! Cubemap convolutions can weight texels by their exact solid angle using [CubemapWeighting],
+//! spherical harmonics for diffuse lighting can be extracted from cubemaps and panoramas with [irradiance_sh_from_cubemap] and [irradiance_sh_from_equirect],
+//! specular mip chains can be prefiltered for increasing roughness with [prefilter_ggx] (and packed for uploading with [pack_cubemap_mipmaps]),
+//! and the mip chains of many probes can be packed into a single texture with [pack_probe_atlas].
+//! Many small textures in any texel format can be packed into one atlas with [pack_texture_atlas] (or `rgbe atlas`), which returns the placement of each.
+//! Light cookies for spot lights can be generated from a smooth falloff or a table of intensities by angle with [light_cookie] (or `rgbe cookie`).
+//! Photometric data for real light fixtures can be read from IES files with [load_ies_file], turned into a cookie profile
+//! with [IesProfile::to_cookie_profile], or baked into a latitude-longitude texture of intensities with [ies_to_equirect].
+//!
+//! HDR photos from phones with a gain map embedded in a JPEG (as in Android Ultra HDR) can be split with [split_gain_map_jpeg],
+//! and once both images are decoded, [apply_gain_map] reconstructs linear HDR colours for use as light sources.
+//! The binary ISO 21496-1 metadata is read from JPEGs, and from the `tmap` items of AVIF and HEIF files with [GainMapMetadata::from_tmap].
 //!
+//! Images can be resized with [resize] using any [ResampleKernel],
+//! including user-supplied kernels.
+//! Full mip chains can be generated in linear light with [generate_mipmaps] (using a selectable downsampling [Filter]) and packed into a single buffer with [pack_mipmaps]
+//! (which the `ktx2` feature can save as a mipmapped KTX2 file).
+//! Simple adjustments such as [mul_color] work directly on any [RgbTexel] format,
+//! and [RGBE8] and [RGB9E5] texels can be brightened or darkened by whole stops without loss using `scale_exposure`.
+//! Highlights too bright for RGB9E5 can be compressed with a [SoftClip] shoulder by [soft_clip] instead of clipping,
+//! and the `ktx2` feature can record its parameters with `encode_rgb9e5_ktx2_with_soft_clip` so that loaders can undo it.
+//! Linear colours can be converted between Rec.709, Rec.2020, Display P3, and the ACES AP0 and ACEScg (AP1) [Primaries] with [convert_primaries],
+//! so ACEScg renders can be stored as RGBE and brought back without external colour management tools
+//! (also available as `hdr2rgbe-png --primaries acescg:rec709`), and other colour matrices can be applied with [apply_color_matrix].
+//! Colours outside the target gamut can be clipped or smoothly desaturated with [convert_primaries_with_gamut_mapping],
+//! such as for baking Rec.709 sources into Rec.2020 textures for engines rendering in wide gamut.
+//! Pipelines which name colour spaces as in the ACES and CG OpenColorIO configs can look up the primaries of their scene-linear spaces
+//! with [primaries_by_config_name] or parse a [NamedPrimariesTransform] such as `ACEScg -> Linear Rec.709 (sRGB)` (without using OpenColorIO itself).
+//! Light probes captured under coloured light can be neutralized with [white_balance], a Bradford or CAT02 [ChromaticAdaptation]
+//! between [WhitePoint]s given as chromaticities or colour temperatures (also available as `hdr2rgbe-png --white-balance 3200K`).
+//! Texels can be split into separate channel buffers with [RgbChannels] for SIMD-friendly processing
+//! (or a single channel-first buffer with [RgbChannels::to_planar]), or into existing buffers with [deinterleave_rgb] and back with [interleave_rgb].
+//! Mostly-black images such as lightmaps can be stored compactly as a [SparseImage],
+//! and texels can be reordered into Morton or tiled layouts for platforms which expect them with [SwizzledImage].
+//! With the `rayon` feature, custom per-texel adjustments can be run in parallel with `HdrImage::par_map_pixels`,
+//! using a limited number of threads within `with_thread_count`.
+//! An [HdrImage] can also be converted to and from `image::Rgb32FImage` and `DynamicImage`
+//! to use the filters of the `image` crate.
+//! Individual texels can be read by coordinates with [HdrImage::get] and [HdrImage::get_unpacked], or filtered with [HdrImage::sample].
+//! Images in any texel format can be cropped, flipped, and rotated by multiples of 90 degrees without unpacking, with [HdrImage::crop],
+//! [HdrImage::flip_x], [HdrImage::flip_y], [HdrImage::rotate90], [HdrImage::rotate180], and [HdrImage::rotate270].
+//!
+//! The metadata in the headers of Radiance files (such as their exposure and primaries) can be read with [load_radiance_file_with_metadata]
+//! and written back out with [RadianceSaveOptions], and files with an `EXPOSURE` can be loaded in absolute units with [load_radiance_file_absolute].
+//! Files from legacy tools which use another exponent bias or centred mantissas can be read with [load_radiance_file_with_convention]
+//! and an [RgbeConvention], which can also be used to pack and unpack individual texels.
+//! The dimensions and [FormatKind] of Radiance, PNG, KTX2, and DDS files can be read from their headers alone with [peek_dimensions].
 //! Radiance HDR images can be converted to RGBE8 PNG files on the command line using
-//! the included `hdr2rgbe-png` tool.
+//! the included `hdr2rgbe-png` tool, and contact sheets of HDR images can be generated with [contact_sheet]
+//! or the `rgbe contactsheet` command. An [ev_strip] (or `rgbe evstrip`) shows a single image at several exposures.
+//! Debug and preview images can be labelled using the built-in bitmap font with [draw_text].
+//! Previews can be tone mapped with [tonemap_srgb8] (or Reinhard, ACES, or AgX with [tonemap_srgb8_with_operator]), and exposure can be checked with a [false_color] map of luminance by stops
+//! (also available as `rgbe tonemap --false-color`).
+//! Previews can target wide-gamut and HDR displays as well as sRGB with a [DisplayTarget],
+//! including 10-bit Rec.2100 PQ buffers from [tonemap_rgb10a2] for HDR swapchains and 16-bit PNGs (`rgbe tonemap --target pq`).
+//! Linear images can also be exchanged with HDR video tools without tone mapping, as 10 or 16-bit Rec.2020 PQ or HLG signals,
+//! using [encode_hdr_signal] and [decode_hdr_signal] with an [HdrTransfer] (and [load_rgb16_png_file] to read 16-bit PNGs).
+//! A [Zebra] overlay marks pixels which would clip when packed as [RGB9E5].
+//! The loss from packing can be measured with [error_metrics], which reports relative errors and log-domain PSNR
+//! between images in any formats, for choosing which format each kind of asset can be stored in.
+//!
+//! The `std` feature is enabled by default. Without it, only the texel types and their conversions are available,
+//! for use in `no_std` environments.
+
+#![cfg_attr(not(feature = "std"), no_std)]
 
 mod types;
+mod colorspace;
+mod convert;
+// the test harness links std, which provides these as inherent methods
+#[cfg(not(any(feature = "std", test)))]
+mod float;
+#[cfg(feature = "std")]
+mod hdrimage;
+#[cfg(feature = "std")]
 mod load;
+#[cfg(feature = "std")]
+mod radiance;
+#[cfg(feature = "std")]
+mod raw;
+#[cfg(feature = "std")]
+mod resample;
+#[cfg(feature = "std")]
+mod mipmap;
+#[cfg(feature = "std")]
+mod ops;
+#[cfg(feature = "std")]
+mod soa;
+#[cfg(feature = "std")]
+mod sparse;
+#[cfg(feature = "std")]
+mod swizzle;
+#[cfg(feature = "std")]
+mod accum;
+#[cfg(feature = "std")]
+mod cubemap;
+#[cfg(feature = "std")]
+mod sh;
+#[cfg(feature = "std")]
+mod prefilter;
+#[cfg(feature = "std")]
+mod atlas;
+#[cfg(feature = "std")]
+mod gainmap;
+#[cfg(feature = "std")]
+mod cookie;
+#[cfg(feature = "std")]
+mod ies;
+#[cfg(feature = "std")]
+mod font;
+#[cfg(feature = "std")]
+mod contactsheet;
+#[cfg(feature = "std")]
+mod tonemap;
+#[cfg(feature = "std")]
+mod metrics;
+#[cfg(feature = "std")]
+mod residual;
+#[cfg(feature = "std")]
+mod texel_alloc;
+#[cfg(feature = "std")]
+mod named_primaries;
+#[cfg(feature = "std")]
+mod checkpoint;
+#[cfg(feature = "ktx2")]
+mod ktx2;
+#[cfg(feature = "dds")]
+mod dds;
+#[cfg(feature = "bc6h")]
+mod bc6h;
+#[cfg(feature = "exr")]
+mod exr;
+#[cfg(feature = "wgpu")]
+mod wgpu_upload;
+#[cfg(feature = "bevy")]
+mod bevy_loader;
+#[cfg(feature = "serde")]
+mod serde_impls;
+#[cfg(feature = "ndarray")]
+mod ndarray_interop;
+#[cfg(feature = "mmap")]
+mod mmap;
+#[cfg(feature = "bench")]
+mod bench;
 
 pub use crate::types::*;
-pub use crate::load::*;
\ No newline at end of file
+pub use crate::colorspace::*;
+pub use crate::convert::*;
+#[cfg(feature = "std")]
+pub use crate::hdrimage::*;
+#[cfg(feature = "std")]
+pub use crate::load::*;
+#[cfg(feature = "std")]
+pub use crate::radiance::*;
+#[cfg(feature = "std")]
+pub use crate::raw::*;
+#[cfg(feature = "std")]
+pub use crate::resample::*;
+#[cfg(feature = "std")]
+pub use crate::mipmap::*;
+#[cfg(feature = "std")]
+pub use crate::ops::*;
+#[cfg(feature = "std")]
+pub use crate::soa::*;
+#[cfg(feature = "std")]
+pub use crate::sparse::*;
+#[cfg(feature = "std")]
+pub use crate::swizzle::*;
+#[cfg(feature = "std")]
+pub use crate::accum::*;
+#[cfg(feature = "std")]
+pub use crate::cubemap::*;
+#[cfg(feature = "std")]
+pub use crate::sh::*;
+#[cfg(feature = "std")]
+pub use crate::prefilter::*;
+#[cfg(feature = "std")]
+pub use crate::atlas::*;
+#[cfg(feature = "std")]
+pub use crate::gainmap::*;
+#[cfg(feature = "std")]
+pub use crate::cookie::*;
+#[cfg(feature = "std")]
+pub use crate::ies::*;
+#[cfg(feature = "std")]
+pub use crate::font::*;
+#[cfg(feature = "std")]
+pub use crate::contactsheet::*;
+#[cfg(feature = "std")]
+pub use crate::tonemap::*;
+#[cfg(feature = "std")]
+pub use crate::metrics::*;
+#[cfg(feature = "std")]
+pub use crate::residual::*;
+#[cfg(feature = "std")]
+pub use crate::texel_alloc::*;
+#[cfg(feature = "std")]
+pub use crate::named_primaries::*;
+#[cfg(feature = "std")]
+pub use crate::checkpoint::*;
+#[cfg(feature = "ktx2")]
+pub use crate::ktx2::*;
+#[cfg(feature = "dds")]
+pub use crate::dds::*;
+#[cfg(feature = "bc6h")]
+pub use crate::bc6h::*;
+#[cfg(feature = "exr")]
+pub use crate::exr::*;
+#[cfg(feature = "wgpu")]
+pub use crate::wgpu_upload::*;
+#[cfg(feature = "bevy")]
+pub use crate::bevy_loader::*;
+#[cfg(feature = "mmap")]
+pub use crate::mmap::*;
+#[cfg(feature = "bench")]
+pub use crate::bench::*;
\ No newline at end of file
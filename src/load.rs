@@ -1,93 +1,657 @@
 use crate::types::*;
+use crate::colorspace::*;
+use crate::hdrimage::*;
+use crate::radiance::RadianceMetadata;
+use crate::texel_alloc::*;
+use crate::tonemap::srgb_to_linear;
 
-use image::{codecs::{hdr::HdrDecoder, png::{self, PngDecoder, PngEncoder}}, ImageDecoder, ImageEncoder, ImageError, ImageResult};
-use std::{fs::File, io::{BufRead, BufReader, Read, Write}, path::Path};
+use image::{codecs::{hdr::HdrDecoder, png::{self, PngDecoder, PngEncoder}}, error::{DecodingError, ImageFormatHint, LimitError, LimitErrorKind, UnsupportedError, UnsupportedErrorKind}, ImageDecoder, ImageEncoder, ImageError, ImageResult};
+use std::{fs::{self, File}, io::{BufRead, BufReader, BufWriter, Cursor, Read, Write}, path::Path};
 
 /// Reads the data from an [HdrDecoder] as a slice of [RGBE8] texels.
 pub fn decode_radiance<R:BufRead>(dec: HdrDecoder<R>) -> ImageResult<Box<[RGBE8]>> {
-    let meta = dec.metadata();
-    let size = (meta.width * meta.height) as usize;
-    let mut out = bytemuck::allocation::zeroed_slice_box::<RGBE8>(size);
-    dec.read_image_transform(|px| {
-        RGBE8{r: px.c[0], g: px.c[1], b: px.c[2], e: px.e}
-    }, &mut out)?;
-    Ok(out)
+    decode_radiance_in(dec, HeapAllocator)
 }
 
 /// Reads the and converts data from an [HdrDecoder] as a slice of RGB9E5 texels.
 pub fn decode_radiance_as_rgb9e5<R:BufRead>(dec: HdrDecoder<R>) -> ImageResult<Box<[RGB9E5]>> {
     let meta = dec.metadata();
-    let size = (meta.width * meta.height) as usize;
-    let mut out = bytemuck::allocation::zeroed_slice_box::<RGB9E5>(size);
+    let extent = image_extent(meta.width, meta.height)?;
+    let mut out = bytemuck::allocation::zeroed_slice_box::<RGB9E5>(extent.texel_count());
     dec.read_image_transform(|px| {
         RGBE8{r: px.c[0], g: px.c[1], b: px.c[2], e: px.e}.repack_rgb9e5()
     }, &mut out)?;
     Ok(out)
 }
 
+/// Validates the dimensions of a decoded image.
+pub(crate) fn image_extent(width: u32, height: u32) -> ImageResult<Extent2D> {
+    Extent2D::new(width, height).ok_or_else(|| ImageError::Limits(LimitError::from_kind(LimitErrorKind::DimensionError)))
+}
 
-/// Loads a radiance file, returning the dimensions and a slice of [RGBE8] texel data.
-pub fn load_radiance_file(path: &Path) -> ImageResult<(u32, u32, Box<[RGBE8]>)> {
-    let file = File::open(path).map_err(ImageError::IoError)?;
-    let decoder = HdrDecoder::new(BufReader::new(file))?;
+/// Reads the header of a Radiance file, returning it (with CRLF line endings replaced and any XYZE format line rewritten to RGBE so that [HdrDecoder] accepts it)
+/// and whether the texel data is in XYZ.
+fn read_radiance_header<R: BufRead>(reader: &mut R) -> ImageResult<(Vec<u8>, bool)> {
+    let mut header = Vec::new();
+    let mut is_xyze = false;
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let len = reader.read_until(b'\n', &mut line).map_err(ImageError::IoError)?;
+        // files written on Windows may have CRLF line endings, which are passed on as plain LF
+        if line.ends_with(b"\r\n") {
+            line.truncate(line.len() - 2);
+            line.push(b'\n');
+        }
+        if line.trim_ascii() == b"FORMAT=32-bit_rle_xyze" {
+            is_xyze = true;
+            header.extend_from_slice(b"FORMAT=32-bit_rle_rgbe\n");
+        } else {
+            header.extend_from_slice(&line);
+        }
+        // the header ends with a blank line
+        if len == 0 || line == b"\n" {
+            return Ok((header, is_xyze));
+        }
+    }
+}
+
+/// Reads a Radiance file in either the RGBE or XYZE format as [RGBE8] texel data.
+///
+/// XYZE data is converted to RGB in the given [Primaries], with out-of-gamut colours clamped.
+/// Use this rather than [decode_radiance] for files which may be in XYZE, as [HdrDecoder] does not support them.
+pub fn decode_radiance_with_primaries<R: BufRead>(reader: R, primaries: Primaries) -> ImageResult<HdrImage<RGBE8>> {
+    Ok(decode_radiance_with_metadata(reader, primaries)?.0)
+}
+
+/// Reads a Radiance file as [decode_radiance_with_primaries] does, along with the metadata in its header
+/// (which [RadianceSaveOptions::from_metadata](crate::RadianceSaveOptions::from_metadata) can write back out).
+///
+/// The texels are returned as stored, without dividing out the exposure (see [decode_radiance_absolute]).
+pub fn decode_radiance_with_metadata<R: BufRead>(reader: R, primaries: Primaries) -> ImageResult<(HdrImage<RGBE8>, RadianceMetadata)> {
+    decode_radiance_impl(reader, primaries, RgbeConvention::RADIANCE, false)
+}
+
+/// Reads a Radiance file as [decode_radiance_with_metadata] does, for files written by tools using a different [RgbeConvention]
+/// (such as another exponent bias), converting the texels to the standard convention so that they have their intended values.
+pub fn decode_radiance_with_convention<R: BufRead>(reader: R, primaries: Primaries, convention: RgbeConvention) -> ImageResult<(HdrImage<RGBE8>, RadianceMetadata)> {
+    decode_radiance_impl(reader, primaries, convention, false)
+}
+
+/// Reads a Radiance file as [decode_radiance_with_metadata] does, but divides the texels by the `EXPOSURE` recorded in the header
+/// so that they are in the original radiance units, as needed for photometric data.
+/// The returned metadata has no exposure, as it no longer applies to the texels.
+///
+/// Exposures which are powers of two are removed losslessly by adjusting the exponents; other exposures require repacking each texel.
+pub fn decode_radiance_absolute<R: BufRead>(reader: R, primaries: Primaries) -> ImageResult<(HdrImage<RGBE8>, RadianceMetadata)> {
+    decode_radiance_impl(reader, primaries, RgbeConvention::RADIANCE, true)
+}
+
+fn decode_radiance_impl<R: BufRead>(mut reader: R, primaries: Primaries, convention: RgbeConvention, absolute: bool) -> ImageResult<(HdrImage<RGBE8>, RadianceMetadata)> {
+    let (header, is_xyze) = read_radiance_header(&mut reader)?;
+    let mut metadata = RadianceMetadata::from_header(&header);
+    let exposure = if absolute { metadata.exposure.take().unwrap_or(1.0) } else { 1.0 };
+    if !(exposure.is_finite() && exposure > 0.0) {
+        return Err(ImageError::Decoding(DecodingError::new(image::ImageFormat::Hdr.into(), format!("invalid EXPOSURE {exposure}"))));
+    }
+    let decoder = HdrDecoder::new(Cursor::new(header).chain(reader))?;
     let meta = decoder.metadata();
-    let data = decode_radiance(decoder)?;
-    Ok((meta.width, meta.height, data))
+    let extent = image_extent(meta.width, meta.height)?;
+    let mut data = decode_radiance(decoder)?;
+    convention.normalize(&mut data);
+    let stops = -exposure.log2();
+    if is_xyze || stops.fract() != 0.0 {
+        for texel in data.iter_mut() {
+            let rgb = texel.unpack().map(|c| c / exposure);
+            *texel = RGBE8::pack(if is_xyze { primaries.rgb_from_xyz(rgb) } else { rgb });
+        }
+    } else if stops != 0.0 {
+        for texel in data.iter_mut() {
+            *texel = texel.scale_exposure(stops as i32);
+        }
+    }
+    Ok((HdrImage::new(extent, data), metadata))
+}
+
+/// Loads a radiance file as [RGBE8] texel data.
+/// Files in the XYZE format are converted to Rec.709 RGB.
+pub fn load_radiance_file(path: &Path) -> ImageResult<HdrImage<RGBE8>> {
+    load_radiance_file_with_primaries(path, Primaries::Rec709)
+}
+
+/// Loads a radiance file as [RGBE8] texel data, converting files in the XYZE format to the given [Primaries].
+pub fn load_radiance_file_with_primaries(path: &Path, primaries: Primaries) -> ImageResult<HdrImage<RGBE8>> {
+    let file = File::open(path).map_err(ImageError::IoError)?;
+    decode_radiance_with_primaries(BufReader::new(file), primaries)
+}
+
+/// Loads a radiance file as [RGBE8] texel data along with the metadata in its header (see [decode_radiance_with_metadata]).
+/// Files in the XYZE format are converted to Rec.709 RGB.
+pub fn load_radiance_file_with_metadata(path: &Path) -> ImageResult<(HdrImage<RGBE8>, RadianceMetadata)> {
+    let file = File::open(path).map_err(ImageError::IoError)?;
+    decode_radiance_with_metadata(BufReader::new(file), Primaries::Rec709)
+}
+
+/// Loads a radiance file written with a different [RgbeConvention] (see [decode_radiance_with_convention]).
+/// Files in the XYZE format are converted to Rec.709 RGB.
+pub fn load_radiance_file_with_convention(path: &Path, convention: RgbeConvention) -> ImageResult<(HdrImage<RGBE8>, RadianceMetadata)> {
+    let file = File::open(path).map_err(ImageError::IoError)?;
+    decode_radiance_with_convention(BufReader::new(file), Primaries::Rec709, convention)
+}
+
+/// Loads a radiance file with the exposure divided out (see [decode_radiance_absolute]), along with the metadata in its header.
+/// Files in the XYZE format are converted to Rec.709 RGB.
+pub fn load_radiance_file_absolute(path: &Path) -> ImageResult<(HdrImage<RGBE8>, RadianceMetadata)> {
+    let file = File::open(path).map_err(ImageError::IoError)?;
+    decode_radiance_absolute(BufReader::new(file), Primaries::Rec709)
+}
+
+/// Decodes a Radiance file from memory as [RGBE8] texel data, such as a file read from an archive.
+/// Files in the XYZE format are converted to Rec.709 RGB.
+pub fn decode_radiance_from_bytes(bytes: &[u8]) -> ImageResult<HdrImage<RGBE8>> {
+    decode_radiance_with_primaries(bytes, Primaries::Rec709)
+}
+
+/// RGBE8 PNGs must be 8-bit RGBA, and reading any other kind of PNG into an RGBE8 buffer would fail.
+pub(crate) fn check_rgba8<R: Read>(dec: &PngDecoder<R>) -> ImageResult<()> {
+    if dec.color_type() == image::ColorType::Rgba8 {
+        Ok(())
+    } else {
+        Err(ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+            image::ImageFormat::Png.into(),
+            UnsupportedErrorKind::Color(dec.color_type().into()),
+        )))
+    }
 }
 
 /// Reads the data from an [PngDecoder] as a slice of [RGBE8] texels.
 pub fn decode_rgbe8_png<R:Read>(dec: PngDecoder<R>) -> ImageResult<Box<[RGBE8]>> {
-    let (width, height) = dec.dimensions();
-    let size = (width * height) as usize;
-    let mut out = bytemuck::allocation::zeroed_slice_box::<RGBE8>(size);
-    dec.read_image(bytemuck::cast_slice_mut(&mut out))?;
-    Ok(out)
+    decode_rgbe8_png_in(dec, HeapAllocator)
 }
 
 /// Reads the and converts data from an [PngDecoder] as a slice of [RGB9E5] texels.
 pub fn decode_rgbe8_png_as_rgb9e5<R:Read>(dec: PngDecoder<R>) -> ImageResult<Box<[RGB9E5]>> {
+    check_rgba8(&dec)?;
     let (width, height) = dec.dimensions();
-    let size = (width * height) as usize;
-    let mut orig = bytemuck::allocation::zeroed_slice_box::<RGBE8>(size);
+    let extent = image_extent(width, height)?;
+    let mut orig = bytemuck::allocation::zeroed_slice_box::<RGBE8>(extent.texel_count());
     dec.read_image(bytemuck::cast_slice_mut(&mut orig))?;
     let out = orig.iter().copied().map(RGBE8::repack_rgb9e5).collect();
     Ok(out)
 }
 
-/// Loads an [RGBE8]-format PNG file, returning the dimensions and a slice of the pixel data.
-pub fn load_rgbe8_png_file(path: &Path) -> ImageResult<(u32, u32, Box<[RGBE8]>)> {
-    let file = File::open(path).map_err(ImageError::IoError)?;
-    let decoder = PngDecoder::new(file)?;
+/// Type of the private PNG chunk marking a PNG as containing RGBE8 texels:
+/// ancillary, private, and unsafe to copy, as editors which change the pixels would not keep them in RGBE8.
+const RGBE_CHUNK: &[u8; 4] = b"rgBE";
+
+/// Version of the layout of the data in the `rgBE` chunk.
+const RGBE_CHUNK_VERSION: u8 = 1;
+
+/// Exponent bias of [RGBE8] texels, as recorded in the `rgBE` chunk.
+const RGBE8_EXPONENT_BIAS: u8 = 128;
+
+/// Type of the private PNG chunk holding the checksum of the texel data:
+/// ancillary, private, and unsafe to copy, so that editors which change the pixels drop it.
+const CHECKSUM_CHUNK: &[u8; 4] = b"rgCK";
+
+/// Table for [crc32], generated at compile time.
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { 0xEDB88320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// The CRC-32 used by PNG (and zlib), of a sequence of bytes.
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &b| CRC32_TABLE[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+/// CRC-32 of the packed texel data of an image, in row-major order, as stored by [encode_rgbe8_png_with_checksum].
+pub fn texel_checksum(data: &[RGBE8]) -> u32 {
+    crc32(bytemuck::cast_slice(data))
+}
+
+/// Serializes a PNG chunk with its length and CRC.
+pub(crate) fn png_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(data.len() + 12);
+    chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    let crc = crc32(&chunk[4..]);
+    chunk.extend_from_slice(&crc.to_be_bytes());
+    chunk
+}
+
+/// Inserts a serialized chunk into an encoded PNG, just before its `IEND` chunk (which is always the last 12 bytes).
+pub(crate) fn insert_png_chunk(png: &mut Vec<u8>, chunk: &[u8]) {
+    let end = png.len() - 12;
+    png.splice(end..end, chunk.iter().copied());
+}
+
+/// Finds the data of the first chunk of the given type in an encoded PNG, if it has one.
+pub(crate) fn find_png_chunk<'a>(png: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    let mut rest = png.strip_prefix(PNG_SIGNATURE)?;
+    while rest.len() >= 12 {
+        let len = u32::from_be_bytes(rest[0..4].try_into().unwrap()) as usize;
+        let data = rest.get(8..8 + len)?;
+        if &rest[4..8] == kind {
+            return Some(data);
+        }
+        rest = rest.get(len + 12..)?;
+    }
+    None
+}
+
+/// Inserts a serialized chunk into an encoded PNG just after its `IHDR` chunk (which is always 25 bytes),
+/// so that readers find it before the image data.
+fn insert_png_chunk_after_header(png: &mut Vec<u8>, chunk: &[u8]) {
+    let start = PNG_SIGNATURE.len() + 25;
+    png.splice(start..start, chunk.iter().copied());
+}
+
+/// Checks whether an encoded PNG is tagged as containing [RGBE8] texels by an `rgBE` chunk,
+/// returning the exponent bias recorded in the chunk if so.
+///
+/// PNGs written by this crate have an exponent bias of 128, so that each texel is `(r, g, b) / 256 * 2^(e - 128)`,
+/// as in Radiance files. Untagged PNGs may be RGBE8 files written by older tools, or ordinary RGBA images.
+pub fn png_rgbe_exponent_bias(png: &[u8]) -> Option<u8> {
+    match find_png_chunk(png, RGBE_CHUNK)? {
+        &[RGBE_CHUNK_VERSION, bias, ..] => Some(bias),
+        _ => None,
+    }
+}
+
+/// Reads the texel checksum stored in an RGBE8 PNG by [encode_rgbe8_png_with_checksum], if it has one.
+pub fn png_texel_checksum(png: &[u8]) -> Option<u32> {
+    find_png_chunk(png, CHECKSUM_CHUNK)?.try_into().ok().map(u32::from_be_bytes)
+}
+
+/// Decodes an [RGBE8]-format PNG from memory, along with its dimensions,
+/// checking the texels against the checksum stored in the file if it has one.
+fn decode_rgbe8_png_image(bytes: &[u8]) -> ImageResult<HdrImage<RGBE8>> {
+    let decoder = PngDecoder::new(bytes)?;
     let (width, height) = decoder.dimensions();
+    let extent = image_extent(width, height)?;
     let data = decode_rgbe8_png(decoder)?;
-    Ok((width, height, data))
+    if let Some(expected) = png_texel_checksum(bytes) {
+        if texel_checksum(&data) != expected {
+            return Err(ImageError::Decoding(DecodingError::new(
+                image::ImageFormat::Png.into(),
+                "texel data does not match its checksum (the file is corrupt)",
+            )));
+        }
+    }
+    Ok(HdrImage::new(extent, data))
+}
+
+/// Loads an [RGBE8]-format PNG file.
+///
+/// If the file was saved with a checksum (see [encode_rgbe8_png_with_checksum]),
+/// it is verified and corrupted files are rejected with a decoding error.
+pub fn load_rgbe8_png_file(path: &Path) -> ImageResult<HdrImage<RGBE8>> {
+    let bytes = fs::read(path).map_err(ImageError::IoError)?;
+    decode_rgbe8_png_image(&bytes)
 }
 
-/// Loads an RGBE8-format PNG file, returning the dimensions and a slice of the pixel data converted to [RGB9E5] format.
+/// Decodes an [RGBE8]-format PNG from memory, such as a file read from an archive.
+/// Like [load_rgbe8_png_file], this verifies the checksum of the texels if the file has one.
+pub fn decode_rgbe8_png_from_bytes(bytes: &[u8]) -> ImageResult<HdrImage<RGBE8>> {
+    decode_rgbe8_png_image(bytes)
+}
+
+/// Loads an RGBE8-format PNG file, converting the pixel data to [RGB9E5] format.
 /// This is intended for loading HDR textures to use on the GPU.
-pub fn load_rgbe8_png_file_as_rgb9e5(path: &Path) -> ImageResult<(u32, u32, Box<[RGB9E5]>)> {
+pub fn load_rgbe8_png_file_as_rgb9e5(path: &Path) -> ImageResult<HdrImage<RGB9E5>> {
+    Ok(load_rgbe8_png_file(path)?.convert())
+}
+
+/// Loads RGBE8-format PNG files as the layers of an [RGB9E5] array texture, in the order they are given.
+///
+/// Every file must have the same dimensions. Errors name the file which could not be loaded or whose dimensions do not match.
+pub fn load_rgb9e5_array_texture<P: AsRef<Path>>(paths: &[P]) -> ImageResult<ArrayTexture<RGB9E5>> {
+    let array_error = |msg: String| ImageError::Decoding(DecodingError::new(ImageFormatHint::Name("array texture".into()), msg));
+    let load = |path: &Path| load_rgbe8_png_file(path).map_err(|e| array_error(format!("failed to load {}: {e}", path.display())));
+    let (first, rest) = paths.split_first().ok_or_else(|| array_error("no layers were given".into()))?;
+    let first_layer = load(first.as_ref())?;
+    let extent = first_layer.extent();
+    let layers = u32::try_from(paths.len()).map_err(|_| array_error("too many layers".into()))?;
+    let mut data = Vec::with_capacity(extent.texel_count().saturating_mul(paths.len()));
+    data.extend(first_layer.pixels().iter().map(|&texel| RGB9E5::from(texel)));
+    for path in rest {
+        let path = path.as_ref();
+        let layer = load(path)?;
+        if layer.extent() != extent {
+            return Err(array_error(format!(
+                "{} is {}x{}, but the first layer ({}) is {}x{}",
+                path.display(), layer.width(), layer.height(), first.as_ref().display(), extent.width(), extent.height()
+            )));
+        }
+        data.extend(layer.pixels().iter().map(|&texel| RGB9E5::from(texel)));
+    }
+    Ok(ArrayTexture::new(extent, layers, data.into()))
+}
+
+/// Decodes a PNG from memory, as [RGBE8] texels if it is tagged as RGBE8 by an `rgBE` chunk
+/// or as an ordinary sRGB image otherwise (see [load_png_auto]).
+pub fn decode_png_auto_from_bytes(bytes: &[u8]) -> ImageResult<AnyHdrImage> {
+    match png_rgbe_exponent_bias(bytes) {
+        Some(exponent_bias) => {
+            let mut image = decode_rgbe8_png_image(bytes)?;
+            RgbeConvention{exponent_bias, centered: false}.normalize(image.pixels_mut());
+            Ok(image.into())
+        }
+        None => {
+            let image = image::load_from_memory_with_format(bytes, image::ImageFormat::Png)?.into_rgb32f();
+            let extent = image_extent(image.width(), image.height())?;
+            let data = image.pixels().map(|px| RGB32F::from(px.0.map(srgb_to_linear))).collect();
+            Ok(HdrImage::<RGB32F>::new(extent, data).into())
+        }
+    }
+}
+
+/// Loads a PNG file, checking whether it contains RGBE8 texels rather than guessing from its extension.
+///
+/// PNGs tagged as RGBE8 by an `rgBE` chunk (as written by [encode_rgbe8_png]) are loaded as [RGBE8] texels
+/// (converted to the standard exponent bias if the tag records another one),
+/// and any other PNG is loaded as an ordinary image, decoded from sRGB to linear [RGB32F] with alpha discarded,
+/// so that photos and textures can be used alongside HDR images rather than having their alpha read as an exponent.
+///
+/// RGBE8 PNGs written before the tag was introduced (or by other tools) are indistinguishable from ordinary images,
+/// and must be loaded with [load_rgbe8_png_file] instead.
+pub fn load_png_auto(path: &Path) -> ImageResult<AnyHdrImage> {
+    let bytes = fs::read(path).map_err(ImageError::IoError)?;
+    decode_png_auto_from_bytes(&bytes)
+}
+
+const PNG_SIGNATURE: &[u8] = &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const RADIANCE_SIGNATURE: &[u8] = b"#?";
+
+/// Loads an HDR image from either a Radiance file or an RGBE8 PNG, choosing based on the file contents rather than its extension.
+/// With the `ktx2` feature, RGB9E5 KTX2 files are also supported.
+pub fn load_hdr_file(path: &Path) -> ImageResult<AnyHdrImage> {
+    let mut reader = BufReader::new(File::open(path).map_err(ImageError::IoError)?);
+    let start = reader.fill_buf().map_err(ImageError::IoError)?;
+    #[cfg(feature = "ktx2")]
+    if start.starts_with(&KTX2_IDENTIFIER) {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).map_err(ImageError::IoError)?;
+        return Ok(crate::ktx2::decode_rgb9e5_ktx2(&bytes)?.into());
+    }
+    if start.starts_with(PNG_SIGNATURE) {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).map_err(ImageError::IoError)?;
+        Ok(decode_rgbe8_png_image(&bytes)?.into())
+    } else if start.starts_with(RADIANCE_SIGNATURE) {
+        Ok(decode_radiance_with_primaries(reader, Primaries::Rec709)?.into())
+    } else {
+        Err(ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+            ImageFormatHint::PathExtension(path.extension().unwrap_or_default().into()),
+            UnsupportedErrorKind::Format(ImageFormatHint::Unknown),
+        )))
+    }
+}
+
+/// File formats recognized by [peek_dimensions].
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+pub enum FormatKind {
+    /// A Radiance HDR file.
+    Radiance,
+    /// A PNG file, which may or may not hold RGBE8 texels.
+    Png,
+    /// A KTX2 texture.
+    Ktx2,
+    /// A DDS texture.
+    Dds,
+}
+
+// These are shared with the KTX2 and DDS writers, but live here so that files can be recognized without those features.
+pub(crate) const KTX2_IDENTIFIER: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+pub(crate) const DDS_MAGIC: &[u8; 4] = b"DDS ";
+
+fn header_error(format: FormatKind, msg: &str) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormatHint::Name(format!("{format:?}")), msg.to_string()))
+}
+
+/// Reads the width, height, and format of an image from the start of its file, without decoding any texels,
+/// so that tools can plan work or list many files quickly.
+///
+/// Radiance, PNG, KTX2, and DDS files are recognized by their contents, regardless of which features are enabled
+/// (so this does not check whether the texels are in a format which can be loaded).
+/// Textures with mipmaps or several layers report the size of their base level.
+pub fn peek_dimensions(path: &Path) -> ImageResult<(u32, u32, FormatKind)> {
     let file = File::open(path).map_err(ImageError::IoError)?;
-    let decoder = PngDecoder::new(file)?;
-    let (width, height) = decoder.dimensions();
-    let data = decode_rgbe8_png_as_rgb9e5(decoder)?;
-    Ok((width, height, data))
+    peek_dimensions_from_reader(BufReader::new(file))
+}
+
+/// Reads the dimensions and format of an image from the start of its data (see [peek_dimensions]).
+pub fn peek_dimensions_from_reader<R: BufRead>(mut reader: R) -> ImageResult<(u32, u32, FormatKind)> {
+    let start = reader.fill_buf().map_err(ImageError::IoError)?;
+    let (format, offsets) = if start.starts_with(PNG_SIGNATURE) {
+        // the IHDR chunk always comes first
+        (FormatKind::Png, (16, 20, 24))
+    } else if start.starts_with(&KTX2_IDENTIFIER) {
+        (FormatKind::Ktx2, (20, 24, 28))
+    } else if start.starts_with(DDS_MAGIC) {
+        // the header stores the height before the width
+        (FormatKind::Dds, (16, 12, 20))
+    } else if start.starts_with(RADIANCE_SIGNATURE) {
+        let (width, height) = peek_radiance_dimensions(reader)?;
+        return Ok((width, height, FormatKind::Radiance));
+    } else {
+        return Err(ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+            ImageFormatHint::Unknown,
+            UnsupportedErrorKind::Format(ImageFormatHint::Unknown),
+        )));
+    };
+    let (width_at, height_at, len) = offsets;
+    let mut header = vec![0; len];
+    reader.read_exact(&mut header).map_err(ImageError::IoError)?;
+    let read_u32 = |at: usize| {
+        let bytes = header[at..at + 4].try_into().unwrap();
+        if format == FormatKind::Png { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) }
+    };
+    // 1D KTX2 textures have a height of 0
+    let (width, height) = (read_u32(width_at), read_u32(height_at).max((format == FormatKind::Ktx2) as u32));
+    if width == 0 || height == 0 {
+        return Err(header_error(format, "image has no texels"));
+    }
+    Ok((width, height, format))
+}
+
+/// Reads the resolution line after the header of a Radiance file, in the form `-Y <height> +X <width>`.
+fn peek_radiance_dimensions<R: BufRead>(mut reader: R) -> ImageResult<(u32, u32)> {
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line).map_err(ImageError::IoError)? == 0 {
+            return Err(header_error(FormatKind::Radiance, "missing resolution"));
+        }
+        // the header ends with a blank line
+        if line.trim_ascii().is_empty() {
+            break;
+        }
+    }
+    line.clear();
+    reader.read_until(b'\n', &mut line).map_err(ImageError::IoError)?;
+    let line = String::from_utf8_lossy(&line);
+    let (mut width, mut height) = (None, None);
+    let mut words = line.split_whitespace();
+    while let (Some(axis), Some(size)) = (words.next(), words.next()) {
+        match axis {
+            "-Y" | "+Y" => height = size.parse().ok(),
+            "-X" | "+X" => width = size.parse().ok(),
+            _ => break,
+        }
+    }
+    width.zip(height).filter(|&(w, h)| w > 0 && h > 0).ok_or_else(|| header_error(FormatKind::Radiance, "invalid resolution"))
 }
 
 /// Encodes [RGBE8] texel data into RGBA8 PNG format, storing the exponent in the alpha channel.
 ///
+/// The file is tagged as RGBE8 by a private `rgBE` chunk containing a version number (currently 1) and the exponent bias (128),
+/// so that other tools can tell it apart from an ordinary RGBA image (see [png_rgbe_exponent_bias]).
+///
 /// Note that PNG compression is slow, so this is intended for asset creation.
-pub fn encode_rgbe8_png<W: Write>(width: u32, height: u32, data: &[RGBE8], out: W) -> ImageResult<()> {
-    let encoder = PngEncoder::new_with_quality(out, png::CompressionType::Best, png::FilterType::Adaptive);
-    encoder.write_image(bytemuck::cast_slice(data), width, height, image::ColorType::Rgba8)?;
-    Ok(())
+pub fn encode_rgbe8_png<W: Write>(extent: Extent2D, data: &[RGBE8], out: W) -> ImageResult<()> {
+    encode_rgbe8_png_with_compression(extent, data, out, png::CompressionType::Best)
+}
+
+/// Encodes [RGBE8] texel data into RGBA8 PNG format using the given compression level.
+/// Faster compression levels are useful for iterating on assets, at the cost of larger files.
+pub fn encode_rgbe8_png_with_compression<W: Write>(extent: Extent2D, data: &[RGBE8], mut out: W, compression: png::CompressionType) -> ImageResult<()> {
+    assert_eq!(data.len(), extent.texel_count(), "data does not match image dimensions");
+    let mut png = Vec::new();
+    let encoder = PngEncoder::new_with_quality(&mut png, compression, png::FilterType::Adaptive);
+    encoder.write_image(bytemuck::cast_slice(data), extent.width(), extent.height(), image::ColorType::Rgba8)?;
+    insert_png_chunk_after_header(&mut png, &png_chunk(RGBE_CHUNK, &[RGBE_CHUNK_VERSION, RGBE8_EXPONENT_BIAS]));
+    out.write_all(&png).map_err(ImageError::IoError)
+}
+
+/// Encodes [RGBE8] texel data into RGBA8 PNG format using the given compression level,
+/// along with a CRC-32 of the texels (see [texel_checksum]) in a private `rgCK` chunk.
+///
+/// The loaders in this crate verify the checksum when it is present, so that bit rot and damaged transfers of large assets
+/// are reported as errors rather than loading as corrupted lighting. Other PNG readers ignore the chunk.
+pub fn encode_rgbe8_png_with_checksum<W: Write>(extent: Extent2D, data: &[RGBE8], mut out: W, compression: png::CompressionType) -> ImageResult<()> {
+    let mut png = Vec::new();
+    encode_rgbe8_png_with_compression(extent, data, &mut png, compression)?;
+    insert_png_chunk(&mut png, &png_chunk(CHECKSUM_CHUNK, &texel_checksum(data).to_be_bytes()));
+    out.write_all(&png).map_err(ImageError::IoError)
 }
 
 /// Saves [RGBE8] texel data into RGBA8 PNG file, storing the exponent in the alpha channel.
 /// This package also exports a command-line tool (hdr2rgbe-png) for converting Radiance HDR images to RGBE8-PNG.
 ///
 /// Note that PNG compression is slow, so this is intended for asset creation.
-pub fn save_rgbe8_png_file(path: &Path, width: u32, height: u32, data: &[RGBE8]) -> ImageResult<()> {
+pub fn save_rgbe8_png_file(path: &Path, extent: Extent2D, data: &[RGBE8]) -> ImageResult<()> {
+    let file = File::create(path).map_err(ImageError::IoError)?;
+    encode_rgbe8_png(extent, data, file)
+}
+
+/// Saves [RGBE8] texel data into RGBA8 PNG file using the given compression level.
+pub fn save_rgbe8_png_file_with_compression(path: &Path, extent: Extent2D, data: &[RGBE8], compression: png::CompressionType) -> ImageResult<()> {
+    let file = File::create(path).map_err(ImageError::IoError)?;
+    encode_rgbe8_png_with_compression(extent, data, file, compression)
+}
+
+/// Saves [RGBE8] texel data into RGBA8 PNG file using the given compression level, with a checksum of the texels
+/// (see [encode_rgbe8_png_with_checksum]).
+pub fn save_rgbe8_png_file_with_checksum(path: &Path, extent: Extent2D, data: &[RGBE8], compression: png::CompressionType) -> ImageResult<()> {
+    let file = File::create(path).map_err(ImageError::IoError)?;
+    encode_rgbe8_png_with_checksum(extent, data, file, compression)
+}
+
+/// Encodes 8-bit RGB pixels (such as tone-mapped previews) into an RGB8 PNG.
+pub fn encode_rgb8_png<W: Write>(extent: Extent2D, data: &[[u8;3]], out: W) -> ImageResult<()> {
+    assert_eq!(data.len(), extent.texel_count(), "data does not match image dimensions");
+    let encoder = PngEncoder::new(out);
+    encoder.write_image(bytemuck::cast_slice(data), extent.width(), extent.height(), image::ColorType::Rgb8)?;
+    Ok(())
+}
+
+/// Saves 8-bit RGB pixels (such as tone-mapped previews) into an RGB8 PNG file which can be opened in any viewer.
+pub fn save_rgb8_png_file(path: &Path, extent: Extent2D, data: &[[u8;3]]) -> ImageResult<()> {
+    let file = File::create(path).map_err(ImageError::IoError)?;
+    encode_rgb8_png(extent, data, BufWriter::new(file))
+}
+
+/// Encodes 16-bit RGB pixels (such as HDR previews from [tonemap_rgb16](crate::tonemap_rgb16)) into an RGB16 PNG.
+pub fn encode_rgb16_png<W: Write>(extent: Extent2D, data: &[[u16;3]], out: W) -> ImageResult<()> {
+    assert_eq!(data.len(), extent.texel_count(), "data does not match image dimensions");
+    let encoder = PngEncoder::new(out);
+    // the encoder converts native-endian samples to big-endian
+    encoder.write_image(bytemuck::cast_slice(data), extent.width(), extent.height(), image::ColorType::Rgb16)?;
+    Ok(())
+}
+
+/// Decodes the pixels of a PNG as 16-bit RGB, such as HDR images for [decode_hdr_signal](crate::decode_hdr_signal).
+/// Pixels with fewer bits are scaled to 16 bits, and alpha is ignored.
+pub fn decode_rgb16_png_from_bytes(bytes: &[u8]) -> ImageResult<HdrImage<[u16;3]>> {
+    let dec = PngDecoder::new(Cursor::new(bytes))?;
+    let pixels = image::DynamicImage::from_decoder(dec)?.into_rgb16();
+    let extent = image_extent(pixels.width(), pixels.height())?;
+    Ok(HdrImage::new(extent, pixels.pixels().map(|px| px.0).collect()))
+}
+
+/// Loads the pixels of a PNG file as 16-bit RGB (see [decode_rgb16_png_from_bytes]).
+pub fn load_rgb16_png_file(path: &Path) -> ImageResult<HdrImage<[u16;3]>> {
+    decode_rgb16_png_from_bytes(&fs::read(path).map_err(ImageError::IoError)?)
+}
+
+/// Saves 16-bit RGB pixels into an RGB16 PNG file.
+/// The file does not record the display encoding of the pixels, so viewers will assume they are sRGB.
+pub fn save_rgb16_png_file(path: &Path, extent: Extent2D, data: &[[u16;3]]) -> ImageResult<()> {
     let file = File::create(path).map_err(ImageError::IoError)?;
-    encode_rgbe8_png(width, height, data, file)
+    encode_rgb16_png(extent, data, BufWriter::new(file))
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_image() -> HdrImage<RGBE8> {
+        let extent = Extent2D::new(7, 5).unwrap();
+        HdrImage::new(extent, (0..35).map(|i| RGBE8{r: i as u8 * 7, g: 128, b: 255 - i as u8, e: 120 + (i % 16) as u8}).collect())
+    }
+
+    fn encode_with_checksum(image: &HdrImage<RGBE8>) -> Vec<u8> {
+        let mut png = Vec::new();
+        encode_rgbe8_png_with_checksum(image.extent(), image.pixels(), &mut png, png::CompressionType::Fast).unwrap();
+        png
+    }
+
+    #[test]
+    fn crc32_reference() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let image = test_image();
+        let mut png = Vec::new();
+        encode_rgbe8_png(image.extent(), image.pixels(), &mut png).unwrap();
+        assert_eq!(png_rgbe_exponent_bias(&png), Some(128));
+        assert_eq!(png_texel_checksum(&png), None);
+        assert_eq!(decode_rgbe8_png_from_bytes(&png).unwrap(), image);
+    }
+
+    #[test]
+    fn checksum_round_trip() {
+        let image = test_image();
+        let png = encode_with_checksum(&image);
+        assert_eq!(png_texel_checksum(&png), Some(texel_checksum(image.pixels())));
+        assert_eq!(decode_rgbe8_png_from_bytes(&png).unwrap(), image);
+    }
+
+    #[test]
+    fn rejects_checksum_mismatch() {
+        let image = test_image();
+        let mut png = encode_with_checksum(&image);
+        // replace the stored checksum with a wrong one in an otherwise valid chunk
+        let start = png.windows(4).position(|w| w == CHECKSUM_CHUNK).unwrap() - 4;
+        let wrong = png_chunk(CHECKSUM_CHUNK, &(texel_checksum(image.pixels()) ^ 1).to_be_bytes());
+        png.splice(start..start + wrong.len(), wrong);
+        assert_eq!(png_texel_checksum(&png), Some(texel_checksum(image.pixels()) ^ 1));
+        let err = decode_rgbe8_png_from_bytes(&png).unwrap_err();
+        assert!(err.to_string().contains("checksum"), "{err}");
+
+        // texels which differ from the ones the checksum was computed for
+        let mut other = test_image();
+        other.pixels_mut()[17].r ^= 0x10;
+        let mut png = Vec::new();
+        encode_rgbe8_png_with_compression(other.extent(), other.pixels(), &mut png, png::CompressionType::Fast).unwrap();
+        insert_png_chunk(&mut png, &png_chunk(CHECKSUM_CHUNK, &texel_checksum(image.pixels()).to_be_bytes()));
+        assert!(decode_rgbe8_png_from_bytes(&png).is_err());
+    }
+}
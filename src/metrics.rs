@@ -0,0 +1,88 @@
+use crate::types::*;
+
+/// Smallest value considered when taking logarithms or dividing by brightness,
+/// so that black texels do not dominate the metrics. This is the smallest normal [RGB9E5] value.
+pub const METRICS_FLOOR: f32 = 1.0 / 65536.0;
+
+/// Statistics of the difference between a reference image and a test image, such as the same image after packing.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct ErrorMetrics {
+    /// Largest relative error in each of the red, green, and blue channels.
+    pub max_relative_error: [f32;3],
+    /// Mean relative error in each of the red, green, and blue channels.
+    pub mean_relative_error: [f32;3],
+    /// Peak signal-to-noise ratio (in decibels) of the base 2 logarithms of each channel,
+    /// with the peak being the range of the reference image in stops. This is infinite for identical images.
+    ///
+    /// Channels more than 8 stops darker than the brightest channel of their texel are treated as being 8 stops darker,
+    /// as the difference is not visible and shared-exponent formats round them to zero.
+    pub log_psnr: [f32;3],
+}
+
+impl ErrorMetrics {
+    /// Largest relative error over all channels.
+    pub fn max_error(&self) -> f32 {
+        self.max_relative_error.into_iter().fold(0.0, f32::max)
+    }
+
+    /// Log-domain PSNR over all channels together.
+    pub fn overall_log_psnr(&self) -> f32 {
+        // average the noise powers, which are relative to the same peak
+        let mean_noise = self.log_psnr.iter().map(|&psnr| 10f32.powf(-psnr / 10.0)).sum::<f32>() / 3.0;
+        -10.0 * mean_noise.log10()
+    }
+}
+
+/// Compare two buffers of texels in any [RgbTexel] formats, such as an [RGBA32F] master and its [RGB9E5] packing.
+///
+/// Relative errors are measured against the brightest channel of each reference texel (or [METRICS_FLOOR] if larger),
+/// as shared-exponent formats only store dim channels of saturated colours to the precision of the brightest one.
+///
+/// Panics if the buffers have different lengths.
+pub fn error_metrics<A: RgbTexel, B: RgbTexel>(reference: &[A], test: &[B]) -> ErrorMetrics {
+    assert_eq!(reference.len(), test.len(), "images have different sizes");
+    let mut max_error = [0.0f32; 3];
+    let mut sum_error = [0.0f64; 3];
+    let mut sum_log_error = [0.0f64; 3];
+    let (mut min_log, mut max_log) = (f32::INFINITY, f32::NEG_INFINITY);
+
+    for (a, b) in reference.iter().zip(test) {
+        let (a, b) = (a.to_rgb(), b.to_rgb());
+        let scale = a.into_iter().fold(METRICS_FLOOR, f32::max);
+        let log_floor = (scale / 256.0).max(METRICS_FLOOR);
+        for c in 0..3 {
+            let error = (a[c] - b[c]).abs() / scale;
+            max_error[c] = max_error[c].max(error);
+            sum_error[c] += error as f64;
+
+            let log_a = a[c].max(log_floor).log2();
+            let log_b = b[c].max(log_floor).log2();
+            sum_log_error[c] += ((log_a - log_b) as f64).powi(2);
+            min_log = min_log.min(log_a);
+            max_log = max_log.max(log_a);
+        }
+    }
+
+    let n = reference.len().max(1) as f64;
+    // a flat reference still has a peak of one stop, so that any difference counts
+    let peak = (max_log - min_log).max(1.0) as f64;
+    ErrorMetrics{
+        max_relative_error: max_error,
+        mean_relative_error: sum_error.map(|sum| (sum / n) as f32),
+        log_psnr: sum_log_error.map(|sum| (10.0 * (peak * peak / (sum / n)).log10()) as f32),
+    }
+}
+
+/// Relative error of each texel of `test` compared to `reference` (in any [RgbTexel] formats),
+/// taking the largest error over the channels and measuring it as in [error_metrics].
+/// This can be visualized to find where an image has been damaged by conversion or compression.
+///
+/// Panics if the buffers have different lengths.
+pub fn relative_error_map<A: RgbTexel, B: RgbTexel>(reference: &[A], test: &[B]) -> Box<[f32]> {
+    assert_eq!(reference.len(), test.len(), "images have different sizes");
+    reference.iter().zip(test).map(|(a, b)| {
+        let (a, b) = (a.to_rgb(), b.to_rgb());
+        let scale = a.into_iter().fold(METRICS_FLOOR, f32::max);
+        (0..3).map(|c| (a[c] - b[c]).abs() / scale).fold(0.0, f32::max)
+    }).collect()
+}
@@ -0,0 +1,119 @@
+use crate::types::*;
+use crate::hdrimage::*;
+use crate::resample::*;
+
+use bytemuck::Pod;
+
+/// Number of levels in a full mip chain for an image, down to 1x1.
+pub fn mip_level_count(extent: Extent2D) -> u32 {
+    u32::BITS - extent.width().max(extent.height()).leading_zeros()
+}
+
+/// Dimensions of mip level `level` of an image, halving (and rounding down) at each level.
+pub fn mip_extent(extent: Extent2D, level: u32) -> Extent2D {
+    Extent2D::new((extent.width() >> level).max(1), (extent.height() >> level).max(1)).unwrap()
+}
+
+/// Downsampling filters for generating mipmaps.
+///
+/// Box filtered mips are visibly soft and alias on environment maps,
+/// so the sharper windowed-sinc filters are usually preferable.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum Filter {
+    /// Averages each 2x2 block. Fast, but soft and prone to aliasing.
+    Box,
+    /// Tent filter, slightly smoother than [Filter::Box].
+    Triangle,
+    /// Kaiser-windowed sinc ([KaiserKernel]). Sharp with little ringing.
+    #[default]
+    Kaiser,
+    /// Three-lobed Lanczos filter ([LanczosKernel::LANCZOS3]). The sharpest, with the most ringing.
+    Lanczos3,
+}
+
+impl ResampleKernel for Filter {
+    fn support(&self) -> f32 {
+        match self {
+            Filter::Box => BoxKernel.support(),
+            Filter::Triangle => TriangleKernel.support(),
+            Filter::Kaiser => KaiserKernel::default().support(),
+            Filter::Lanczos3 => LanczosKernel::LANCZOS3.support(),
+        }
+    }
+
+    fn eval(&self, x: f32) -> f32 {
+        match self {
+            Filter::Box => BoxKernel.eval(x),
+            Filter::Triangle => TriangleKernel.eval(x),
+            Filter::Kaiser => KaiserKernel::default().eval(x),
+            Filter::Lanczos3 => LanczosKernel::LANCZOS3.eval(x),
+        }
+    }
+}
+
+/// Generate a full mip chain for an image, down to 1x1, using the default [Filter].
+pub fn generate_mipmaps<T: RgbTexel + From<[f32;3]>>(image: &HdrImage<T>) -> Vec<HdrImage<T>> {
+    generate_mipmaps_with_filter(image, Filter::default())
+}
+
+/// Generate a full mip chain for an image, down to 1x1, using the given downsampling [Filter].
+///
+/// Each level is filtered from the previous one in linear light (not in the packed representation),
+/// and is only repacked once it has been computed, so rounding errors do not accumulate down the chain.
+/// Filters with negative lobes are applied with [ResampleMode::ClampedLobes]
+/// so that bright highlights such as the sun do not ring into their surroundings.
+/// The returned levels start with a copy of the image itself.
+pub fn generate_mipmaps_with_filter<T: RgbTexel + From<[f32;3]>>(image: &HdrImage<T>, filter: Filter) -> Vec<HdrImage<T>> {
+    let count = mip_level_count(image.extent());
+    let mut levels = Vec::with_capacity(count as usize);
+    levels.push(image.clone());
+
+    let mut extent = image.extent();
+    let mut linear: Box<[[f32;3]]> = image.pixels().iter().map(|texel| texel.to_rgb()).collect();
+    for level in 1..count {
+        let next_extent = mip_extent(image.extent(), level);
+        linear = resize_with_mode(extent, &linear, next_extent, &filter, ResampleMode::ClampedLobes);
+        extent = next_extent;
+        levels.push(HdrImage::new(extent, linear.iter().map(|&rgb| T::from(rgb)).collect()));
+    }
+    levels
+}
+
+/// Location of one mip level within a [PackedMipChain].
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct MipLevelRange {
+    /// Offset of the first byte of the level.
+    pub offset: usize,
+    /// Length of the level in bytes.
+    pub len: usize,
+}
+
+/// The texels of a mip chain concatenated into a single buffer, laid out as in a KTX2 file.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct PackedMipChain {
+    /// Dimensions of the base level.
+    pub extent: Extent2D,
+    /// Texel data of every level, smallest level first.
+    pub data: Box<[u8]>,
+    /// Location of each level in `data`, largest level first.
+    pub levels: Vec<MipLevelRange>,
+}
+
+/// Pack a mip chain (such as one produced by [generate_mipmaps]) into a single buffer.
+///
+/// Levels are stored smallest first, as KTX2 requires, in native byte order.
+/// As every level is a whole number of texels, each level is aligned to the texel size.
+///
+/// Panics if `levels` is empty or the levels do not have the dimensions of a mip chain.
+pub fn pack_mipmaps<T: Pod>(levels: &[HdrImage<T>]) -> PackedMipChain {
+    let base = levels.first().expect("mip chain must have at least one level");
+    let total: usize = levels.iter().map(|level| level.as_bytes().len()).sum();
+    let mut data = Vec::with_capacity(total);
+    let mut ranges = vec![MipLevelRange{offset: 0, len: 0}; levels.len()];
+    for (i, level) in levels.iter().enumerate().rev() {
+        assert_eq!(level.extent(), mip_extent(base.extent(), i as u32), "mip level {i} has the wrong dimensions");
+        ranges[i] = MipLevelRange{offset: data.len(), len: level.as_bytes().len()};
+        data.extend_from_slice(level.as_bytes());
+    }
+    PackedMipChain{extent: base.extent(), data: data.into_boxed_slice(), levels: ranges}
+}
@@ -0,0 +1,50 @@
+use crate::types::*;
+
+use image::{error::{DecodingError, ImageFormatHint, ParameterError, ParameterErrorKind}, ImageError, ImageResult};
+use memmap2::Mmap;
+use std::{fs::File, path::Path};
+
+/// A raw file of little-endian [RGB9E5] texels in row-major order, mapped into memory
+/// so that large textures can be streamed without copying them through a decoder.
+#[derive(Debug)]
+pub struct MappedRgb9e5 {
+    extent: Extent2D,
+    offset: usize,
+    mmap: Mmap,
+}
+
+impl MappedRgb9e5 {
+    /// Map a file containing exactly the texels of an image with the given dimensions.
+    pub fn open(path: &Path, extent: Extent2D) -> ImageResult<Self> {
+        Self::open_with_offset(path, extent, 0)
+    }
+
+    /// Map a file containing the texels of an image with the given dimensions starting `offset` bytes in,
+    /// such as after the 20-byte header of an RGB9E5 file saved by [save_raw](crate::save_raw).
+    /// Fails if the file is too short, or if the texels are not aligned to 4 bytes.
+    pub fn open_with_offset(path: &Path, extent: Extent2D, offset: usize) -> ImageResult<Self> {
+        let file = File::open(path).map_err(ImageError::IoError)?;
+        // SAFETY: the mapping is read-only. Modifying the file while it is mapped is undefined behaviour,
+        // which callers of memory-mapping APIs must already avoid.
+        let mmap = unsafe { Mmap::map(&file) }.map_err(ImageError::IoError)?;
+        let end = extent.texel_count().checked_mul(4).and_then(|len| offset.checked_add(len));
+        let Some(end) = end.filter(|&end| end <= mmap.len()) else {
+            return Err(ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::DimensionMismatch)));
+        };
+        // validate the alignment and length once so that texels() cannot fail.
+        bytemuck::try_cast_slice::<u8, RGB9E5>(&mmap[offset..end]).map_err(|e| {
+            ImageError::Decoding(DecodingError::new(ImageFormatHint::Name("raw RGB9E5".into()), format!("{e:?}")))
+        })?;
+        Ok(MappedRgb9e5{extent, offset, mmap})
+    }
+
+    pub fn extent(&self) -> Extent2D {
+        self.extent
+    }
+
+    /// Texels in row-major order, borrowed directly from the mapped file.
+    /// As the file is little-endian, these are only correct on little-endian targets; others should use [load_raw](crate::load_raw).
+    pub fn texels(&self) -> &[RGB9E5] {
+        bytemuck::cast_slice(&self.mmap[self.offset..self.offset + self.extent.texel_count() * 4])
+    }
+}
@@ -0,0 +1,87 @@
+use crate::types::*;
+use crate::colorspace::*;
+use crate::hdrimage::*;
+
+use std::fmt;
+
+/// Colour space names (and aliases) used by the ACES and CG OpenColorIO configs, with the primaries they correspond to.
+/// Only scene-linear colour spaces, which are a matrix away from each other, are included.
+const COLOR_SPACES: &[(&str, Primaries)] = &[
+    ("ACES2065-1", Primaries::AcesAp0),
+    ("ACES - ACES2065-1", Primaries::AcesAp0),
+    ("aces2065_1", Primaries::AcesAp0),
+    ("lin_ap0", Primaries::AcesAp0),
+    ("ACEScg", Primaries::AcesCg),
+    ("ACES - ACEScg", Primaries::AcesCg),
+    ("lin_ap1", Primaries::AcesCg),
+    ("Linear Rec.709 (sRGB)", Primaries::Rec709),
+    ("Linear Rec.709", Primaries::Rec709),
+    ("Utility - Linear - sRGB", Primaries::Rec709),
+    ("Utility - Linear - Rec.709", Primaries::Rec709),
+    ("lin_rec709", Primaries::Rec709),
+    ("lin_srgb", Primaries::Rec709),
+    ("Linear Rec.2020", Primaries::Rec2020),
+    ("Utility - Linear - Rec.2020", Primaries::Rec2020),
+    ("lin_rec2020", Primaries::Rec2020),
+    ("Linear P3-D65", Primaries::DisplayP3),
+    ("Utility - Linear - P3-D65", Primaries::DisplayP3),
+    ("lin_p3d65", Primaries::DisplayP3),
+];
+
+/// Look up a scene-linear colour space by its name in the ACES or CG OpenColorIO configs, ignoring case.
+pub fn primaries_by_config_name(name: &str) -> Option<Primaries> {
+    let name = name.trim();
+    COLOR_SPACES.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|&(_, primaries)| primaries)
+}
+
+/// A change of primaries between two colour spaces named as in an OpenColorIO config,
+/// such as `ACEScg -> Linear Rec.709 (sRGB)`.
+///
+/// This is not an OpenColorIO binding: the names are only looked up in a built-in table of the scene-linear colour spaces
+/// of the ACES and CG configs (see [primaries_by_config_name]), which are a matrix away from each other.
+/// Transforms involving display encodings, looks, LUTs, or custom configs need OpenColorIO itself.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct NamedPrimariesTransform {
+    pub src: Primaries,
+    pub dst: Primaries,
+}
+
+impl NamedPrimariesTransform {
+    /// Parse a transform written as `<source> -> <destination>` (or with `→`).
+    pub fn parse(transform: &str) -> Result<Self, NamedPrimariesTransformError> {
+        let (src, dst) = transform.split_once("->").or_else(|| transform.split_once('→'))
+            .ok_or_else(|| NamedPrimariesTransformError::Syntax(transform.to_string()))?;
+        let lookup = |name: &str| primaries_by_config_name(name).ok_or_else(|| NamedPrimariesTransformError::UnknownColorSpace(name.trim().to_string()));
+        Ok(NamedPrimariesTransform{src: lookup(src)?, dst: lookup(dst)?})
+    }
+
+    /// Matrix applying the transform to linear RGB colours.
+    pub fn matrix(self) -> [[f32;3];3] {
+        self.src.conversion_matrix(self.dst)
+    }
+
+    /// Apply the transform to every texel of an image.
+    pub fn apply<T: RgbTexel>(self, image: &mut HdrImage<T>) {
+        convert_primaries(image.pixels_mut(), self.src, self.dst);
+    }
+}
+
+/// Error returned when parsing an [NamedPrimariesTransform].
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum NamedPrimariesTransformError {
+    /// The transform is not of the form `<source> -> <destination>`.
+    Syntax(String),
+    /// The named colour space is not one of the supported scene-linear spaces.
+    UnknownColorSpace(String),
+}
+
+impl fmt::Display for NamedPrimariesTransformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NamedPrimariesTransformError::Syntax(transform) => write!(f, "invalid colour transform '{transform}' (expected '<source> -> <destination>')"),
+            NamedPrimariesTransformError::UnknownColorSpace(name) => write!(f, "unsupported colour space '{name}'"),
+        }
+    }
+}
+
+impl std::error::Error for NamedPrimariesTransformError {}
@@ -0,0 +1,32 @@
+use crate::types::*;
+use crate::hdrimage::*;
+use crate::load::image_extent;
+
+use bytemuck::Pod;
+use image::{error::{LimitError, LimitErrorKind, ParameterError, ParameterErrorKind}, ImageError, ImageResult};
+use ndarray::{Array3, ArrayView3};
+
+impl<T: RgbTexel> HdrImage<T> {
+    /// Unpack into an array of shape `(height, width, 3)` for analysis with ndarray.
+    pub fn to_ndarray(&self) -> Array3<f32> {
+        let data: Vec<f32> = self.pixels().iter().flat_map(|texel| texel.to_rgb()).collect();
+        Array3::from_shape_vec((self.height() as usize, self.width() as usize, 3), data).unwrap()
+    }
+}
+
+impl<T: Pod + From<[f32;3]>> HdrImage<T> {
+    /// Pack an array of shape `(height, width, 3)` (in any memory layout).
+    /// Fails if the array is empty or does not have three channels.
+    pub fn from_ndarray(array: ArrayView3<f32>) -> ImageResult<Self> {
+        let (height, width, channels) = array.dim();
+        if channels != 3 {
+            return Err(ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::DimensionMismatch)));
+        }
+        let (Ok(width), Ok(height)) = (u32::try_from(width), u32::try_from(height)) else {
+            return Err(ImageError::Limits(LimitError::from_kind(LimitErrorKind::DimensionError)));
+        };
+        let extent = image_extent(width, height)?;
+        let data = array.rows().into_iter().map(|px| T::from([px[0], px[1], px[2]])).collect();
+        Ok(HdrImage::new(extent, data))
+    }
+}
@@ -0,0 +1,154 @@
+use crate::types::*;
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+/// Apply a function to the colour of every texel, unpacking and repacking as necessary.
+pub fn map_rgb<T: RgbTexel>(data: &mut [T], f: impl Fn([f32;3]) -> [f32;3]) {
+    for texel in data.iter_mut() {
+        *texel = texel.with_rgb(f(texel.to_rgb()));
+    }
+}
+
+/// Like [map_rgb], but processes texels in parallel on the rayon thread pool.
+#[cfg(feature = "rayon")]
+pub fn par_map_rgb<T: RgbTexel + Send>(data: &mut [T], f: impl Fn([f32;3]) -> [f32;3] + Sync) {
+    data.par_iter_mut().for_each(|texel| *texel = texel.with_rgb(f(texel.to_rgb())));
+}
+
+/// Runs `f` on a separate rayon thread pool of `threads` threads, so that the parallel functions it calls
+/// (such as [par_map_rgb]) leave the other CPUs free for other applications. A count of 0 uses one thread per CPU.
+///
+/// The pool is created for each call, so batches should run entirely within a single call.
+#[cfg(feature = "rayon")]
+pub fn with_thread_count<R: Send>(threads: usize, f: impl FnOnce() -> R + Send) -> Result<R, rayon::ThreadPoolBuildError> {
+    Ok(rayon::ThreadPoolBuilder::new().num_threads(threads).build()?.install(f))
+}
+
+/// A highlight shoulder which compresses values above a threshold smoothly towards a maximum (with a Reinhard curve),
+/// so that suns and other very bright sources roll off instead of hard-clipping when packed into a format with a limited range.
+///
+/// The curve is applied to the largest channel of each colour, scaling the others to keep the hue,
+/// and can be undone (within the precision of the packed format) with [SoftClip::invert] given the same parameters,
+/// which can be stored alongside textures (as `encode_rgb9e5_ktx2_with_soft_clip` does).
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct SoftClip {
+    /// Values up to this are left unchanged.
+    pub threshold: f32,
+    /// Value which the largest inputs approach. This should be no more than the maximum of the format being packed into.
+    pub max: f32,
+}
+
+impl SoftClip {
+    /// A shoulder starting at `threshold` which keeps values within the range of [RGB9E5].
+    pub fn rgb9e5(threshold: f32) -> Self {
+        SoftClip{threshold, max: RGB9E5::MAX}
+    }
+
+    /// Compress the highlights of a colour.
+    pub fn apply(self, rgb: [f32;3]) -> [f32;3] {
+        let peak = rgb[0].max(rgb[1]).max(rgb[2]);
+        if peak <= self.threshold || self.max <= self.threshold {
+            return rgb;
+        }
+        let over = peak - self.threshold;
+        let compressed = self.threshold + over / (1.0 + over / (self.max - self.threshold));
+        rgb.map(|c| c * (compressed / peak))
+    }
+
+    /// Expand the highlights of a colour compressed by [SoftClip::apply], restoring its original value.
+    /// Colours at or above the maximum (which no finite input reaches) are returned as they are.
+    pub fn invert(self, rgb: [f32;3]) -> [f32;3] {
+        let peak = rgb[0].max(rgb[1]).max(rgb[2]);
+        if peak <= self.threshold || peak >= self.max {
+            return rgb;
+        }
+        let over = peak - self.threshold;
+        let expanded = self.threshold + over / (1.0 - over / (self.max - self.threshold));
+        rgb.map(|c| c * (expanded / peak))
+    }
+}
+
+/// Compress the highlights of every texel with a [SoftClip] shoulder, such as before packing into [RGB9E5].
+pub fn soft_clip<T: RgbTexel>(data: &mut [T], clip: SoftClip) {
+    map_rgb(data, |rgb| clip.apply(rgb));
+}
+
+/// Multiply every texel by a constant colour, such as for exposure or white balance adjustments.
+pub fn mul_color<T: RgbTexel>(data: &mut [T], color: [f32;3]) {
+    map_rgb(data, |rgb| [rgb[0] * color[0], rgb[1] * color[1], rgb[2] * color[2]]);
+}
+
+/// Add a constant colour to every texel.
+/// Results are clamped to zero when packed into unsigned formats.
+pub fn add_color<T: RgbTexel>(data: &mut [T], color: [f32;3]) {
+    map_rgb(data, |rgb| [rgb[0] + color[0], rgb[1] + color[1], rgb[2] + color[2]]);
+}
+
+/// Raise each channel of every texel to a per-channel power.
+/// Negative values are treated as zero.
+pub fn pow<T: RgbTexel>(data: &mut [T], exponent: [f32;3]) {
+    map_rgb(data, |rgb| [
+        rgb[0].max(0.0).powf(exponent[0]),
+        rgb[1].max(0.0).powf(exponent[1]),
+        rgb[2].max(0.0).powf(exponent[2]),
+    ]);
+}
+
+/// Scale down any texels brighter than `max_luminance`, preserving their chromaticity.
+/// This is useful for removing fireflies or limiting the brightness of the sun in light probes.
+pub fn clamp_luminance<T: RgbTexel>(data: &mut [T], max_luminance: f32) {
+    map_rgb(data, |rgb| {
+        let lum = luminance(rgb);
+        if lum > max_luminance {
+            let scale = max_luminance / lum;
+            [rgb[0] * scale, rgb[1] * scale, rgb[2] * scale]
+        } else {
+            rgb
+        }
+    });
+}
+
+/// Single-channel mask which restricts an operation to the texels where it is greater than `threshold`.
+#[derive(Clone, Copy, Debug)]
+pub struct Mask<'a> {
+    /// One value per texel, in the same order as the image being masked.
+    pub data: &'a [f32],
+    pub threshold: f32,
+}
+
+impl<'a> Mask<'a> {
+    pub fn new(data: &'a [f32], threshold: f32) -> Self {
+        Mask{data, threshold}
+    }
+
+    /// Whether the texel at index `i` is affected by masked operations.
+    pub fn selects(&self, i: usize) -> bool {
+        self.data[i] > self.threshold
+    }
+}
+
+/// Apply a function to the colour of the texels selected by `mask`, leaving the rest untouched.
+pub fn map_rgb_masked<T: RgbTexel>(data: &mut [T], mask: &Mask, f: impl Fn([f32;3]) -> [f32;3]) {
+    assert_eq!(data.len(), mask.data.len(), "mask does not match image dimensions");
+    for (i, texel) in data.iter_mut().enumerate() {
+        if mask.selects(i) {
+            *texel = texel.with_rgb(f(texel.to_rgb()));
+        }
+    }
+}
+
+/// Apply any whole-image operation (such as [mul_color] or a blur) to only the texels selected by `mask`.
+///
+/// The operation is run on a copy of the image, and the result is kept only where the mask is set.
+/// This allows operations that read neighbouring texels to see the whole image.
+pub fn apply_masked<T: Copy>(data: &mut [T], mask: &Mask, op: impl FnOnce(&mut [T])) {
+    assert_eq!(data.len(), mask.data.len(), "mask does not match image dimensions");
+    let mut modified = data.to_vec();
+    op(&mut modified);
+    for (i, (texel, new)) in data.iter_mut().zip(modified).enumerate() {
+        if mask.selects(i) {
+            *texel = new;
+        }
+    }
+}
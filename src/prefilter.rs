@@ -0,0 +1,215 @@
+use crate::types::*;
+use crate::cubemap::*;
+
+use std::f32::consts::PI;
+
+/// Filtered copies of a cubemap at every power-of-two size, for sampling with a level of detail.
+struct SourceChain {
+    /// Levels in linear RGB, largest first.
+    levels: Vec<HdrCubemap<[f32;3]>>,
+    weighting: CubemapWeighting,
+}
+
+impl SourceChain {
+    /// Each texel of a level is the average of the four texels it covers in the level above, weighted by `weighting`.
+    fn new<T: RgbTexel>(cubemap: &HdrCubemap<T>, weighting: CubemapWeighting) -> Self {
+        let base = cubemap.faces().each_ref().map(|face| face.iter().map(|texel| texel.to_rgb()).collect());
+        let mut levels = vec![HdrCubemap::new(cubemap.face_size(), base)];
+        while let Some(level) = levels.last().filter(|level| level.face_size() > 1) {
+            let size = level.face_size();
+            let half = size / 2;
+            let weights = weighting.face_weights(size);
+            let next = level.faces().each_ref().map(|face| {
+                (0..half * half).map(|i| {
+                    let (x, y) = (2 * (i % half), 2 * (i / half));
+                    let mut sum = [0.0; 3];
+                    let mut total_weight = 0.0;
+                    for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                        let index = ((y + dy) * size + x + dx) as usize;
+                        let weight = weights[index];
+                        for c in 0..3 {
+                            sum[c] += face[index][c] * weight;
+                        }
+                        total_weight += weight;
+                    }
+                    sum.map(|c| c / total_weight)
+                }).collect()
+            });
+            levels.push(HdrCubemap::new(half, next));
+        }
+        SourceChain{levels, weighting}
+    }
+
+    /// Weight (the solid angle, or the average solid angle for uniform weighting) of the texel of the base level in the direction `dir`.
+    fn texel_angle(&self, dir: [f32;3]) -> f32 {
+        let size = self.levels[0].face_size();
+        let (_, s, t) = CubeFace::from_direction(dir);
+        let coord = |s: f32| (((s + 1.0) * 0.5 * size as f32) as u32).min(size - 1);
+        self.weighting.texel_weight(size, coord(s), coord(t))
+    }
+
+    /// Bilinear sample of one level in the direction `dir`, clamped at the edges of faces.
+    fn sample_level(&self, level: usize, dir: [f32;3]) -> [f32;3] {
+        let level = &self.levels[level];
+        let size = level.face_size();
+        let (face, s, t) = CubeFace::from_direction(dir);
+        let texels = level.face(face);
+        let max = (size - 1) as f32;
+        let x = ((s + 1.0) * 0.5 * size as f32 - 0.5).clamp(0.0, max);
+        let y = ((t + 1.0) * 0.5 * size as f32 - 0.5).clamp(0.0, max);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as u32, y0 as u32);
+        let (x1, y1) = ((x0 + 1).min(size - 1), (y0 + 1).min(size - 1));
+        let at = |x: u32, y: u32| texels[(y * size + x) as usize];
+        let (a, b, c, d) = (at(x0, y0), at(x1, y0), at(x0, y1), at(x1, y1));
+        core::array::from_fn(|i| {
+            (a[i] * (1.0 - fx) + b[i] * fx) * (1.0 - fy) + (c[i] * (1.0 - fx) + d[i] * fx) * fy
+        })
+    }
+
+    /// Trilinear sample at a fractional level of detail.
+    fn sample(&self, lod: f32, dir: [f32;3]) -> [f32;3] {
+        let lod = lod.clamp(0.0, (self.levels.len() - 1) as f32);
+        let level = lod.floor() as usize;
+        let f = lod - level as f32;
+        let a = self.sample_level(level, dir);
+        if f == 0.0 || level + 1 >= self.levels.len() {
+            return a;
+        }
+        let b = self.sample_level(level + 1, dir);
+        core::array::from_fn(|i| a[i] * (1.0 - f) + b[i] * f)
+    }
+}
+
+/// The `i`th of `n` points of the Hammersley sequence on the unit square.
+fn hammersley(i: u32, n: u32) -> (f32, f32) {
+    (i as f32 / n as f32, i.reverse_bits() as f32 / 2f32.powi(32))
+}
+
+/// GGX normal distribution function for `alpha` (roughness squared).
+fn ggx_d(n_dot_h: f32, alpha: f32) -> f32 {
+    let a2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    a2 / (PI * d * d)
+}
+
+/// Fraction of light reflected by a GGX surface with a white specular colour viewed along its normal, counting only single scattering.
+/// With the view direction along the normal, this is the average of the Smith masking term of the light direction over the lobe.
+fn ggx_directional_albedo(alpha: f32) -> f32 {
+    const SAMPLES: u32 = 1024;
+    let a2 = alpha * alpha;
+    let sum: f32 = (0..SAMPLES).map(|i| {
+        let (_, u) = hammersley(i, SAMPLES);
+        let n_dot_h = ((1.0 - u) / (1.0 + (a2 - 1.0) * u)).sqrt();
+        let n_dot_l = 2.0 * n_dot_h * n_dot_h - 1.0;
+        if n_dot_l <= 0.0 {
+            return 0.0;
+        }
+        2.0 * n_dot_l / (n_dot_l + (a2 + (1.0 - a2) * n_dot_l * n_dot_l).sqrt())
+    }).sum();
+    sum / SAMPLES as f32
+}
+
+fn normalize(v: [f32;3]) -> [f32;3] {
+    let len = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    v.map(|c| c / len)
+}
+
+fn cross(a: [f32;3], b: [f32;3]) -> [f32;3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+/// Prefilter radiance around the normal `n` for a GGX lobe with roughness `alpha`,
+/// assuming that the view direction is the normal as in the split-sum approximation.
+/// The sample points are offset by `rotation` (wrapping around the unit square).
+fn prefilter_texel(source: &SourceChain, n: [f32;3], alpha: f32, quality: BakeQuality, rotation: (f32, f32)) -> [f32;3] {
+    let up = if n[1].abs() < 0.999 { [0.0, 1.0, 0.0] } else { [1.0, 0.0, 0.0] };
+    let tangent = normalize(cross(up, n));
+    let bitangent = cross(n, tangent);
+    let mut sum = [0.0; 3];
+    let mut total_weight = 0.0;
+    for i in 0..quality.sample_count {
+        let (u1, u2) = hammersley(i, quality.sample_count);
+        let (u1, u2) = ((u1 + rotation.0).fract(), (u2 + rotation.1).fract());
+        let phi = 2.0 * PI * u1;
+        // in tangent space, the half vector for importance sampling and the light direction otherwise
+        let cos_theta = if quality.importance_sampling {
+            ((1.0 - u2) / (1.0 + (alpha * alpha - 1.0) * u2)).sqrt()
+        } else {
+            1.0 - u2
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let local = [sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta];
+        let v = core::array::from_fn::<f32, 3, _>(|c| tangent[c] * local[0] + bitangent[c] * local[1] + n[c] * local[2]);
+
+        let (l, n_dot_h, weight_scale) = if quality.importance_sampling {
+            // reflect the view direction (the normal) about the half vector
+            let n_dot_h = cos_theta;
+            (core::array::from_fn(|c| 2.0 * n_dot_h * v[c] - n[c]), n_dot_h, 1.0)
+        } else {
+            let h = normalize(core::array::from_fn(|c| v[c] + n[c]));
+            let n_dot_h = n[0] * h[0] + n[1] * h[1] + n[2] * h[2];
+            (v, n_dot_h, ggx_d(n_dot_h, alpha))
+        };
+        let n_dot_l = n[0] * l[0] + n[1] * l[1] + n[2] * l[2];
+        if n_dot_l <= 0.0 {
+            continue;
+        }
+
+        // sample a blurrier level where samples are sparse, to avoid aliasing (Colbert and Krivanek's filtered importance sampling)
+        let pdf = if quality.importance_sampling { ggx_d(n_dot_h, alpha) / 4.0 } else { 1.0 / (2.0 * PI) };
+        let sample_angle = 1.0 / (quality.sample_count as f32 * pdf).max(f32::MIN_POSITIVE);
+        let lod = 0.5 * (sample_angle / source.texel_angle(l)).log2() + 1.0;
+
+        let weight = n_dot_l * weight_scale;
+        let radiance = source.sample(lod, l);
+        for c in 0..3 {
+            sum[c] += radiance[c] * weight;
+        }
+        total_weight += weight;
+    }
+    if total_weight > 0.0 { sum.map(|c| c / total_weight) } else { source.sample(0.0, n) }
+}
+
+/// Prefilter a cubemap of radiance with GGX lobes of increasing roughness for image-based specular lighting,
+/// producing a mip chain of `levels` [RGB9E5] cubemaps which can be packed with [pack_cubemap_mipmaps] for uploading.
+///
+/// Level `i` is filtered for a perceptual roughness of `i / (levels - 1)` (a GGX alpha of its square),
+/// so shaders should sample at a level of detail of `roughness * (levels - 1)`. Level 0 is a copy of the source.
+/// Lobes are evaluated with the view direction equal to the normal, as in the split-sum approximation,
+/// taking `quality.sample_count` samples per texel from a Hammersley sequence shifted by random offsets drawn from `quality.seed`.
+/// Samples are read from blurred copies of the source chosen by the solid angle of each sample relative to that of the texel it lands on,
+/// with texels weighted according to `quality.weighting` (by their exact solid angle by default, see [CubemapWeighting]).
+/// If `quality.multiscatter_compensation` is set, each level is divided by the single-scattering albedo of its lobe
+/// for a white surface viewed along the normal, restoring the energy lost to multiple scattering.
+/// This is exact for white metals and slightly brightens darker specular colours, and shaders should not apply their own compensation on top of it.
+///
+/// This is slow for large cubemaps; the base level is usually 128 or 256 texels per side.
+///
+/// Panics if `levels` is zero or more than the number of mip levels of the cubemap.
+pub fn prefilter_ggx<T: RgbTexel>(cubemap: &HdrCubemap<T>, levels: u32, quality: BakeQuality) -> Vec<HdrCubemap<RGB9E5>> {
+    let face_size = cubemap.face_size();
+    let max_levels = u32::BITS - face_size.leading_zeros();
+    assert!(levels > 0 && levels <= max_levels, "a {face_size}x{face_size} cubemap cannot have {levels} mip levels");
+    let source = SourceChain::new(cubemap, quality.weighting);
+    let mut rng = SeededRng::new(quality.seed);
+    let rotation = (rng.next_f32(), rng.next_f32());
+
+    let mut out = Vec::with_capacity(levels as usize);
+    out.push(HdrCubemap::new(face_size, cubemap.faces().each_ref().map(|face| face.iter().map(|texel| RGB9E5::pack(texel.to_rgb())).collect())));
+    for level in 1..levels {
+        let roughness = level as f32 / (levels - 1) as f32;
+        let alpha = (roughness * roughness).max(1e-4);
+        let size = (face_size >> level).max(1);
+        let compensation = if quality.multiscatter_compensation { 1.0 / ggx_directional_albedo(alpha) } else { 1.0 };
+        let faces = CubeFace::ALL.map(|face| {
+            (0..size * size).map(|i| {
+                let n = normalize(face.direction(texel_face_coord(size, i % size), texel_face_coord(size, i / size)));
+                RGB9E5::pack(prefilter_texel(&source, n, alpha, quality, rotation).map(|c| c * compensation))
+            }).collect()
+        });
+        out.push(HdrCubemap::new(size, faces));
+    }
+    out
+}
@@ -0,0 +1,303 @@
+use crate::types::*;
+
+use image::{ImageError, ImageResult};
+use std::{fs::File, io::{BufWriter, Write}, path::Path};
+
+/// Optional header information of a Radiance HDR file, as returned by [decode_radiance_with_metadata](crate::decode_radiance_with_metadata).
+/// This can be passed back to the encoder with [RadianceSaveOptions::from_metadata], so that converting a file does not lose its calibration.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct RadianceMetadata {
+    /// Product of all the `EXPOSURE` headers, which records that the pixel values have been multiplied by this
+    /// relative to the original radiance values.
+    pub exposure: Option<f32>,
+    /// CIE (x, y) chromaticities of the red, green, and blue primaries and white point from the `PRIMARIES` header,
+    /// as written in the file.
+    pub primaries: Option<[f32; 8]>,
+    /// Height of each pixel divided by its width, from the product of all the `PIXASPECT` headers.
+    pub pixel_aspect: Option<f32>,
+    /// Program which wrote the file, from the `SOFTWARE` header.
+    pub software: Option<String>,
+    /// Comment lines of the header, without the leading `#`.
+    pub comments: Vec<String>,
+}
+
+impl RadianceMetadata {
+    /// Parses the header of a Radiance file, up to the blank line ending it.
+    /// Unrecognized or malformed lines are ignored, as other readers do.
+    pub(crate) fn from_header(header: &[u8]) -> Self {
+        let mut meta = RadianceMetadata::default();
+        let text = String::from_utf8_lossy(header);
+        // the first line is the magic number `#?RADIANCE` (or another program name)
+        for line in text.lines().skip(1).map(str::trim_end) {
+            if let Some(comment) = line.strip_prefix('#') {
+                meta.comments.push(comment.strip_prefix(' ').unwrap_or(comment).to_string());
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else { continue };
+            let value = value.trim();
+            match key.trim() {
+                "EXPOSURE" => if let Ok(v) = value.parse::<f32>() {
+                    meta.exposure = Some(meta.exposure.unwrap_or(1.0) * v);
+                },
+                "PIXASPECT" => if let Ok(v) = value.parse::<f32>() {
+                    meta.pixel_aspect = Some(meta.pixel_aspect.unwrap_or(1.0) * v);
+                },
+                "PRIMARIES" => {
+                    let values: Vec<f32> = value.split_whitespace().filter_map(|v| v.parse().ok()).collect();
+                    if let Ok(p) = values.try_into() {
+                        meta.primaries = Some(p);
+                    }
+                }
+                "SOFTWARE" => meta.software = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        meta
+    }
+}
+
+/// Header options for writing Radiance HDR files, in builder form.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct RadianceSaveOptions {
+    exposure: Option<f32>,
+    primaries: Option<[f32; 8]>,
+    pixel_aspect: Option<f32>,
+    software: Option<String>,
+    comments: Vec<String>,
+}
+
+/// Header lines cannot contain line breaks.
+fn header_line(s: &str) -> String {
+    s.replace(['\r', '\n'], " ")
+}
+
+impl RadianceSaveOptions {
+    /// Options for a file with no optional headers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the `EXPOSURE` header, which records that the pixel values have been multiplied by `exposure`
+    /// relative to the original radiance values.
+    pub fn exposure(mut self, exposure: f32) -> Self {
+        self.exposure = Some(exposure);
+        self
+    }
+
+    /// Set the `PRIMARIES` header to the CIE (x, y) chromaticities of the red, green, and blue primaries and white point
+    /// (in that order), for data not in the default Radiance primaries.
+    pub fn primaries(mut self, primaries: [f32; 8]) -> Self {
+        self.primaries = Some(primaries);
+        self
+    }
+
+    /// Set the `PIXASPECT` header to the height of each pixel divided by its width, for images with non-square pixels.
+    pub fn pixel_aspect(mut self, pixel_aspect: f32) -> Self {
+        self.pixel_aspect = Some(pixel_aspect);
+        self
+    }
+
+    /// Options which write all the headers of a file read with [decode_radiance_with_metadata](crate::decode_radiance_with_metadata).
+    pub fn from_metadata(metadata: &RadianceMetadata) -> Self {
+        RadianceSaveOptions{
+            exposure: metadata.exposure,
+            primaries: metadata.primaries,
+            pixel_aspect: metadata.pixel_aspect,
+            software: metadata.software.clone(),
+            comments: metadata.comments.clone(),
+        }
+    }
+
+    /// Set the `SOFTWARE` header naming the program which wrote the file.
+    pub fn software(mut self, software: impl Into<String>) -> Self {
+        self.software = Some(software.into());
+        self
+    }
+
+    /// Add a comment line to the header. May be called multiple times.
+    pub fn comment(mut self, comment: impl Into<String>) -> Self {
+        self.comments.push(comment.into());
+        self
+    }
+
+    fn header(&self, extent: Extent2D) -> String {
+        let mut header = String::from("#?RADIANCE\n");
+        for comment in &self.comments {
+            header += &format!("# {}\n", header_line(comment));
+        }
+        if let Some(software) = &self.software {
+            header += &format!("SOFTWARE={}\n", header_line(software));
+        }
+        if let Some(exposure) = self.exposure {
+            header += &format!("EXPOSURE={exposure}\n");
+        }
+        if let Some(p) = self.primaries {
+            header += &format!("PRIMARIES={} {} {} {} {} {} {} {}\n", p[0], p[1], p[2], p[3], p[4], p[5], p[6], p[7]);
+        }
+        if let Some(pixel_aspect) = self.pixel_aspect {
+            header += &format!("PIXASPECT={pixel_aspect}\n");
+        }
+        header += "FORMAT=32-bit_rle_rgbe\n\n";
+        header += &format!("-Y {} +X {}\n", extent.height(), extent.width());
+        header
+    }
+
+    /// Encodes [RGBE8] texel data as a Radiance HDR image with these header options.
+    pub fn encode<W: Write>(&self, extent: Extent2D, data: &[RGBE8], mut out: W) -> ImageResult<()> {
+        assert_eq!(data.len(), extent.texel_count(), "data does not match image dimensions");
+        out.write_all(self.header(extent).as_bytes()).map_err(ImageError::IoError)?;
+
+        let width = extent.width() as usize;
+        let mut line = Vec::new();
+        for row in data.chunks_exact(width) {
+            line.clear();
+            if (8..0x8000).contains(&width) {
+                // run-length encode each channel separately
+                line.extend_from_slice(&[2, 2, (width >> 8) as u8, width as u8]);
+                for c in 0..4 {
+                    let channel: Vec<u8> = row.iter().map(|px| bytemuck::cast::<RGBE8, [u8; 4]>(*px)[c]).collect();
+                    write_rle(&mut line, &channel);
+                }
+            } else {
+                // the RLE format only supports these widths, so write flat scanlines.
+                line.extend_from_slice(bytemuck::cast_slice(row));
+            }
+            out.write_all(&line).map_err(ImageError::IoError)?;
+        }
+        out.flush().map_err(ImageError::IoError)
+    }
+
+    /// Saves [RGBE8] texel data as a Radiance HDR file with these header options.
+    pub fn save(&self, path: &Path, extent: Extent2D, data: &[RGBE8]) -> ImageResult<()> {
+        let file = File::create(path).map_err(ImageError::IoError)?;
+        self.encode(extent, data, BufWriter::new(file))
+    }
+}
+
+/// Length of the run of identical bytes at the start of `bytes`, up to the maximum run length.
+fn run_length(bytes: &[u8]) -> usize {
+    bytes.iter().take(127).take_while(|&&b| b == bytes[0]).count()
+}
+
+/// Append one channel of a scanline in the run-length encoding used by Radiance.
+fn write_rle(out: &mut Vec<u8>, bytes: &[u8]) {
+    // runs shorter than this are cheaper to store as literals
+    const MIN_RUN: usize = 4;
+    let mut i = 0;
+    while i < bytes.len() {
+        let run = run_length(&bytes[i..]);
+        if run >= MIN_RUN {
+            out.extend_from_slice(&[128 + run as u8, bytes[i]]);
+            i += run;
+            continue;
+        }
+        let start = i;
+        while i < bytes.len() && i - start < 128 && run_length(&bytes[i..]) < MIN_RUN {
+            i += 1;
+        }
+        out.push((i - start) as u8);
+        out.extend_from_slice(&bytes[start..i]);
+    }
+}
+
+/// Encodes [RGBE8] texel data as a Radiance HDR image with no optional headers.
+/// Use [RadianceSaveOptions] to add metadata.
+pub fn encode_radiance<W: Write>(extent: Extent2D, data: &[RGBE8], out: W) -> ImageResult<()> {
+    RadianceSaveOptions::new().encode(extent, data, out)
+}
+
+/// Saves [RGBE8] texel data as a Radiance HDR file with no optional headers.
+pub fn save_radiance_file(path: &Path, extent: Extent2D, data: &[RGBE8]) -> ImageResult<()> {
+    RadianceSaveOptions::new().save(path, extent, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode_radiance_with_metadata, HdrImage, Primaries};
+
+    /// A row with runs long enough to be run-length encoded, separated by literals.
+    fn test_texels(extent: Extent2D) -> Vec<RGBE8> {
+        (0..extent.texel_count()).map(|i| {
+            let x = i % extent.width() as usize;
+            if x % 16 < 10 { RGBE8{r: 200, g: 100, b: 50, e: 129} } else { RGBE8{r: i as u8, g: 128, b: 255 - i as u8, e: 120 + (i % 8) as u8} }
+        }).collect()
+    }
+
+    fn decode(bytes: &[u8]) -> (HdrImage<RGBE8>, RadianceMetadata) {
+        decode_radiance_with_metadata(bytes, Primaries::Rec709).unwrap()
+    }
+
+    #[test]
+    fn rle_scanline_round_trip() {
+        let extent = Extent2D::new(40, 3).unwrap();
+        let data = test_texels(extent);
+        let mut bytes = Vec::new();
+        encode_radiance(extent, &data, &mut bytes).unwrap();
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 3 +X 40\n";
+        assert!(bytes.starts_with(header));
+        // each scanline starts with the RLE marker and width, and is smaller than it would be flat
+        assert_eq!(bytes[header.len()..header.len() + 4], [2, 2, 0, 40]);
+        assert!(bytes.len() - header.len() < 3 * 40 * 4);
+        assert_eq!(decode(&bytes).0.pixels(), data);
+    }
+
+    #[test]
+    fn flat_scanline_round_trip() {
+        // too narrow for the RLE format
+        let extent = Extent2D::new(5, 2).unwrap();
+        let data = test_texels(extent);
+        let mut bytes = Vec::new();
+        encode_radiance(extent, &data, &mut bytes).unwrap();
+        let header_len = bytes.len() - 10 * 4;
+        assert_eq!(&bytes[header_len..], bytemuck::cast_slice::<RGBE8, u8>(&data));
+        assert_eq!(decode(&bytes).0.pixels(), data);
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let extent = Extent2D::new(9, 2).unwrap();
+        let data = test_texels(extent);
+        let primaries = [0.708, 0.292, 0.17, 0.797, 0.131, 0.046, 0.3127, 0.329];
+        let options = RadianceSaveOptions::new().exposure(0.5).primaries(primaries).pixel_aspect(2.0)
+            .software("rgbe tests").comment("first\nline").comment("second");
+        let mut bytes = Vec::new();
+        options.encode(extent, &data, &mut bytes).unwrap();
+        let (image, meta) = decode(&bytes);
+        assert_eq!(image.pixels(), data);
+        assert_eq!(meta, RadianceMetadata{
+            exposure: Some(0.5),
+            primaries: Some(primaries),
+            pixel_aspect: Some(2.0),
+            software: Some("rgbe tests".into()),
+            comments: vec!["first line".into(), "second".into()],
+        });
+        // line breaks in comments are replaced when writing
+        let written = RadianceSaveOptions::new().exposure(0.5).primaries(primaries).pixel_aspect(2.0)
+            .software("rgbe tests").comment("first line").comment("second");
+        assert_eq!(RadianceSaveOptions::from_metadata(&meta), written);
+    }
+
+    #[test]
+    fn crlf_header() {
+        let extent = Extent2D::new(12, 2).unwrap();
+        let data = test_texels(extent);
+        let mut bytes = Vec::new();
+        RadianceSaveOptions::new().exposure(2.0).software("rgbe tests").encode(extent, &data, &mut bytes).unwrap();
+        // convert the header and resolution line (up to the first texel) to CRLF line endings
+        let end = bytes.windows(2).position(|w| w == b"\n-").unwrap() + 1;
+        let end = end + bytes[end..].iter().position(|&b| b == b'\n').unwrap() + 1;
+        let mut crlf = Vec::new();
+        for &b in &bytes[..end] {
+            if b == b'\n' {
+                crlf.push(b'\r');
+            }
+            crlf.push(b);
+        }
+        crlf.extend_from_slice(&bytes[end..]);
+
+        let (image, meta) = decode(&crlf);
+        assert_eq!(image.pixels(), data);
+        assert_eq!((meta.exposure, meta.software.as_deref()), (Some(2.0), Some("rgbe tests")));
+    }
+}
@@ -0,0 +1,257 @@
+use crate::types::*;
+use crate::hdrimage::*;
+use crate::mipmap::mip_extent;
+use crate::load::image_extent;
+
+use bytemuck::Pod;
+use image::{error::{DecodingError, ImageFormatHint}, ImageError, ImageResult};
+use std::{fs::File, io::{BufReader, BufWriter, Read, Write}, path::Path};
+
+/// Magic number at the start of a raw texel file.
+const RAW_MAGIC: &[u8; 4] = b"RGBR";
+
+/// Identifier of each texel format in the header.
+fn format_id(format: TexelFormat) -> u32 {
+    match format {
+        TexelFormat::Rgbe8 => 0,
+        TexelFormat::Rgb9e5 => 1,
+        TexelFormat::R11g11b10f => 2,
+        TexelFormat::Rgba16f => 3,
+        TexelFormat::Rgb32f => 4,
+        TexelFormat::Rgba32f => 5,
+    }
+}
+
+/// Size in bytes of each of the little-endian values making up a texel of each format.
+fn component_size(format: TexelFormat) -> usize {
+    match format {
+        TexelFormat::Rgbe8 => 1,
+        TexelFormat::Rgba16f => 2,
+        TexelFormat::Rgb9e5 | TexelFormat::R11g11b10f | TexelFormat::Rgb32f | TexelFormat::Rgba32f => 4,
+    }
+}
+
+/// Converts texels between native and little-endian byte order (in either direction), which does nothing on little-endian targets.
+fn swap_to_le(bytes: &mut [u8], component_size: usize) {
+    if cfg!(target_endian = "big") {
+        for component in bytes.chunks_exact_mut(component_size) {
+            component.reverse();
+        }
+    }
+}
+
+/// Writes texels in little-endian byte order.
+fn write_texels<W: Write>(out: &mut W, texels: &[u8], format: TexelFormat) -> ImageResult<()> {
+    if cfg!(target_endian = "big") {
+        let mut texels = texels.to_vec();
+        swap_to_le(&mut texels, component_size(format));
+        out.write_all(&texels)
+    } else {
+        out.write_all(texels)
+    }.map_err(ImageError::IoError)
+}
+
+fn raw_error(msg: &str) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormatHint::Name("raw texels".into()), msg.to_string()))
+}
+
+/// Writes the header of a raw texel file.
+fn write_header<W: Write>(out: &mut W, extent: Extent2D, format: TexelFormat, level_count: u32) -> ImageResult<()> {
+    let mut header = Vec::with_capacity(20);
+    header.extend_from_slice(RAW_MAGIC);
+    for field in [extent.width(), extent.height(), format_id(format), level_count] {
+        header.extend_from_slice(&field.to_le_bytes());
+    }
+    out.write_all(&header).map_err(ImageError::IoError)
+}
+
+/// Reads and validates the header of a raw texel file, returning the dimensions, format ID, and number of mip levels.
+fn read_header<R: Read>(reader: &mut R) -> ImageResult<(Extent2D, u32, u32)> {
+    let mut header = [0; 20];
+    reader.read_exact(&mut header).map_err(ImageError::IoError)?;
+    if &header[0..4] != RAW_MAGIC {
+        return Err(raw_error("not a raw texel file"));
+    }
+    let [width, height, format, level_count] = [4, 8, 12, 16].map(|i| u32::from_le_bytes(header[i..i + 4].try_into().unwrap()));
+    let extent = image_extent(width, height)?;
+    if level_count == 0 || level_count > 32 {
+        return Err(raw_error("invalid number of mip levels"));
+    }
+    Ok((extent, format, level_count))
+}
+
+/// Encodes an image and (optionally) its mip levels in a minimal container for caching converted textures.
+///
+/// The file consists of the magic bytes `RGBR`, then the width, height, format, and number of mip levels as little-endian `u32`s,
+/// followed by the texels of each level (largest first) in row-major order, as laid out in memory on little-endian targets
+/// (with each multi-byte value, such as a packed [RGB9E5] texel or a channel of an [RGBA16F] texel, stored little-endian).
+/// The format is 0 for [RGBE8], 1 for [RGB9E5], 2 for [R11G11B10F], 3 for [RGBA16F], 4 for [RGB32F], and 5 for [RGBA32F].
+/// Each mip level is half the size of the previous one, rounded down (but at least 1).
+///
+/// Panics if `levels` is empty, or if the levels do not all have the same format and the dimensions of a mip chain.
+pub fn encode_raw<W: Write>(levels: &[AnyHdrImage], mut out: W) -> ImageResult<()> {
+    let base = levels.first().expect("raw file must have at least one level");
+    write_header(&mut out, base.extent(), base.format(), levels.len() as u32)?;
+    for (i, level) in levels.iter().enumerate() {
+        assert_eq!(level.format(), base.format(), "mip levels have different formats");
+        assert_eq!(level.extent(), mip_extent(base.extent(), i as u32), "mip level {i} has the wrong dimensions");
+        write_texels(&mut out, level.as_bytes(), level.format())?;
+    }
+    out.flush().map_err(ImageError::IoError)
+}
+
+/// Saves an image and its mip levels in the format described in [encode_raw].
+pub fn save_raw(path: &Path, levels: &[AnyHdrImage]) -> ImageResult<()> {
+    let file = File::create(path).map_err(ImageError::IoError)?;
+    encode_raw(levels, BufWriter::new(file))
+}
+
+/// Reads one level of little-endian texels.
+///
+/// The buffer grows as texels are read rather than being allocated up front,
+/// so that the dimensions in the header of a truncated or corrupt file cannot cause a huge allocation.
+fn read_level<T: Pod, R: Read>(reader: &mut R, extent: Extent2D, format: TexelFormat) -> ImageResult<HdrImage<T>> {
+    const CHUNK_TEXELS: usize = 1 << 16;
+    let len = extent.texel_count();
+    let mut data = Vec::new();
+    while data.len() < len {
+        let start = data.len();
+        data.resize((start + CHUNK_TEXELS).min(len), T::zeroed());
+        reader.read_exact(bytemuck::cast_slice_mut(&mut data[start..])).map_err(|e| match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => raw_error("raw texel file is truncated"),
+            _ => ImageError::IoError(e),
+        })?;
+    }
+    swap_to_le(bytemuck::cast_slice_mut(&mut data), component_size(format));
+    Ok(HdrImage::new(extent, data.into()))
+}
+
+/// Decodes an image and its mip levels (largest first) written by [encode_raw].
+pub fn decode_raw<R: Read>(mut reader: R) -> ImageResult<Vec<AnyHdrImage>> {
+    let (extent, format, level_count) = read_header(&mut reader)?;
+    let mut levels = Vec::with_capacity(level_count as usize);
+    for i in 0..level_count {
+        let extent = mip_extent(extent, i);
+        levels.push(match format {
+            0 => read_level::<RGBE8, R>(&mut reader, extent, TexelFormat::Rgbe8)?.into(),
+            1 => read_level::<RGB9E5, R>(&mut reader, extent, TexelFormat::Rgb9e5)?.into(),
+            2 => read_level::<R11G11B10F, R>(&mut reader, extent, TexelFormat::R11g11b10f)?.into(),
+            3 => read_level::<RGBA16F, R>(&mut reader, extent, TexelFormat::Rgba16f)?.into(),
+            4 => read_level::<RGB32F, R>(&mut reader, extent, TexelFormat::Rgb32f)?.into(),
+            5 => read_level::<RGBA32F, R>(&mut reader, extent, TexelFormat::Rgba32f)?.into(),
+            _ => return Err(raw_error("unknown texel format")),
+        });
+    }
+    Ok(levels)
+}
+
+/// Loads an image and its mip levels (largest first) saved by [save_raw].
+pub fn load_raw(path: &Path) -> ImageResult<Vec<AnyHdrImage>> {
+    let file = File::open(path).map_err(ImageError::IoError)?;
+    decode_raw(BufReader::new(file))
+}
+
+/// Encodes a single [RGBA16F] image in the format described in [encode_raw],
+/// which is a lightweight way to pass working images with alpha between pipeline stages.
+/// The texels are not compressed; stages which need smaller files can compress the whole stream (such as with zstd).
+pub fn encode_rgba16f_raw<W: Write>(image: &HdrImage<RGBA16F>, mut out: W) -> ImageResult<()> {
+    write_header(&mut out, image.extent(), TexelFormat::Rgba16f, 1)?;
+    write_texels(&mut out, bytemuck::cast_slice(image.pixels()), TexelFormat::Rgba16f)?;
+    out.flush().map_err(ImageError::IoError)
+}
+
+/// Saves a single [RGBA16F] image in the format described in [encode_raw].
+pub fn save_rgba16f_raw(path: &Path, image: &HdrImage<RGBA16F>) -> ImageResult<()> {
+    let file = File::create(path).map_err(ImageError::IoError)?;
+    encode_rgba16f_raw(image, BufWriter::new(file))
+}
+
+/// Decodes the base level of a raw texel file containing [RGBA16F] texels, ignoring any mip levels.
+/// Fails if the file contains texels in another format, rather than converting them.
+pub fn decode_rgba16f_raw<R: Read>(mut reader: R) -> ImageResult<HdrImage<RGBA16F>> {
+    let (extent, format, _) = read_header(&mut reader)?;
+    if format != format_id(TexelFormat::Rgba16f) {
+        return Err(raw_error("raw texel file does not contain RGBA16F texels"));
+    }
+    read_level(&mut reader, extent, TexelFormat::Rgba16f)
+}
+
+/// Loads the base level of a raw [RGBA16F] texel file saved by [save_rgba16f_raw] (or [save_raw]).
+pub fn load_rgba16f_raw(path: &Path) -> ImageResult<HdrImage<RGBA16F>> {
+    let file = File::open(path).map_err(ImageError::IoError)?;
+    decode_rgba16f_raw(BufReader::new(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mipmap::generate_mipmaps;
+
+    fn test_image(width: u32, height: u32) -> HdrImage<RGB9E5> {
+        let extent = Extent2D::new(width, height).unwrap();
+        let data = (0..extent.texel_count()).map(|i| RGB9E5::pack([i as f32, 0.5, 1.0 / (i + 1) as f32])).collect();
+        HdrImage::new(extent, data)
+    }
+
+    #[test]
+    fn mip_chain_round_trip() {
+        let levels: Vec<AnyHdrImage> = generate_mipmaps(&test_image(6, 5)).into_iter().map(AnyHdrImage::from).collect();
+        assert_eq!(levels.len(), 3);
+        let mut bytes = Vec::new();
+        encode_raw(&levels, &mut bytes).unwrap();
+        assert_eq!(bytes.len(), 20 + 4 * (30 + 6 + 1));
+        assert_eq!(&bytes[..20], b"RGBR\x06\0\0\0\x05\0\0\0\x01\0\0\0\x03\0\0\0");
+        // texels are little-endian
+        let AnyHdrImage::Rgb9e5(base) = &levels[0] else { unreachable!() };
+        assert_eq!(bytes[20..24], base.pixels()[0].0.to_le_bytes());
+        assert_eq!(decode_raw(&bytes[..]).unwrap(), levels);
+    }
+
+    #[test]
+    fn every_format_round_trip() {
+        let image = AnyHdrImage::from(test_image(3, 2));
+        for format in [TexelFormat::Rgbe8, TexelFormat::Rgb9e5, TexelFormat::R11g11b10f, TexelFormat::Rgba16f, TexelFormat::Rgb32f, TexelFormat::Rgba32f] {
+            let levels = [image.convert(format)];
+            let mut bytes = Vec::new();
+            encode_raw(&levels, &mut bytes).unwrap();
+            assert_eq!(decode_raw(&bytes[..]).unwrap(), levels, "{format:?}");
+        }
+    }
+
+    #[test]
+    fn rgba16f_round_trip() {
+        let extent = Extent2D::new(4, 3).unwrap();
+        let image = HdrImage::new(extent, (0..12).map(|i| RGBA16F::from_f32([i as f32, 0.25, 100.0, 0.5])).collect());
+        let mut bytes = Vec::new();
+        encode_rgba16f_raw(&image, &mut bytes).unwrap();
+        assert_eq!(decode_rgba16f_raw(&bytes[..]).unwrap(), image);
+        assert_eq!(decode_raw(&bytes[..]).unwrap(), [AnyHdrImage::from(image)]);
+
+        let mut bytes = Vec::new();
+        encode_raw(&[test_image(2, 2).into()], &mut bytes).unwrap();
+        assert!(decode_rgba16f_raw(&bytes[..]).is_err());
+    }
+
+    #[test]
+    fn rejects_corrupt_files() {
+        let mut bytes = Vec::new();
+        encode_raw(&[test_image(4, 4).into()], &mut bytes).unwrap();
+        assert!(decode_raw(&bytes[..bytes.len() - 1]).is_err());
+        assert!(decode_raw(&bytes[..10]).is_err());
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert!(decode_raw(&bad_magic[..]).is_err());
+        for (offset, value) in [(4, 0), (12, 6), (16, 0), (16, 33)] {
+            let mut corrupt = bytes.clone();
+            corrupt[offset..offset + 4].copy_from_slice(&(value as u32).to_le_bytes());
+            assert!(decode_raw(&corrupt[..]).is_err(), "field at {offset} set to {value}");
+        }
+
+        // a huge size in the header fails as truncated rather than allocating the whole image
+        let mut huge = bytes[..20].to_vec();
+        huge[4..12].copy_from_slice(&[0xFF; 8]);
+        huge.extend_from_slice(&[0; 64]);
+        assert!(decode_raw(&huge[..]).is_err());
+    }
+}
@@ -0,0 +1,352 @@
+use crate::types::{luminance, Extent2D, LUMA};
+
+/// A reconstruction filter used when resizing images.
+///
+/// Kernels are evaluated in units of destination texels, so the same kernel can be used for both upsampling and downsampling
+/// (it is stretched by the scale factor when downsampling).
+/// Weights do not need to be normalized as they are divided by their sum for each output texel.
+pub trait ResampleKernel {
+    /// Radius (in texels) outside of which the kernel is zero.
+    fn support(&self) -> f32;
+
+    /// Evaluate the kernel at an offset of `x` texels from its center.
+    fn eval(&self, x: f32) -> f32;
+}
+
+impl<K: ResampleKernel + ?Sized> ResampleKernel for &K {
+    fn support(&self) -> f32 {
+        (**self).support()
+    }
+
+    fn eval(&self, x: f32) -> f32 {
+        (**self).eval(x)
+    }
+}
+
+/// Box filter. Nearest-neighbour when upsampling and area averaging when downsampling.
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub struct BoxKernel;
+
+impl ResampleKernel for BoxKernel {
+    fn support(&self) -> f32 {
+        0.5
+    }
+
+    fn eval(&self, x: f32) -> f32 {
+        if (-0.5..0.5).contains(&x) {1.0} else {0.0}
+    }
+}
+
+/// Tent filter. Bilinear interpolation when upsampling.
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub struct TriangleKernel;
+
+impl ResampleKernel for TriangleKernel {
+    fn support(&self) -> f32 {
+        1.0
+    }
+
+    fn eval(&self, x: f32) -> f32 {
+        (1.0 - x.abs()).max(0.0)
+    }
+}
+
+/// Mitchell-Netravali family of cubic filters, parameterized by `b` (blurring) and `c` (ringing).
+///
+/// Kernels with `b < 1` have negative lobes, which sharpen the image but can cause visible ringing
+/// around very bright highlights in HDR images.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct CubicKernel {
+    pub b: f32,
+    pub c: f32,
+}
+
+impl CubicKernel {
+    /// The filter recommended by Mitchell and Netravali, `B = C = 1/3`.
+    pub const MITCHELL: Self = CubicKernel{b: 1.0 / 3.0, c: 1.0 / 3.0};
+    /// Cubic B-spline, `B = 1, C = 0`. Very smooth and has no negative lobes.
+    pub const B_SPLINE: Self = CubicKernel{b: 1.0, c: 0.0};
+    /// Catmull-Rom spline, `B = 0, C = 1/2`. Interpolating and sharp.
+    pub const CATMULL_ROM: Self = CubicKernel{b: 0.0, c: 0.5};
+}
+
+impl Default for CubicKernel {
+    fn default() -> Self {
+        Self::MITCHELL
+    }
+}
+
+impl ResampleKernel for CubicKernel {
+    fn support(&self) -> f32 {
+        2.0
+    }
+
+    fn eval(&self, x: f32) -> f32 {
+        let (b, c) = (self.b, self.c);
+        let x = x.abs();
+        let x2 = x * x;
+        let x3 = x2 * x;
+        if x < 1.0 {
+            ((12.0 - 9.0 * b - 6.0 * c) * x3 + (-18.0 + 12.0 * b + 6.0 * c) * x2 + (6.0 - 2.0 * b)) / 6.0
+        } else if x < 2.0 {
+            ((-b - 6.0 * c) * x3 + (6.0 * b + 30.0 * c) * x2 + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)) / 6.0
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Normalized sinc function, `sin(πx) / πx`.
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-6 {
+        1.0
+    } else {
+        let px = std::f32::consts::PI * x;
+        px.sin() / px
+    }
+}
+
+/// Lanczos filter: a sinc windowed by a wider sinc, with `lobes` lobes on each side.
+///
+/// Sharper than the cubic filters, but has larger negative lobes.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct LanczosKernel {
+    pub lobes: f32,
+}
+
+impl LanczosKernel {
+    /// Three-lobed Lanczos filter, the most common choice for downsampling.
+    pub const LANCZOS3: Self = LanczosKernel{lobes: 3.0};
+}
+
+impl Default for LanczosKernel {
+    fn default() -> Self {
+        Self::LANCZOS3
+    }
+}
+
+impl ResampleKernel for LanczosKernel {
+    fn support(&self) -> f32 {
+        self.lobes
+    }
+
+    fn eval(&self, x: f32) -> f32 {
+        if x.abs() < self.lobes {sinc(x) * sinc(x / self.lobes)} else {0.0}
+    }
+}
+
+/// Sinc filter windowed by a Kaiser window, as used for mipmap generation by NVIDIA Texture Tools.
+///
+/// `alpha` trades sharpness (low values) against ringing (high values).
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct KaiserKernel {
+    pub width: f32,
+    pub alpha: f32,
+}
+
+impl Default for KaiserKernel {
+    fn default() -> Self {
+        KaiserKernel{width: 3.0, alpha: 4.0}
+    }
+}
+
+/// Zeroth-order modified Bessel function of the first kind, by its power series.
+fn bessel_i0(x: f32) -> f32 {
+    let (mut sum, mut term) = (1.0, 1.0);
+    let half_x2 = 0.25 * x * x;
+    for k in 1..32 {
+        term *= half_x2 / (k * k) as f32;
+        sum += term;
+        if term < sum * 1e-7 {
+            break;
+        }
+    }
+    sum
+}
+
+impl ResampleKernel for KaiserKernel {
+    fn support(&self) -> f32 {
+        self.width
+    }
+
+    fn eval(&self, x: f32) -> f32 {
+        let t = x / self.width;
+        if t.abs() < 1.0 {
+            sinc(x) * bessel_i0(self.alpha * (1.0 - t * t).sqrt()) / bessel_i0(self.alpha)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Normalized filter taps for a single output texel along one axis.
+struct Taps {
+    start: usize,
+    weights: Box<[f32]>,
+}
+
+fn compute_taps<K: ResampleKernel + ?Sized>(src_len: u32, dst_len: u32, kernel: &K) -> Box<[Taps]> {
+    let scale = src_len as f32 / dst_len as f32;
+    // stretch the kernel when downsampling so that it covers all source texels.
+    let filter_scale = scale.max(1.0);
+    let radius = kernel.support() * filter_scale;
+
+    (0..dst_len).map(|i| {
+        let center = (i as f32 + 0.5) * scale;
+        let lo = (center - radius).floor().max(0.0) as usize;
+        let hi = ((center + radius).ceil() as usize).min(src_len as usize);
+        let mut weights: Box<[f32]> = (lo..hi).map(|j| {
+            kernel.eval((j as f32 + 0.5 - center) / filter_scale)
+        }).collect();
+
+        let sum: f32 = weights.iter().sum();
+        if sum != 0.0 {
+            weights.iter_mut().for_each(|w| *w /= sum);
+            Taps{start: lo, weights}
+        } else {
+            // degenerate kernel, fall back to nearest-neighbour.
+            let nearest = (center as usize).min(src_len as usize - 1);
+            Taps{start: nearest, weights: Box::new([1.0])}
+        }
+    }).collect()
+}
+
+/// How filter taps are combined when resampling.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum ResampleMode {
+    /// Plain weighted sum of all taps.
+    #[default]
+    Standard,
+    /// Highlight-aware mode which suppresses ringing around extremely bright texels (such as sun disks).
+    ///
+    /// Texels under the negative lobes of the kernel are clamped (per channel) to the range of the texels under its positive lobes
+    /// so that a single bright texel cannot produce dark rings or negative values in its neighbours.
+    /// This has no effect for kernels without negative lobes.
+    ClampedLobes,
+    /// Filter the logarithm of luminance rather than linear values, with chroma filtered linearly.
+    ///
+    /// This prevents a small number of extremely bright texels from dominating the result
+    /// and is sometimes preferred for content with an extreme dynamic range.
+    /// Note that this darkens downsampled images as averaging in log space gives the geometric mean.
+    LogLuminance,
+}
+
+/// Offset added to luminance before taking the log so that black texels have a finite value.
+const LOG_EPSILON: f32 = 1.0 / 65536.0;
+
+/// Convert linear RGB to (log luminance, red chroma, blue chroma).
+fn to_log_luminance(rgb: [f32;3]) -> [f32;3] {
+    let lum = luminance(rgb).max(0.0);
+    if lum > 0.0 {
+        [(lum + LOG_EPSILON).ln(), rgb[0] / lum, rgb[2] / lum]
+    } else {
+        // treat black as neutral grey
+        [LOG_EPSILON.ln(), 1.0, 1.0]
+    }
+}
+
+/// Inverse of [to_log_luminance], recovering green from luminance.
+fn from_log_luminance(llc: [f32;3]) -> [f32;3] {
+    let lum = (llc[0].exp() - LOG_EPSILON).max(0.0);
+    let r = (llc[1] * lum).max(0.0);
+    let b = (llc[2] * lum).max(0.0);
+    let g = ((lum - LUMA[0] * r - LUMA[2] * b) / LUMA[1]).max(0.0);
+    [r, g, b]
+}
+
+/// Resize an image of linear RGB float values using a separable [ResampleKernel].
+///
+/// Edges are handled by renormalizing the kernel over the texels inside the image.
+/// Negative results (from kernels with negative lobes) are not clamped.
+pub fn resize<K: ResampleKernel + ?Sized>(extent: Extent2D, data: &[[f32;3]], new_extent: Extent2D, kernel: &K) -> Box<[[f32;3]]> {
+    resize_with_mode(extent, data, new_extent, kernel, ResampleMode::Standard)
+}
+
+/// Resize an image of linear RGB float values using a separable [ResampleKernel] and the given [ResampleMode].
+pub fn resize_with_mode<K: ResampleKernel + ?Sized>(extent: Extent2D, data: &[[f32;3]], new_extent: Extent2D, kernel: &K, mode: ResampleMode) -> Box<[[f32;3]]> {
+    assert_eq!(data.len(), extent.texel_count(), "data does not match image dimensions");
+    if mode == ResampleMode::LogLuminance {
+        let log_data: Box<[[f32;3]]> = data.iter().copied().map(to_log_luminance).collect();
+        let mut out = resize_with_mode(extent, &log_data, new_extent, kernel, ResampleMode::Standard);
+        out.iter_mut().for_each(|px| *px = from_log_luminance(*px));
+        return out;
+    }
+
+    let (width, height) = (extent.width(), extent.height());
+    let (new_width, new_height) = (new_extent.width(), new_extent.height());
+
+    // horizontal pass
+    let h_taps = compute_taps(width, new_width, kernel);
+    let mut tmp = vec![[0.0f32; 3]; new_width as usize * height as usize];
+    for (src_row, dst_row) in data.chunks_exact(width as usize).zip(tmp.chunks_exact_mut(new_width as usize)) {
+        for (taps, out) in h_taps.iter().zip(dst_row.iter_mut()) {
+            let src = &src_row[taps.start..taps.start + taps.weights.len()];
+            *out = apply_taps(&taps.weights, src.iter(), mode);
+        }
+    }
+
+    // vertical pass
+    let v_taps = compute_taps(height, new_height, kernel);
+    let row_len = new_width as usize;
+    let mut out = vec![[0.0f32; 3]; new_extent.texel_count()].into_boxed_slice();
+    for (taps, dst_row) in v_taps.iter().zip(out.chunks_exact_mut(row_len)) {
+        for (x, px) in dst_row.iter_mut().enumerate() {
+            let col = (0..taps.weights.len()).map(|k| &tmp[(taps.start + k) * row_len + x]);
+            *px = apply_taps(&taps.weights, col, mode);
+        }
+    }
+    out
+}
+
+fn apply_taps<'a, I>(weights: &[f32], samples: I, mode: ResampleMode) -> [f32;3]
+where I: Iterator<Item = &'a [f32;3]> + Clone {
+    match mode {
+        ResampleMode::Standard | ResampleMode::LogLuminance => {
+            weighted_sum(weights.iter().copied().zip(samples.copied()))
+        }
+        ResampleMode::ClampedLobes => {
+            let mut lo = [f32::INFINITY; 3];
+            let mut hi = [f32::NEG_INFINITY; 3];
+            for (_, px) in weights.iter().zip(samples.clone()).filter(|(w, _)| **w > 0.0) {
+                for c in 0..3 {
+                    lo[c] = lo[c].min(px[c]);
+                    hi[c] = hi[c].max(px[c]);
+                }
+            }
+            // the bounds stay empty (lo > hi) for channels where every positive tap is NaN, which are left unclamped
+            let clamped = samples.map(|px| {
+                core::array::from_fn(|c| if lo[c] <= hi[c] { px[c].clamp(lo[c], hi[c]) } else { px[c] })
+            });
+            // positive taps are inside the range by construction, so this only affects negative lobes.
+            weighted_sum(weights.iter().copied().zip(clamped))
+        }
+    }
+}
+
+fn weighted_sum(samples: impl Iterator<Item = (f32, [f32;3])>) -> [f32;3] {
+    samples.fold([0.0; 3], |acc, (w, px)| {
+        [acc[0] + w * px[0], acc[1] + w * px[1], acc[2] + w * px[2]]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamped_lobes_non_finite_input() {
+        let extent = Extent2D::new(1, 1).unwrap();
+        let new_extent = Extent2D::new(4, 4).unwrap();
+        let out = resize_with_mode(extent, &[[f32::NAN, 1.0, 1.0]], new_extent, &CubicKernel::CATMULL_ROM, ResampleMode::ClampedLobes);
+        for px in out.iter() {
+            assert!(px[0].is_nan());
+            assert!((px[1] - 1.0).abs() < 1e-5 && (px[2] - 1.0).abs() < 1e-5, "{px:?}");
+        }
+
+        let out = resize_with_mode(extent, &[[f32::INFINITY, 0.0, 2.0]], new_extent, &CubicKernel::CATMULL_ROM, ResampleMode::ClampedLobes);
+        for px in out.iter() {
+            assert_eq!(px[0], f32::INFINITY);
+            assert!((px[2] - 2.0).abs() < 1e-5, "{px:?}");
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use crate::types::*;
+use crate::hdrimage::*;
+use crate::load::image_extent;
+
+use half::f16;
+use image::{codecs::png::{PngDecoder, PngEncoder}, error::{DecodingError, ImageFormatHint}, ColorType, ImageDecoder, ImageEncoder, ImageError, ImageResult};
+use std::{fs::{self, File}, io::{BufWriter, Cursor, Write}, path::Path};
+
+fn residual_error(msg: &str) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormatHint::Name("RGB9E5 residual".into()), msg.to_string()))
+}
+
+/// Bits of each channel of a texel, in RGBA order.
+fn half_bits(texel: RGBA16F) -> [u16;4] {
+    [texel.r, texel.g, texel.b, texel.a].map(|c| c.to_bits())
+}
+
+/// Packs an [RGBA16F] image into [RGB9E5] for use on the GPU, along with a residual from which the original texels
+/// can be reconstructed bit for bit with [reconstruct_rgba16f], so that archives can keep GPU-ready textures without losing the master.
+///
+/// The residual holds the bits of each channel (including alpha) of the original texels exclusive-or those of the [RGB9E5] texels
+/// unpacked to [RGBA16F], so it is mostly zero or small and compresses well (such as with [save_residual_png]).
+/// Negative, infinite, and NaN values, which [RGB9E5] cannot hold, are also restored exactly.
+pub fn split_rgb9e5_residual(image: &HdrImage<RGBA16F>) -> (HdrImage<RGB9E5>, HdrImage<[u16;4]>) {
+    let (packed, residual) = image.pixels().iter().map(|&texel| {
+        let packed = RGB9E5::from(texel);
+        let unpacked = half_bits(RGBA16F::from(packed));
+        let original = half_bits(texel);
+        (packed, core::array::from_fn(|i| original[i] ^ unpacked[i]))
+    }).unzip::<_, _, Vec<_>, Vec<_>>();
+    (HdrImage::new(image.extent(), packed.into()), HdrImage::new(image.extent(), residual.into()))
+}
+
+/// Reconstructs the original [RGBA16F] texels of an image split by [split_rgb9e5_residual].
+///
+/// Panics if the texture and residual have different dimensions.
+pub fn reconstruct_rgba16f(texture: &HdrImage<RGB9E5>, residual: &HdrImage<[u16;4]>) -> HdrImage<RGBA16F> {
+    assert_eq!(texture.extent(), residual.extent(), "residual does not match texture dimensions");
+    let data = texture.pixels().iter().zip(residual.pixels()).map(|(&packed, residual)| {
+        let unpacked = half_bits(RGBA16F::from(packed));
+        let [r, g, b, a] = core::array::from_fn(|i| f16::from_bits(unpacked[i] ^ residual[i]));
+        RGBA16F{r, g, b, a}
+    }).collect();
+    HdrImage::new(texture.extent(), data)
+}
+
+/// Encodes a residual from [split_rgb9e5_residual] as an RGBA16 PNG, which compresses its runs of zeros losslessly.
+pub fn encode_residual_png<W: Write>(residual: &HdrImage<[u16;4]>, out: W) -> ImageResult<()> {
+    let encoder = PngEncoder::new(out);
+    // the encoder converts native-endian samples to big-endian
+    encoder.write_image(bytemuck::cast_slice(residual.pixels()), residual.width(), residual.height(), ColorType::Rgba16)?;
+    Ok(())
+}
+
+/// Saves a residual from [split_rgb9e5_residual] as an RGBA16 PNG file (see [encode_residual_png]).
+pub fn save_residual_png(path: &Path, residual: &HdrImage<[u16;4]>) -> ImageResult<()> {
+    let file = File::create(path).map_err(ImageError::IoError)?;
+    encode_residual_png(residual, BufWriter::new(file))
+}
+
+/// Decodes a residual written by [encode_residual_png].
+/// Only RGBA16 PNGs are accepted, as any conversion of the samples would prevent exact reconstruction.
+pub fn decode_residual_png(bytes: &[u8]) -> ImageResult<HdrImage<[u16;4]>> {
+    let dec = PngDecoder::new(Cursor::new(bytes))?;
+    if dec.color_type() != ColorType::Rgba16 {
+        return Err(residual_error("residual PNG is not RGBA16"));
+    }
+    let (width, height) = dec.dimensions();
+    let extent = image_extent(width, height)?;
+    let mut data = vec![[0u16;4]; extent.texel_count()];
+    // the decoder converts big-endian samples to native-endian
+    dec.read_image(bytemuck::cast_slice_mut(&mut data))?;
+    Ok(HdrImage::new(extent, data.into()))
+}
+
+/// Loads a residual saved by [save_residual_png].
+pub fn load_residual_png_file(path: &Path) -> ImageResult<HdrImage<[u16;4]>> {
+    decode_residual_png(&fs::read(path).map_err(ImageError::IoError)?)
+}
@@ -0,0 +1,48 @@
+use crate::types::*;
+
+use half::f16;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Serialized as a `u32` with R in the lowest byte and the exponent in the highest, matching the in-memory layout.
+impl Serialize for RGBE8 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        u32::from_le_bytes([self.r, self.g, self.b, self.e]).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RGBE8 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let [r, g, b, e] = u32::deserialize(deserializer)?.to_le_bytes();
+        Ok(RGBE8{r, g, b, e})
+    }
+}
+
+/// Serialized as its packed `u32`.
+impl Serialize for RGB9E5 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RGB9E5 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(RGB9E5(u32::deserialize(deserializer)?))
+    }
+}
+
+/// Serialized as a `u64` containing the bits of R, G, B, and A from least to most significant, matching the in-memory layout.
+impl Serialize for RGBA16F {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let bits = [self.r, self.g, self.b, self.a].iter().enumerate()
+            .fold(0u64, |acc, (i, c)| acc | (c.to_bits() as u64) << (16 * i));
+        bits.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RGBA16F {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bits = u64::deserialize(deserializer)?;
+        let [r, g, b, a] = [0, 1, 2, 3].map(|i| f16::from_bits((bits >> (16 * i)) as u16));
+        Ok(RGBA16F{r, g, b, a})
+    }
+}
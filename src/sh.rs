@@ -0,0 +1,104 @@
+use crate::types::*;
+use crate::hdrimage::*;
+use crate::cubemap::*;
+
+use std::f64::consts::PI;
+
+/// Real spherical harmonic basis functions up to band 2 (third order) in the direction `dir` (which need not be normalized),
+/// in the order `Y00, Y1-1, Y10, Y11, Y2-2, Y2-1, Y20, Y21, Y22`, where band 1 is `(y, z, x)`.
+///
+/// The first four are the basis for second order (band 1) harmonics.
+pub fn sh9_basis(dir: [f32;3]) -> [f32;9] {
+    let len = (dir[0] * dir[0] + dir[1] * dir[1] + dir[2] * dir[2]).sqrt();
+    let [x, y, z] = if len > 0.0 { dir.map(|c| c / len) } else { [0.0; 3] };
+    [
+        0.282095,
+        0.488603 * y,
+        0.488603 * z,
+        0.488603 * x,
+        1.092548 * x * y,
+        1.092548 * y * z,
+        0.315392 * (3.0 * z * z - 1.0),
+        1.092548 * x * z,
+        0.546274 * (x * x - y * y),
+    ]
+}
+
+/// Evaluate spherical harmonic coefficients (in the order of [sh9_basis]) in the direction `dir`.
+///
+/// For coefficients from [irradiance_sh_from_cubemap] or [irradiance_sh_from_equirect],
+/// this is the irradiance received by a surface facing `dir`.
+/// Divide by π to get the radiance reflected by a white Lambertian surface.
+pub fn eval_sh9(coeffs: &[[f32;3];9], dir: [f32;3]) -> [f32;3] {
+    let basis = sh9_basis(dir);
+    let mut rgb = [0.0; 3];
+    for (coeff, y) in coeffs.iter().zip(basis) {
+        for c in 0..3 {
+            rgb[c] += coeff[c] * y;
+        }
+    }
+    rgb
+}
+
+/// Accumulates texels weighted by solid angle into radiance coefficients, then convolves them with the clamped cosine lobe.
+#[derive(Default)]
+struct ShAccumulator {
+    sums: [[f64;3];9],
+}
+
+impl ShAccumulator {
+    fn add(&mut self, dir: [f32;3], rgb: [f32;3], weight: f32) {
+        for (sum, y) in self.sums.iter_mut().zip(sh9_basis(dir)) {
+            for c in 0..3 {
+                sum[c] += (rgb[c] * y * weight) as f64;
+            }
+        }
+    }
+
+    fn irradiance(self) -> [[f32;3];9] {
+        // Ramamoorthi and Hanrahan's convolution of each band with the clamped cosine
+        const BAND_SCALE: [f64; 3] = [PI, 2.0 * PI / 3.0, PI / 4.0];
+        let band = [0, 1, 1, 1, 2, 2, 2, 2, 2];
+        core::array::from_fn(|i| self.sums[i].map(|sum| (sum * BAND_SCALE[band[i]]) as f32))
+    }
+}
+
+/// Project a cubemap of radiance onto third order (9 coefficient) spherical harmonics for diffuse irradiance,
+/// with each texel weighted by the solid angle it subtends (or uniformly, see [CubemapWeighting]).
+///
+/// The coefficients are in the order of [sh9_basis] and can be evaluated with [eval_sh9].
+/// Second order (4 coefficient) harmonics are the first four coefficients.
+pub fn irradiance_sh_from_cubemap<T: RgbTexel>(cubemap: &HdrCubemap<T>, weighting: CubemapWeighting) -> [[f32;3];9] {
+    let face_size = cubemap.face_size();
+    let weights = weighting.face_weights(face_size);
+    let mut acc = ShAccumulator::default();
+    for face in CubeFace::ALL {
+        for (i, (&texel, &weight)) in cubemap.face(face).iter().zip(weights.iter()).enumerate() {
+            let i = i as u32;
+            let dir = face.direction(texel_face_coord(face_size, i % face_size), texel_face_coord(face_size, i / face_size));
+            acc.add(dir, texel.to_rgb(), weight);
+        }
+    }
+    acc.irradiance()
+}
+
+/// Project an equirectangular panorama of radiance (with the orientation of [equirect_coord]) onto third order
+/// spherical harmonics for diffuse irradiance, with each texel weighted by the exact solid angle it subtends.
+///
+/// See [irradiance_sh_from_cubemap] for the layout of the coefficients.
+pub fn irradiance_sh_from_equirect<T: RgbTexel>(image: &HdrImage<T>) -> [[f32;3];9] {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let mut acc = ShAccumulator::default();
+    for (y, row) in image.pixels().chunks_exact(width).enumerate() {
+        // solid angle of a texel is its longitude span times the difference in the sine of the latitudes of its edges
+        let latitude = |y: f64| PI * (0.5 - y / height as f64);
+        let weight = 2.0 * PI / width as f64 * (latitude(y as f64).sin() - latitude(y as f64 + 1.0).sin());
+        let lat = latitude(y as f64 + 0.5);
+        for (x, &texel) in row.iter().enumerate() {
+            let lon = 2.0 * PI * ((x as f64 + 0.5) / width as f64 - 0.5);
+            let dir = [lat.cos() * lon.sin(), lat.sin(), -lat.cos() * lon.cos()].map(|c| c as f32);
+            acc.add(dir, texel.to_rgb(), weight as f32);
+        }
+    }
+    acc.irradiance()
+}
@@ -0,0 +1,81 @@
+use crate::types::*;
+
+/// Colours of an image stored as a separate buffer for each channel (struct-of-arrays layout),
+/// for SIMD-friendly processing and interop with analysis code.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct RgbChannels {
+    pub r: Vec<f32>,
+    pub g: Vec<f32>,
+    pub b: Vec<f32>,
+}
+
+impl RgbChannels {
+    /// Unpack texels in any [RgbTexel] format into separate channels.
+    pub fn from_texels<T: RgbTexel>(data: &[T]) -> Self {
+        let mut channels = RgbChannels{
+            r: Vec::with_capacity(data.len()),
+            g: Vec::with_capacity(data.len()),
+            b: Vec::with_capacity(data.len()),
+        };
+        for texel in data {
+            let [r, g, b] = texel.to_rgb();
+            channels.r.push(r);
+            channels.g.push(g);
+            channels.b.push(b);
+        }
+        channels
+    }
+
+    /// Pack the channels back into interleaved texels.
+    ///
+    /// Panics if the channels are not all the same length.
+    pub fn to_texels<T: From<[f32;3]>>(&self) -> Box<[T]> {
+        assert!(self.g.len() == self.r.len() && self.b.len() == self.r.len(), "channels have different lengths");
+        self.r.iter().zip(&self.g).zip(&self.b).map(|((&r, &g), &b)| T::from([r, g, b])).collect()
+    }
+
+    /// Split a planar buffer holding all the red values, then all the green, then all the blue
+    /// (the channel-first layout used by many machine learning frameworks) into channels.
+    ///
+    /// Panics if the length of `data` is not a multiple of 3.
+    pub fn from_planar(data: &[f32]) -> Self {
+        assert!(data.len().is_multiple_of(3), "planar data must have 3 equal channels");
+        let len = data.len() / 3;
+        RgbChannels{r: data[..len].to_vec(), g: data[len..2 * len].to_vec(), b: data[2 * len..].to_vec()}
+    }
+
+    /// Concatenate the channels into a single planar buffer, inverting [RgbChannels::from_planar].
+    pub fn to_planar(&self) -> Vec<f32> {
+        [self.r.as_slice(), &self.g, &self.b].concat()
+    }
+
+    /// Number of texels.
+    pub fn len(&self) -> usize {
+        self.r.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.r.is_empty()
+    }
+}
+
+/// Unpack texels in any [RgbTexel] format into caller-provided channel buffers, such as planes of a larger array,
+/// without allocating.
+///
+/// Panics if the buffers are not all the same length as `data`.
+pub fn deinterleave_rgb<T: RgbTexel>(data: &[T], r: &mut [f32], g: &mut [f32], b: &mut [f32]) {
+    assert!(r.len() == data.len() && g.len() == data.len() && b.len() == data.len(), "channels do not match the number of texels");
+    for (i, texel) in data.iter().enumerate() {
+        [r[i], g[i], b[i]] = texel.to_rgb();
+    }
+}
+
+/// Pack separate channel buffers into texels in place, keeping any other channels (such as alpha) of the existing texels.
+///
+/// Panics if the buffers are not all the same length as `data`.
+pub fn interleave_rgb<T: RgbTexel>(r: &[f32], g: &[f32], b: &[f32], data: &mut [T]) {
+    assert!(r.len() == data.len() && g.len() == data.len() && b.len() == data.len(), "channels do not match the number of texels");
+    for (i, texel) in data.iter_mut().enumerate() {
+        *texel = texel.with_rgb([r[i], g[i], b[i]]);
+    }
+}
@@ -0,0 +1,244 @@
+use crate::types::*;
+use crate::hdrimage::*;
+use crate::load::image_extent;
+
+use bytemuck::Pod;
+use image::{error::{DecodingError, ImageFormatHint}, ImageError, ImageResult};
+use std::{collections::BTreeMap, fs::File, io::{BufReader, BufWriter, Read, Write}, path::Path};
+
+/// Magic number at the start of a serialized sparse image.
+const SPARSE_MAGIC: &[u8; 4] = b"RGBS";
+
+/// An image divided into square tiles, where only tiles containing non-black texels are stored.
+/// This is much smaller than a dense image for baked emissive maps and lightmaps which are mostly zero.
+#[derive(PartialEq, Clone, Debug)]
+pub struct SparseImage<T: Pod> {
+    extent: Extent2D,
+    tile_size: u32,
+    /// Non-empty tiles by index in row-major order, each with `tile_size * tile_size` texels.
+    /// Tiles on the right and bottom edges are padded with zeros.
+    tiles: BTreeMap<u32, Box<[T]>>,
+}
+
+fn sparse_error(msg: &str) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormatHint::Name("sparse RGBE".into()), msg.to_string()))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> ImageResult<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes).map_err(ImageError::IoError)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// Number of tiles of `tile_size` texels per side covering an image, which must fit in the `u32` tile indices.
+fn count_tiles(extent: Extent2D, tile_size: u32) -> Option<u32> {
+    extent.width().div_ceil(tile_size).checked_mul(extent.height().div_ceil(tile_size))
+}
+
+/// Number of texels in a tile of `tile_size` texels per side.
+fn tile_len(tile_size: u32) -> usize {
+    tile_size as usize * tile_size as usize
+}
+
+/// Index of the first texel of row `y` of tile `(tx, ty)` in an image `width` texels wide.
+fn texel_index(width: u32, tile_size: u32, tx: u32, ty: u32, y: u32) -> usize {
+    (ty as usize * tile_size as usize + y as usize) * width as usize + tx as usize * tile_size as usize
+}
+
+impl<T: RgbTexel> SparseImage<T> {
+    /// Split an image into tiles of `tile_size` texels per side, discarding tiles which are entirely black.
+    /// Panics if `tile_size` is zero or larger than [SparseImage::MAX_TILE_SIZE],
+    /// or if the image has more tiles than fit in a `u32`.
+    pub fn from_dense(image: &HdrImage<T>, tile_size: u32) -> Self {
+        assert!(tile_size > 0 && tile_size <= Self::MAX_TILE_SIZE, "tile size must be between 1 and {}", Self::MAX_TILE_SIZE);
+        assert!(count_tiles(image.extent(), tile_size).is_some(), "image has too many tiles");
+        let (tiles_x, tiles_y) = (image.width().div_ceil(tile_size), image.height().div_ceil(tile_size));
+        let mut tiles = BTreeMap::new();
+        for ty in 0..tiles_y {
+            for tx in 0..tiles_x {
+                let mut tile = vec![T::zeroed(); tile_len(tile_size)].into_boxed_slice();
+                let mut empty = true;
+                for y in 0..tile_size.min(image.height() - ty * tile_size) {
+                    let src = texel_index(image.width(), tile_size, tx, ty, y);
+                    let len = tile_size.min(image.width() - tx * tile_size) as usize;
+                    let row = &image.pixels()[src..src + len];
+                    empty &= row.iter().all(|texel| texel.to_rgb() == [0.0; 3]);
+                    tile[y as usize * tile_size as usize..][..len].copy_from_slice(row);
+                }
+                if !empty {
+                    // cannot overflow, as the number of tiles fits in a u32
+                    tiles.insert(ty * tiles_x + tx, tile);
+                }
+            }
+        }
+        SparseImage{extent: image.extent(), tile_size, tiles}
+    }
+}
+
+impl<T: Pod> SparseImage<T> {
+    /// Largest supported tile size, which keeps the padded tiles of small images and of corrupt files from using excessive memory.
+    pub const MAX_TILE_SIZE: u32 = 1024;
+
+    pub fn extent(&self) -> Extent2D {
+        self.extent
+    }
+
+    pub fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+
+    /// Number of tiles in each row.
+    pub fn tiles_x(&self) -> u32 {
+        self.extent.width().div_ceil(self.tile_size)
+    }
+
+    /// Number of tiles which are stored (rather than empty).
+    pub fn stored_tile_count(&self) -> usize {
+        self.tiles.len()
+    }
+
+    /// Texels of tile `(tx, ty)`, or `None` if it is empty.
+    pub fn tile(&self, tx: u32, ty: u32) -> Option<&[T]> {
+        if tx >= self.tiles_x() {
+            return None;
+        }
+        let index = ty.checked_mul(self.tiles_x())? + tx;
+        self.tiles.get(&index).map(|tile| &tile[..])
+    }
+
+    /// Expand into a dense image. Empty tiles are filled with all-zero texels.
+    pub fn to_dense(&self) -> HdrImage<T> {
+        let width = self.extent.width();
+        let mut data = vec![T::zeroed(); self.extent.texel_count()].into_boxed_slice();
+        for (&index, tile) in &self.tiles {
+            let (tx, ty) = (index % self.tiles_x(), index / self.tiles_x());
+            let len = self.tile_size.min(width - tx * self.tile_size) as usize;
+            for y in 0..self.tile_size.min(self.extent.height() - ty * self.tile_size) {
+                let dst = texel_index(width, self.tile_size, tx, ty, y);
+                data[dst..dst + len].copy_from_slice(&tile[y as usize * self.tile_size as usize..][..len]);
+            }
+        }
+        HdrImage::new(self.extent, data)
+    }
+
+    /// Serialize the image in a compact binary form.
+    ///
+    /// This consists of the magic bytes `RGBS`, then the width, height, tile size, bytes per texel, and number of stored tiles,
+    /// followed by the index and texels of each stored tile. All values other than texels are little-endian `u32`s.
+    pub fn encode<W: Write>(&self, mut out: W) -> ImageResult<()> {
+        let mut header = Vec::with_capacity(24);
+        header.extend_from_slice(SPARSE_MAGIC);
+        let texel_bytes = std::mem::size_of::<T>() as u32;
+        for field in [self.extent.width(), self.extent.height(), self.tile_size, texel_bytes, self.tiles.len() as u32] {
+            header.extend_from_slice(&field.to_le_bytes());
+        }
+        out.write_all(&header).map_err(ImageError::IoError)?;
+        for (index, tile) in &self.tiles {
+            out.write_all(&index.to_le_bytes()).map_err(ImageError::IoError)?;
+            out.write_all(bytemuck::cast_slice(tile)).map_err(ImageError::IoError)?;
+        }
+        out.flush().map_err(ImageError::IoError)
+    }
+
+    /// Read an image serialized by [SparseImage::encode].
+    /// Fails if the texels were serialized from a type of a different size.
+    pub fn decode<R: Read>(mut reader: R) -> ImageResult<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic).map_err(ImageError::IoError)?;
+        if &magic != SPARSE_MAGIC {
+            return Err(sparse_error("not a sparse RGBE image"));
+        }
+        let mut header = [0; 5];
+        for field in header.iter_mut() {
+            *field = read_u32(&mut reader)?;
+        }
+        let [width, height, tile_size, texel_bytes, tile_count] = header;
+        let extent = image_extent(width, height)?;
+        if tile_size == 0 || tile_size > Self::MAX_TILE_SIZE {
+            return Err(sparse_error(&format!("tile size must be between 1 and {}", Self::MAX_TILE_SIZE)));
+        }
+        if texel_bytes as usize != std::mem::size_of::<T>() {
+            return Err(sparse_error("texel size does not match"));
+        }
+        let mut image = SparseImage{extent, tile_size, tiles: BTreeMap::new()};
+        let tile_limit = count_tiles(extent, tile_size).ok_or_else(|| sparse_error("image has too many tiles"))?;
+        if tile_count > tile_limit {
+            return Err(sparse_error("too many stored tiles"));
+        }
+        for _ in 0..tile_count {
+            let index = read_u32(&mut reader)?;
+            if index >= tile_limit {
+                return Err(sparse_error("tile index out of range"));
+            }
+            let mut tile = vec![T::zeroed(); tile_len(tile_size)].into_boxed_slice();
+            reader.read_exact(bytemuck::cast_slice_mut(&mut tile)).map_err(ImageError::IoError)?;
+            image.tiles.insert(index, tile);
+        }
+        Ok(image)
+    }
+
+    /// Save the image in the format written by [SparseImage::encode].
+    pub fn save(&self, path: &Path) -> ImageResult<()> {
+        let file = File::create(path).map_err(ImageError::IoError)?;
+        self.encode(BufWriter::new(file))
+    }
+
+    /// Load an image saved by [SparseImage::save].
+    pub fn load(path: &Path) -> ImageResult<Self> {
+        let file = File::open(path).map_err(ImageError::IoError)?;
+        Self::decode(BufReader::new(file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 10x7 image which is black except for texels in tiles (0, 0) and (2, 1) of size 4, the second of which is partial.
+    fn test_image() -> HdrImage<RGBE8> {
+        let extent = Extent2D::new(10, 7).unwrap();
+        let mut data = vec![RGBE8{r: 0, g: 0, b: 0, e: 0}; 70];
+        data[1] = RGBE8{r: 128, g: 64, b: 32, e: 129};
+        data[6 * 10 + 9] = RGBE8{r: 255, g: 1, b: 2, e: 100};
+        HdrImage::new(extent, data.into_boxed_slice())
+    }
+
+    #[test]
+    fn dense_round_trip() {
+        let image = test_image();
+        let sparse = SparseImage::from_dense(&image, 4);
+        assert_eq!((sparse.tiles_x(), sparse.stored_tile_count()), (3, 2));
+        assert!(sparse.tile(0, 0).is_some() && sparse.tile(2, 1).is_some());
+        assert!(sparse.tile(1, 0).is_none() && sparse.tile(3, 0).is_none());
+        // the partial tile is padded with zeros
+        let tile = sparse.tile(2, 1).unwrap();
+        assert_eq!(tile.len(), 16);
+        assert_eq!(tile[2 * 4 + 1], image.pixels()[6 * 10 + 9]);
+        assert_eq!(tile[2 * 4 + 2], RGBE8{r: 0, g: 0, b: 0, e: 0});
+        assert_eq!(sparse.to_dense(), image);
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let sparse = SparseImage::from_dense(&test_image(), 4);
+        let mut bytes = Vec::new();
+        sparse.encode(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 24 + 2 * (4 + 16 * 4));
+        assert_eq!(SparseImage::<RGBE8>::decode(&bytes[..]).unwrap(), sparse);
+        // the texel size is checked
+        assert!(SparseImage::<RGB32F>::decode(&bytes[..]).is_err());
+    }
+
+    #[test]
+    fn rejects_corrupt_files() {
+        let mut bytes = Vec::new();
+        SparseImage::from_dense(&test_image(), 4).encode(&mut bytes).unwrap();
+        assert!(SparseImage::<RGBE8>::decode(&bytes[..bytes.len() - 1]).is_err());
+        // tile size, tile count, and the index of the first tile
+        for (offset, value) in [(12, 0), (12, SparseImage::<RGBE8>::MAX_TILE_SIZE + 1), (20, 7), (24, 6)] {
+            let mut corrupt = bytes.clone();
+            corrupt[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+            assert!(SparseImage::<RGBE8>::decode(&corrupt[..]).is_err(), "field at {offset} set to {value}");
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use crate::types::*;
+use crate::hdrimage::*;
+
+use bytemuck::Pod;
+
+/// Order of texels within a buffer.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum TexelLayout {
+    /// Row-major order, as used by [HdrImage].
+    #[default]
+    Linear,
+    /// Z-order, with the bits of the x and y coordinates interleaved (x in the lowest bit).
+    /// Texels are sorted by their Morton code, so for square power-of-two images the index of each texel is exactly its code,
+    /// and other sizes are compacted without gaps.
+    Morton,
+    /// Row-major order of `tile_width` by `tile_height` tiles, each of which is in row-major order.
+    /// Tiles on the right and bottom edges are padded with zeros to the full tile size.
+    Tiled{tile_width: u32, tile_height: u32},
+}
+
+/// Interleave the bits of `x` and `y` to get the Morton (Z-order) code of a texel.
+pub fn morton_code(x: u32, y: u32) -> u64 {
+    fn spread(v: u32) -> u64 {
+        let mut v = v as u64;
+        v = (v | (v << 16)) & 0x0000_FFFF_0000_FFFF;
+        v = (v | (v << 8)) & 0x00FF_00FF_00FF_00FF;
+        v = (v | (v << 4)) & 0x0F0F_0F0F_0F0F_0F0F;
+        v = (v | (v << 2)) & 0x3333_3333_3333_3333;
+        v = (v | (v << 1)) & 0x5555_5555_5555_5555;
+        v
+    }
+    spread(x) | (spread(y) << 1)
+}
+
+impl TexelLayout {
+    /// For each texel of the buffer, the index of the corresponding texel in row-major order (or `None` for padding).
+    fn source_indices(self, extent: Extent2D) -> Vec<Option<usize>> {
+        let (width, height) = (extent.width() as usize, extent.height() as usize);
+        match self {
+            TexelLayout::Linear => (0..extent.texel_count()).map(Some).collect(),
+            TexelLayout::Morton => {
+                let mut indices: Vec<usize> = (0..extent.texel_count()).collect();
+                indices.sort_by_key(|&i| morton_code((i % width) as u32, (i / width) as u32));
+                indices.into_iter().map(Some).collect()
+            }
+            TexelLayout::Tiled{tile_width, tile_height} => {
+                assert!(tile_width > 0 && tile_height > 0, "tile size must be greater than zero");
+                // buffer_len() checks that the padded size fits in a usize
+                let mut indices = Vec::with_capacity(self.buffer_len(extent));
+                let (tile_width, tile_height) = (tile_width as usize, tile_height as usize);
+                for ty in 0..height.div_ceil(tile_height) {
+                    for tx in 0..width.div_ceil(tile_width) {
+                        for y in ty * tile_height..(ty + 1) * tile_height {
+                            for x in tx * tile_width..(tx + 1) * tile_width {
+                                indices.push((x < width && y < height).then_some(y * width + x));
+                            }
+                        }
+                    }
+                }
+                indices
+            }
+        }
+    }
+
+    /// Number of texels in a buffer with this layout, including any padding.
+    /// Panics if the padded size does not fit in a `usize`.
+    pub fn buffer_len(self, extent: Extent2D) -> usize {
+        match self {
+            TexelLayout::Linear | TexelLayout::Morton => extent.texel_count(),
+            TexelLayout::Tiled{tile_width, tile_height} => {
+                let padded = |size: u32, tile: u32| (size.div_ceil(tile) as usize).checked_mul(tile as usize);
+                padded(extent.width(), tile_width).zip(padded(extent.height(), tile_height))
+                    .and_then(|(width, height)| width.checked_mul(height))
+                    .expect("padded size of tiled layout does not fit in a usize")
+            }
+        }
+    }
+}
+
+/// A texel buffer in a (possibly) non-linear layout, along with the information needed to convert it back.
+#[derive(PartialEq, Clone, Debug)]
+pub struct SwizzledImage<T: Pod> {
+    pub extent: Extent2D,
+    pub layout: TexelLayout,
+    pub data: Box<[T]>,
+}
+
+impl<T: Pod> SwizzledImage<T> {
+    /// Reorder the texels of an image into `layout`.
+    /// Panics if a tiled layout has a tile size of zero.
+    pub fn new(image: &HdrImage<T>, layout: TexelLayout) -> Self {
+        let pixels = image.pixels();
+        let data = layout.source_indices(image.extent()).into_iter()
+            .map(|i| i.map_or(T::zeroed(), |i| pixels[i]))
+            .collect();
+        SwizzledImage{extent: image.extent(), layout, data}
+    }
+
+    /// Convert back to row-major order, discarding any padding.
+    /// Panics if the length of the data does not match the layout.
+    pub fn to_linear(&self) -> HdrImage<T> {
+        assert_eq!(self.data.len(), self.layout.buffer_len(self.extent), "data does not match layout");
+        let mut data = vec![T::zeroed(); self.extent.texel_count()].into_boxed_slice();
+        for (texel, i) in self.data.iter().zip(self.layout.source_indices(self.extent)) {
+            if let Some(i) = i {
+                data[i] = *texel;
+            }
+        }
+        HdrImage::new(self.extent, data)
+    }
+}
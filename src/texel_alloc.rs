@@ -0,0 +1,106 @@
+use crate::types::*;
+use crate::convert::convert_into;
+
+use bytemuck::Pod;
+use core::ops::DerefMut;
+use image::{codecs::{hdr::HdrDecoder, png::PngDecoder}, error::{LimitError, LimitErrorKind}, ImageDecoder, ImageError, ImageResult};
+use std::io::{BufRead, Read};
+
+/// Source of the texel buffers returned by the `_in` variants of decoding and conversion functions (such as [decode_radiance_in]),
+/// so that engines can decode straight into their own allocators or frame arenas instead of copying out of a `Box<[T]>`.
+///
+/// Implemented by [HeapAllocator] (which gives the same `Box<[T]>` as the other functions), by [TexelArena] for a borrowed block of memory,
+/// and by mutable references to other allocators. Wrapping a bump allocator takes only a few lines, for example for `&'a bumpalo::Bump`
+/// with a `Buffer<T>` of `&'a mut [T]` allocated by `alloc_slice_fill_copy(len, T::zeroed())`.
+pub trait TexelAllocator {
+    /// Buffer of texels owned by or borrowed from the allocator.
+    type Buffer<T: Pod>: DerefMut<Target = [T]>;
+
+    /// Allocates a buffer of `len` zeroed texels, or returns `None` if there is not enough memory.
+    fn alloc_zeroed<T: Pod>(&mut self, len: usize) -> Option<Self::Buffer<T>>;
+}
+
+impl<A: TexelAllocator + ?Sized> TexelAllocator for &mut A {
+    type Buffer<T: Pod> = A::Buffer<T>;
+
+    fn alloc_zeroed<T: Pod>(&mut self, len: usize) -> Option<Self::Buffer<T>> {
+        (**self).alloc_zeroed(len)
+    }
+}
+
+/// Allocates texel buffers on the heap as `Box<[T]>`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct HeapAllocator;
+
+impl TexelAllocator for HeapAllocator {
+    type Buffer<T: Pod> = Box<[T]>;
+
+    fn alloc_zeroed<T: Pod>(&mut self, len: usize) -> Option<Box<[T]>> {
+        bytemuck::allocation::try_zeroed_slice_box(len).ok()
+    }
+}
+
+/// Bump allocator handing out texel buffers from a borrowed block of memory, such as a per-frame arena of an engine.
+/// Buffers are never freed individually; the whole block becomes available again when the arena is dropped.
+#[derive(Debug)]
+pub struct TexelArena<'a> {
+    free: &'a mut [u8],
+}
+
+impl<'a> TexelArena<'a> {
+    pub fn new(memory: &'a mut [u8]) -> Self {
+        TexelArena{free: memory}
+    }
+
+    /// Number of bytes which have not been allocated yet (some of which may be skipped to align the next buffer).
+    pub fn remaining(&self) -> usize {
+        self.free.len()
+    }
+}
+
+impl<'a> TexelAllocator for TexelArena<'a> {
+    type Buffer<T: Pod> = &'a mut [T];
+
+    fn alloc_zeroed<T: Pod>(&mut self, len: usize) -> Option<&'a mut [T]> {
+        let padding = self.free.as_ptr().align_offset(align_of::<T>());
+        let size = len.checked_mul(size_of::<T>())?;
+        if padding.checked_add(size)? > self.free.len() {
+            return None;
+        }
+        let (buffer, rest) = core::mem::take(&mut self.free)[padding..].split_at_mut(size);
+        self.free = rest;
+        buffer.fill(0);
+        Some(bytemuck::cast_slice_mut(buffer))
+    }
+}
+
+/// Allocates `len` texels from `alloc`, reporting failure as an [ImageError].
+fn alloc_texels<T: Pod, A: TexelAllocator>(alloc: &mut A, len: usize) -> ImageResult<A::Buffer<T>> {
+    alloc.alloc_zeroed(len).ok_or_else(|| ImageError::Limits(LimitError::from_kind(LimitErrorKind::InsufficientMemory)))
+}
+
+/// Reads the data from an [HdrDecoder] as [RGBE8] texels in a buffer from `alloc` (see [decode_radiance](crate::decode_radiance)).
+pub fn decode_radiance_in<R: BufRead, A: TexelAllocator>(dec: HdrDecoder<R>, mut alloc: A) -> ImageResult<A::Buffer<RGBE8>> {
+    let meta = dec.metadata();
+    let mut out = alloc_texels(&mut alloc, crate::load::image_extent(meta.width, meta.height)?.texel_count())?;
+    dec.read_image_transform(|px| {
+        RGBE8{r: px.c[0], g: px.c[1], b: px.c[2], e: px.e}
+    }, &mut out)?;
+    Ok(out)
+}
+
+/// Reads the data from a [PngDecoder] as [RGBE8] texels in a buffer from `alloc` (see [decode_rgbe8_png](crate::decode_rgbe8_png)).
+pub fn decode_rgbe8_png_in<R: Read, A: TexelAllocator>(dec: PngDecoder<R>, mut alloc: A) -> ImageResult<A::Buffer<RGBE8>> {
+    crate::load::check_rgba8(&dec)?;
+    let (width, height) = dec.dimensions();
+    let mut out = alloc_texels(&mut alloc, crate::load::image_extent(width, height)?.texel_count())?;
+    dec.read_image(bytemuck::cast_slice_mut(&mut out))?;
+    Ok(out)
+}
+
+/// Converts texels to another format in a buffer from `alloc`, or returns `None` if it is out of memory.
+pub fn convert_in<T: Copy, U: Pod + From<T>, A: TexelAllocator>(data: &[T], mut alloc: A) -> Option<A::Buffer<U>> {
+    let mut out = alloc.alloc_zeroed(data.len())?;
+    convert_into(data, &mut out);
+    Some(out)
+}
@@ -0,0 +1,371 @@
+use crate::types::*;
+use crate::colorspace::*;
+
+/// Encode a linear value with the sRGB transfer function, clamping it to the range 0 to 1.
+pub fn linear_to_srgb(v: f32) -> f32 {
+    let v = v.clamp(0.0, 1.0);
+    if v <= 0.0031308 { 12.92 * v } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 }
+}
+
+/// Decode a value encoded with the sRGB transfer function (from 0 to 1) to linear light.
+pub fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+}
+
+/// Encode a linear value as 8-bit sRGB, clamping it to the range 0 to 1.
+pub fn linear_to_srgb8(v: f32) -> u8 {
+    (linear_to_srgb(v) * 255.0).round() as u8
+}
+
+/// Encode an absolute luminance in nits (cd/m²) with the SMPTE ST 2084 (PQ) transfer function,
+/// giving a signal value from 0 to 1 (at 10000 nits).
+pub fn pq_encode(nits: f32) -> f32 {
+    const M1: f32 = 2610.0 / 16384.0;
+    const M2: f32 = 2523.0 / 4096.0 * 128.0;
+    const C1: f32 = 3424.0 / 4096.0;
+    const C2: f32 = 2413.0 / 4096.0 * 32.0;
+    const C3: f32 = 2392.0 / 4096.0 * 32.0;
+    let y = (nits / 10000.0).clamp(0.0, 1.0).powf(M1);
+    ((C1 + C2 * y) / (1.0 + C3 * y)).powf(M2)
+}
+
+/// Decode a SMPTE ST 2084 (PQ) signal value from 0 to 1 to an absolute luminance in nits (cd/m²), inverting [pq_encode].
+pub fn pq_decode(signal: f32) -> f32 {
+    const M1: f32 = 2610.0 / 16384.0;
+    const M2: f32 = 2523.0 / 4096.0 * 128.0;
+    const C1: f32 = 3424.0 / 4096.0;
+    const C2: f32 = 2413.0 / 4096.0 * 32.0;
+    const C3: f32 = 2392.0 / 4096.0 * 32.0;
+    let e = signal.clamp(0.0, 1.0).powf(1.0 / M2);
+    ((e - C1).max(0.0) / (C2 - C3 * e)).powf(1.0 / M1) * 10000.0
+}
+
+const HLG_A: f32 = 0.17883277;
+const HLG_B: f32 = 0.28466892;
+const HLG_C: f32 = 0.5599107;
+
+/// Encode a relative scene-linear value from 0 to 1 with the ITU-R BT.2100 hybrid log-gamma (HLG) OETF,
+/// giving a signal value from 0 to 1.
+pub fn hlg_encode(v: f32) -> f32 {
+    let v = v.clamp(0.0, 1.0);
+    if v <= 1.0 / 12.0 { (3.0 * v).sqrt() } else { HLG_A * (12.0 * v - HLG_B).ln() + HLG_C }
+}
+
+/// Decode a hybrid log-gamma (HLG) signal value from 0 to 1 to a relative scene-linear value, inverting [hlg_encode].
+pub fn hlg_decode(signal: f32) -> f32 {
+    let signal = signal.clamp(0.0, 1.0);
+    if signal <= 0.5 { signal * signal / 3.0 } else { (((signal - HLG_C) / HLG_A).exp() + HLG_B) / 12.0 }
+}
+
+/// Transfer functions of ITU-R BT.2100 HDR video, for exchanging images with HDR10 and broadcast tools
+/// with [encode_hdr_signal] and [decode_hdr_signal].
+///
+/// Linear values are relative to the reference white of ITU-R BT.2408, so a scene value of 1.0
+/// is [HDR_REFERENCE_WHITE_NITS] with PQ or a 75% signal with HLG, and other values keep their ratio to it.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum HdrTransfer {
+    /// SMPTE ST 2084 perceptual quantizer, covering absolute luminances up to 10000 nits. Used by HDR10.
+    Pq,
+    /// Hybrid log-gamma, a relative encoding with a nominal peak of about 3.8 times reference white.
+    Hlg,
+}
+
+/// Scene-linear HLG value (relative to the nominal peak of 1) of reference white, which has a 75% signal.
+const HLG_REFERENCE_WHITE: f32 = 0.26496256;
+
+impl HdrTransfer {
+    /// Encode a linear value relative to reference white as a signal value from 0 to 1, clipping values above the peak.
+    pub fn encode(self, v: f32) -> f32 {
+        match self {
+            HdrTransfer::Pq => pq_encode(v * HDR_REFERENCE_WHITE_NITS),
+            HdrTransfer::Hlg => hlg_encode(v * HLG_REFERENCE_WHITE),
+        }
+    }
+
+    /// Decode a signal value from 0 to 1 to a linear value relative to reference white.
+    pub fn decode(self, signal: f32) -> f32 {
+        match self {
+            HdrTransfer::Pq => pq_decode(signal) / HDR_REFERENCE_WHITE_NITS,
+            HdrTransfer::Hlg => hlg_decode(signal) / HLG_REFERENCE_WHITE,
+        }
+    }
+}
+
+/// Encode linear Rec.709 texels as full-range Rec.2020 signal values with an [HdrTransfer] function, without tone mapping,
+/// quantized to `bit_depth` bits (such as 10 for HDR10 or 16 for PNG, which can be saved with [save_rgb16_png_file](crate::save_rgb16_png_file)).
+///
+/// Colours outside Rec.2020 and values above the peak of the transfer function are clipped.
+/// Panics if `bit_depth` is not between 1 and 16.
+pub fn encode_hdr_signal<T: RgbTexel>(data: &[T], transfer: HdrTransfer, bit_depth: u32) -> Box<[[u16;3]]> {
+    assert!((1..=16).contains(&bit_depth), "bit depth must be between 1 and 16");
+    let max = ((1u32 << bit_depth) - 1) as f32;
+    let matrix = Primaries::Rec709.conversion_matrix(Primaries::Rec2020);
+    data.iter().map(|texel| mul_matrix(&matrix, texel.to_rgb()).map(|c| (transfer.encode(c) * max).round() as u16)).collect()
+}
+
+/// Decode full-range Rec.2020 signal values of `bit_depth` bits with an [HdrTransfer] function into linear Rec.709 colours,
+/// inverting [encode_hdr_signal]. Rec.2020 colours outside Rec.709 have negative components, which are clamped when packed.
+///
+/// Panics if `bit_depth` is not between 1 and 16.
+pub fn decode_hdr_signal(data: &[[u16;3]], transfer: HdrTransfer, bit_depth: u32) -> Box<[RGB32F]> {
+    assert!((1..=16).contains(&bit_depth), "bit depth must be between 1 and 16");
+    let max = ((1u32 << bit_depth) - 1) as f32;
+    let matrix = Primaries::Rec2020.conversion_matrix(Primaries::Rec709);
+    data.iter().map(|px| RGB32F::from(mul_matrix(&matrix, px.map(|c| transfer.decode(c as f32 / max))))).collect()
+}
+
+/// Tone map a linear colour (scaled by a linear `exposure` factor) to 8-bit sRGB using the Reinhard operator on each channel.
+pub fn reinhard_srgb8(rgb: [f32;3], exposure: f32) -> [u8;3] {
+    rgb.map(|c| {
+        let c = c.max(0.0) * exposure;
+        linear_to_srgb8(c / (1.0 + c))
+    })
+}
+
+/// Curves for compressing scene-referred linear colours into the displayable range.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum ToneMapOperator {
+    /// `x / (1 + x)` on each channel. Simple and never clips, but desaturates highlights slowly.
+    #[default]
+    Reinhard,
+    /// Stephen Hill's fit of the ACES reference rendering and sRGB output transforms.
+    /// Filmic contrast, with bright saturated colours shifting in hue.
+    Aces,
+    /// Troy Sobotka's AgX, using the polynomial approximation by Benjamin Wrensch.
+    /// Bright colours desaturate towards white, which suits skies and light sources.
+    AgX,
+}
+
+const ACES_INPUT: [[f32;3];3] = [
+    [0.59719, 0.35458, 0.04823],
+    [0.07600, 0.90834, 0.01566],
+    [0.02840, 0.13383, 0.83777],
+];
+
+const ACES_OUTPUT: [[f32;3];3] = [
+    [1.60475, -0.53108, -0.07367],
+    [-0.10208, 1.10813, -0.00605],
+    [-0.00327, -0.07276, 1.07602],
+];
+
+const AGX_INSET: [[f32;3];3] = [
+    [0.8424791, 0.0784336, 0.0792237],
+    [0.0423282, 0.8784686, 0.0791661],
+    [0.0423757, 0.0784336, 0.879143],
+];
+
+const AGX_OUTSET: [[f32;3];3] = [
+    [1.196879, -0.0980209, -0.0990297],
+    [-0.0528969, 1.1519031, -0.0989612],
+    [-0.0529716, -0.0980435, 1.1510737],
+];
+
+/// Range of the log2 encoding used by AgX, in stops relative to middle grey.
+const AGX_MIN_EV: f32 = -12.47393;
+const AGX_MAX_EV: f32 = 4.026069;
+
+impl ToneMapOperator {
+    /// Map a linear Rec.709 colour to linear display values between 0 and 1.
+    pub fn apply(self, rgb: [f32;3]) -> [f32;3] {
+        let rgb = rgb.map(|c| c.max(0.0));
+        match self {
+            ToneMapOperator::Reinhard => rgb.map(|c| c / (1.0 + c)),
+            ToneMapOperator::Aces => {
+                let v = mul_matrix(&ACES_INPUT, rgb).map(|v| {
+                    let a = v * (v + 0.0245786) - 0.000090537;
+                    let b = v * (0.983729 * v + 0.432951) + 0.238081;
+                    a / b
+                });
+                mul_matrix(&ACES_OUTPUT, v).map(|c| c.clamp(0.0, 1.0))
+            }
+            ToneMapOperator::AgX => {
+                let v = mul_matrix(&AGX_INSET, rgb).map(|v| {
+                    let x = (v.max(1e-10).log2().clamp(AGX_MIN_EV, AGX_MAX_EV) - AGX_MIN_EV) / (AGX_MAX_EV - AGX_MIN_EV);
+                    let x2 = x * x;
+                    let x4 = x2 * x2;
+                    15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232
+                });
+                // the curve produces display-encoded values, which are decoded with a 2.2 gamma
+                mul_matrix(&AGX_OUTSET, v).map(|c| c.max(0.0).powf(2.2).min(1.0))
+            }
+        }
+    }
+
+    /// Tone map a linear colour (scaled by a linear `exposure` factor) to 8-bit sRGB.
+    pub fn srgb8(self, rgb: [f32;3], exposure: f32) -> [u8;3] {
+        self.apply(rgb.map(|c| c * exposure)).map(linear_to_srgb8)
+    }
+}
+
+/// Tone map texels (in any [RgbTexel] format) to 8-bit sRGB for previews using the Reinhard operator, with an exposure adjustment in stops.
+pub fn tonemap_srgb8<T: RgbTexel>(data: &[T], exposure: f32) -> Box<[[u8;3]]> {
+    tonemap_srgb8_with_operator(data, exposure, ToneMapOperator::Reinhard)
+}
+
+/// Tone map texels (in any [RgbTexel] format) to 8-bit sRGB using the given [ToneMapOperator],
+/// with an exposure adjustment in stops. This is intended for quick previews such as editor thumbnails.
+pub fn tonemap_srgb8_with_operator<T: RgbTexel>(data: &[T], exposure: f32, operator: ToneMapOperator) -> Box<[[u8;3]]> {
+    let exposure = exposure.exp2();
+    data.iter().map(|texel| operator.srgb8(texel.to_rgb(), exposure)).collect()
+}
+
+/// Luminance (in nits) which a scene value of 1.0 is displayed at on HDR targets, following the reference white of ITU-R BT.2408.
+pub const HDR_REFERENCE_WHITE_NITS: f32 = 203.0;
+
+/// Displays which tone-mapped previews can be encoded for.
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub enum DisplayTarget {
+    /// Standard dynamic range sRGB, which any viewer can show.
+    #[default]
+    Srgb,
+    /// Display P3: P3 primaries with a D65 white point and the sRGB transfer function, as used by recent Apple displays.
+    DisplayP3,
+    /// ITU-R BT.2100 HDR with Rec.2020 primaries and the PQ transfer function,
+    /// with highlights rolled off towards the given peak luminance of the display.
+    Rec2100Pq{peak_nits: f32},
+}
+
+impl DisplayTarget {
+    /// A typical HDR monitor, peaking at 1000 nits.
+    pub const REC2100_PQ_1000: DisplayTarget = DisplayTarget::Rec2100Pq{peak_nits: 1000.0};
+
+    /// Primaries of the encoded signal.
+    pub fn primaries(self) -> Primaries {
+        match self {
+            DisplayTarget::Srgb => Primaries::Rec709,
+            DisplayTarget::DisplayP3 => Primaries::DisplayP3,
+            DisplayTarget::Rec2100Pq{..} => Primaries::Rec2020,
+        }
+    }
+
+    /// Whether the target is a high dynamic range display.
+    pub fn is_hdr(self) -> bool {
+        matches!(self, DisplayTarget::Rec2100Pq{..})
+    }
+
+    /// Tone map a linear Rec.709 colour (scaled by a linear `exposure` factor) with the given [ToneMapOperator]
+    /// and encode it for this display, giving signal values between 0 and 1.
+    ///
+    /// For HDR targets, scene values are scaled so that 1.0 corresponds to [HDR_REFERENCE_WHITE_NITS]
+    /// and the operator is applied relative to the display's peak, so highlights roll off towards the peak instead of SDR white.
+    pub fn encode(self, rgb: [f32;3], exposure: f32, operator: ToneMapOperator) -> [f32;3] {
+        let rgb = rgb.map(|c| c * exposure);
+        match self {
+            DisplayTarget::Srgb => operator.apply(rgb).map(linear_to_srgb),
+            DisplayTarget::DisplayP3 => Primaries::Rec709.convert_rgb(operator.apply(rgb), Primaries::DisplayP3).map(linear_to_srgb),
+            DisplayTarget::Rec2100Pq{peak_nits} => {
+                let headroom = peak_nits / HDR_REFERENCE_WHITE_NITS;
+                let display = operator.apply(rgb.map(|c| c / headroom));
+                Primaries::Rec709.convert_rgb(display, Primaries::Rec2020).map(|c| pq_encode(c * peak_nits))
+            }
+        }
+    }
+}
+
+/// Tone map texels (in any [RgbTexel] format) for a [DisplayTarget], with an exposure adjustment in stops,
+/// giving display-encoded signal values between 0 and 1.
+pub fn tonemap_display<T: RgbTexel>(data: &[T], exposure: f32, operator: ToneMapOperator, target: DisplayTarget) -> Box<[[f32;3]]> {
+    let exposure = exposure.exp2();
+    data.iter().map(|texel| target.encode(texel.to_rgb(), exposure, operator)).collect()
+}
+
+/// Tone map texels for a [DisplayTarget] into 10-bit pixels with 2 bits of alpha (always opaque),
+/// laid out as in `RGB10A2_UNORM` textures (red in the lowest bits). These can be copied directly into an HDR swapchain.
+pub fn tonemap_rgb10a2<T: RgbTexel>(data: &[T], exposure: f32, operator: ToneMapOperator, target: DisplayTarget) -> Box<[u32]> {
+    let exposure = exposure.exp2();
+    data.iter().map(|texel| {
+        let [r, g, b] = target.encode(texel.to_rgb(), exposure, operator).map(|c| (c * 1023.0).round() as u32);
+        (3 << 30) | (b << 20) | (g << 10) | r
+    }).collect()
+}
+
+/// Tone map texels for a [DisplayTarget] into 8-bit pixels, such as for saving SDR previews with [save_rgb8_png_file](crate::save_rgb8_png_file).
+/// HDR targets need more precision than this, so should use [tonemap_rgb10a2] or [tonemap_rgb16] instead.
+pub fn tonemap_rgb8<T: RgbTexel>(data: &[T], exposure: f32, operator: ToneMapOperator, target: DisplayTarget) -> Box<[[u8;3]]> {
+    let exposure = exposure.exp2();
+    data.iter().map(|texel| target.encode(texel.to_rgb(), exposure, operator).map(|c| (c * 255.0).round() as u8)).collect()
+}
+
+/// Tone map texels for a [DisplayTarget] into 16-bit pixels, such as for saving HDR previews with [save_rgb16_png_file](crate::save_rgb16_png_file).
+pub fn tonemap_rgb16<T: RgbTexel>(data: &[T], exposure: f32, operator: ToneMapOperator, target: DisplayTarget) -> Box<[[u16;3]]> {
+    let exposure = exposure.exp2();
+    data.iter().map(|texel| target.encode(texel.to_rgb(), exposure, operator).map(|c| (c * 65535.0).round() as u16)).collect()
+}
+
+/// Diagonal stripes drawn over clipping pixels in previews, showing which parts of an image will not survive packing.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Zebra {
+    /// Pixels with any channel above this value (before exposure) are striped.
+    pub threshold: f32,
+    pub color: [u8;3],
+    /// Width of each stripe and of the gaps between them, in pixels.
+    pub stripe_width: u32,
+}
+
+impl Zebra {
+    /// Stripes over pixels with any channel above `threshold`.
+    pub fn new(threshold: f32) -> Self {
+        Zebra{threshold, color: [255, 0, 255], stripe_width: 4}
+    }
+
+    /// Whether pixel `(x, y)` with colour `rgb` is covered by a stripe.
+    pub fn marks(&self, rgb: [f32;3], x: u32, y: u32) -> bool {
+        let on_stripe = ((x + y) / self.stripe_width.max(1)).is_multiple_of(2);
+        on_stripe && rgb.iter().any(|&c| c > self.threshold)
+    }
+}
+
+/// Stripes over values which [RGB9E5] cannot represent.
+impl Default for Zebra {
+    fn default() -> Self {
+        Zebra::new(RGB9E5::MAX)
+    }
+}
+
+/// Draw zebra stripes onto a preview of `data` (such as from [tonemap_srgb8]) wherever it exceeds the threshold.
+pub fn zebra_overlay<T: RgbTexel>(extent: Extent2D, data: &[T], preview: &mut [[u8;3]], zebra: &Zebra) {
+    assert_eq!(data.len(), extent.texel_count(), "data does not match image dimensions");
+    assert_eq!(preview.len(), extent.texel_count(), "preview does not match image dimensions");
+    let width = extent.width() as usize;
+    for (i, (texel, px)) in data.iter().zip(preview.iter_mut()).enumerate() {
+        if zebra.marks(texel.to_rgb(), (i % width) as u32, (i / width) as u32) {
+            *px = zebra.color;
+        }
+    }
+}
+
+/// Luminance of middle grey, which false colour stops are measured relative to.
+pub const MIDDLE_GREY: f32 = 0.18;
+
+/// False colours for each stop from -6 (and below) to +6 (and above) relative to middle grey.
+/// Underexposed regions are purple and blue, middle grey is green, and highlights go through yellow to red and white.
+pub const FALSE_COLOR_PALETTE: [[u8;3]; 13] = [
+    [40, 0, 60],
+    [90, 0, 140],
+    [0, 0, 200],
+    [0, 90, 255],
+    [0, 170, 200],
+    [50, 110, 90],
+    [60, 200, 60],
+    [160, 200, 60],
+    [240, 220, 0],
+    [255, 150, 0],
+    [255, 60, 0],
+    [220, 0, 0],
+    [255, 255, 255],
+];
+
+/// False colour of a luminance value, rounded to the nearest stop relative to [MIDDLE_GREY].
+pub fn false_color_of_luminance(lum: f32) -> [u8;3] {
+    let stop = if lum > 0.0 { (lum / MIDDLE_GREY).log2().round().clamp(-6.0, 6.0) } else { -6.0 };
+    FALSE_COLOR_PALETTE[(stop + 6.0) as usize]
+}
+
+/// Visualize the luminance of texels (in any [RgbTexel] format) as a heat map by stops, like the false colour modes of cameras.
+/// This is useful for checking the exposure of captured HDR images.
+///
+/// `exposure` (in stops) is applied before measuring luminance.
+pub fn false_color<T: RgbTexel>(data: &[T], exposure: f32) -> Box<[[u8;3]]> {
+    let exposure = exposure.exp2();
+    data.iter().map(|texel| false_color_of_luminance(luminance(texel.to_rgb()) * exposure)).collect()
+}
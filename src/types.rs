@@ -1,5 +1,45 @@
 use bytemuck::{Pod, Zeroable};
 use half::f16;
+#[cfg(feature = "std")]
+use half::slice::HalfFloatSliceExt;
+
+#[cfg(not(any(feature = "std", test)))]
+use crate::float::FloatExt;
+
+/// Dimensions of a 2D image in texels.
+///
+/// Both dimensions are non-zero and the total number of texels is guaranteed to fit in a `usize`,
+/// so image buffers can always be indexed without overflow.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+pub struct Extent2D {
+    width: u32,
+    height: u32,
+}
+
+impl Extent2D {
+    /// Returns `None` if either dimension is zero or the texel count would overflow.
+    pub fn new(width: u32, height: u32) -> Option<Self> {
+        if width == 0 || height == 0 {
+            return None;
+        }
+        (width as usize).checked_mul(height as usize)?;
+        Some(Extent2D{width, height})
+    }
+
+    pub fn width(self) -> u32 {
+        self.width
+    }
+
+    pub fn height(self) -> u32 {
+        self.height
+    }
+
+    /// Number of texels in an image of this size.
+    pub fn texel_count(self) -> usize {
+        // checked in new()
+        self.width as usize * self.height as usize
+    }
+}
 
 /// Aligned representation of Radiance RGBE8 pixel.
 /// r, g, and b are subnormal mantissas and e (taking the place of the alpha channel) is a common exponent.
@@ -27,6 +67,13 @@ pub struct RGBE8 {
 #[derive(PartialEq, Eq, Clone, Copy, Debug, Pod, Zeroable)]
 pub struct RGB9E5(pub u32);
 
+/// Aligned representation of `rg11b10ufloat` texel.
+/// Field order (from LSB to MSB) is 11 bits each of R and G then 10 bits of B,
+/// each an unsigned float with 5 bits of exponent (with the same bias as `f16`) and 6 or 5 bits of mantissa.
+#[repr(transparent)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Pod, Zeroable)]
+pub struct R11G11B10F(pub u32);
+
 /// Aligned representation of `rgba16float` texel.
 /// This is a common render format for HDR images when creating or processing assets before conversion to a GPU-read-only RGBE format.
 #[repr(C, align(8))]
@@ -38,17 +85,119 @@ pub struct RGBA16F {
     pub a: f16,
 }
 
+/// Representation of an `rgb32float` texel.
+/// This is a convenient format for offline processing as it has no loss of precision.
+#[repr(C)]
+#[derive(PartialEq, Clone, Copy, Debug, Default, Pod, Zeroable)]
+pub struct RGB32F {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+
+/// Aligned representation of an `rgba32float` texel.
+#[repr(C, align(16))]
+#[derive(PartialEq, Clone, Copy, Debug, Default, Pod, Zeroable)]
+pub struct RGBA32F {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+/// Aligned representation of an RGBM8 texel, as used by many mobile and legacy pipelines.
+/// r, g, and b are scaled by a common multiplier `m / 255 * range`, where `range` is the maximum representable value
+/// (chosen per project, commonly 6 or 8).
+#[repr(C, align(4))]
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Pod, Zeroable)]
+pub struct RGBM8 {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub m: u8,
+}
+
+/// Aligned representation of an RGBD8 texel, which stores colour divided by a common divisor in the alpha channel.
+/// The value of each channel is `c / 255 * range / d`, where `range` is the maximum representable value.
+///
+/// Unlike RGBE8 and RGBM8, interpolating between RGBD8 texels gives reasonable results,
+/// so this format is sometimes used for textures which are filtered before decoding.
+#[repr(C, align(4))]
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Pod, Zeroable)]
+pub struct RGBD8 {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub d: u8,
+}
+
+/// Greg Ward's 32-bit LogLuv encoding, as used in TIFF files.
+/// From MSB to LSB, this contains a sign bit, 15 bits of log2 luminance, then 8 bits each of the CIE (u', v') chromaticity.
+///
+/// This has a constant relative precision of about 0.3% in luminance over a very wide range,
+/// which is better than RGBE8 for some archival uses.
+#[repr(transparent)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Pod, Zeroable)]
+pub struct LogLuv32(pub u32);
+
+/// How packing treats non-finite (NaN or infinite) channels, such as fireflies in renders.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum NonFinitePolicy {
+    /// Infinities are clamped to the largest representable value and NaN channels become zero.
+    /// This is what `pack` does.
+    #[default]
+    Clamp,
+    /// Texels with any non-finite channel become black.
+    Zero,
+    /// Packing fails with a [NonFiniteError], so that asset builds can reject bad pixels.
+    Error,
+}
+
+impl NonFinitePolicy {
+    /// Apply the policy to a colour before packing it.
+    /// Finite colours are returned unchanged.
+    pub fn apply(self, rgb: [f32;3]) -> Result<[f32;3], NonFiniteError> {
+        if rgb.iter().all(|c| c.is_finite()) {
+            return Ok(rgb);
+        }
+        match self {
+            NonFinitePolicy::Clamp => Ok(rgb.map(|c| if c.is_nan() { 0.0 } else { c.clamp(f32::MIN, f32::MAX) })),
+            NonFinitePolicy::Zero => Ok([0.0; 3]),
+            NonFinitePolicy::Error => Err(NonFiniteError{rgb}),
+        }
+    }
+}
+
+/// Error returned when packing a colour with a NaN or infinite channel using [NonFinitePolicy::Error].
+#[derive(Clone, Copy, Debug)]
+pub struct NonFiniteError {
+    /// The colour which could not be packed.
+    pub rgb: [f32;3],
+}
+
+impl core::fmt::Display for NonFiniteError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "colour {:?} has a non-finite channel", self.rgb)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for NonFiniteError {}
+
 impl RGB9E5 {
+    /// Largest value representable in each channel. Larger values are clamped when packing.
+    pub const MAX: f32 = 65408.0;
+
     /// Clamp and pack a triple of RGB float values into an RGB9E5 value.
+    /// Infinities are clamped to [RGB9E5::MAX] and NaN channels become zero ([NonFinitePolicy::Clamp]).
     ///
     /// Ported from the C++ example in the DirectX docs (MIT licensed)
     /// https://github.com/microsoft/DirectX-Graphics-Samples/blob/master/MiniEngine/Core/Color.cpp
     pub fn pack(rgb: [f32;3]) -> Self {
         const MAX_F14:f32 = (0x1FFu32 << 7) as f32;
         const MIN_NORM_F14:f32 = 1.0 / ((1u32 << 16) as f32);
-        let r = rgb[0].clamp(0.0, MAX_F14);
-        let g = rgb[1].clamp(0.0, MAX_F14);
-        let b = rgb[2].clamp(0.0, MAX_F14);
+        // unlike clamp, this maps NaN to zero
+        let [r, g, b] = rgb.map(|c| if c > 0.0 { c.min(MAX_F14) } else { 0.0 });
 
         // Compute the maximum channel, no less than 1.0*2^-15
         let max_channel =  MIN_NORM_F14.max(r).max(g).max(b);
@@ -71,8 +220,8 @@ impl RGB9E5 {
         let g_bits = (g + bias).to_bits() & 0x1ff;
         let b_bits = (b + bias).to_bits() & 0x1ff;
 
-        // Convert the Bias to the correct exponent in the upper 5 bits.
-        let e_bits = (bias_bits << 4) + 0x10000000;
+        // Convert the Bias to the correct exponent in the upper 5 bits (this wraps to zero for the smallest exponent).
+        let e_bits = (bias_bits << 4).wrapping_add(0x10000000);
 
         // Combine the fields.  RGB floats have unwanted data in the upper 9
         // bits.  Only red needs to mask them off because green and blue shift
@@ -80,6 +229,51 @@ impl RGB9E5 {
         RGB9E5(e_bits | (b_bits << 18) | (g_bits << 9) | r_bits)
     }
 
+    /// Pack a triple of RGB float values into an RGB9E5 value, handling non-finite channels according to `policy`.
+    pub fn try_pack(rgb: [f32;3], policy: NonFinitePolicy) -> Result<Self, NonFiniteError> {
+        policy.apply(rgb).map(RGB9E5::pack)
+    }
+
+    /// Clamp and pack a triple of RGB float values into an RGB9E5 value
+    /// using the exact algorithm from the OpenGL and Vulkan specifications.
+    ///
+    /// [RGB9E5::pack] uses float addition to round each channel, which breaks ties to even and can pick a different
+    /// exponent near powers of two, so it occasionally differs from GPU packing by one ULP.
+    /// This follows the spec (choosing the exponent from the largest channel and rounding half up)
+    /// so that results bit-match reference images, at the cost of being slower. NaN is packed as zero.
+    pub fn pack_spec(rgb: [f32;3]) -> Self {
+        const N: i32 = 9;
+        const B: i32 = 15;
+        let [r, g, b] = rgb.map(|c| if c > 0.0 { c.min(Self::MAX) as f64 } else { 0.0 });
+        let max_c = r.max(g).max(b);
+
+        // floor(log2(max_c)), read from the float's exponent bits so that it is exact
+        let floor_log2 = ((max_c.to_bits() >> 52) & 0x7ff) as i32 - 1023;
+        let mut exp_shared = floor_log2.max(-B - 1) + 1 + B;
+        // c / 2^(exp - B - N), rounded half up
+        let quantize = |c: f64, exp: i32| (c * f64::from_bits(((1023 + N + B - exp) as u64) << 52) + 0.5) as u32;
+        if quantize(max_c, exp_shared) == 1 << N {
+            exp_shared += 1;
+        }
+        let [r_s, g_s, b_s] = [r, g, b].map(|c| quantize(c, exp_shared));
+        RGB9E5(((exp_shared as u32) << 27) | (b_s << 18) | (g_s << 9) | r_s)
+    }
+
+    /// Multiply the colour by `2^stops` by adjusting the shared exponent, which is lossless.
+    /// If the exponent would leave its range, the colour is repacked instead (saturating or losing precision).
+    pub fn scale_exposure(self, stops: i32) -> Self {
+        if self.0 & 0x07ffffff == 0 {
+            return self;
+        }
+        let e = (self.0 >> 27) as i32 + stops;
+        if (0..=31).contains(&e) {
+            RGB9E5(((e as u32) << 27) | (self.0 & 0x07ffffff))
+        } else {
+            let scale = (stops as f32).exp2();
+            RGB9E5::pack(self.unpack().map(|c| c * scale))
+        }
+    }
+
     /// Convert a packed color to individual floats
     pub fn unpack(self) -> [f32;3] {
         let bias = (((self.0 & 0xf8000000) >> 27) as f32 - 15.0).exp2();
@@ -90,23 +284,149 @@ impl RGB9E5 {
     }
 }
 
+/// Integer hash with good avalanche behaviour (Chris Wellons' lowbias32).
+fn hash32(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846ca68b);
+    x ^= x >> 16;
+    x
+}
+
+/// Small deterministic random number generator, used by the stochastic operations in this crate
+/// so that their results depend only on the seed they are given.
+///
+/// Each value is a hash of the next step of a Weyl sequence starting at the seed,
+/// so the sequence is the same on every platform and generators can be cheaply created for each task.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct SeededRng {
+    state: u32,
+}
+
+impl SeededRng {
+    /// Creates a generator whose sequence is determined by `seed`.
+    pub const fn new(seed: u32) -> Self {
+        SeededRng{state: seed}
+    }
+
+    /// Returns the next uniformly distributed 32-bit value.
+    pub fn next_u32(&mut self) -> u32 {
+        self.state = self.state.wrapping_add(0x9E3779B9);
+        hash32(self.state)
+    }
+
+    /// Returns the next value uniformly distributed in `[0, 1)`.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+/// Noise for dithering the texel at `(x, y)` with [RGBE8::pack_dithered], independently for each channel.
+///
+/// Values have a triangular distribution between -1 and 1, which makes the quantization error independent of the signal.
+/// The noise is a hash of the position, so dithering an image always gives the same result.
+/// This is the same as [triangular_dither_seeded] with a seed of 0.
+pub fn triangular_dither(x: u32, y: u32) -> [f32;3] {
+    triangular_dither_seeded(x, y, 0)
+}
+
+/// Noise for dithering the texel at `(x, y)`, as [triangular_dither], but from one of many independent patterns chosen by `seed`.
+pub fn triangular_dither_seeded(x: u32, y: u32, seed: u32) -> [f32;3] {
+    let base = hash32(x ^ hash32(y ^ hash32(seed)));
+    core::array::from_fn(|c| {
+        let h = hash32(base.wrapping_add(c as u32));
+        // difference of two uniform values
+        ((h & 0xffff) as f32 - (h >> 16) as f32) / 65536.0
+    })
+}
+
+/// Round a float to an unsigned float with a 5-bit exponent and `mant_bits` bits of mantissa,
+/// clamping to the largest finite value.
+fn pack_small_float(v: f32, mant_bits: u32) -> u32 {
+    let max_bits = (30 << mant_bits) | ((1 << mant_bits) - 1);
+    // also maps NaN to zero
+    let v = if v > 0.0 { v } else { return 0 };
+    let exp = ((v.to_bits() >> 23) & 0xFF) as i32 - 127 + 15;
+    if exp <= 0 {
+        // subnormal, which may round up to the smallest normal value.
+        return (v * ((14 + mant_bits) as f32).exp2()).round() as u32;
+    }
+    if exp >= 31 {
+        return max_bits;
+    }
+    // rounding can carry into the exponent, which gives the correct result.
+    let shift = 23 - mant_bits;
+    let combined = ((exp as u32) << 23) | (v.to_bits() & 0x7FFFFF);
+    ((combined + (1 << (shift - 1))) >> shift).min(max_bits)
+}
+
+/// Inverse of [pack_small_float].
+fn unpack_small_float(bits: u32, mant_bits: u32) -> f32 {
+    let exp = bits >> mant_bits;
+    let mant = (bits & ((1 << mant_bits) - 1)) as f32 / (1 << mant_bits) as f32;
+    match exp {
+        0 => mant * (-14.0f32).exp2(),
+        31 => if mant == 0.0 { f32::INFINITY } else { f32::NAN },
+        _ => (1.0 + mant) * (exp as f32 - 15.0).exp2(),
+    }
+}
+
+impl R11G11B10F {
+    /// Clamp and pack a triple of RGB float values into an R11G11B10F value, rounding to nearest.
+    pub fn pack(rgb: [f32;3]) -> Self {
+        let r = pack_small_float(rgb[0], 6);
+        let g = pack_small_float(rgb[1], 6);
+        let b = pack_small_float(rgb[2], 5);
+        R11G11B10F((b << 22) | (g << 11) | r)
+    }
+
+    /// Convert a packed color to individual floats
+    pub fn unpack(self) -> [f32;3] {
+        let r = unpack_small_float(self.0 & 0x7FF, 6);
+        let g = unpack_small_float((self.0 >> 11) & 0x7FF, 6);
+        let b = unpack_small_float(self.0 >> 22, 5);
+        [r, g, b]
+    }
+}
+
 impl RGBE8 {
+    /// Largest value representable in each channel. Larger values are clamped when packing.
+    pub const MAX: f32 = 255.0 * (2u128.pow(119) as f32);
+
     /// Pack a triple of RGB float values into an RGBE8.
+    /// Infinities are clamped to [RGBE8::MAX] and NaN channels become zero ([NonFinitePolicy::Clamp]).
     /// This is not as optimized as [RGB9E5::pack] since it is designed for use in tooling instead of asset loading.
     pub fn pack(rgb: [f32;3]) -> Self {
+        RGBE8::pack_dithered(rgb, [0.0; 3])
+    }
+
+    /// Pack a triple of RGB float values into an RGBE8, adding `noise` (in units of the least significant mantissa bit)
+    /// to each channel before rounding.
+    ///
+    /// With noise from [triangular_dither], this breaks up the banding which 8-bit mantissas cause in smooth gradients such as skies,
+    /// at the cost of a little grain. Zero noise gives the same result as [RGBE8::pack].
+    pub fn pack_dithered(rgb: [f32;3], noise: [f32;3]) -> Self {
+        // larger values would overflow the exponent when rounded up
+        let rgb = rgb.map(|c| if c > 0.0 { c.min(Self::MAX) } else { 0.0 });
         let max_channel = f32::MIN_POSITIVE.max(rgb[0]).max(rgb[1]).max(rgb[2]);
         // round to 8 bits of precision than take the next power of 2.
         let bias = f32::from_bits((max_channel.to_bits() + 0x00808000) & 0x7F800000);
 
         // clamping is necessary in case of negative values.
-        let r = ((rgb[0] / bias) * 256.0).round().clamp(0.0,255.0) as u8;
-        let g = ((rgb[1] / bias) * 256.0).round().clamp(0.0,255.0) as u8;
-        let b = ((rgb[2] / bias) * 256.0).round().clamp(0.0,255.0) as u8;
+        let r = ((rgb[0] / bias) * 256.0 + noise[0]).round().clamp(0.0,255.0) as u8;
+        let g = ((rgb[1] / bias) * 256.0 + noise[1]).round().clamp(0.0,255.0) as u8;
+        let b = ((rgb[2] / bias) * 256.0 + noise[2]).round().clamp(0.0,255.0) as u8;
         let e = ((bias.to_bits() >> 23) + 1).clamp(0,255) as u8;
 
         RGBE8{r, g, b, e}
     }
 
+    /// Pack a triple of RGB float values into an RGBE8, handling non-finite channels according to `policy`.
+    pub fn try_pack(rgb: [f32;3], policy: NonFinitePolicy) -> Result<Self, NonFiniteError> {
+        policy.apply(rgb).map(RGBE8::pack)
+    }
+
     /// Convert a packed color to individual floats
     pub fn unpack(self) -> [f32;3] {
         let bias = ((self.e as f32) - 128.0).exp2();
@@ -116,6 +436,43 @@ impl RGBE8 {
         [r,g,b]
     }
 
+    /// Pack a triple of RGB float values into an RGBE8 in the given [RgbeConvention], for writing files for other tools.
+    pub fn pack_with_convention(rgb: [f32;3], convention: RgbeConvention) -> Self {
+        let offset = if convention.centered { -0.5 } else { 0.0 };
+        RGBE8::pack_dithered(rgb, [offset; 3]).scale_exposure(convention.exponent_bias as i32 - 128)
+    }
+
+    /// Convert a color packed in the given [RgbeConvention] to individual floats.
+    pub fn unpack_with_convention(self, convention: RgbeConvention) -> [f32;3] {
+        if self.e == 0 {
+            return [0.0; 3];
+        }
+        let offset = if convention.centered { 0.5 } else { 0.0 };
+        let scale = ((self.e as i32 - convention.exponent_bias as i32) as f32).exp2() / 256.0;
+        [self.r, self.g, self.b].map(|c| (c as f32 + offset) * scale)
+    }
+
+    /// Multiply the colour by `2^stops` by adjusting the shared exponent, which is lossless.
+    /// If the exponent would leave its range, the colour is repacked instead (saturating or losing precision).
+    pub fn scale_exposure(self, stops: i32) -> Self {
+        if self.r == 0 && self.g == 0 && self.b == 0 {
+            return self;
+        }
+        let e = self.e as i32 + stops;
+        if (1..=255).contains(&e) {
+            RGBE8{e: e as u8, ..self}
+        } else if e > 255 {
+            // scale up the mantissas at the largest exponent as far as possible, keeping the chromaticity
+            let max = self.r.max(self.g).max(self.b) as f32;
+            let scale = (255.0 / max).min(((e - 255) as f32).exp2());
+            let [r, g, b] = [self.r, self.g, self.b].map(|c| (c as f32 * scale).round() as u8);
+            RGBE8{r, g, b, e: 255}
+        } else {
+            let scale = (stops as f32).exp2();
+            RGBE8::pack(self.unpack().map(|c| c * scale))
+        }
+    }
+
     /// Repack RGBE8 into [RGB9E5] for use on the GPU.
     /// This can cause saturation or loss of precision if the exponent is outside the range of RGB9E5.
     pub fn repack_rgb9e5(self) -> RGB9E5 {
@@ -133,6 +490,173 @@ impl RGBE8 {
     }
 }
 
+/// How the exponent and mantissas of RGBE texels are interpreted, for exchanging files with tools which differ from this crate.
+///
+/// Texels are `(m + offset) / 256 * 2^(e - exponent_bias)` for each mantissa `m`, where the offset is 0.5 if `centered` is set
+/// and 0 otherwise, and texels with an exponent of 0 are black. Everything else in this crate uses [RgbeConvention::RADIANCE],
+/// so texels in other conventions should be converted with [RgbeConvention::normalize] when loaded.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct RgbeConvention {
+    /// Exponent which gives mantissas their face value divided by 256. Radiance files use 128.
+    pub exponent_bias: u8,
+    /// Whether mantissas are taken from the centre of their rounding interval, as in later versions of the Radiance tools.
+    pub centered: bool,
+}
+
+impl RgbeConvention {
+    /// The convention of Radiance files and [RGBE8::pack] and [RGBE8::unpack].
+    pub const RADIANCE: RgbeConvention = RgbeConvention{exponent_bias: 128, centered: false};
+    /// Radiance's exponent bias with mantissas centred in their rounding interval, as written by `ra_tiff` and other Radiance 3 tools.
+    pub const RADIANCE_CENTERED: RgbeConvention = RgbeConvention{exponent_bias: 128, centered: true};
+
+    /// Convert texels in this convention to [RgbeConvention::RADIANCE], as expected by the rest of this crate.
+    /// Conventions which differ only in the exponent bias are converted losslessly where the exponents stay in range.
+    pub fn normalize(self, data: &mut [RGBE8]) {
+        if self == RgbeConvention::RADIANCE {
+            return;
+        }
+        for texel in data.iter_mut() {
+            // e = 0 is black in every convention, whatever the mantissas
+            *texel = if texel.e == 0 {
+                RGBE8{r: 0, g: 0, b: 0, e: 0}
+            } else if self.centered {
+                RGBE8::pack(texel.unpack_with_convention(self))
+            } else {
+                texel.scale_exposure(128 - self.exponent_bias as i32)
+            };
+        }
+    }
+}
+
+impl Default for RgbeConvention {
+    fn default() -> Self {
+        RgbeConvention::RADIANCE
+    }
+}
+
+impl RGBM8 {
+    /// A commonly used value for the `range` parameter.
+    pub const DEFAULT_RANGE: f32 = 6.0;
+
+    /// Pack a triple of RGB float values into an RGBM8 with the given range.
+    /// Values above `range` are clamped.
+    pub fn pack(rgb: [f32;3], range: f32) -> Self {
+        let r = (rgb[0] / range).clamp(0.0, 1.0);
+        let g = (rgb[1] / range).clamp(0.0, 1.0);
+        let b = (rgb[2] / range).clamp(0.0, 1.0);
+        // round the multiplier up so that the largest channel does not saturate.
+        let m = (r.max(g).max(b) * 255.0).ceil().max(1.0);
+        let scale = 255.0 * 255.0 / m;
+        RGBM8 {
+            r: (r * scale).round().min(255.0) as u8,
+            g: (g * scale).round().min(255.0) as u8,
+            b: (b * scale).round().min(255.0) as u8,
+            m: m as u8,
+        }
+    }
+
+    /// Convert a packed color with the given range to individual floats.
+    pub fn unpack(self, range: f32) -> [f32;3] {
+        let scale = self.m as f32 * range / (255.0 * 255.0);
+        [self.r as f32 * scale, self.g as f32 * scale, self.b as f32 * scale]
+    }
+
+    /// Convert to [RGB9E5] for use on the GPU.
+    /// RGB9E5 covers the whole range of RGBM8 with slightly more precision for the brightest channel, so little is lost.
+    pub fn into_rgb9e5(self, range: f32) -> RGB9E5 {
+        RGB9E5::pack(self.unpack(range))
+    }
+
+    /// Convert to [RGBA16F] with an alpha of 1.
+    pub fn into_rgba16f(self, range: f32) -> RGBA16F {
+        let rgb = self.unpack(range);
+        RGBA16F::from_f32([rgb[0], rgb[1], rgb[2], 1.0])
+    }
+}
+
+impl RGBD8 {
+    /// A commonly used value for the `range` parameter.
+    pub const DEFAULT_RANGE: f32 = 255.0;
+
+    /// Pack a triple of RGB float values into an RGBD8 with the given range.
+    /// Values above `range` are clamped.
+    pub fn pack(rgb: [f32;3], range: f32) -> Self {
+        let r = rgb[0].clamp(0.0, range);
+        let g = rgb[1].clamp(0.0, range);
+        let b = rgb[2].clamp(0.0, range);
+        // use the largest divisor which does not saturate the brightest channel.
+        let max_channel = f32::MIN_POSITIVE.max(r).max(g).max(b);
+        let d = (range / max_channel).floor().clamp(1.0, 255.0);
+        let scale = 255.0 * d / range;
+        RGBD8 {
+            r: (r * scale).round().min(255.0) as u8,
+            g: (g * scale).round().min(255.0) as u8,
+            b: (b * scale).round().min(255.0) as u8,
+            d: d as u8,
+        }
+    }
+
+    /// Convert a packed color with the given range to individual floats.
+    /// A divisor of zero is treated as black.
+    pub fn unpack(self, range: f32) -> [f32;3] {
+        if self.d == 0 {
+            return [0.0; 3];
+        }
+        let scale = range / (255.0 * self.d as f32);
+        [self.r as f32 * scale, self.g as f32 * scale, self.b as f32 * scale]
+    }
+
+    /// Repack into [RGB9E5] for use on the GPU.
+    pub fn repack_rgb9e5(self, range: f32) -> RGB9E5 {
+        RGB9E5::pack(self.unpack(range))
+    }
+}
+
+impl LogLuv32 {
+    /// Pack a triple of linear Rec.709 RGB float values into LogLuv.
+    /// Negative values are clamped to zero.
+    pub fn pack(rgb: [f32;3]) -> Self {
+        let rgb = rgb.map(|c| c.max(0.0));
+        let [x, y, z] = mul_matrix(&RGB_TO_XYZ, rgb);
+        if y <= 0.0 {
+            return LogLuv32(0);
+        }
+        let le = (256.0 * (y.log2() + 64.0)).floor().clamp(1.0, 0x7FFF as f32) as u32;
+        let denom = x + 15.0 * y + 3.0 * z;
+        let (u, v) = if denom > 0.0 { (4.0 * x / denom, 9.0 * y / denom) } else { (WHITE_U, WHITE_V) };
+        let ue = (410.0 * u).floor().clamp(0.0, 255.0) as u32;
+        let ve = (410.0 * v).floor().clamp(0.0, 255.0) as u32;
+        LogLuv32((le << 16) | (ue << 8) | ve)
+    }
+
+    /// Convert a packed color to linear Rec.709 RGB floats.
+    /// Colours outside the Rec.709 gamut have negative components.
+    pub fn unpack(self) -> [f32;3] {
+        let le = (self.0 >> 16) & 0x7FFF;
+        if le == 0 {
+            return [0.0; 3];
+        }
+        let lum = ((le as f32 + 0.5) / 256.0 - 64.0).exp2();
+        let u = (((self.0 >> 8) & 0xFF) as f32 + 0.5) / 410.0;
+        let v = ((self.0 & 0xFF) as f32 + 0.5) / 410.0;
+        let denom = 6.0 * u - 16.0 * v + 12.0;
+        let x = 9.0 * u / denom;
+        let y = 4.0 * v / denom;
+        let xyz = [x / y * lum, lum, (1.0 - x - y) / y * lum];
+        let rgb = mul_matrix(&XYZ_TO_RGB, xyz);
+        if self.0 & 0x8000_0000 != 0 {
+            rgb.map(|c| -c)
+        } else {
+            rgb
+        }
+    }
+
+    /// Convert into [RGB9E5] for use on the GPU. Out-of-gamut colours are clamped.
+    pub fn into_rgb9e5(self) -> RGB9E5 {
+        RGB9E5::pack(self.unpack())
+    }
+}
+
 impl RGBA16F {
     /// Convert four f32 values to f16.
     /// Causes loss of precision.
@@ -156,6 +680,164 @@ impl RGBA16F {
     pub fn into_rgbe8(self) -> RGBE8 {
         RGBE8::pack([self.r.to_f32(), self.g.to_f32(), self.b.to_f32()])
     }
+
+    /// Convert interleaved RGBA f32 values (such as a render target read back from the GPU) to texels in bulk,
+    /// which is much faster than converting each texel with [RGBA16F::from_f32]. Causes loss of precision.
+    ///
+    /// Panics if the length of `data` is not a multiple of 4.
+    #[cfg(feature = "std")]
+    pub fn slice_from_f32(data: &[f32]) -> Vec<RGBA16F> {
+        assert!(data.len().is_multiple_of(4), "data must have 4 values per texel");
+        let mut out = vec![RGBA16F::zeroed(); data.len() / 4];
+        bytemuck::cast_slice_mut::<RGBA16F, f16>(&mut out).convert_from_f32_slice(data);
+        out
+    }
+
+    /// Convert texels to interleaved RGBA f32 values in bulk, inverting [RGBA16F::slice_from_f32] without loss.
+    #[cfg(feature = "std")]
+    pub fn slice_to_f32(data: &[RGBA16F]) -> Vec<f32> {
+        bytemuck::cast_slice::<RGBA16F, f16>(data).to_f32_vec()
+    }
+}
+
+impl RGB32F {
+    pub fn from_f32(c: [f32; 3]) -> Self {
+        RGB32F{r: c[0], g: c[1], b: c[2]}
+    }
+
+    /// Pack into an [RGB9E5] texel.
+    /// Causes a loss of precision.
+    pub fn into_rgb9e5(self) -> RGB9E5 {
+        RGB9E5::pack(self.into())
+    }
+
+    /// Pack into an [RGBE8] texel.
+    /// Causes a loss of precision.
+    pub fn into_rgbe8(self) -> RGBE8 {
+        RGBE8::pack(self.into())
+    }
+}
+
+impl RGBA32F {
+    pub fn from_f32(c: [f32; 4]) -> Self {
+        RGBA32F{r: c[0], g: c[1], b: c[2], a: c[3]}
+    }
+
+    /// Pack the RGB values into an [RGB9E5] texel. Ignores alpha.
+    /// Causes a loss of precision.
+    pub fn into_rgb9e5(self) -> RGB9E5 {
+        RGB9E5::pack([self.r, self.g, self.b])
+    }
+
+    /// Pack the RGB values into an [RGBE8] texel. Ignores alpha.
+    /// Causes a loss of precision.
+    pub fn into_rgbe8(self) -> RGBE8 {
+        RGBE8::pack([self.r, self.g, self.b])
+    }
+}
+
+impl From<RGB32F> for [f32; 3] {
+    fn from(color: RGB32F) -> Self {
+        [color.r, color.g, color.b]
+    }
+}
+
+impl From<[f32; 3]> for RGB32F {
+    fn from(rgb: [f32; 3]) -> Self {
+        RGB32F::from_f32(rgb)
+    }
+}
+
+impl From<RGBA32F> for [f32; 4] {
+    fn from(color: RGBA32F) -> Self {
+        [color.r, color.g, color.b, color.a]
+    }
+}
+
+impl From<[f32; 4]> for RGBA32F {
+    fn from(rgba: [f32; 4]) -> Self {
+        RGBA32F::from_f32(rgba)
+    }
+}
+
+/// Converts with an alpha of 1.
+impl From<RGB32F> for RGBA32F {
+    fn from(color: RGB32F) -> Self {
+        RGBA32F{r: color.r, g: color.g, b: color.b, a: 1.0}
+    }
+}
+
+/// Ignores alpha.
+impl From<RGBA32F> for RGB32F {
+    fn from(color: RGBA32F) -> Self {
+        RGB32F{r: color.r, g: color.g, b: color.b}
+    }
+}
+
+/// [RGBA16F] can be converted to [RGBA32F] without loss of precision.
+impl From<RGBA16F> for RGBA32F {
+    fn from(color: RGBA16F) -> Self {
+        RGBA32F::from_f32(color.into())
+    }
+}
+
+/// Causes loss of precision.
+impl From<RGBA32F> for RGBA16F {
+    fn from(color: RGBA32F) -> Self {
+        RGBA16F::from_f32(color.into())
+    }
+}
+
+impl From<RGB9E5> for RGB32F {
+    fn from(color: RGB9E5) -> Self {
+        RGB32F::from_f32(color.unpack())
+    }
+}
+
+impl From<RGBE8> for RGB32F {
+    fn from(color: RGBE8) -> Self {
+        RGB32F::from_f32(color.unpack())
+    }
+}
+
+impl From<RGB32F> for RGB9E5 {
+    fn from(color: RGB32F) -> Self {
+        color.into_rgb9e5()
+    }
+}
+
+impl From<RGB32F> for RGBE8 {
+    fn from(color: RGB32F) -> Self {
+        color.into_rgbe8()
+    }
+}
+
+/// Unpacks with an alpha of 1.
+impl From<RGB9E5> for RGBA32F {
+    fn from(color: RGB9E5) -> Self {
+        RGB32F::from(color).into()
+    }
+}
+
+/// Unpacks with an alpha of 1.
+impl From<RGBE8> for RGBA32F {
+    fn from(color: RGBE8) -> Self {
+        RGB32F::from(color).into()
+    }
+}
+
+/// Same as [RGBA32F::into_rgb9e5]. Ignores alpha.
+impl From<RGBA32F> for RGB9E5 {
+    fn from(color: RGBA32F) -> Self {
+        color.into_rgb9e5()
+    }
+}
+
+/// Same as [RGBA32F::into_rgbe8]. Ignores alpha.
+impl From<RGBA32F> for RGBE8 {
+    fn from(color: RGBA32F) -> Self {
+        color.into_rgbe8()
+    }
 }
 
 impl From<RGBA16F> for [f32; 4] {
@@ -183,3 +865,328 @@ impl From<RGB9E5> for RGBA16F {
         RGBA16F::from_f32([col32[0], col32[1], col32[2], 1.0])
     }
 }
+
+/// Unpacks to [RGBA16F] with an alpha of 1. Values outside the range of `f16` become infinite.
+impl From<RGBE8> for RGBA16F {
+    fn from(color: RGBE8) -> Self {
+        let col32 = color.unpack();
+        RGBA16F::from_f32([col32[0], col32[1], col32[2], 1.0])
+    }
+}
+
+/// Same as [RGBE8::repack_rgb9e5].
+impl From<RGBE8> for RGB9E5 {
+    fn from(color: RGBE8) -> Self {
+        color.repack_rgb9e5()
+    }
+}
+
+/// [RGBE8] covers the whole range of [RGB9E5], so this only loses the lowest bit of each mantissa.
+impl From<RGB9E5> for RGBE8 {
+    fn from(color: RGB9E5) -> Self {
+        RGBE8::pack(color.unpack())
+    }
+}
+
+/// Same as [RGBA16F::into_rgb9e5]. Ignores alpha.
+impl From<RGBA16F> for RGB9E5 {
+    fn from(color: RGBA16F) -> Self {
+        color.into_rgb9e5()
+    }
+}
+
+/// Same as [RGBA16F::into_rgbe8]. Ignores alpha.
+impl From<RGBA16F> for RGBE8 {
+    fn from(color: RGBA16F) -> Self {
+        color.into_rgbe8()
+    }
+}
+
+impl From<[f32; 3]> for RGBE8 {
+    fn from(rgb: [f32; 3]) -> Self {
+        RGBE8::pack(rgb)
+    }
+}
+
+impl From<[f32; 3]> for RGB9E5 {
+    fn from(rgb: [f32; 3]) -> Self {
+        RGB9E5::pack(rgb)
+    }
+}
+
+/// Converts with an alpha of 1.
+impl From<[f32; 3]> for RGBA16F {
+    fn from(rgb: [f32; 3]) -> Self {
+        RGBA16F::from_f32([rgb[0], rgb[1], rgb[2], 1.0])
+    }
+}
+
+impl From<[f32; 4]> for RGBA16F {
+    fn from(rgba: [f32; 4]) -> Self {
+        RGBA16F::from_f32(rgba)
+    }
+}
+
+/// Ignores alpha.
+impl From<RGBA16F> for [f32; 3] {
+    fn from(color: RGBA16F) -> Self {
+        [color.r.to_f32(), color.g.to_f32(), color.b.to_f32()]
+    }
+}
+
+impl From<LogLuv32> for [f32; 3] {
+    fn from(color: LogLuv32) -> Self {
+        color.unpack()
+    }
+}
+
+/// Same as [LogLuv32::into_rgb9e5].
+impl From<LogLuv32> for RGB9E5 {
+    fn from(color: LogLuv32) -> Self {
+        color.into_rgb9e5()
+    }
+}
+
+impl From<R11G11B10F> for [f32; 3] {
+    fn from(color: R11G11B10F) -> Self {
+        color.unpack()
+    }
+}
+
+impl From<[f32; 3]> for R11G11B10F {
+    fn from(rgb: [f32; 3]) -> Self {
+        R11G11B10F::pack(rgb)
+    }
+}
+
+/// [R11G11B10F] can be unpacked to [RGBA16F] without loss of precision.
+impl From<R11G11B10F> for RGBA16F {
+    fn from(color: R11G11B10F) -> Self {
+        color.unpack().into()
+    }
+}
+
+/// Ignores alpha.
+impl From<RGBA16F> for R11G11B10F {
+    fn from(color: RGBA16F) -> Self {
+        R11G11B10F::pack(color.into())
+    }
+}
+
+impl From<R11G11B10F> for RGB9E5 {
+    fn from(color: R11G11B10F) -> Self {
+        RGB9E5::pack(color.unpack())
+    }
+}
+
+impl From<RGB9E5> for R11G11B10F {
+    fn from(color: RGB9E5) -> Self {
+        R11G11B10F::pack(color.unpack())
+    }
+}
+
+impl From<R11G11B10F> for RGBE8 {
+    fn from(color: R11G11B10F) -> Self {
+        RGBE8::pack(color.unpack())
+    }
+}
+
+impl From<RGBE8> for R11G11B10F {
+    fn from(color: RGBE8) -> Self {
+        R11G11B10F::pack(color.unpack())
+    }
+}
+
+/// [R11G11B10F] can be unpacked to [RGBA32F] without loss of precision.
+impl From<R11G11B10F> for RGBA32F {
+    fn from(color: R11G11B10F) -> Self {
+        RGB32F::from_f32(color.unpack()).into()
+    }
+}
+
+/// Ignores alpha.
+impl From<RGBA32F> for R11G11B10F {
+    fn from(color: RGBA32F) -> Self {
+        R11G11B10F::pack([color.r, color.g, color.b])
+    }
+}
+
+/// Rec.709 luminance coefficients.
+pub(crate) const LUMA: [f32;3] = [0.2126, 0.7152, 0.0722];
+
+/// Relative luminance of a linear Rec.709 colour.
+pub fn luminance(rgb: [f32;3]) -> f32 {
+    LUMA[0] * rgb[0] + LUMA[1] * rgb[1] + LUMA[2] * rgb[2]
+}
+
+/// Conversion from linear Rec.709 RGB to CIE XYZ.
+pub(crate) const RGB_TO_XYZ: [[f32;3];3] = [
+    [0.4124, 0.3576, 0.1805],
+    [0.2126, 0.7152, 0.0722],
+    [0.0193, 0.1192, 0.9505],
+];
+
+/// Conversion from CIE XYZ to linear Rec.709 RGB.
+pub(crate) const XYZ_TO_RGB: [[f32;3];3] = [
+    [3.2406, -1.5372, -0.4986],
+    [-0.9689, 1.8758, 0.0415],
+    [0.0557, -0.2040, 1.0570],
+];
+
+/// CIE (u', v') chromaticity of the D65 white point.
+const WHITE_U: f32 = 0.1978;
+const WHITE_V: f32 = 0.4683;
+
+pub(crate) fn mul_matrix(m: &[[f32;3];3], v: [f32;3]) -> [f32;3] {
+    [
+        m[0][0] * v[0] + m[0][1] * v[1] + m[0][2] * v[2],
+        m[1][0] * v[0] + m[1][1] * v[1] + m[1][2] * v[2],
+        m[2][0] * v[0] + m[2][1] * v[1] + m[2][2] * v[2],
+    ]
+}
+
+/// Texel formats which store a linear RGB colour, allowing the same operations to be used on all of them.
+pub trait RgbTexel: Pod {
+    /// Unpack the colour of this texel.
+    fn to_rgb(self) -> [f32;3];
+
+    /// Repack this texel with a new colour, keeping any other channels (such as alpha).
+    fn with_rgb(self, rgb: [f32;3]) -> Self;
+}
+
+impl RgbTexel for RGBE8 {
+    fn to_rgb(self) -> [f32;3] {
+        self.unpack()
+    }
+
+    fn with_rgb(self, rgb: [f32;3]) -> Self {
+        RGBE8::pack(rgb)
+    }
+}
+
+impl RgbTexel for RGB9E5 {
+    fn to_rgb(self) -> [f32;3] {
+        self.unpack()
+    }
+
+    fn with_rgb(self, rgb: [f32;3]) -> Self {
+        RGB9E5::pack(rgb)
+    }
+}
+
+impl RgbTexel for R11G11B10F {
+    fn to_rgb(self) -> [f32;3] {
+        self.unpack()
+    }
+
+    fn with_rgb(self, rgb: [f32;3]) -> Self {
+        R11G11B10F::pack(rgb)
+    }
+}
+
+impl RgbTexel for RGBA16F {
+    fn to_rgb(self) -> [f32;3] {
+        [self.r.to_f32(), self.g.to_f32(), self.b.to_f32()]
+    }
+
+    fn with_rgb(self, rgb: [f32;3]) -> Self {
+        RGBA16F{r: f16::from_f32(rgb[0]), g: f16::from_f32(rgb[1]), b: f16::from_f32(rgb[2]), a: self.a}
+    }
+}
+
+impl RgbTexel for RGB32F {
+    fn to_rgb(self) -> [f32;3] {
+        self.into()
+    }
+
+    fn with_rgb(self, rgb: [f32;3]) -> Self {
+        rgb.into()
+    }
+}
+
+impl RgbTexel for RGBA32F {
+    fn to_rgb(self) -> [f32;3] {
+        [self.r, self.g, self.b]
+    }
+
+    fn with_rgb(self, rgb: [f32;3]) -> Self {
+        RGBA32F{r: rgb[0], g: rgb[1], b: rgb[2], a: self.a}
+    }
+}
+
+impl RgbTexel for [f32;3] {
+    fn to_rgb(self) -> [f32;3] {
+        self
+    }
+
+    fn with_rgb(self, rgb: [f32;3]) -> Self {
+        rgb
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pack_spec_bits(rgb: [f32;3]) -> u32 {
+        RGB9E5::pack_spec(rgb).0
+    }
+
+    /// Reference encodings worked out by hand from the shared exponent algorithm in
+    /// KHR_texture_shared_exponent / EXT_texture_shared_exponent (N = 9, B = 15, Emax = 31).
+    #[test]
+    fn pack_spec_reference_encodings() {
+        // zero
+        assert_eq!(pack_spec_bits([0.0, 0.0, 0.0]), 0);
+        // max representable value: 511 * 2^(31 - 15 - 9)
+        assert_eq!(pack_spec_bits([RGB9E5::MAX; 3]), 0xFFFF_FFFF);
+        assert_eq!(pack_spec_bits([65408.0, 0.0, 0.0]), 0xF800_01FF);
+        // exact values with exponent 16
+        assert_eq!(pack_spec_bits([1.0, 1.0, 1.0]), 0x8402_0100);
+        assert_eq!(pack_spec_bits([1.0, 0.5, 0.25]), 0x8101_0100);
+        // denormal range: exponent 0, each step is 2^-24
+        assert_eq!(pack_spec_bits([2f32.powi(-24), 0.0, 0.0]), 0x0000_0001);
+        assert_eq!(pack_spec_bits([0.0, 0.0, 2f32.powi(-16)]), 0x0400_0000);
+        assert_eq!(pack_spec_bits([2f32.powi(-25), 0.0, 0.0]), 0x0000_0001);
+        assert_eq!(pack_spec_bits([2f32.powi(-26), 0.0, 0.0]), 0);
+        // exponent rounding boundary: 511.5 / 256 rounds up to 512, so the exponent is incremented
+        assert_eq!(pack_spec_bits([511.0 / 256.0, 0.0, 0.0]), 0x8000_01FF);
+        assert_eq!(pack_spec_bits([511.5 / 256.0, 0.0, 0.0]), 0x8800_0100);
+        assert_eq!(pack_spec_bits([511.25 / 256.0, 0.0, 0.0]), 0x8000_01FF);
+        // ties round half up rather than to even
+        assert_eq!(pack_spec_bits([256.5 / 256.0, 0.0, 0.0]), 0x8000_0101);
+        // negative and NaN channels become zero, larger values and infinity are clamped
+        assert_eq!(pack_spec_bits([-1.0, 1.0, f32::NAN]), 0x8002_0000);
+        assert_eq!(pack_spec_bits([f32::NAN; 3]), 0);
+        assert_eq!(pack_spec_bits([f32::INFINITY, 0.0, 0.0]), 0xF800_01FF);
+        assert_eq!(pack_spec_bits([1.0e6, -f32::INFINITY, 0.0]), 0xF800_01FF);
+    }
+
+    #[test]
+    fn pack_spec_matches_pack() {
+        let mut rng = SeededRng::new(537);
+        for _ in 0..100_000 {
+            let rgb: [f32;3] = core::array::from_fn(|_| 2f32.powi((rng.next_f32() * 44.0) as i32 - 28) * (1.0 + rng.next_f32()));
+            let spec = RGB9E5::pack_spec(rgb);
+            let fast = RGB9E5::pack(rgb);
+            // both are within one step of the larger shared exponent
+            let e = (spec.0 >> 27).max(fast.0 >> 27) as i32;
+            let step = 2f32.powi(e - 24);
+            for (s, f) in spec.unpack().into_iter().zip(fast.unpack()) {
+                assert!((s - f).abs() <= step, "{rgb:?}: {spec:?} {fast:?}");
+            }
+            // values which are exactly representable pack identically
+            let exact = spec.unpack();
+            assert_eq!(RGB9E5::pack_spec(exact), RGB9E5::pack(exact), "{exact:?}");
+        }
+    }
+    #[test]
+    fn normalize_keeps_black() {
+        let mut data = [RGBE8{r: 12, g: 34, b: 56, e: 0}, RGBE8{r: 128, g: 64, b: 0, e: 130}];
+        RgbeConvention{exponent_bias: 136, centered: false}.normalize(&mut data);
+        assert_eq!(data, [RGBE8{r: 0, g: 0, b: 0, e: 0}, RGBE8{r: 128, g: 64, b: 0, e: 122}]);
+        let mut data = [RGBE8{r: 12, g: 34, b: 56, e: 0}];
+        RgbeConvention::RADIANCE_CENTERED.normalize(&mut data);
+        assert_eq!(data, [RGBE8{r: 0, g: 0, b: 0, e: 0}]);
+    }
+}
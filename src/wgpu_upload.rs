@@ -0,0 +1,127 @@
+use crate::types::*;
+
+use wgpu::{BindGroupDescriptor, BindGroupEntry, BindingResource, BufferDescriptor, BufferUsages, CommandEncoderDescriptor,
+    ComputePassDescriptor, ComputePipelineDescriptor, Device, Extent3d, Maintain, MapMode, Origin3d, Queue, ShaderModuleDescriptor,
+    ShaderSource, TexelCopyBufferLayout, TexelCopyTextureInfo, Texture, TextureAspect, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureUsages, TextureViewDescriptor, COPY_BYTES_PER_ROW_ALIGNMENT};
+
+fn extent_3d(extent: Extent2D) -> Extent3d {
+    Extent3d{width: extent.width(), height: extent.height(), depth_or_array_layers: 1}
+}
+
+/// Descriptor for a sampled 2D [RGB9E5] texture with no mipmaps, which can be filled with [write_rgb9e5_texture].
+pub fn rgb9e5_texture_descriptor(label: Option<&str>, extent: Extent2D) -> TextureDescriptor<'_> {
+    TextureDescriptor{
+        label,
+        size: extent_3d(extent),
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: TextureFormat::Rgb9e5Ufloat,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        view_formats: &[],
+    }
+}
+
+/// Upload [RGB9E5] texel data to mip level 0 of a texture created from [rgb9e5_texture_descriptor].
+pub fn write_rgb9e5_texture(queue: &Queue, texture: &Texture, extent: Extent2D, data: &[RGB9E5]) {
+    assert_eq!(data.len(), extent.texel_count(), "data does not match image dimensions");
+    let layout = TexelCopyBufferLayout{offset: 0, bytes_per_row: Some(extent.width() * 4), rows_per_image: Some(extent.height())};
+    let target = TexelCopyTextureInfo{texture, mip_level: 0, origin: Origin3d::ZERO, aspect: TextureAspect::All};
+    queue.write_texture(target, bytemuck::cast_slice(data), layout, extent_3d(extent));
+}
+
+/// Create an [RGB9E5] texture and upload texel data to it, such as an image from [load_rgbe8_png_file_as_rgb9e5](crate::load_rgbe8_png_file_as_rgb9e5).
+pub fn create_rgb9e5_texture(device: &Device, queue: &Queue, label: Option<&str>, extent: Extent2D, data: &[RGB9E5]) -> Texture {
+    let texture = device.create_texture(&rgb9e5_texture_descriptor(label, extent));
+    write_rgb9e5_texture(queue, &texture, extent, data);
+    texture
+}
+
+/// Copy [RGB9E5] texel data into rows padded to `COPY_BYTES_PER_ROW_ALIGNMENT`,
+/// as required when copying from a buffer (such as a staging buffer) to a texture.
+///
+/// Returns the padded data and the number of bytes per row.
+pub fn pad_rgb9e5_rows(extent: Extent2D, data: &[RGB9E5]) -> (Vec<u8>, u32) {
+    assert_eq!(data.len(), extent.texel_count(), "data does not match image dimensions");
+    let row_bytes = extent.width() as usize * 4;
+    let bytes_per_row = row_bytes.next_multiple_of(COPY_BYTES_PER_ROW_ALIGNMENT as usize);
+    let mut padded = vec![0; bytes_per_row * extent.height() as usize];
+    for (src, dst) in bytemuck::cast_slice::<_, u8>(data).chunks_exact(row_bytes).zip(padded.chunks_exact_mut(bytes_per_row)) {
+        dst[..row_bytes].copy_from_slice(src);
+    }
+    (padded, bytes_per_row as u32)
+}
+
+/// Compute shader copying every texel of a texture, as decoded by the GPU, into a buffer of `vec4<f32>`.
+const DECODE_SHADER: &str = "
+@group(0) @binding(0) var src: texture_2d<f32>;
+@group(0) @binding(1) var<storage, read_write> dst: array<vec4<f32>>;
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(src);
+    if (id.x < size.x && id.y < size.y) {
+        dst[id.y * size.x + id.x] = textureLoad(src, id.xy, 0);
+    }
+}
+";
+
+/// Upload [RGB9E5] texel data to a texture and read back the linear RGB values the GPU decodes it to.
+///
+/// These should match [RGB9E5::to_rgb] exactly, so this can be used to catch drivers or shader paths which decode the format differently.
+/// The texture is read with `textureLoad` in a compute shader, so the device must support compute and storage buffers,
+/// and the image must fit within its `max_storage_buffer_binding_size` at 16 bytes per texel.
+/// Blocks until the GPU has finished.
+pub fn decode_rgb9e5_on_gpu(device: &Device, queue: &Queue, extent: Extent2D, data: &[RGB9E5]) -> Box<[[f32;3]]> {
+    let texture = create_rgb9e5_texture(device, queue, Some("rgb9e5 decode source"), extent, data);
+    let size = extent.texel_count() as u64 * 16;
+    let output = device.create_buffer(&BufferDescriptor{
+        label: Some("rgb9e5 decode output"),
+        size,
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let readback = device.create_buffer(&BufferDescriptor{
+        label: Some("rgb9e5 decode readback"),
+        size,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let module = device.create_shader_module(ShaderModuleDescriptor{label: Some("rgb9e5 decode"), source: ShaderSource::Wgsl(DECODE_SHADER.into())});
+    let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor{
+        label: Some("rgb9e5 decode"),
+        layout: None,
+        module: &module,
+        entry_point: Some("main"),
+        compilation_options: Default::default(),
+        cache: None,
+    });
+    let view = texture.create_view(&TextureViewDescriptor::default());
+    let bind_group = device.create_bind_group(&BindGroupDescriptor{
+        label: Some("rgb9e5 decode"),
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &[
+            BindGroupEntry{binding: 0, resource: BindingResource::TextureView(&view)},
+            BindGroupEntry{binding: 1, resource: output.as_entire_binding()},
+        ],
+    });
+
+    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor{label: Some("rgb9e5 decode")});
+    {
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor{label: Some("rgb9e5 decode"), timestamp_writes: None});
+        pass.set_pipeline(&pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch_workgroups(extent.width().div_ceil(8), extent.height().div_ceil(8), 1);
+    }
+    encoder.copy_buffer_to_buffer(&output, 0, &readback, 0, size);
+    queue.submit([encoder.finish()]);
+
+    let slice = readback.slice(..);
+    slice.map_async(MapMode::Read, |result| result.expect("failed to map readback buffer"));
+    device.poll(Maintain::Wait);
+    let texels: Box<[[f32;3]]> = bytemuck::cast_slice::<u8, [f32;4]>(&slice.get_mapped_range()).iter().map(|&[r, g, b, _]| [r, g, b]).collect();
+    readback.unmap();
+    texels
+}